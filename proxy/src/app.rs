@@ -1,7 +1,22 @@
 use config::{self, Config};
+use crash;
 use logging;
 
-pub fn init() -> Result<Config, config::Error> {
+/// Initializes logging and loads the `Config`, with `args` (excluding the program
+/// name) overriding ENV variables.
+pub fn init<I>(args: I) -> Result<Config, config::Error>
+where
+    I: IntoIterator<Item = String>,
+{
     logging::init();
-    Config::load_from_env()
+    let config = Config::load_from_args(args)?;
+
+    // Logging starts with the filter from the environment, which the arguments or the
+    // config file may replace.
+    if let Some(filter) = config.log_filter() {
+        logging::set_filter(filter);
+    }
+    info!("configuration: {}", config.to_json());
+    crash::init(config.crash_report_path().map(|p| p.to_path_buf()));
+    Ok(config)
 }
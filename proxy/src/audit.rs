@@ -0,0 +1,422 @@
+//! Records exchanges with audited hosts to a spool directory.
+//!
+//! For requests to configured hosts, the request's headers and the response's headers
+//! and body are written to a file in the spool directory once the response body ends.
+//! The values of sensitive headers (e.g. `authorization`) are redacted, and at most
+//! `max_body_bytes` of each body are recorded.
+//!
+//! Records are written by a dedicated thread so that file I/O never blocks the proxy. If
+//! that thread falls behind, records are dropped rather than buffered without bound.
+//! Each record is written to a temporary file that is then renamed, so that a consumer
+//! shipping the spool elsewhere (e.g. to object storage) never reads a partial record.
+//!
+//! Request bodies are not yet recorded.
+
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::{Buf, Bytes, IntoBuf};
+use futures::{Async, Future, Poll};
+use h2;
+use http;
+use tower::Service;
+use tower_h2;
+
+use local::{host_matches, request_host};
+
+/// The number of records that may be waiting to be written.
+const SPOOL_CAPACITY: usize = 1_024;
+
+/// Replaces the values of redacted headers.
+const REDACTED: &str = "[redacted]";
+
+/// The hosts whose exchanges are recorded, and how.
+///
+/// Each host is either an exact host name or a `*.`-prefixed suffix.
+#[derive(Clone, Debug)]
+pub struct Routes {
+    hosts: Arc<Vec<String>>,
+    max_body_bytes: usize,
+    redacted_headers: Arc<Vec<String>>,
+    spool: Option<Spool>,
+}
+
+/// Writes records to a directory on a background thread.
+#[derive(Clone, Debug)]
+pub struct Spool {
+    tx: SyncSender<Record>,
+}
+
+/// Records exchanges for requests to configured routes.
+pub struct Audit<S> {
+    inner: S,
+    routes: Routes,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    /// The routes and rendered request head, if the exchange is recorded.
+    audit: Option<(Routes, Vec<u8>)>,
+}
+
+/// A response body that is recorded as it is streamed.
+pub struct Body<B> {
+    inner: B,
+    tee: Option<Tee>,
+}
+
+/// The chunk type of a `Body`.
+#[derive(Debug)]
+pub enum Data<B> {
+    Unrecorded(B),
+    Recorded(Cursor<Bytes>),
+}
+
+/// An exchange being recorded.
+struct Tee {
+    spool: Spool,
+    max_body_bytes: usize,
+    record: Record,
+}
+
+#[derive(Debug)]
+struct Record {
+    /// The request and response heads.
+    head: Vec<u8>,
+    body: Vec<u8>,
+    body_truncated: bool,
+    complete: bool,
+}
+
+// ===== impl Routes =====
+
+impl Routes {
+    /// Creates `Routes` that record exchanges with `hosts` to `spool`, if any.
+    pub fn new<H, R>(
+        hosts: H,
+        max_body_bytes: usize,
+        redacted_headers: R,
+        spool: Option<Spool>,
+    ) -> Self
+    where
+        H: IntoIterator<Item = String>,
+        R: IntoIterator<Item = String>,
+    {
+        Routes {
+            hosts: Arc::new(hosts.into_iter().map(|h| h.to_lowercase()).collect()),
+            max_body_bytes,
+            redacted_headers: Arc::new(
+                redacted_headers.into_iter().map(|h| h.to_lowercase()).collect(),
+            ),
+            spool,
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.spool.is_some() && self.hosts.iter().any(|p| host_matches(p, host))
+    }
+
+    fn render_headers(&self, out: &mut Vec<u8>, headers: &http::HeaderMap) {
+        for (name, value) in headers.iter() {
+            let _ = out.write_all(name.as_str().as_bytes());
+            let _ = out.write_all(b": ");
+            if self.redacted_headers.iter().any(|h| h == name.as_str()) {
+                let _ = out.write_all(REDACTED.as_bytes());
+            } else {
+                let _ = out.write_all(value.as_bytes());
+            }
+            let _ = out.write_all(b"\n");
+        }
+    }
+}
+
+// ===== impl Spool =====
+
+impl Spool {
+    /// Creates `dir`, if necessary, and starts a thread that writes records to it.
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        let (tx, rx) = mpsc::sync_channel::<Record>(SPOOL_CAPACITY);
+        thread::Builder::new()
+            .name("audit-spool".into())
+            .spawn(move || {
+                let mut seq = 0u64;
+                for record in rx {
+                    let since_epoch = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    let name = format!(
+                        "{}.{:09}-{}",
+                        since_epoch.as_secs(),
+                        since_epoch.subsec_nanos(),
+                        seq,
+                    );
+                    seq += 1;
+                    let path = dir.join(format!("{}.audit", name));
+                    let tmp = dir.join(format!("{}.tmp", name));
+                    let written = fs::File::create(&tmp)
+                        .and_then(|mut f| record.write_to(&mut f))
+                        .and_then(|_| fs::rename(&tmp, &path));
+                    if let Err(e) = written {
+                        warn!("could not write audit record {}: {}", path.display(), e);
+                    }
+                }
+            })?;
+
+        Ok(Spool {
+            tx,
+        })
+    }
+
+    fn send(&self, record: Record) {
+        match self.tx.try_send(record) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => warn!("audit spool is full; dropping record"),
+            Err(TrySendError::Disconnected(_)) => warn!("audit spool stopped; dropping record"),
+        }
+    }
+}
+
+// ===== impl Audit =====
+
+impl<S> Audit<S> {
+    pub fn new(inner: S, routes: Routes) -> Self {
+        Audit {
+            inner,
+            routes,
+        }
+    }
+}
+
+impl<S, A, B> Service for Audit<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+{
+    type Request = S::Request;
+    type Response = http::Response<Body<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let audit = request_host(&req)
+            .map(|h| self.routes.matches(h))
+            .unwrap_or(false);
+
+        let audit = if audit {
+            let mut head = Vec::new();
+            let _ = writeln!(head, "{} {}", req.method(), req.uri());
+            self.routes.render_headers(&mut head, req.headers());
+            Some((self.routes.clone(), head))
+        } else {
+            None
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            audit,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = http::Response<Body<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = try_ready!(self.inner.poll());
+
+        let tee = self.audit.take().and_then(|(routes, mut head)| {
+            let _ = writeln!(head, "\n{:?} {}", rsp.version(), rsp.status());
+            routes.render_headers(&mut head, rsp.headers());
+            let max_body_bytes = routes.max_body_bytes;
+            routes.spool.map(|spool| Tee {
+                spool,
+                max_body_bytes,
+                record: Record {
+                    head,
+                    body: Vec::new(),
+                    body_truncated: false,
+                    complete: false,
+                },
+            })
+        });
+
+        let (parts, inner) = rsp.into_parts();
+        let body = Body {
+            inner,
+            tee,
+        };
+        Ok(Async::Ready(http::Response::from_parts(parts, body)))
+    }
+}
+
+// ===== impl Body =====
+
+impl<B: Default> Default for Body<B> {
+    fn default() -> Self {
+        Body {
+            inner: B::default(),
+            tee: None,
+        }
+    }
+}
+
+impl<B> tower_h2::Body for Body<B>
+where
+    B: tower_h2::Body + 'static,
+{
+    type Data = Data<<B::Data as IntoBuf>::Buf>;
+
+    fn is_end_stream(&self) -> bool {
+        // A recorded body must be polled to its end so that the record is complete.
+        self.tee.is_none() && self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        let data = try_ready!(self.inner.poll_data());
+
+        let mut tee = match self.tee.take() {
+            None => return Ok(Async::Ready(data.map(|d| Data::Unrecorded(d.into_buf())))),
+            Some(tee) => tee,
+        };
+
+        match data {
+            Some(data) => {
+                let bytes = data.into_buf().collect::<Bytes>();
+                tee.record(&bytes);
+                self.tee = Some(tee);
+                Ok(Async::Ready(Some(Data::Recorded(bytes.into_buf()))))
+            }
+            None => {
+                tee.record.complete = true;
+                tee.spool.send(tee.record);
+                Ok(Async::Ready(None))
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+impl<B> Drop for Body<B> {
+    fn drop(&mut self) {
+        // The body did not end, e.g. because the stream was reset.
+        if let Some(tee) = self.tee.take() {
+            tee.spool.send(tee.record);
+        }
+    }
+}
+
+// ===== impl Tee =====
+
+impl Tee {
+    fn record(&mut self, bytes: &[u8]) {
+        let remaining = self.max_body_bytes - self.record.body.len();
+        if bytes.len() > remaining {
+            self.record.body.extend_from_slice(&bytes[..remaining]);
+            self.record.body_truncated = true;
+        } else {
+            self.record.body.extend_from_slice(bytes);
+        }
+    }
+}
+
+// ===== impl Record =====
+
+impl Record {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "complete: {}", self.complete)?;
+        writeln!(w, "body-truncated: {}\n", self.body_truncated)?;
+        w.write_all(&self.head)?;
+        w.write_all(b"\n")?;
+        w.write_all(&self.body)
+    }
+}
+
+// ===== impl Data =====
+
+impl<B: Buf> Buf for Data<B> {
+    fn remaining(&self) -> usize {
+        match *self {
+            Data::Unrecorded(ref b) => b.remaining(),
+            Data::Recorded(ref b) => b.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match *self {
+            Data::Unrecorded(ref b) => b.bytes(),
+            Data::Recorded(ref b) => b.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match *self {
+            Data::Unrecorded(ref mut b) => b.advance(cnt),
+            Data::Recorded(ref mut b) => b.advance(cnt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_headers_and_truncates_bodies() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let routes = Routes::new(
+            vec!["audit.example.com".to_owned()],
+            4,
+            vec!["Authorization".to_owned()],
+            Some(Spool {
+                tx,
+            }),
+        );
+        assert!(routes.matches("audit.example.com"));
+        assert!(!routes.matches("other.example.com"));
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        headers.insert("accept", "*/*".parse().unwrap());
+        let mut head = Vec::new();
+        routes.render_headers(&mut head, &headers);
+        assert_eq!(head, b"authorization: [redacted]\naccept: */*\n".to_vec());
+
+        let mut tee = Tee {
+            spool: routes.spool.clone().unwrap(),
+            max_body_bytes: 4,
+            record: Record {
+                head,
+                body: Vec::new(),
+                body_truncated: false,
+                complete: false,
+            },
+        };
+        tee.record(b"abc");
+        tee.record(b"def");
+        tee.spool.send(tee.record);
+
+        let record = rx.recv().unwrap();
+        assert_eq!(record.body, b"abcd".to_vec());
+        assert!(record.body_truncated);
+        assert!(!record.complete);
+    }
+}
@@ -13,6 +13,8 @@ use tower_reconnect::{self, Reconnect};
 
 use control;
 use ctx;
+use debug_headers;
+use map_err;
 use telemetry;
 use transport;
 
@@ -22,6 +24,12 @@ const DEFAULT_TIMEOUT_MS: u64 = 300;
 ///
 /// The returned `Service` buffers request until a connection is established.
 ///
+/// Clients are identified only by the address they connect to: every connection is
+/// plaintext HTTP/2, and all of a `Bind`'s connections share its settings. Connections
+/// to the same address with different requirements (e.g. connect timeouts or TLS
+/// identities) must be established through different `Bind`s, and so different routers,
+/// so that they are never reused for one another.
+///
 /// # TODO
 ///
 /// Buffering is not bounded and no timeouts are applied.
@@ -32,18 +40,27 @@ pub struct Bind<C, B> {
     executor: Handle,
     req_ids: Arc<AtomicUsize>,
     connect_timeout: Duration,
+    dial_limit: Option<transport::DialLimit>,
+    nodelay: bool,
+    keepalive: transport::Keepalive,
+    buffer_sizes: transport::BufferSizes,
+    debug_headers: bool,
     _p: PhantomData<B>,
 }
 
-type Service<B> = Reconnect<
-    telemetry::sensor::NewHttp<
-        tower_h2::client::Client<
-            telemetry::sensor::Connect<transport::TimeoutConnect<transport::Connect>>,
-            CtxtExec,
-            B,
+pub type Service<B> = debug_headers::Annotate<
+    Reconnect<
+        map_err::NewRefused<
+            telemetry::sensor::NewHttp<
+                tower_h2::client::Client<
+                    telemetry::sensor::Connect<transport::TimeoutConnect<transport::Connect>>,
+                    CtxtExec,
+                    telemetry::sensor::http::RequestBody<B>,
+                >,
+                B,
+                tower_h2::RecvBody,
+            >,
         >,
-        B,
-        tower_h2::RecvBody,
     >,
 >;
 
@@ -58,6 +75,11 @@ impl<B> Bind<(), B> {
             req_ids: Default::default(),
             h2_builder: h2::client::Builder::default(),
             connect_timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+            dial_limit: None,
+            nodelay: true,
+            keepalive: transport::Keepalive::default(),
+            buffer_sizes: transport::BufferSizes::default(),
+            debug_headers: false,
             _p: PhantomData,
         }
     }
@@ -69,6 +91,44 @@ impl<B> Bind<(), B> {
         }
     }
 
+    /// Sets the initial flow control window of each HTTP/2 stream, in bytes.
+    pub fn with_initial_window_size(mut self, size: u32) -> Self {
+        self.h2_builder.initial_window_size(size);
+        self
+    }
+
+    /// Sets whether Nagle's algorithm is disabled on each connection to an endpoint.
+    pub fn with_nodelay(self, nodelay: bool) -> Self {
+        Self {
+            nodelay,
+            ..self
+        }
+    }
+
+    /// Enables TCP keepalive on each connection to an endpoint.
+    pub fn with_keepalive(self, keepalive: transport::Keepalive) -> Self {
+        Self {
+            keepalive,
+            ..self
+        }
+    }
+
+    /// Sets the kernel buffer sizes of each connection to an endpoint.
+    pub fn with_buffer_sizes(self, buffer_sizes: transport::BufferSizes) -> Self {
+        Self {
+            buffer_sizes,
+            ..self
+        }
+    }
+
+    /// Annotates each response with headers describing how its request was handled.
+    pub fn with_debug_headers(self, debug_headers: bool) -> Self {
+        Self {
+            debug_headers,
+            ..self
+        }
+    }
+
     pub fn with_sensors(self, sensors: telemetry::Sensors) -> Self {
         Self {
             sensors,
@@ -84,6 +144,11 @@ impl<B> Bind<(), B> {
             executor: self.executor,
             req_ids: self.req_ids,
             connect_timeout: self.connect_timeout,
+            dial_limit: self.dial_limit,
+            nodelay: self.nodelay,
+            keepalive: self.keepalive,
+            buffer_sizes: self.buffer_sizes,
+            debug_headers: self.debug_headers,
             _p: PhantomData,
         }
     }
@@ -98,6 +163,11 @@ impl<C: Clone, B> Clone for Bind<C, B> {
             executor: self.executor.clone(),
             req_ids: self.req_ids.clone(),
             connect_timeout: self.connect_timeout,
+            dial_limit: self.dial_limit.clone(),
+            nodelay: self.nodelay,
+            keepalive: self.keepalive,
+            buffer_sizes: self.buffer_sizes,
+            debug_headers: self.debug_headers,
             _p: PhantomData,
         }
     }
@@ -113,6 +183,15 @@ impl<C, B> Bind<C, B> {
         &self.executor
     }
 
+    /// Limits the simultaneous connection attempts of all services bound by this `Bind`
+    /// and its clones.
+    pub fn with_dial_limit(self, dial_limit: Option<transport::DialLimit>) -> Self {
+        Self {
+            dial_limit,
+            ..self
+        }
+    }
+
     // pub fn req_ids(&self) -> &Arc<AtomicUsize> {
     //     &self.req_ids
     // }
@@ -133,7 +212,11 @@ where
         // Map a socket address to an HTTP/2.0 connection.
         let connect = {
             let c = transport::TimeoutConnect::new(
-                transport::Connect::new(*addr, &self.executor),
+                transport::Connect::new(*addr, &self.executor)
+                    .with_limit(self.dial_limit.clone())
+                    .with_nodelay(self.nodelay)
+                    .with_keepalive(self.keepalive)
+                    .with_buffer_sizes(self.buffer_sizes),
                 self.connect_timeout,
                 &self.executor,
             );
@@ -150,10 +233,19 @@ where
 
         let h2_proxy = self.sensors.http(self.req_ids.clone(), client, &client_ctx);
 
+        // Requests that the endpoint refused are answered with 503s, so they may be retried.
+        let h2_proxy = map_err::NewRefused::new(h2_proxy);
+
         // Automatically perform reconnects if the connection fails.
         //
         // TODO: Add some sort of backoff logic.
-        Reconnect::new(h2_proxy)
+        let reconnect = Reconnect::new(h2_proxy);
+
+        if self.debug_headers {
+            debug_headers::Annotate::new(reconnect, addr, self.connect_timeout)
+        } else {
+            debug_headers::Annotate::disabled(reconnect)
+        }
     }
 }
 
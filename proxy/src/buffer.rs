@@ -0,0 +1,467 @@
+//! Optionally buffers response bodies before their headers are forwarded.
+//!
+//! Responses are streamed by default: every layer of the server stack forwards response
+//! headers as soon as they are received, and body chunks as they arrive, so a slow body
+//! never delays its headers. Some routes (e.g. those whose clients retry or cache based
+//! on a complete response) need the whole response before anything is forwarded.
+//!
+//! For requests to configured hosts, the response body is read into memory before the
+//! response is returned. At most `max_bytes` are buffered; the rest of a larger body is
+//! streamed after the buffered prefix.
+
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::mem;
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes, IntoBuf};
+use futures::{Async, Future, Poll};
+use h2;
+use http;
+use tower::Service;
+use tower_h2;
+
+use local::{host_matches, request_host};
+
+/// The hosts whose responses are buffered.
+///
+/// Each host is either an exact host name or a `*.`-prefixed suffix.
+#[derive(Clone, Debug)]
+pub struct Routes {
+    hosts: Arc<Vec<String>>,
+    max_bytes: usize,
+}
+
+/// Buffers response bodies for requests to configured routes.
+pub struct Buffer<S> {
+    inner: S,
+    routes: Routes,
+}
+
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+enum State<F, B> {
+    Waiting {
+        future: F,
+        /// The buffer limit, if the response is to be buffered.
+        max_bytes: Option<usize>,
+    },
+    Buffering {
+        parts: Option<http::response::Parts>,
+        body: Option<B>,
+        buffered: VecDeque<Bytes>,
+        len: usize,
+        max_bytes: usize,
+    },
+}
+
+/// A response body that may have been (partially) buffered.
+pub enum Body<B> {
+    Streaming(B),
+    Buffered {
+        inner: B,
+        buffered: VecDeque<Bytes>,
+        /// Whether the inner body's data has ended.
+        done: bool,
+    },
+    /// A body that was read in full, with its trailers, e.g. one shared by coalesced
+    /// requests.
+    Complete {
+        buffered: VecDeque<Bytes>,
+        trailers: Option<http::HeaderMap>,
+    },
+}
+
+/// The chunk type of a `Body`.
+#[derive(Debug)]
+pub enum Data<B> {
+    Streaming(B),
+    Buffered(Cursor<Bytes>),
+}
+
+// ===== impl Routes =====
+
+impl Routes {
+    pub fn new<I: IntoIterator<Item = String>>(hosts: I, max_bytes: usize) -> Self {
+        Routes {
+            hosts: Arc::new(hosts.into_iter().map(|h| h.to_lowercase()).collect()),
+            max_bytes,
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.hosts.iter().any(|p| host_matches(p, host))
+    }
+}
+
+// ===== impl Buffer =====
+
+impl<S> Buffer<S> {
+    pub fn new(inner: S, routes: Routes) -> Self {
+        Buffer {
+            inner,
+            routes,
+        }
+    }
+}
+
+impl<S, A, B> Service for Buffer<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    S::Error: From<h2::Error>,
+    B: tower_h2::Body,
+{
+    type Request = S::Request;
+    type Response = http::Response<Body<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, B>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let buffer = request_host(&req)
+            .map(|h| self.routes.matches(h))
+            .unwrap_or(false);
+
+        ResponseFuture {
+            state: State::Waiting {
+                future: self.inner.call(req),
+                max_bytes: if buffer { Some(self.routes.max_bytes) } else { None },
+            },
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = http::Response<B>>,
+    F::Error: From<h2::Error>,
+    B: tower_h2::Body,
+{
+    type Item = http::Response<Body<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.state {
+                State::Waiting {
+                    ref mut future,
+                    max_bytes,
+                } => {
+                    let (parts, body) = try_ready!(future.poll()).into_parts();
+                    match max_bytes {
+                        None => {
+                            let rsp = http::Response::from_parts(parts, Body::Streaming(body));
+                            return Ok(Async::Ready(rsp));
+                        }
+                        Some(max_bytes) => {
+                            State::Buffering {
+                                parts: Some(parts),
+                                body: Some(body),
+                                buffered: VecDeque::new(),
+                                len: 0,
+                                max_bytes,
+                            }
+                        }
+                    }
+                }
+
+                State::Buffering {
+                    ref mut parts,
+                    ref mut body,
+                    ref mut buffered,
+                    ref mut len,
+                    max_bytes,
+                } => {
+                    let done = {
+                        let body = body.as_mut().expect("polled after ready");
+                        loop {
+                            if *len >= max_bytes {
+                                trace!("response exceeds buffer limit; streaming the rest");
+                                break false;
+                            }
+                            match try_ready!(body.poll_data().map_err(F::Error::from)) {
+                                Some(data) => {
+                                    let bytes = data.into_buf().collect::<Bytes>();
+                                    *len += bytes.len();
+                                    buffered.push_back(bytes);
+                                }
+                                None => break true,
+                            }
+                        }
+                    };
+
+                    let body = Body::Buffered {
+                        inner: body.take().expect("polled after ready"),
+                        buffered: mem::replace(buffered, VecDeque::new()),
+                        done,
+                    };
+                    let parts = parts.take().expect("polled after ready");
+                    return Ok(Async::Ready(http::Response::from_parts(parts, body)));
+                }
+            };
+            self.state = next;
+        }
+    }
+}
+
+// ===== impl Body =====
+
+impl<B: Default> Default for Body<B> {
+    fn default() -> Self {
+        Body::Streaming(B::default())
+    }
+}
+
+impl<B> tower_h2::Body for Body<B>
+where
+    B: tower_h2::Body + 'static,
+{
+    type Data = Data<<B::Data as IntoBuf>::Buf>;
+
+    fn is_end_stream(&self) -> bool {
+        match *self {
+            Body::Streaming(ref b) => b.is_end_stream(),
+            Body::Buffered {
+                ref inner,
+                ref buffered,
+                done,
+            } => buffered.is_empty() && done && inner.is_end_stream(),
+            Body::Complete {
+                ref buffered,
+                ref trailers,
+            } => buffered.is_empty() && trailers.is_none(),
+        }
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        match *self {
+            Body::Streaming(ref mut b) => {
+                let data = try_ready!(b.poll_data());
+                Ok(Async::Ready(data.map(|d| Data::Streaming(d.into_buf()))))
+            }
+            Body::Buffered {
+                ref mut inner,
+                ref mut buffered,
+                done,
+            } => {
+                if let Some(bytes) = buffered.pop_front() {
+                    return Ok(Async::Ready(Some(Data::Buffered(bytes.into_buf()))));
+                }
+                if done {
+                    return Ok(Async::Ready(None));
+                }
+                let data = try_ready!(inner.poll_data());
+                Ok(Async::Ready(data.map(|d| Data::Streaming(d.into_buf()))))
+            }
+            Body::Complete {
+                ref mut buffered,
+                ..
+            } => Ok(Async::Ready(buffered.pop_front().map(|b| Data::Buffered(b.into_buf())))),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        match *self {
+            Body::Streaming(ref mut b) => b.poll_trailers(),
+            Body::Buffered {
+                ref mut inner,
+                ..
+            } => inner.poll_trailers(),
+            Body::Complete {
+                ref mut trailers,
+                ..
+            } => Ok(Async::Ready(trailers.take())),
+        }
+    }
+}
+
+// ===== impl Data =====
+
+impl<B: Buf> Buf for Data<B> {
+    fn remaining(&self) -> usize {
+        match *self {
+            Data::Streaming(ref b) => b.remaining(),
+            Data::Buffered(ref b) => b.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match *self {
+            Data::Streaming(ref b) => b.bytes(),
+            Data::Buffered(ref b) => b.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match *self {
+            Data::Streaming(ref mut b) => b.advance(cnt),
+            Data::Buffered(ref mut b) => b.advance(cnt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+    use tower_h2::Body as BodyTrait;
+
+    use super::*;
+
+    /// A body that yields its chunks and then, unless `ended`, is never ready again.
+    #[derive(Default)]
+    struct Chunks {
+        chunks: VecDeque<Bytes>,
+        ended: bool,
+    }
+
+    impl tower_h2::Body for Chunks {
+        type Data = Bytes;
+
+        fn is_end_stream(&self) -> bool {
+            self.ended && self.chunks.is_empty()
+        }
+
+        fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+            match self.chunks.pop_front() {
+                Some(c) => Ok(Async::Ready(Some(c))),
+                None if self.ended => Ok(Async::Ready(None)),
+                None => Ok(Async::NotReady),
+            }
+        }
+
+        fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+            Ok(Async::Ready(None))
+        }
+    }
+
+    struct Respond(bool);
+
+    impl Service for Respond {
+        type Request = http::Request<()>;
+        type Response = http::Response<Chunks>;
+        type Error = h2::Error;
+        type Future = FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            let chunks = vec![Bytes::from_static(b"hello "), Bytes::from_static(b"world")];
+            future::ok(http::Response::new(Chunks {
+                chunks: chunks.into_iter().collect(),
+                ended: self.0,
+            }))
+        }
+    }
+
+    fn request(uri: &str) -> http::Request<()> {
+        http::Request::builder().uri(uri).body(()).unwrap()
+    }
+
+    fn read(body: &mut Body<Chunks>) -> Vec<u8> {
+        let mut out = Vec::new();
+        while let Ok(Async::Ready(Some(data))) = body.poll_data() {
+            out.extend_from_slice(data.bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn headers_are_not_delayed_by_unbuffered_bodies() {
+        // The body never ends, but the response is still returned immediately.
+        let routes = Routes::new(vec!["buffered.example.com".to_owned()], 1024);
+        let mut svc = Buffer::new(Respond(false), routes);
+        let mut rsp = match svc.call(request("http://streamed.example.com/")).poll() {
+            Ok(Async::Ready(rsp)) => rsp,
+            _ => panic!("response headers were not forwarded immediately"),
+        };
+        assert_eq!(read(rsp.body_mut()), b"hello world");
+        assert!(!rsp.body().is_end_stream());
+    }
+
+    #[test]
+    fn buffers_configured_routes() {
+        let routes = Routes::new(vec!["*.example.com".to_owned()], 1024);
+
+        let mut svc = Buffer::new(Respond(false), routes.clone());
+        let mut fut = svc.call(request("http://buffered.example.com/"));
+        assert!(fut.poll().unwrap().is_not_ready(), "incomplete body must be buffered");
+
+        let mut svc = Buffer::new(Respond(true), routes);
+        let mut rsp = match svc.call(request("http://buffered.example.com/")).poll() {
+            Ok(Async::Ready(rsp)) => rsp,
+            _ => panic!("complete body was not buffered"),
+        };
+        assert_eq!(read(rsp.body_mut()), b"hello world");
+        assert!(rsp.body().is_end_stream());
+    }
+
+    #[test]
+    fn streams_bodies_over_the_limit() {
+        let routes = Routes::new(vec!["buffered.example.com".to_owned()], 4);
+        let mut svc = Buffer::new(Respond(false), routes);
+        let mut rsp = match svc.call(request("http://buffered.example.com/")).poll() {
+            Ok(Async::Ready(rsp)) => rsp,
+            _ => panic!("body over the limit was not forwarded"),
+        };
+        assert_eq!(read(rsp.body_mut()), b"hello world");
+    }
+
+    struct Partial;
+
+    impl Service for Partial {
+        type Request = http::Request<()>;
+        type Response = http::Response<Chunks>;
+        type Error = h2::Error;
+        type Future = FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            let chunks = vec![
+                Bytes::from_static(b"--SEP\r\nContent-Range: bytes 0-4/11\r\n\r\nhello\r\n"),
+                Bytes::from_static(b"--SEP\r\nContent-Range: bytes 6-10/11\r\n\r\nworld\r\n"),
+                Bytes::from_static(b"--SEP--\r\n"),
+            ];
+            let rsp = http::Response::builder()
+                .status(http::StatusCode::PARTIAL_CONTENT)
+                .header("content-type", "multipart/byteranges; boundary=SEP")
+                .body(Chunks {
+                    chunks: chunks.into_iter().collect(),
+                    ended: true,
+                })
+                .unwrap();
+            future::ok(rsp)
+        }
+    }
+
+    #[test]
+    fn passes_multipart_ranges_through() {
+        let expected = b"--SEP\r\nContent-Range: bytes 0-4/11\r\n\r\nhello\r\n\
+            --SEP\r\nContent-Range: bytes 6-10/11\r\n\r\nworld\r\n--SEP--\r\n";
+        let routes = Routes::new(vec!["buffered.example.com".to_owned()], 1024);
+        for uri in &["http://buffered.example.com/", "http://streamed.example.com/"] {
+            let mut svc = Buffer::new(Partial, routes.clone());
+            let mut rsp = match svc.call(request(uri)).poll() {
+                Ok(Async::Ready(rsp)) => rsp,
+                _ => panic!("partial response was not forwarded"),
+            };
+            assert_eq!(rsp.status(), http::StatusCode::PARTIAL_CONTENT);
+            assert_eq!(
+                rsp.headers()["content-type"],
+                "multipart/byteranges; boundary=SEP"
+            );
+            assert_eq!(&read(rsp.body_mut())[..], &expected[..], "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Validates response bodies against the checksums advertised in their headers.
+//!
+//! For requests to configured hosts, a response carrying a `Content-MD5` header (the
+//! base64-encoded MD5 digest of the body, per RFC 1864) or an `x-checksum` header (the
+//! hex-encoded MD5 digest) has its body digested as it is streamed. If the digest doesn't
+//! match once the body ends, the stream is reset instead of being ended, so that the
+//! client never observes a complete, corrupt body.
+//!
+//! `206 Partial Content` responses are not validated: servers commonly advertise the
+//! digest of the full representation on them, which no range of it would match.
+//!
+//! Request bodies are not yet validated.
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes, IntoBuf};
+use futures::{Async, Future, Poll};
+use h2;
+use http;
+use tower::Service;
+use tower_h2;
+
+use local::{host_matches, request_host};
+
+mod md5;
+
+use self::md5::Md5;
+
+const CONTENT_MD5: &str = "content-md5";
+const X_CHECKSUM: &str = "x-checksum";
+
+/// The hosts whose responses are validated.
+///
+/// Each host is either an exact host name or a `*.`-prefixed suffix.
+#[derive(Clone, Debug)]
+pub struct Routes(Arc<Vec<String>>);
+
+/// Validates response bodies for requests to configured routes.
+pub struct Checksum<S> {
+    inner: S,
+    routes: Routes,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    check: bool,
+}
+
+/// A response body that is validated as it is streamed.
+pub struct Body<B> {
+    inner: B,
+    check: Option<Check>,
+}
+
+/// The chunk type of a `Body`.
+#[derive(Debug)]
+pub enum Data<B> {
+    Unchecked(B),
+    Checked(Cursor<Bytes>),
+}
+
+struct Check {
+    md5: Md5,
+    /// `None` if the advertised checksum could not be parsed.
+    expected: Option<[u8; 16]>,
+}
+
+// ===== impl Routes =====
+
+impl Routes {
+    pub fn new<I: IntoIterator<Item = String>>(hosts: I) -> Self {
+        Routes(Arc::new(hosts.into_iter().map(|h| h.to_lowercase()).collect()))
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.0.iter().any(|p| host_matches(p, host))
+    }
+}
+
+// ===== impl Checksum =====
+
+impl<S> Checksum<S> {
+    pub fn new(inner: S, routes: Routes) -> Self {
+        Checksum {
+            inner,
+            routes,
+        }
+    }
+}
+
+impl<S, A, B> Service for Checksum<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+{
+    type Request = S::Request;
+    type Response = http::Response<Body<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let check = *req.method() != http::Method::HEAD
+            && request_host(&req)
+                .map(|h| self.routes.matches(h))
+                .unwrap_or(false);
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            check,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = http::Response<Body<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = try_ready!(self.inner.poll());
+
+        let check = if self.check && is_checked(rsp.status()) {
+            expected_digest(rsp.headers()).map(|expected| Check {
+                md5: Md5::new(),
+                expected,
+            })
+        } else {
+            None
+        };
+
+        let (parts, inner) = rsp.into_parts();
+        let body = Body {
+            inner,
+            check,
+        };
+        Ok(Async::Ready(http::Response::from_parts(parts, body)))
+    }
+}
+
+/// Returns true if a response with the given status may have its body validated.
+fn is_checked(status: http::StatusCode) -> bool {
+    status != http::StatusCode::NO_CONTENT
+        && status != http::StatusCode::NOT_MODIFIED
+        && status != http::StatusCode::PARTIAL_CONTENT
+}
+
+/// Returns the digest advertised by a response, if any.
+///
+/// `Some(None)` indicates that a checksum was advertised but could not be parsed.
+fn expected_digest(headers: &http::HeaderMap) -> Option<Option<[u8; 16]>> {
+    if let Some(v) = headers.get(CONTENT_MD5) {
+        return Some(v.to_str().ok().and_then(|v| decode_base64(v.trim())));
+    }
+
+    if let Some(v) = headers.get(X_CHECKSUM) {
+        return Some(v.to_str().ok().and_then(|v| decode_hex(v.trim())));
+    }
+
+    None
+}
+
+fn decode_hex(s: &str) -> Option<[u8; 16]> {
+    if s.len() != 32 {
+        return None;
+    }
+
+    let mut out = [0u8; 16];
+    for (i, b) in out.iter_mut().enumerate() {
+        match s.get(i * 2..i * 2 + 2).map(|h| u8::from_str_radix(h, 16)) {
+            Some(Ok(v)) => *b = v,
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+fn decode_base64(s: &str) -> Option<[u8; 16]> {
+    // 16 bytes encode to 22 significant characters followed by `==`.
+    let s = s.as_bytes();
+    if s.len() != 24 || &s[22..] != b"==" {
+        return None;
+    }
+
+    let mut bits = 0u32;
+    let mut nbits = 0;
+    let mut out = [0u8; 16];
+    let mut n = 0;
+    for &c in &s[..22] {
+        let v = if b'A' <= c && c <= b'Z' {
+            c - b'A'
+        } else if b'a' <= c && c <= b'z' {
+            c - b'a' + 26
+        } else if b'0' <= c && c <= b'9' {
+            c - b'0' + 52
+        } else if c == b'+' {
+            62
+        } else if c == b'/' {
+            63
+        } else {
+            return None;
+        };
+        bits = (bits << 6) | u32::from(v);
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            if n == out.len() {
+                return None;
+            }
+            out[n] = (bits >> nbits) as u8;
+            n += 1;
+        }
+    }
+    Some(out)
+}
+
+// ===== impl Body =====
+
+impl<B: Default> Default for Body<B> {
+    fn default() -> Self {
+        Body {
+            inner: B::default(),
+            check: None,
+        }
+    }
+}
+
+impl<B> tower_h2::Body for Body<B>
+where
+    B: tower_h2::Body + 'static,
+{
+    type Data = Data<<B::Data as IntoBuf>::Buf>;
+
+    fn is_end_stream(&self) -> bool {
+        // A checked body must be polled to its end so that it can be validated.
+        self.check.is_none() && self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        let data = try_ready!(self.inner.poll_data());
+
+        let check = match self.check.take() {
+            None => return Ok(Async::Ready(data.map(|d| Data::Unchecked(d.into_buf())))),
+            Some(check) => check,
+        };
+
+        match data {
+            Some(data) => {
+                let mut check = check;
+                let bytes = data.into_buf().collect::<Bytes>();
+                check.md5.update(&bytes);
+                self.check = Some(check);
+                Ok(Async::Ready(Some(Data::Checked(bytes.into_buf()))))
+            }
+            None => {
+                let actual = check.md5.finish();
+                if check.expected == Some(actual) {
+                    trace!("response body checksum validated");
+                    Ok(Async::Ready(None))
+                } else {
+                    warn!("response body checksum mismatch; resetting stream");
+                    Err(h2::Reason::INTERNAL_ERROR.into())
+                }
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+// ===== impl Data =====
+
+impl<B: Buf> Buf for Data<B> {
+    fn remaining(&self) -> usize {
+        match *self {
+            Data::Unchecked(ref b) => b.remaining(),
+            Data::Checked(ref b) => b.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match *self {
+            Data::Unchecked(ref b) => b.bytes(),
+            Data::Checked(ref b) => b.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match *self {
+            Data::Unchecked(ref mut b) => b.advance(cnt),
+            Data::Checked(ref mut b) => b.advance(cnt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_digests() {
+        let digest = [
+            0x9e, 0x10, 0x7d, 0x9d, 0x37, 0x2b, 0xb6, 0x82,
+            0x6b, 0xd8, 0x1d, 0x35, 0x42, 0xa4, 0x19, 0xd6,
+        ];
+        assert_eq!(decode_hex("9e107d9d372bb6826bd81d3542a419d6"), Some(digest));
+        assert_eq!(decode_base64("nhB9nTcrtoJr2B01QqQZ1g=="), Some(digest));
+
+        assert_eq!(decode_hex("9e107d9d372bb6826bd81d3542a419"), None);
+        assert_eq!(decode_hex("9e107d9d372bb6826bd81d3542a419zz"), None);
+        assert_eq!(decode_base64("nhB9nTcrtoJr2B01QqQZ1g"), None);
+        assert_eq!(decode_base64("nhB9nTcrtoJr2B01QqQZ1?=="), None);
+    }
+
+    #[test]
+    fn prefers_content_md5() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(expected_digest(&headers), None);
+
+        headers.insert(X_CHECKSUM, "not hex".parse().unwrap());
+        assert_eq!(expected_digest(&headers), Some(None));
+
+        headers.insert(
+            CONTENT_MD5,
+            "1B2M2Y8AsgTpgAmY7PhCfg==".parse().unwrap(),
+        );
+        assert!(expected_digest(&headers).unwrap().is_some());
+    }
+
+    #[test]
+    fn skips_partial_content() {
+        assert!(is_checked(http::StatusCode::OK));
+        assert!(!is_checked(http::StatusCode::NO_CONTENT));
+        assert!(!is_checked(http::StatusCode::NOT_MODIFIED));
+        assert!(!is_checked(http::StatusCode::PARTIAL_CONTENT));
+    }
+}
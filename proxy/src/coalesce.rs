@@ -0,0 +1,520 @@
+//! Optionally coalesces concurrent identical GET requests into a single upstream request.
+//!
+//! When many clients request the same hot resource at once, each request would otherwise
+//! be forwarded upstream. For requests to configured hosts, a GET request without a body
+//! that is identical to one already in flight (the same host, URI, and headers) waits for
+//! the in-flight request's response instead of being forwarded. Only requests received on
+//! the same listener are coalesced.
+//!
+//! The first request's response body is read into memory and then shared with the
+//! requests that waited for it. At most `max_bytes` are shared: if the body is larger,
+//! or the first request fails or is canceled, the waiting requests fail with a
+//! `502 Bad Gateway` rather than being forwarded themselves.
+
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use bytes::{Buf, Bytes, IntoBuf};
+use futures::{Async, Future, Poll};
+use futures::sync::oneshot;
+use h2;
+use http;
+use tower::Service;
+use tower_h2;
+
+use buffer::Body;
+use local::{host_matches, request_host};
+
+/// The hosts whose requests are coalesced.
+///
+/// Each host is either an exact host name or a `*.`-prefixed suffix.
+#[derive(Clone, Debug)]
+pub struct Routes {
+    hosts: Arc<Vec<String>>,
+    max_bytes: usize,
+
+    /// The requests in flight, keyed by request, each with the requests waiting for it.
+    in_flight: Arc<Mutex<HashMap<Vec<u8>, Vec<oneshot::Sender<Outcome>>>>>,
+}
+
+/// Coalesces identical GET requests to configured routes.
+pub struct Coalesce<S> {
+    inner: S,
+    routes: Routes,
+}
+
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+enum State<F, B> {
+    Uncoalesced(F),
+    Leading {
+        future: F,
+        lead: Lead,
+    },
+    Buffering {
+        parts: Option<http::response::Parts>,
+        body: Option<B>,
+        buffered: VecDeque<Bytes>,
+        len: usize,
+        data_done: bool,
+        lead: Lead,
+    },
+    Following(oneshot::Receiver<Outcome>),
+}
+
+/// A request in flight, which publishes its response to the requests waiting for it.
+struct Lead {
+    routes: Routes,
+
+    /// The request's key, until its outcome is published.
+    key: Option<Vec<u8>>,
+}
+
+/// A response that is shared by coalesced requests, if there is one.
+type Outcome = Result<Arc<Shared>, ()>;
+
+#[derive(Debug)]
+struct Shared {
+    status: http::StatusCode,
+    version: http::Version,
+    headers: http::HeaderMap,
+    buffered: Vec<Bytes>,
+    trailers: Option<http::HeaderMap>,
+}
+
+// ===== impl Routes =====
+
+impl Routes {
+    pub fn new<I: IntoIterator<Item = String>>(hosts: I, max_bytes: usize) -> Self {
+        Routes {
+            hosts: Arc::new(hosts.into_iter().map(|h| h.to_lowercase()).collect()),
+            max_bytes,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns `Routes` that never coalesce requests.
+    pub fn disabled() -> Self {
+        Self::new(Vec::new(), 0)
+    }
+
+    /// Returns `Routes` for the same hosts, whose requests are never coalesced with those
+    /// of these `Routes`.
+    pub fn for_listener(&self) -> Self {
+        Routes {
+            hosts: self.hosts.clone(),
+            max_bytes: self.max_bytes,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.hosts.iter().any(|p| host_matches(p, host))
+    }
+
+    /// Identifies requests that may share a response.
+    fn key<A>(host: &str, req: &http::Request<A>) -> Vec<u8> {
+        let mut key = Vec::new();
+        key.extend_from_slice(host.as_bytes());
+        key.push(b'\n');
+        key.extend_from_slice(req.uri().to_string().as_bytes());
+        for (name, value) in req.headers().iter() {
+            key.push(b'\n');
+            key.extend_from_slice(name.as_str().as_bytes());
+            key.extend_from_slice(b": ");
+            key.extend_from_slice(value.as_bytes());
+        }
+        key
+    }
+}
+
+// ===== impl Coalesce =====
+
+impl<S> Coalesce<S> {
+    pub fn new(inner: S, routes: Routes) -> Self {
+        Coalesce {
+            inner,
+            routes,
+        }
+    }
+}
+
+impl<S, A, B> Service for Coalesce<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    S::Error: From<h2::Error>,
+    A: tower_h2::Body,
+    B: tower_h2::Body,
+{
+    type Request = S::Request;
+    type Response = http::Response<Body<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, B>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let coalesced = *req.method() == http::Method::GET && req.body().is_end_stream();
+        let key = match request_host(&req) {
+            Some(host) if coalesced && self.routes.matches(host) => Routes::key(host, &req),
+            _ => {
+                return ResponseFuture {
+                    state: State::Uncoalesced(self.inner.call(req)),
+                };
+            }
+        };
+
+        {
+            let mut in_flight = self.routes.in_flight.lock().expect("coalesce lock");
+            if let Some(waiting) = in_flight.get_mut(&key) {
+                trace!("waiting for an identical request in flight");
+                let (tx, rx) = oneshot::channel();
+                waiting.push(tx);
+                return ResponseFuture {
+                    state: State::Following(rx),
+                };
+            }
+            in_flight.insert(key.clone(), Vec::new());
+        }
+
+        ResponseFuture {
+            state: State::Leading {
+                future: self.inner.call(req),
+                lead: Lead {
+                    routes: self.routes.clone(),
+                    key: Some(key),
+                },
+            },
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = http::Response<B>>,
+    F::Error: From<h2::Error>,
+    B: tower_h2::Body,
+{
+    type Item = http::Response<Body<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.state {
+                State::Uncoalesced(ref mut future) => {
+                    let (parts, body) = try_ready!(future.poll()).into_parts();
+                    let rsp = http::Response::from_parts(parts, Body::Streaming(body));
+                    return Ok(Async::Ready(rsp));
+                }
+
+                State::Following(ref mut rx) => {
+                    let outcome = match rx.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(outcome)) => outcome,
+                        // The request in flight was dropped before it completed.
+                        Err(oneshot::Canceled) => Err(()),
+                    };
+                    let rsp = match outcome {
+                        Ok(shared) => shared.response(),
+                        Err(()) => {
+                            debug!("coalesced request could not share a response");
+                            http::Response::builder()
+                                .status(http::StatusCode::BAD_GATEWAY)
+                                .body(Body::Complete {
+                                    buffered: VecDeque::new(),
+                                    trailers: None,
+                                })
+                                .expect("bad gateway response must be valid")
+                        }
+                    };
+                    return Ok(Async::Ready(rsp));
+                }
+
+                State::Leading {
+                    ref mut future,
+                    ref mut lead,
+                } => {
+                    let (parts, body) = match future.poll() {
+                        Ok(Async::Ready(rsp)) => rsp.into_parts(),
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(e) => {
+                            lead.publish(Err(()));
+                            return Err(e);
+                        }
+                    };
+                    State::Buffering {
+                        parts: Some(parts),
+                        body: Some(body),
+                        buffered: VecDeque::new(),
+                        len: 0,
+                        data_done: false,
+                        lead: lead.take(),
+                    }
+                }
+
+                State::Buffering {
+                    ref mut parts,
+                    ref mut body,
+                    ref mut buffered,
+                    ref mut len,
+                    ref mut data_done,
+                    ref mut lead,
+                } => {
+                    let max_bytes = lead.routes.max_bytes;
+                    let trailers = {
+                        let body = body.as_mut().expect("polled after ready");
+                        while !*data_done && *len <= max_bytes {
+                            let data = match body.poll_data() {
+                                Ok(Async::Ready(data)) => data,
+                                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                                Err(e) => {
+                                    lead.publish(Err(()));
+                                    return Err(e.into());
+                                }
+                            };
+                            match data {
+                                Some(data) => {
+                                    let bytes = data.into_buf().collect::<Bytes>();
+                                    *len += bytes.len();
+                                    buffered.push_back(bytes);
+                                }
+                                None => *data_done = true,
+                            }
+                        }
+
+                        if *data_done {
+                            match body.poll_trailers() {
+                                Ok(Async::Ready(trailers)) => Some(trailers),
+                                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                                Err(e) => {
+                                    lead.publish(Err(()));
+                                    return Err(e.into());
+                                }
+                            }
+                        } else {
+                            None
+                        }
+                    };
+
+                    let parts = parts.take().expect("polled after ready");
+                    let buffered = mem::replace(buffered, VecDeque::new());
+                    let body = body.take().expect("polled after ready");
+                    let trailers = match trailers {
+                        Some(trailers) => trailers,
+                        None => {
+                            trace!("response exceeds coalescing limit; not sharing it");
+                            lead.publish(Err(()));
+                            let body = Body::Buffered {
+                                inner: body,
+                                buffered,
+                                done: false,
+                            };
+                            return Ok(Async::Ready(http::Response::from_parts(parts, body)));
+                        }
+                    };
+
+                    lead.publish(Ok(Arc::new(Shared {
+                        status: parts.status,
+                        version: parts.version,
+                        headers: parts.headers.clone(),
+                        buffered: buffered.iter().cloned().collect(),
+                        trailers: trailers.clone(),
+                    })));
+                    let body = Body::Complete {
+                        buffered,
+                        trailers,
+                    };
+                    return Ok(Async::Ready(http::Response::from_parts(parts, body)));
+                }
+            };
+            self.state = next;
+        }
+    }
+}
+
+// ===== impl Lead =====
+
+impl Lead {
+    /// Moves the obligation to publish an outcome into a new `Lead`.
+    fn take(&mut self) -> Self {
+        Lead {
+            routes: self.routes.clone(),
+            key: self.key.take(),
+        }
+    }
+
+    /// Sends `outcome` to the waiting requests, and stops new requests from waiting.
+    fn publish(&mut self, outcome: Outcome) {
+        let key = match self.key.take() {
+            Some(key) => key,
+            None => return,
+        };
+        let waiting = self.routes
+            .in_flight
+            .lock()
+            .expect("coalesce lock")
+            .remove(&key)
+            .unwrap_or_default();
+        for tx in waiting {
+            let _ = tx.send(outcome.clone());
+        }
+    }
+}
+
+impl Drop for Lead {
+    fn drop(&mut self) {
+        // The request was dropped before it completed, e.g. because it was canceled.
+        self.publish(Err(()));
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    fn response<B>(&self) -> http::Response<Body<B>> {
+        let mut rsp = http::Response::new(Body::Complete {
+            buffered: self.buffered.iter().cloned().collect(),
+            trailers: self.trailers.clone(),
+        });
+        *rsp.status_mut() = self.status;
+        *rsp.version_mut() = self.version;
+        *rsp.headers_mut() = self.headers.clone();
+        rsp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use futures::future::{self, FutureResult};
+    use tower_h2::Body as BodyTrait;
+
+    use super::*;
+
+    /// A body with a single chunk.
+    struct Chunk(Option<Bytes>);
+
+    impl tower_h2::Body for Chunk {
+        type Data = Bytes;
+
+        fn is_end_stream(&self) -> bool {
+            self.0.is_none()
+        }
+
+        fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+            Ok(Async::Ready(self.0.take()))
+        }
+    }
+
+    /// Responds to every request with `hello world`, counting the requests.
+    struct Respond(Rc<Cell<usize>>);
+
+    impl Service for Respond {
+        type Request = http::Request<()>;
+        type Response = http::Response<Chunk>;
+        type Error = h2::Error;
+        type Future = FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            self.0.set(self.0.get() + 1);
+            future::ok(http::Response::new(Chunk(Some(Bytes::from_static(b"hello world")))))
+        }
+    }
+
+    fn request(method: http::Method, uri: &str) -> http::Request<()> {
+        http::Request::builder().method(method).uri(uri).body(()).unwrap()
+    }
+
+    fn ready<F: Future>(mut fut: F) -> F::Item {
+        match fut.poll() {
+            Ok(Async::Ready(item)) => item,
+            _ => panic!("future was not ready"),
+        }
+    }
+
+    fn read(body: &mut Body<Chunk>) -> Vec<u8> {
+        let mut out = Vec::new();
+        while let Ok(Async::Ready(Some(data))) = body.poll_data() {
+            out.extend_from_slice(data.bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn shares_responses_between_identical_gets() {
+        let calls = Rc::new(Cell::new(0));
+        let routes = Routes::new(vec!["*.example.com".to_owned()], 1024);
+        let mut svc = Coalesce::new(Respond(calls.clone()), routes);
+
+        let first = svc.call(request(http::Method::GET, "http://hot.example.com/key"));
+        let second = svc.call(request(http::Method::GET, "http://hot.example.com/key"));
+        let other = svc.call(request(http::Method::GET, "http://hot.example.com/other"));
+        let post = svc.call(request(http::Method::POST, "http://hot.example.com/key"));
+        let uncoalesced = svc.call(request(http::Method::GET, "http://cold.test/key"));
+        assert_eq!(calls.get(), 4);
+
+        let mut first = ready(first);
+        let mut second = ready(second);
+        assert_eq!(read(first.body_mut()), b"hello world");
+        assert_eq!(read(second.body_mut()), b"hello world");
+        assert!(second.body().is_end_stream());
+        for rsp in vec![ready(other), ready(post), ready(uncoalesced)] {
+            assert_eq!(rsp.status(), http::StatusCode::OK);
+        }
+
+        // Once the first request has completed, identical requests are forwarded again.
+        let _ = ready(svc.call(request(http::Method::GET, "http://hot.example.com/key")));
+        assert_eq!(calls.get(), 5);
+    }
+
+    #[test]
+    fn fails_waiting_requests_when_the_response_is_not_shared() {
+        let routes = Routes::new(vec!["hot.example.com".to_owned()], 4);
+        let mut svc = Coalesce::new(Respond(Rc::new(Cell::new(0))), routes);
+
+        // The response is larger than the limit.
+        let first = svc.call(request(http::Method::GET, "http://hot.example.com/"));
+        let second = svc.call(request(http::Method::GET, "http://hot.example.com/"));
+        let mut first = ready(first);
+        assert_eq!(read(first.body_mut()), b"hello world");
+        assert_eq!(ready(second).status(), http::StatusCode::BAD_GATEWAY);
+
+        // The first request is canceled.
+        let first = svc.call(request(http::Method::GET, "http://hot.example.com/"));
+        let second = svc.call(request(http::Method::GET, "http://hot.example.com/"));
+        drop(first);
+        assert_eq!(ready(second).status(), http::StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn never_coalesces_requests_from_different_listeners() {
+        let calls = Rc::new(Cell::new(0));
+        let routes = Routes::new(vec!["hot.example.com".to_owned()], 1024);
+        let mut outbound = Coalesce::new(Respond(calls.clone()), routes.for_listener());
+        let mut socks = Coalesce::new(Respond(calls.clone()), routes.for_listener());
+        let mut inbound = Coalesce::new(Respond(calls.clone()), Routes::disabled());
+
+        let first = outbound.call(request(http::Method::GET, "http://hot.example.com/"));
+        let second = socks.call(request(http::Method::GET, "http://hot.example.com/"));
+        let third = inbound.call(request(http::Method::GET, "http://hot.example.com/"));
+        let fourth = inbound.call(request(http::Method::GET, "http://hot.example.com/"));
+        assert_eq!(calls.get(), 4);
+
+        for rsp in vec![ready(first), ready(second), ready(third), ready(fourth)] {
+            assert_eq!(rsp.status(), http::StatusCode::OK);
+        }
+    }
+}
@@ -1,47 +1,322 @@
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Duration;
 
-use url::{Host, HostAndPort, Url};
+use http;
+use ipnet::{Contains, Ipv4Net, Ipv6Net};
+use url::{self, Host, HostAndPort, Url};
 
-// TODO:
-//
-// * Make struct fields private.
+use logging::json_str;
 
 /// Tracks all configuration settings for the process.
+///
+/// A `Config`'s settings are checked for conflicts as it's loaded, so its fields are
+/// only exposed through accessors.
 #[derive(Clone, Debug)]
 pub struct Config {
     /// Where to listen for connections that are initiated on the host.
-    pub private_listener: Listener,
+    private_listener: Listener,
 
     /// Where to listen for connections initiated by external sources.
-    pub public_listener: Listener,
+    public_listener: Listener,
 
     /// Where to listen for connectoins initiated by the control planey.
-    pub control_listener: Listener,
+    control_listener: Listener,
+
+    /// The networks from which the control listener accepts connections. If empty,
+    /// connections are accepted from any source.
+    control_listener_sources: Vec<Network>,
+
+    /// A `<user>:<password>` pair that requests to the control listener must present
+    /// with HTTP basic authentication, if any.
+    control_listener_credentials: Option<String>,
+
+    /// Where to accept SOCKS5 connections that should be routed like outbound
+    /// connections, if at all.
+    socks_listener: Option<Listener>,
 
     /// Where to forward externally received connections.
-    pub private_forward: Option<Addr>,
+    private_forward: Option<Forward>,
+
+    /// How long the resolved address of a forwarded-to host, for `private_forward` or an
+    /// additional public listener, is used before the host is resolved again.
+    private_forward_ttl: Duration,
+
+    /// Additional public listeners, each forwarding to its own target with its own
+    /// policies.
+    additional_public_listeners: Vec<PublicListener>,
+
+    /// When true, the public listener acts as an ingress: requests are routed by their
+    /// authority through service discovery rather than to the original destination.
+    ingress_mode: bool,
+
+    /// Hosts that may be routed in ingress mode. If empty, all hosts are routed.
+    ingress_hosts: Vec<String>,
+
+    /// Headers set on every response sent through the public listener in ingress mode,
+    /// replacing any values set by the application.
+    ingress_response_headers: Vec<ResponseHeader>,
+
+    /// How the targets of requests received on the public listeners are normalized
+    /// before policies and the application see them.
+    inbound_normalization: Normalization,
+
+    /// What to do with outbound requests for destinations that have no endpoints.
+    no_endpoints: NoEndpoints,
+
+    /// How long outbound requests may wait for a destination to have endpoints.
+    no_endpoints_timeout: Duration,
+
+    /// Whether outbound requests for destinations without endpoints are forwarded to
+    /// their original destinations, regardless of `no_endpoints`, until the proxy has
+    /// first connected to the controller.
+    no_endpoints_startup_fallback: bool,
+
+    /// Gateways through which requests for services in other clusters are routed.
+    cluster_gateways: Vec<ClusterGateway>,
+
+    /// Hosts for which the proxy follows redirects on outbound requests.
+    redirect_hosts: Vec<String>,
+
+    /// The maximum number of redirects followed for a single outbound request.
+    redirect_limit: usize,
+
+    /// Which failed responses are replaced with a standard error envelope.
+    error_translations: Vec<ErrorTranslation>,
+
+    /// Pages served in place of responses from hosts that are under maintenance.
+    maintenance_pages: Vec<HostPage>,
+
+    /// Pages served in place of responses from hosts that are unavailable.
+    unavailable_pages: Vec<HostPage>,
+
+    /// Which origins may make cross-origin requests to inbound hosts.
+    cors_rules: Vec<CorsRule>,
+
+    /// Which request methods are forwarded to inbound routes.
+    allowed_methods: Vec<MethodRule>,
+
+    /// Which request content types are forwarded to inbound routes.
+    allowed_content_types: Vec<ContentTypeRule>,
+
+    /// Hosts whose response bodies are validated against advertised checksums.
+    checksum_hosts: Vec<String>,
+
+    /// Hosts whose response bodies are buffered before their headers are forwarded.
+    buffer_hosts: Vec<String>,
+
+    /// The maximum number of bytes of a response body that are buffered.
+    buffer_max_bytes: usize,
+
+    /// Hosts for which concurrent identical outbound GET requests share a single upstream
+    /// request.
+    coalesce_hosts: Vec<String>,
+
+    /// The maximum number of bytes of a response body that are shared between coalesced
+    /// requests.
+    coalesce_max_bytes: usize,
+
+    /// Hosts whose exchanges are recorded to the audit spool.
+    audit_hosts: Vec<String>,
+
+    /// The directory to which audit records are written, if any.
+    audit_spool_path: Option<PathBuf>,
+
+    /// The maximum number of bytes of a response body that are recorded.
+    audit_max_body_bytes: usize,
+
+    /// Headers whose values are redacted from audit records.
+    audit_redacted_headers: Vec<String>,
+
+    /// Hosts whose failed requests are journaled and replayed until they're delivered.
+    replay_hosts: Vec<String>,
 
-    /// The maximum amount of time to wait for a connection to the public peer.
-    pub public_connect_timeout: Option<Duration>,
+    /// The directory in which failed requests are journaled, if any.
+    replay_journal_path: Option<PathBuf>,
 
-    /// The maximum amount of time to wait for a connection to the private peer.
-    pub private_connect_timeout: Option<Duration>,
+    /// The maximum number of requests that the replay journal holds.
+    replay_journal_max_entries: usize,
+
+    /// How long to wait before first replaying a failed request.
+    replay_backoff: Duration,
+
+    /// The maximum amount of time to wait between replays of a failing request.
+    replay_max_backoff: Duration,
+
+    /// The maximum amount of time to wait for a connection to the public peer, unless
+    /// the listener that received the request sets its own.
+    public_connect_timeout: Option<Duration>,
+
+    /// The maximum amount of time to wait for a connection to the private peer, unless
+    /// the listener that received the request sets its own.
+    private_connect_timeout: Option<Duration>,
+
+    /// The maximum number of simultaneous connection attempts to each outbound
+    /// authority's endpoints, if limited. Further attempts wait, within the connect
+    /// timeout, for earlier ones to finish.
+    outbound_max_concurrent_connects: Option<usize>,
+
+    /// Connections received on the public listener for these original destination ports
+    /// are forwarded opaquely, rather than handled as HTTP/2.
+    inbound_skip_ports: HashSet<u16>,
+
+    /// Connections received on the private listener for these original destination ports
+    /// are forwarded opaquely, rather than handled as HTTP/2.
+    outbound_skip_ports: HashSet<u16>,
+
+    /// The maximum number of concurrent streams on each connection accepted on the
+    /// public listener, if limited.
+    public_max_concurrent_streams: Option<usize>,
+
+    /// How long a connection accepted on the public listener may be idle before it is
+    /// closed, if at all.
+    public_idle_timeout: Option<Duration>,
+
+    /// How long a connection accepted on the private listener may be idle before it is
+    /// closed, if at all.
+    private_idle_timeout: Option<Duration>,
+
+    /// Whether Nagle's algorithm is disabled on accepted and outbound TCP connections,
+    /// favoring latency over fewer, fuller segments.
+    tcp_nodelay: bool,
+
+    /// How long an accepted or outbound TCP connection may be idle before keepalive
+    /// probes are sent. Keepalive is disabled unless this is set.
+    tcp_keepalive: Option<Duration>,
+
+    /// How long to wait between keepalive probes, if not the system's default.
+    tcp_keepalive_interval: Option<Duration>,
+
+    /// How many unanswered keepalive probes close a connection, if not the system's
+    /// default.
+    tcp_keepalive_probes: Option<u32>,
+
+    /// The kernel receive buffer size of connections to endpoints, in bytes, if not the
+    /// system's default.
+    connect_recv_buffer_size: Option<usize>,
+
+    /// The kernel send buffer size of connections to endpoints, in bytes, if not the
+    /// system's default.
+    connect_send_buffer_size: Option<usize>,
+
+    /// The initial flow control window of each HTTP/2 stream, in bytes, if not the
+    /// protocol's default.
+    ///
+    /// Data received on a stream counts against its connection's window until it has
+    /// been forwarded, so a window smaller than the connection's keeps a single slow
+    /// stream from stalling the others on its connection.
+    h2_initial_window_size: Option<u32>,
 
     /// The path to "/etc/resolv.conf"
-    pub resolv_conf_path: PathBuf,
+    resolv_conf_path: PathBuf,
+
+    /// Where to append a report when the proxy panics, if anywhere.
+    crash_report_path: Option<PathBuf>,
+
+    /// How long the proxy must go without requests before it releases the memory held
+    /// for earlier requests, if it ever does.
+    idle_trim_period: Option<Duration>,
+
+    /// The profile whose defaults were applied to unset settings, if any.
+    profile: Option<Profile>,
+
+    /// The log filter, if set. Logging starts before the `Config` is loaded, with the
+    /// filter from the environment, so this is applied once the `Config` is loaded.
+    log_filter: Option<String>,
+
+    /// Where to persist the last-known endpoints of each destination, if anywhere.
+    endpoint_cache_path: Option<PathBuf>,
+
+    /// Where to write the addresses to which the listeners are bound, if anywhere.
+    ///
+    /// This makes it possible to discover the ports of listeners bound to port 0.
+    ports_file_path: Option<PathBuf>,
+
+    /// Where to answer DNS queries redirected from the local application, if at all.
+    dns_intercept_listener: Option<Listener>,
+
+    /// UDP ports to relay, and where to relay them.
+    udp_forwards: Vec<UdpForward>,
 
     /// Where to talk to the control plane.
-    pub control_host_and_port: HostAndPort,
+    control_host_and_port: HostAndPort,
+
+    /// A file holding a token, e.g. a Kubernetes service account token, that's presented
+    /// to the control plane as a bearer credential, if any.
+    control_token_path: Option<PathBuf>,
 
     /// Event queue capacity.
-    pub event_buffer_capacity: usize,
+    event_buffer_capacity: usize,
 
     /// Interval after which to flush metrics
-    pub metrics_flush_interval: Duration,
+    metrics_flush_interval: Duration,
+
+    /// The maximum size, in bytes, of a single telemetry report sent to the controller.
+    /// Larger reports are split into several messages.
+    max_report_bytes: usize,
+
+    /// Requests that take at least this long are logged in detail.
+    slow_request_threshold: Option<Duration>,
+
+    /// Where to send metrics reports as statsd datagrams, in addition to the controller,
+    /// if anywhere.
+    statsd_addr: Option<Addr>,
+
+    /// When true, no telemetry events are recorded, so neither metrics nor taps are
+    /// available. This measures the proxy's forwarding overhead on its own.
+    telemetry_disabled: bool,
+
+    /// The request headers whose values telemetry events may carry. Other headers are
+    /// only counted.
+    telemetry_headers: Vec<http::header::HeaderName>,
+
+    /// If set, tap reports the progress of request bodies at most this often, so that
+    /// large uploads can be watched as they're sent.
+    tap_request_progress_interval: Option<Duration>,
+
+    /// Requests for these paths are health checks.
+    health_check_paths: Vec<String>,
+
+    /// Requests whose `user-agent` starts with one of these prefixes are health checks.
+    ///
+    /// Matching relies on `user-agent` being one of the `telemetry_headers`.
+    health_check_user_agents: Vec<String>,
+
+    /// Requests from these networks are health checks.
+    health_check_sources: Vec<Network>,
+
+    /// A token that, when presented by an inbound request, causes the proxy to answer
+    /// with a synthetic response instead of forwarding to the application.
+    synthetic_token: Option<String>,
+
+    /// Whether the local application is refused any request to `conduit.local` that
+    /// would change the proxy's state, e.g. its log scope.
+    admin_read_only: bool,
+
+    /// Whether responses are annotated with headers describing how the proxy handled
+    /// their requests, e.g. which endpoint served them.
+    debug_headers: bool,
+
+    /// The files that settings' values were read from, which are watched for changes.
+    value_files: Vec<PathBuf>,
+
+    /// Where the settings were loaded from, so that they can be reloaded.
+    source: Source,
+}
+
+/// Constructs a `Config` without ENV variables.
+///
+/// Settings that aren't set keep their defaults. The settings are checked for conflicts
+/// when the `Config` is built.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    config: Config,
 }
 
 /// Configuration settings for binding a listener.
@@ -50,33 +325,315 @@ pub struct Config {
 #[derive(Clone, Debug)]
 pub struct Listener {
     /// The address to which the listener should bind.
+    addr: Addr,
+
+    /// How long an accepted connection may take to send its first request, including
+    /// any negotiation, before it's closed.
+    accept_timeout: Option<Duration>,
+
+    /// How long to wait when connecting on behalf of requests received on the listener,
+    /// overriding the connect timeout of the listener's destinations.
+    connect_timeout: Option<Duration>,
+
+    /// Whether `SO_REUSEPORT` is set on the listener's socket, so that other processes,
+    /// e.g. the proxy replacing this one during a deploy, may bind the same port.
+    reuse_port: bool,
+
+    /// Whether a listener bound to an IPv6 address, e.g. `[::]:4143`, also accepts IPv4
+    /// clients, as IPv4-mapped addresses. Ignored for IPv4 addresses.
+    dual_stack: bool,
+
+    /// The length of the listener's queue of connections that have been established but
+    /// not yet accepted, if not the default of 128. Connections beyond it are dropped.
+    backlog: Option<usize>,
+
+    /// The kernel receive buffer size of accepted connections, in bytes, if not the
+    /// system's default.
+    recv_buffer_size: Option<usize>,
+
+    /// The kernel send buffer size of accepted connections, in bytes, if not the
+    /// system's default.
+    send_buffer_size: Option<usize>,
+
+    /// Whether accepted connections must begin with a PROXY protocol header, e.g. from a
+    /// load balancer, whose client address is used instead of the connection's peer.
+    /// Only public listeners honor it.
+    proxy_protocol: bool,
+}
+
+/// Where connections are forwarded.
+#[derive(Clone, Debug)]
+pub enum Forward {
+    Addr(Addr),
+
+    /// A host name, which is resolved again periodically, so that the target may
+    /// change addresses.
+    Host(HostAndPort),
+}
+
+/// Configures an additional public listener.
+#[derive(Clone, Debug)]
+pub struct PublicListener {
+    /// Identifies the listener in logs and in the names of its policies' variables.
+    pub name: String,
+
+    pub listener: Listener,
+
+    /// Where connections received on the listener are forwarded.
+    pub forward: Forward,
+
+    /// Which request methods are forwarded to the listener's routes.
+    pub allowed_methods: Vec<MethodRule>,
+
+    /// Which request content types are forwarded to the listener's routes.
+    pub allowed_content_types: Vec<ContentTypeRule>,
+
+    /// The maximum number of concurrent streams on each of the listener's connections.
+    pub max_concurrent_streams: Option<usize>,
+}
+
+/// Configures a UDP relay.
+#[derive(Clone, Debug)]
+pub struct UdpForward {
+    /// The address on which datagrams are received.
+    pub listen: Addr,
+
+    /// The address to which datagrams are forwarded.
+    pub target: Addr,
+}
+
+/// Determines how outbound requests are handled when their destination has no endpoints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoEndpoints {
+    /// Fail requests immediately with a 503.
+    Fail,
+
+    /// Forward requests to their original destination.
+    Fallback,
+
+    /// Hold requests until an endpoint is discovered or they time out.
+    Queue,
+}
+
+/// Determines how inbound request targets that policies and the application might
+/// interpret differently are handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// Forward requests unchanged.
+    Off,
+
+    /// Resolve `.` and `..` path segments, and refuse requests that are ambiguous.
+    Lenient,
+
+    /// Refuse requests whose targets aren't already normal.
+    Strict,
+}
+
+/// A bundle of defaults suited to an environment. Settings that are set explicitly
+/// override their profile's defaults.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// Verbose logging, requests to destinations without endpoints fall back to their
+    /// original destinations, generous connect timeouts, and responses annotated with
+    /// debug headers.
+    Dev,
+
+    /// Requests to destinations without endpoints fail immediately, so that tests see
+    /// failures rather than timeouts.
+    Test,
+
+    /// Quieter logging, inbound requests with ambiguous targets are refused, and the
+    /// local application may not change the proxy's state.
+    Prod,
+}
+
+/// Configures the gateway for a remote cluster.
+///
+/// Authorities of the form `<svc>.<ns>.svc.<cluster>` are routed to `addr`. Connections
+/// to the gateway are currently plaintext.
+#[derive(Clone, Debug)]
+pub struct ClusterGateway {
+    /// The cluster name, as it appears in qualified authorities.
+    pub cluster: String,
+
+    /// The address of the cluster's gateway.
     pub addr: Addr,
 }
 
+/// Configures which failed responses are translated for a host.
+#[derive(Clone, Debug)]
+pub struct ErrorTranslation {
+    /// An exact host name or a `*.`-prefixed suffix.
+    pub host: String,
+
+    /// The response statuses that are translated.
+    pub statuses: Vec<http::StatusCode>,
+}
+
+/// Configures a static page served for a host.
+#[derive(Clone, Debug)]
+pub struct HostPage {
+    /// An exact host name or a `*.`-prefixed suffix.
+    pub host: String,
+
+    /// The file containing the page.
+    pub path: PathBuf,
+}
+
+/// Configures which origins may make cross-origin requests to a host.
+#[derive(Clone, Debug)]
+pub struct CorsRule {
+    /// An exact host name or a `*.`-prefixed suffix.
+    pub host: String,
+
+    /// The allowed origins, e.g. `https://app.example.com`, or `*` for any origin.
+    pub origins: Vec<String>,
+}
+
+/// Configures which request methods are forwarded to a route.
+#[derive(Clone, Debug)]
+pub struct MethodRule {
+    /// An exact host name or a `*.`-prefixed suffix.
+    pub host: String,
+
+    /// The path prefix of the route, if the rule doesn't apply to all of the host's paths.
+    pub path: Option<String>,
+
+    /// The methods that are forwarded.
+    pub methods: Vec<http::Method>,
+}
+
+/// Configures which request content types are forwarded to a route.
+#[derive(Clone, Debug)]
+pub struct ContentTypeRule {
+    /// An exact host name or a `*.`-prefixed suffix.
+    pub host: String,
+
+    /// The path prefix of the route, if the rule doesn't apply to all of the host's paths.
+    pub path: Option<String>,
+
+    /// The media types that are forwarded, e.g. `application/json`.
+    pub media_types: Vec<String>,
+}
+
+/// A header that is set on responses.
+#[derive(Clone, Debug)]
+pub struct ResponseHeader {
+    pub name: http::header::HeaderName,
+    pub value: http::header::HeaderValue,
+}
+
 /// A logical address. This abstracts over the various strategies for cross
 /// process communication.
+///
+/// Currently only TCP socket addresses are supported. Unix domain sockets (e.g.
+/// `unix:///path/to.sock`), including Linux's abstract namespace (e.g.
+/// `unix-abstract:name`), would need a Unix stream transport (e.g. `tokio-uds`), and
+/// connection contexts and telemetry that aren't keyed by `SocketAddr`; such addresses fail
+/// to parse with `ParseError::UnixSocketsUnsupported`.
 #[derive(Clone, Copy, Debug)]
 pub struct Addr(SocketAddr);
 
+/// An IP network, e.g. `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    V4(Ipv4Net),
+    V6(Ipv6Net),
+}
+
 /// Errors produced when loading a `Config` struct.
+///
+/// Each variant describes every problem of its kind, so that a configuration can be
+/// fixed in a single pass.
 #[derive(Clone, Debug)]
 pub enum Error {
-    InvalidEnvVar {
-        name: String,
-        value: Option<String>,
-        parse_error: ParseError,
+    /// Settings that could not be parsed, whether they were set by environment
+    /// variables, command-line arguments, or a config file.
+    InvalidEnvVars(Vec<InvalidEnvVar>),
+
+    /// Settings that parsed but are invalid: ones that conflict with one another, and
+    /// timeouts, named by their environment variables, that are set to zero. At least
+    /// one of the two is nonempty.
+    Invalid {
+        conflicts: Vec<Conflict>,
+        zero_timeouts: Vec<String>,
+    },
+
+    /// Command-line arguments that are malformed or that don't name a setting.
+    InvalidArgs(Vec<String>),
+
+    /// A config file could not be loaded.
+    InvalidFile {
+        path: PathBuf,
+        error: FileError,
+    },
+}
+
+/// Describes why a config file could not be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileError {
+    /// The file could not be read.
+    Unreadable(String),
+
+    /// A line (numbered from 1) isn't a supported `key = value` pair.
+    Syntax(usize),
+
+    /// Keys that don't name a setting.
+    UnknownKeys(Vec<String>),
+}
+
+/// Describes a setting that could not be parsed, named by its environment variable.
+#[derive(Clone, Debug)]
+pub struct InvalidEnvVar {
+    pub name: String,
+    pub value: Option<String>,
+    pub parse_error: ParseError,
+}
+
+/// Describes settings that conflict with one another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// Two listeners would bind the same address.
+    Listeners {
+        first: String,
+        second: String,
+        addr: SocketAddr,
+    },
+
+    /// Connections received by `listener` would be forwarded back to the proxy's `to`
+    /// listener.
+    ForwardToListener {
+        listener: String,
+        to: String,
+        addr: SocketAddr,
     },
 }
 
 #[derive(Clone, Debug)]
 pub enum ParseError {
     NotANumber,
+    NotAPositiveNumber,
+    NotABool,
+    NotADuration,
+    NotANoEndpointsBehavior,
+    NotANormalization,
+    NotAProfile,
+    NotAHeader,
+    NotAHeaderName,
+    NotAHostPage,
+    NotAMethodRule,
+    NotAContentTypeRule,
+    NotAPublicListener,
+    NotANetwork,
+    NotAUserAndPassword,
     HostIsNotAnIpAddress,
+    UnixSocketsUnsupported,
     NotUnicode,
+    NotAReadableFile(String),
     UrlError(UrlError),
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum UrlError {
     /// The URl is syntactically invalid according to general URL parsing rules.
     SyntaxError,
@@ -87,9 +644,21 @@ pub enum UrlError {
     /// The URL is missing the host part.
     MissingHost,
 
+    /// The URL's host is neither a valid domain nor a valid IP address.
+    InvalidHost,
+
     /// The URL is missing the port and there is no default port.
     MissingPort,
 
+    /// The URL's port isn't a number from 0 to 65535.
+    InvalidPort,
+
+    /// The URL contains a username or password, which isn't allowed.
+    UserInfoNotAllowed,
+
+    /// The URL contains a query, which isn't allowed.
+    QueryNotAllowed,
+
     /// The URL contains a path component that isn't "/", which isn't allowed.
     PathNotAllowed,
 
@@ -97,158 +666,3307 @@ pub enum UrlError {
     FragmentNotAllowed,
 }
 
+/// Reads environment variables, collecting errors rather than failing on the first.
+#[derive(Default)]
+struct Env {
+    /// When true, the process's environment isn't read.
+    isolated: bool,
+
+    /// Settings from command-line arguments, which override the environment, along
+    /// with the argument that set each.
+    args: HashMap<String, (String, String)>,
+
+    /// Settings read from a config file, which are overridden by the environment.
+    file: HashMap<String, String>,
+
+    /// The names of all of the settings that have been read.
+    read: HashSet<String>,
+
+    /// The settings whose values were read from `<NAME>_FILE` files, and those files.
+    /// Their values may be secret, so they're omitted from errors.
+    from_files: HashMap<String, PathBuf>,
+
+    /// The profile whose defaults are used for settings that aren't set.
+    profile: Option<Profile>,
+
+    errors: Vec<InvalidEnvVar>,
+}
+
+/// Renders the members of a JSON object, as names and values already rendered as JSON.
+#[derive(Debug, Default)]
+struct JsonObject(Vec<(String, String)>);
+
+/// Where a `Config`'s settings were loaded from.
+#[derive(Clone, Debug)]
+enum Source {
+    Env,
+    File(PathBuf),
+    Args(Vec<String>),
+    Builder,
+}
+
 // Environment variables to look at when loading the configuration
 const ENV_EVENT_BUFFER_CAPACITY: &str = "CONDUIT_PROXY_EVENT_BUFFER_CAPACITY";
-const ENV_METRICS_FLUSH_INTERVAL_SECS: &str = "CONDUIT_PROXY_METRICS_FLUSH_INTERVAL_SECS";
+const ENV_METRICS_FLUSH_INTERVAL: &str = "CONDUIT_PROXY_METRICS_FLUSH_INTERVAL";
+const ENV_MAX_REPORT_BYTES: &str = "CONDUIT_PROXY_METRICS_MAX_REPORT_BYTES";
+const ENV_SLOW_REQUEST_THRESHOLD: &str = "CONDUIT_PROXY_SLOW_REQUEST_THRESHOLD";
+const ENV_TELEMETRY_DISABLED: &str = "CONDUIT_PROXY_TELEMETRY_DISABLED";
+const ENV_STATSD_ADDR: &str = "CONDUIT_PROXY_STATSD_ADDR";
+const ENV_TELEMETRY_HEADERS: &str = "CONDUIT_PROXY_TELEMETRY_HEADERS";
+const ENV_TAP_REQUEST_PROGRESS_INTERVAL: &str = "CONDUIT_PROXY_TAP_REQUEST_PROGRESS_INTERVAL";
+const ENV_HEALTH_CHECK_PATHS: &str = "CONDUIT_PROXY_HEALTH_CHECK_PATHS";
+const ENV_HEALTH_CHECK_USER_AGENTS: &str = "CONDUIT_PROXY_HEALTH_CHECK_USER_AGENTS";
+const ENV_HEALTH_CHECK_SOURCES: &str = "CONDUIT_PROXY_HEALTH_CHECK_SOURCES";
 const ENV_PRIVATE_LISTENER: &str = "CONDUIT_PROXY_PRIVATE_LISTENER";
 const ENV_PRIVATE_FORWARD: &str = "CONDUIT_PROXY_PRIVATE_FORWARD";
+const ENV_PRIVATE_FORWARD_TTL: &str = "CONDUIT_PROXY_PRIVATE_FORWARD_TTL";
+const ENV_INGRESS_MODE: &str = "CONDUIT_PROXY_INGRESS_MODE";
+const ENV_INGRESS_HOSTS: &str = "CONDUIT_PROXY_INGRESS_HOSTS";
+const ENV_INGRESS_RESPONSE_HEADERS: &str = "CONDUIT_PROXY_INGRESS_RESPONSE_HEADERS";
+const ENV_INBOUND_NORMALIZATION: &str = "CONDUIT_PROXY_INBOUND_NORMALIZATION";
+const ENV_NO_ENDPOINTS: &str = "CONDUIT_PROXY_NO_ENDPOINTS";
+const ENV_NO_ENDPOINTS_TIMEOUT: &str = "CONDUIT_PROXY_NO_ENDPOINTS_TIMEOUT";
+const ENV_NO_ENDPOINTS_STARTUP_FALLBACK: &str = "CONDUIT_PROXY_NO_ENDPOINTS_STARTUP_FALLBACK";
+const ENV_CLUSTER_GATEWAYS: &str = "CONDUIT_PROXY_CLUSTER_GATEWAYS";
+const ENV_REDIRECT_HOSTS: &str = "CONDUIT_PROXY_REDIRECT_HOSTS";
+const ENV_REDIRECT_LIMIT: &str = "CONDUIT_PROXY_REDIRECT_LIMIT";
+const ENV_ERROR_TRANSLATIONS: &str = "CONDUIT_PROXY_ERROR_TRANSLATIONS";
+const ENV_CORS_RULES: &str = "CONDUIT_PROXY_CORS_RULES";
+const ENV_ALLOWED_METHODS: &str = "CONDUIT_PROXY_ALLOWED_METHODS";
+const ENV_ALLOWED_CONTENT_TYPES: &str = "CONDUIT_PROXY_ALLOWED_CONTENT_TYPES";
+const ENV_MAINTENANCE_PAGES: &str = "CONDUIT_PROXY_MAINTENANCE_PAGES";
+const ENV_UNAVAILABLE_PAGES: &str = "CONDUIT_PROXY_UNAVAILABLE_PAGES";
+const ENV_CHECKSUM_HOSTS: &str = "CONDUIT_PROXY_CHECKSUM_HOSTS";
+const ENV_BUFFER_HOSTS: &str = "CONDUIT_PROXY_BUFFER_HOSTS";
+const ENV_BUFFER_MAX_BYTES: &str = "CONDUIT_PROXY_BUFFER_MAX_BYTES";
+const ENV_COALESCE_HOSTS: &str = "CONDUIT_PROXY_COALESCE_HOSTS";
+const ENV_COALESCE_MAX_BYTES: &str = "CONDUIT_PROXY_COALESCE_MAX_BYTES";
+const ENV_AUDIT_HOSTS: &str = "CONDUIT_PROXY_AUDIT_HOSTS";
+const ENV_AUDIT_SPOOL_PATH: &str = "CONDUIT_PROXY_AUDIT_SPOOL_PATH";
+const ENV_AUDIT_MAX_BODY_BYTES: &str = "CONDUIT_PROXY_AUDIT_MAX_BODY_BYTES";
+const ENV_AUDIT_REDACTED_HEADERS: &str = "CONDUIT_PROXY_AUDIT_REDACTED_HEADERS";
+const ENV_REPLAY_HOSTS: &str = "CONDUIT_PROXY_REPLAY_HOSTS";
+const ENV_REPLAY_JOURNAL_PATH: &str = "CONDUIT_PROXY_REPLAY_JOURNAL_PATH";
+const ENV_REPLAY_JOURNAL_MAX_ENTRIES: &str = "CONDUIT_PROXY_REPLAY_JOURNAL_MAX_ENTRIES";
+const ENV_REPLAY_BACKOFF: &str = "CONDUIT_PROXY_REPLAY_BACKOFF";
+const ENV_REPLAY_MAX_BACKOFF: &str = "CONDUIT_PROXY_REPLAY_MAX_BACKOFF";
 const ENV_PUBLIC_LISTENER: &str = "CONDUIT_PROXY_PUBLIC_LISTENER";
+const ENV_PUBLIC_LISTENERS: &str = "CONDUIT_PROXY_PUBLIC_LISTENERS";
 const ENV_CONTROL_LISTENER: &str = "CONDUIT_PROXY_CONTROL_LISTENER";
+const ENV_CONTROL_LISTENER_SOURCES: &str = "CONDUIT_PROXY_CONTROL_LISTENER_SOURCES";
+const ENV_CONTROL_LISTENER_CREDENTIALS: &str = "CONDUIT_PROXY_CONTROL_LISTENER_CREDENTIALS";
+const ENV_SOCKS_LISTENER: &str = "CONDUIT_PROXY_SOCKS_LISTENER";
 const ENV_PRIVATE_CONNECT_TIMEOUT: &str = "CONDUIT_PROXY_PRIVATE_CONNECT_TIMEOUT";
 const ENV_PUBLIC_CONNECT_TIMEOUT: &str = "CONDUIT_PROXY_PUBLIC_CONNECT_TIMEOUT";
+const ENV_OUTBOUND_MAX_CONCURRENT_CONNECTS: &str =
+    "CONDUIT_PROXY_OUTBOUND_MAX_CONCURRENT_CONNECTS";
+const ENV_INBOUND_SKIP_PORTS: &str = "CONDUIT_PROXY_INBOUND_SKIP_PORTS";
+const ENV_OUTBOUND_SKIP_PORTS: &str = "CONDUIT_PROXY_OUTBOUND_SKIP_PORTS";
+const ENV_PRIVATE_IDLE_TIMEOUT: &str = "CONDUIT_PROXY_PRIVATE_IDLE_TIMEOUT";
+const ENV_PUBLIC_IDLE_TIMEOUT: &str = "CONDUIT_PROXY_PUBLIC_IDLE_TIMEOUT";
+const ENV_TCP_NODELAY: &str = "CONDUIT_PROXY_TCP_NODELAY";
+const ENV_TCP_KEEPALIVE: &str = "CONDUIT_PROXY_TCP_KEEPALIVE";
+const ENV_TCP_KEEPALIVE_INTERVAL: &str = "CONDUIT_PROXY_TCP_KEEPALIVE_INTERVAL";
+const ENV_TCP_KEEPALIVE_PROBES: &str = "CONDUIT_PROXY_TCP_KEEPALIVE_PROBES";
+const ENV_CONNECT_RECV_BUFFER_SIZE: &str = "CONDUIT_PROXY_CONNECT_RECV_BUFFER_SIZE";
+const ENV_CONNECT_SEND_BUFFER_SIZE: &str = "CONDUIT_PROXY_CONNECT_SEND_BUFFER_SIZE";
+const ENV_PUBLIC_MAX_CONCURRENT_STREAMS: &str = "CONDUIT_PROXY_PUBLIC_MAX_CONCURRENT_STREAMS";
+const ENV_H2_INITIAL_WINDOW_SIZE: &str = "CONDUIT_PROXY_H2_INITIAL_WINDOW_SIZE";
+const ENV_SYNTHETIC_TOKEN: &str = "CONDUIT_PROXY_SYNTHETIC_TOKEN";
+const ENV_ADMIN_READ_ONLY: &str = "CONDUIT_PROXY_ADMIN_READ_ONLY";
+const ENV_DEBUG_HEADERS: &str = "CONDUIT_PROXY_DEBUG_HEADERS";
 
 // the following are `pub` because they're used in the `ctx` module for populating `Process`.
 pub const ENV_NODE_NAME: &str = "CONDUIT_PROXY_NODE_NAME";
 pub const ENV_POD_NAME: &str = "CONDUIT_PROXY_POD_NAME";
 pub const ENV_POD_NAMESPACE: &str = "CONDUIT_PROXY_POD_NAMESPACE";
+pub const ENV_ZONE_NAME: &str = "CONDUIT_PROXY_ZONE_NAME";
+pub const ENV_CLUSTER_NAME: &str = "CONDUIT_PROXY_CLUSTER_NAME";
+
+/// The prefix of the proxy's environment variables' names.
+const ENV_PREFIX: &str = "CONDUIT_PROXY_";
+
+/// Any setting `<NAME>` may instead be set by `<NAME>_FILE`, which names a file whose
+/// contents, with surrounding whitespace trimmed, are the value. This keeps secrets out
+/// of the process's environment. The file is read again when the configuration is
+/// reloaded.
+const FILE_SUFFIX: &str = "_FILE";
+
+/// Replaces `ENV_PREFIX` in the names of the process's environment variables, so that
+/// products that embed the proxy can use their own naming scheme. This variable itself
+/// is never renamed.
+const ENV_ENV_PREFIX: &str = "CONDUIT_PROXY_ENV_PREFIX";
 
 const ENV_CONTROL_URL: &str = "CONDUIT_PROXY_CONTROL_URL";
+/// The port of the control URL, when the URL doesn't name one.
+const ENV_CONTROL_DEFAULT_PORT: &str = "CONDUIT_PROXY_CONTROL_DEFAULT_PORT";
+const ENV_CONTROL_TOKEN_PATH: &str = "CONDUIT_PROXY_CONTROL_TOKEN_PATH";
+const ENV_CONFIG_FILE: &str = "CONDUIT_PROXY_CONFIG_FILE";
+const ENV_CRASH_REPORT_PATH: &str = "CONDUIT_PROXY_CRASH_REPORT_PATH";
+const ENV_IDLE_TRIM_PERIOD: &str = "CONDUIT_PROXY_IDLE_TRIM_PERIOD";
+const ENV_LOG: &str = "CONDUIT_PROXY_LOG";
+const ENV_PROFILE: &str = "CONDUIT_PROXY_PROFILE";
+const ENV_ENDPOINT_CACHE_PATH: &str = "CONDUIT_PROXY_ENDPOINT_CACHE_PATH";
+const ENV_PORTS_FILE: &str = "CONDUIT_PROXY_PORTS_FILE";
 const ENV_RESOLV_CONF: &str = "CONDUIT_RESOLV_CONF";
+const ENV_DNS_INTERCEPT_LISTENER: &str = "CONDUIT_PROXY_DNS_INTERCEPT_LISTENER";
+
+// Appended to the names of the private, public, SOCKS, and additional public listeners'
+// variables to configure each listener's timeouts and socket options.
+const ACCEPT_TIMEOUT_SUFFIX: &str = "_ACCEPT_TIMEOUT";
+const CONNECT_TIMEOUT_SUFFIX: &str = "_CONNECT_TIMEOUT";
+const REUSE_PORT_SUFFIX: &str = "_REUSE_PORT";
+const DUAL_STACK_SUFFIX: &str = "_DUAL_STACK";
+const BACKLOG_SUFFIX: &str = "_BACKLOG";
+const RECV_BUFFER_SIZE_SUFFIX: &str = "_RECV_BUFFER_SIZE";
+const SEND_BUFFER_SIZE_SUFFIX: &str = "_SEND_BUFFER_SIZE";
+const PROXY_PROTOCOL_SUFFIX: &str = "_PROXY_PROTOCOL";
+const ENV_UDP_FORWARD: &str = "CONDUIT_PROXY_UDP_FORWARD";
+
+// Renamed environment variables, as `(old, new)` pairs. An old name is still read, with
+// a warning, when the new name isn't set.
+const DEPRECATED_ENV_VARS: &[(&str, &str)] = &[
+    // Units are now part of the values.
+    ("CONDUIT_PROXY_METRICS_FLUSH_INTERVAL_SECS", ENV_METRICS_FLUSH_INTERVAL),
+];
 
 // Default values for various configuration fields
 const DEFAULT_EVENT_BUFFER_CAPACITY: usize = 10_000; // FIXME
 const DEFAULT_METRICS_FLUSH_INTERVAL_SECS: u64 = 10;
+// Comfortably below gRPC's default 4MB limit on message sizes.
+const DEFAULT_MAX_REPORT_BYTES: usize = 3 * 1024 * 1024;
+const DEFAULT_REDIRECT_LIMIT: usize = 5;
+const DEFAULT_BUFFER_MAX_BYTES: usize = 1024 * 1024;
+const DEFAULT_COALESCE_MAX_BYTES: usize = 1024 * 1024;
+const DEFAULT_AUDIT_MAX_BODY_BYTES: usize = 64 * 1024;
+const DEFAULT_AUDIT_REDACTED_HEADERS: &[&str] =
+    &["authorization", "proxy-authorization", "cookie", "set-cookie"];
+const DEFAULT_REPLAY_JOURNAL_MAX_ENTRIES: usize = 1_000;
+const DEFAULT_REPLAY_BACKOFF_MS: u64 = 1_000;
+const DEFAULT_REPLAY_MAX_BACKOFF_MS: u64 = 60_000;
+const DEFAULT_NO_ENDPOINTS_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_PRIVATE_FORWARD_TTL_SECS: u64 = 30;
 const DEFAULT_PRIVATE_LISTENER: &str = "tcp://127.0.0.1:4140";
 const DEFAULT_PUBLIC_LISTENER: &str = "tcp://0.0.0.0:4143";
 const DEFAULT_CONTROL_LISTENER: &str = "tcp://0.0.0.0:4190";
 const DEFAULT_CONTROL_URL: &str = "tcp://proxy-api.conduit.svc.cluster.local:8086";
+// The controller's proxy-api port.
+const DEFAULT_CONTROL_PORT: u16 = 8086;
 const DEFAULT_RESOLV_CONF: &str = "/etc/resolv.conf";
+// Kubelet probes, and Google Cloud and AWS load balancer health checks.
+const DEFAULT_HEALTH_CHECK_USER_AGENTS: &[&str] =
+    &["kube-probe/", "GoogleHC/", "ELB-HealthChecker/"];
+
+/// Replaces the values of secret settings when the configuration is rendered.
+const REDACTED: &str = "<redacted>";
+
+/// Settings whose values are secret, and so are never printed, even if they're invalid.
+const SECRET_ENV_VARS: &[&str] = &[ENV_CONTROL_LISTENER_CREDENTIALS, ENV_SYNTHETIC_TOKEN];
+
+/// Settings whose variables are named `CONDUIT_<NAME>` rather than `ENV_PREFIX<NAME>`.
+const UNPREFIXED_ENV_VARS: &[&str] = &[ENV_RESOLV_CONF];
 
 // ===== impl Config =====
 
 impl Config {
     /// Load a `Config` by reading ENV variables.
+    ///
+    /// Every variable is read, even after one fails to parse, so that all of the
+    /// invalid variables are reported together.
     pub fn load_from_env() -> Result<Self, Error> {
-        let event_buffer_capacity = env_var_parse(ENV_EVENT_BUFFER_CAPACITY, parse_number)?
-            .unwrap_or(DEFAULT_EVENT_BUFFER_CAPACITY);
+        Self::load(&mut Env::default(), Source::Env)
+    }
 
-        let metrics_flush_interval = Duration::from_secs(
-            env_var_parse(ENV_METRICS_FLUSH_INTERVAL_SECS, parse_number)?
-                .unwrap_or(DEFAULT_METRICS_FLUSH_INTERVAL_SECS));
+    /// Load a `Config` from the file at `path`, overridden by ENV variables.
+    ///
+    /// The file is a subset of TOML: each line is blank, a `#` comment, or a
+    /// `key = value` pair. Keys are the names of the ENV variables, which may be
+    /// abbreviated by dropping the `CONDUIT_PROXY_` prefix and lowercasing the rest
+    /// (e.g. `private_listener`). Values are strings, arrays of strings on a single line
+    /// (which are joined with commas), numbers, or booleans. Tables are not supported.
+    pub fn load_from_file(path: &Path) -> Result<Self, Error> {
+        let mut env = Env {
+            file: read_file(path)?,
+            ..Env::default()
+        };
+        let config = Self::load(&mut env, Source::File(path.to_owned()));
+        env.check_file_keys(path)?;
+        config
+    }
 
-        Ok(Config {
-            private_listener: Listener {
-                addr: env_var_parse(ENV_PRIVATE_LISTENER, str::parse)?
-                    .unwrap_or_else(|| Addr::from_str(DEFAULT_PRIVATE_LISTENER).unwrap()),
-            },
-            public_listener: Listener {
-                addr: env_var_parse(ENV_PUBLIC_LISTENER, str::parse)?
-                    .unwrap_or_else(|| Addr::from_str(DEFAULT_PUBLIC_LISTENER).unwrap()),
-            },
-            control_listener: Listener {
-                addr: env_var_parse(ENV_CONTROL_LISTENER, str::parse)?
-                    .unwrap_or_else(|| Addr::from_str(DEFAULT_CONTROL_LISTENER).unwrap()),
-            },
-            private_forward: env_var_parse(ENV_PRIVATE_FORWARD, str::parse)?,
+    /// Load a `Config` from command-line arguments, which override ENV variables, which
+    /// override the config file, if any.
+    ///
+    /// Each argument is `--<key>=<value>` or `--<key> <value>`, where keys are spelled
+    /// as in the config file, e.g. `--private-listener=tcp://127.0.0.1:4140`. The config
+    /// file may be named with `--config-file`.
+    pub fn load_from_args<I>(args: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let args = args.into_iter().collect::<Vec<_>>();
+        let parsed = parse_args(args.clone()).map_err(|arg| Error::InvalidArgs(vec![arg]))?;
+        let mut env = Env {
+            args: parsed,
+            ..Env::default()
+        };
 
-            public_connect_timeout: env_var_parse(ENV_PUBLIC_CONNECT_TIMEOUT, parse_number)?
-                .map(Duration::from_millis),
+        let path = env.var(ENV_CONFIG_FILE).map(PathBuf::from);
+        if let Some(ref path) = path {
+            env.file = read_file(path)?;
+        }
 
-            private_connect_timeout: env_var_parse(ENV_PRIVATE_CONNECT_TIMEOUT, parse_number)?
-                .map(Duration::from_millis),
+        let config = Self::load(&mut env, Source::Args(args));
 
-            resolv_conf_path: env_var(ENV_RESOLV_CONF)?
-                .unwrap_or(DEFAULT_RESOLV_CONF.into())
-                .into(),
+        // Settings are only known once they've been read, so misspelled keys are
+        // identified after loading.
+        let mut unknown = env.args
+            .iter()
+            .filter(|&(name, _)| !env.read.contains(name))
+            .map(|(_, &(ref arg, _))| arg.clone())
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            unknown.sort();
+            return Err(Error::InvalidArgs(unknown));
+        }
+        if let Some(ref path) = path {
+            env.check_file_keys(path)?;
+        }
+        config
+    }
 
-            control_host_and_port: env_var_parse(ENV_CONTROL_URL, parse_url)?
-                .unwrap_or_else(|| parse_url(DEFAULT_CONTROL_URL).unwrap()),
+    fn load(env: &mut Env, source: Source) -> Result<Self, Error> {
+        // The profile is read first, so that its defaults apply to every other setting.
+        env.profile = env.parse(ENV_PROFILE, str::parse);
 
-            event_buffer_capacity,
-            metrics_flush_interval,
-        })
-    }
-}
+        let event_buffer_capacity = env.parse(ENV_EVENT_BUFFER_CAPACITY, parse_number)
+            .unwrap_or(DEFAULT_EVENT_BUFFER_CAPACITY);
 
-// ===== impl Addr =====
+        let metrics_flush_interval = env.parse(ENV_METRICS_FLUSH_INTERVAL, parse_secs)
+            .unwrap_or(Duration::from_secs(DEFAULT_METRICS_FLUSH_INTERVAL_SECS));
 
-impl FromStr for Addr {
-    type Err = ParseError;
+        // `tcp` has no well-known port, so the control URL's default is the controller's.
+        let control_default_port = env.parse(ENV_CONTROL_DEFAULT_PORT, parse_port)
+            .unwrap_or(DEFAULT_CONTROL_PORT);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match parse_url(s)? {
-            HostAndPort {
-                host: Host::Ipv4(ip),
-                port,
-            } => Ok(Addr(SocketAddr::new(ip.into(), port))),
-            HostAndPort {
-                host: Host::Ipv6(ip),
-                port,
-            } => Ok(Addr(SocketAddr::new(ip.into(), port))),
-            HostAndPort {
-                host: Host::Domain(_),
-                ..
-            } => Err(ParseError::HostIsNotAnIpAddress),
+        // Each additional public listener's policies are configured by variables named
+        // after it, e.g. `CONDUIT_PROXY_PUBLIC_LISTENER_ADMIN_ALLOWED_METHODS`.
+        let mut additional_public_listeners =
+            env.parse(ENV_PUBLIC_LISTENERS, parse_public_listeners).unwrap_or_default();
+        for l in &mut additional_public_listeners {
+            let prefix = public_listener_prefix(&l.name);
+            l.listener = l.listener.clone().load_settings(env, &prefix);
+            l.allowed_methods =
+                env.parse(&format!("{}_ALLOWED_METHODS", prefix), parse_method_rules)
+                    .unwrap_or_default();
+            l.allowed_content_types = env.parse(
+                &format!("{}_ALLOWED_CONTENT_TYPES", prefix),
+                parse_content_type_rules,
+            ).unwrap_or_default();
+            l.max_concurrent_streams =
+                env.parse(&format!("{}_MAX_CONCURRENT_STREAMS", prefix), parse_number);
         }
-    }
-}
 
-impl From<Addr> for SocketAddr {
-    fn from(addr: Addr) -> SocketAddr {
-        addr.0
-    }
-}
+        let config = Config {
+            private_listener: Listener::new(
+                env.parse(ENV_PRIVATE_LISTENER, str::parse)
+                    .unwrap_or_else(|| default_addr(DEFAULT_PRIVATE_LISTENER)),
+            ).load_settings(env, ENV_PRIVATE_LISTENER),
+            public_listener: Listener::new(
+                env.parse(ENV_PUBLIC_LISTENER, str::parse)
+                    .unwrap_or_else(|| default_addr(DEFAULT_PUBLIC_LISTENER)),
+            ).load_settings(env, ENV_PUBLIC_LISTENER),
+            control_listener: Listener::new(
+                env.parse(ENV_CONTROL_LISTENER, str::parse)
+                    .unwrap_or_else(|| default_addr(DEFAULT_CONTROL_LISTENER)),
+            ),
+            control_listener_sources: env.parse(ENV_CONTROL_LISTENER_SOURCES, parse_networks)
+                .unwrap_or_default(),
+            control_listener_credentials: env.parse(ENV_CONTROL_LISTENER_CREDENTIALS, |s| {
+                if s.contains(':') {
+                    Ok(s.to_owned())
+                } else {
+                    Err(ParseError::NotAUserAndPassword)
+                }
+            }),
+            socks_listener: env.parse(ENV_SOCKS_LISTENER, str::parse)
+                .map(|addr| Listener::new(addr).load_settings(env, ENV_SOCKS_LISTENER)),
+            private_forward: env.parse(ENV_PRIVATE_FORWARD, str::parse),
 
-fn parse_number<T>(s: &str) -> Result<T, ParseError> where T: FromStr {
-    s.parse().map_err(|_| ParseError::NotANumber)
-}
+            private_forward_ttl: env.parse(ENV_PRIVATE_FORWARD_TTL, parse_secs)
+                .unwrap_or(Duration::from_secs(DEFAULT_PRIVATE_FORWARD_TTL_SECS)),
 
-fn parse_url(s: &str) -> Result<HostAndPort, ParseError> {
-    let url = Url::parse(&s).map_err(|_| ParseError::UrlError(UrlError::SyntaxError))?;
-    let host = url.host()
-        .ok_or_else(|| ParseError::UrlError(UrlError::MissingHost))?
-        .to_owned();
-    if url.scheme() != "tcp" {
-        return Err(ParseError::UrlError(UrlError::UnsupportedScheme));
-    }
-    let port = url.port().ok_or_else(|| ParseError::UrlError(UrlError::MissingPort))?;
-    if url.path() != "/" {
-        return Err(ParseError::UrlError(UrlError::PathNotAllowed));
-    }
-    if url.fragment().is_some() {
-        return Err(ParseError::UrlError(UrlError::FragmentNotAllowed));
-    }
-    Ok(HostAndPort {
-        host,
-        port,
-    })
-}
+            additional_public_listeners,
 
-fn env_var(name: &str) -> Result<Option<String>, Error> {
-    match env::var(name) {
-        Ok(value) => Ok(Some(value)),
-        Err(env::VarError::NotPresent) => Ok(None),
-        Err(env::VarError::NotUnicode(_)) => Err(Error::InvalidEnvVar {
-            name: name.to_owned(),
-            value: None,
-            parse_error: ParseError::NotUnicode,
-        }),
-    }
-}
+            ingress_mode: env.parse(ENV_INGRESS_MODE, parse_bool).unwrap_or(false),
 
-fn env_var_parse<T, Parse>(name: &str, parse: Parse) -> Result<Option<T>, Error>
-    where Parse: FnOnce(&str) -> Result<T, ParseError> {
-    match env_var(name)? {
-        Some(ref s) => {
-            let r = parse(s).map_err(|parse_error| {
-                Error::InvalidEnvVar {
-                    name: name.to_owned(),
-                    value: Some(s.to_owned()),
-                    parse_error,
-                }
-            })?;
-            Ok(Some(r))
-        },
-        None => Ok(None),
+            ingress_hosts: env.parse(ENV_INGRESS_HOSTS, parse_list).unwrap_or_default(),
+
+            ingress_response_headers:
+                env.parse(ENV_INGRESS_RESPONSE_HEADERS, parse_response_headers)
+                    .unwrap_or_default(),
+
+            inbound_normalization: env.parse(ENV_INBOUND_NORMALIZATION, str::parse)
+                .unwrap_or(Normalization::Off),
+
+            no_endpoints: env.parse(ENV_NO_ENDPOINTS, str::parse)
+                .unwrap_or(NoEndpoints::Queue),
+
+            no_endpoints_timeout: env.parse(ENV_NO_ENDPOINTS_TIMEOUT, parse_millis)
+                .unwrap_or(Duration::from_millis(DEFAULT_NO_ENDPOINTS_TIMEOUT_MS)),
+
+            no_endpoints_startup_fallback: env
+                .parse(ENV_NO_ENDPOINTS_STARTUP_FALLBACK, parse_bool)
+                .unwrap_or(false),
+
+            cluster_gateways: env.parse(ENV_CLUSTER_GATEWAYS, parse_cluster_gateways)
+                .unwrap_or_default(),
+
+            redirect_hosts: env.parse(ENV_REDIRECT_HOSTS, parse_list).unwrap_or_default(),
+
+            redirect_limit: env.parse(ENV_REDIRECT_LIMIT, parse_number)
+                .unwrap_or(DEFAULT_REDIRECT_LIMIT),
+
+            error_translations: env.parse(ENV_ERROR_TRANSLATIONS, parse_error_translations)
+                .unwrap_or_default(),
+
+            maintenance_pages: env.parse(ENV_MAINTENANCE_PAGES, parse_host_pages)
+                .unwrap_or_default(),
+
+            unavailable_pages: env.parse(ENV_UNAVAILABLE_PAGES, parse_host_pages)
+                .unwrap_or_default(),
+
+            cors_rules: env.parse(ENV_CORS_RULES, parse_cors_rules).unwrap_or_default(),
+
+            allowed_methods: env.parse(ENV_ALLOWED_METHODS, parse_method_rules)
+                .unwrap_or_default(),
+
+            allowed_content_types:
+                env.parse(ENV_ALLOWED_CONTENT_TYPES, parse_content_type_rules)
+                    .unwrap_or_default(),
+
+            checksum_hosts: env.parse(ENV_CHECKSUM_HOSTS, parse_list).unwrap_or_default(),
+
+            buffer_hosts: env.parse(ENV_BUFFER_HOSTS, parse_list).unwrap_or_default(),
+
+            buffer_max_bytes: env.parse(ENV_BUFFER_MAX_BYTES, parse_number)
+                .unwrap_or(DEFAULT_BUFFER_MAX_BYTES),
+
+            coalesce_hosts: env.parse(ENV_COALESCE_HOSTS, parse_list).unwrap_or_default(),
+
+            coalesce_max_bytes: env.parse(ENV_COALESCE_MAX_BYTES, parse_number)
+                .unwrap_or(DEFAULT_COALESCE_MAX_BYTES),
+
+            audit_hosts: env.parse(ENV_AUDIT_HOSTS, parse_list).unwrap_or_default(),
+
+            audit_spool_path: env.var(ENV_AUDIT_SPOOL_PATH).map(PathBuf::from),
+
+            audit_max_body_bytes: env.parse(ENV_AUDIT_MAX_BODY_BYTES, parse_number)
+                .unwrap_or(DEFAULT_AUDIT_MAX_BODY_BYTES),
+
+            audit_redacted_headers: env.parse(ENV_AUDIT_REDACTED_HEADERS, parse_list)
+                .unwrap_or_else(|| {
+                    DEFAULT_AUDIT_REDACTED_HEADERS.iter().map(|&h| h.to_owned()).collect()
+                }),
+
+            replay_hosts: env.parse(ENV_REPLAY_HOSTS, parse_list).unwrap_or_default(),
+
+            replay_journal_path: env.var(ENV_REPLAY_JOURNAL_PATH).map(PathBuf::from),
+
+            replay_journal_max_entries: env.parse(ENV_REPLAY_JOURNAL_MAX_ENTRIES, parse_positive)
+                .unwrap_or(DEFAULT_REPLAY_JOURNAL_MAX_ENTRIES),
+
+            replay_backoff: env.parse(ENV_REPLAY_BACKOFF, parse_millis)
+                .unwrap_or(Duration::from_millis(DEFAULT_REPLAY_BACKOFF_MS)),
+
+            replay_max_backoff: env.parse(ENV_REPLAY_MAX_BACKOFF, parse_millis)
+                .unwrap_or(Duration::from_millis(DEFAULT_REPLAY_MAX_BACKOFF_MS)),
+
+            public_connect_timeout: env.parse(ENV_PUBLIC_CONNECT_TIMEOUT, parse_millis),
+
+            private_connect_timeout: env.parse(ENV_PRIVATE_CONNECT_TIMEOUT, parse_millis),
+
+            outbound_max_concurrent_connects:
+                env.parse(ENV_OUTBOUND_MAX_CONCURRENT_CONNECTS, parse_positive),
+
+            inbound_skip_ports: env.parse(ENV_INBOUND_SKIP_PORTS, parse_ports).unwrap_or_default(),
+
+            outbound_skip_ports: env.parse(ENV_OUTBOUND_SKIP_PORTS, parse_ports)
+                .unwrap_or_default(),
+
+            public_max_concurrent_streams:
+                env.parse(ENV_PUBLIC_MAX_CONCURRENT_STREAMS, parse_number),
+
+            public_idle_timeout: env.parse(ENV_PUBLIC_IDLE_TIMEOUT, parse_millis),
+
+            private_idle_timeout: env.parse(ENV_PRIVATE_IDLE_TIMEOUT, parse_millis),
+
+            tcp_nodelay: env.parse(ENV_TCP_NODELAY, parse_bool).unwrap_or(true),
+
+            tcp_keepalive: env.parse(ENV_TCP_KEEPALIVE, parse_secs),
+
+            tcp_keepalive_interval: env.parse(ENV_TCP_KEEPALIVE_INTERVAL, parse_secs),
+
+            tcp_keepalive_probes: env.parse(ENV_TCP_KEEPALIVE_PROBES, parse_number),
+
+            connect_recv_buffer_size: env.parse(ENV_CONNECT_RECV_BUFFER_SIZE, parse_positive),
+
+            connect_send_buffer_size: env.parse(ENV_CONNECT_SEND_BUFFER_SIZE, parse_positive),
+
+            h2_initial_window_size: env.parse(ENV_H2_INITIAL_WINDOW_SIZE, parse_number),
+
+            resolv_conf_path: env.var(ENV_RESOLV_CONF)
+                .unwrap_or(DEFAULT_RESOLV_CONF.into())
+                .into(),
+
+            crash_report_path: env.var(ENV_CRASH_REPORT_PATH).map(PathBuf::from),
+
+            idle_trim_period: env.parse(ENV_IDLE_TRIM_PERIOD, parse_secs),
+
+            profile: env.profile,
+
+            log_filter: env.var(ENV_LOG),
+
+            endpoint_cache_path: env.var(ENV_ENDPOINT_CACHE_PATH).map(PathBuf::from),
+
+            ports_file_path: env.var(ENV_PORTS_FILE).map(PathBuf::from),
+
+            dns_intercept_listener: env.parse(ENV_DNS_INTERCEPT_LISTENER, parse_udp_addr)
+                .map(Listener::new),
+
+            udp_forwards: env.parse(ENV_UDP_FORWARD, parse_udp_forwards)
+                .unwrap_or_default(),
+
+            control_host_and_port: env.parse(ENV_CONTROL_URL, |s| {
+                parse_url_with_default_port(s, "tcp", control_default_port)
+            })
+                .unwrap_or_else(|| {
+                    parse_url(DEFAULT_CONTROL_URL).expect("default control URL must be valid")
+                }),
+
+            control_token_path: env.var(ENV_CONTROL_TOKEN_PATH).map(PathBuf::from),
+
+            event_buffer_capacity,
+            metrics_flush_interval,
+
+            max_report_bytes: env.parse(ENV_MAX_REPORT_BYTES, parse_number)
+                .unwrap_or(DEFAULT_MAX_REPORT_BYTES),
+
+            slow_request_threshold: env.parse(ENV_SLOW_REQUEST_THRESHOLD, parse_millis),
+
+            statsd_addr: env.parse(ENV_STATSD_ADDR, parse_udp_addr),
+
+            telemetry_disabled: env.parse(ENV_TELEMETRY_DISABLED, parse_bool).unwrap_or(false),
+
+            telemetry_headers: env.parse(ENV_TELEMETRY_HEADERS, parse_header_names)
+                .unwrap_or_else(|| vec![http::header::USER_AGENT]),
+
+            tap_request_progress_interval: env.parse(
+                ENV_TAP_REQUEST_PROGRESS_INTERVAL,
+                parse_millis,
+            ),
+
+            health_check_paths: env.parse(ENV_HEALTH_CHECK_PATHS, parse_list).unwrap_or_default(),
+
+            health_check_user_agents: env.parse(ENV_HEALTH_CHECK_USER_AGENTS, parse_list)
+                .unwrap_or_else(|| {
+                    DEFAULT_HEALTH_CHECK_USER_AGENTS.iter().map(|&ua| ua.to_owned()).collect()
+                }),
+
+            health_check_sources: env.parse(ENV_HEALTH_CHECK_SOURCES, parse_networks)
+                .unwrap_or_default(),
+
+            synthetic_token: env.var(ENV_SYNTHETIC_TOKEN),
+
+            admin_read_only: env.parse(ENV_ADMIN_READ_ONLY, parse_bool).unwrap_or(false),
+
+            debug_headers: env.parse(ENV_DEBUG_HEADERS, parse_bool).unwrap_or(false),
+
+            // Read last, once every other setting has been read.
+            value_files: env.from_files.values().cloned().collect(),
+
+            source,
+        };
+        if !env.errors.is_empty() {
+            return Err(Error::InvalidEnvVars(env.errors.clone()));
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads the settings again, from the sources this `Config` was loaded from.
+    ///
+    /// The process's environment can't change while it runs, so only settings from a
+    /// config file may differ. A `Config` built by a `Builder` is returned unchanged.
+    pub fn reload(&self) -> Result<Self, Error> {
+        match self.source {
+            Source::Env => Self::load_from_env(),
+            Source::File(ref path) => Self::load_from_file(path),
+            Source::Args(ref args) => Self::load_from_args(args.clone()),
+            Source::Builder => Ok(self.clone()),
+        }
+    }
+
+    /// Returns a `Builder` that starts from the default settings, without reading ENV
+    /// variables.
+    pub fn builder() -> Builder {
+        let mut env = Env {
+            isolated: true,
+            ..Env::default()
+        };
+        let config =
+            Self::load(&mut env, Source::Builder).expect("default config must be valid");
+        Builder {
+            config,
+        }
+    }
+
+    /// Checks that the settings are consistent with one another, reporting all of the
+    /// conflicts between them rather than only the first.
+    fn validate(&self) -> Result<(), Error> {
+        let mut listeners = vec![
+            ("private".to_owned(), self.private_listener.addr.0, self.private_listener.dual_stack),
+            ("public".to_owned(), self.public_listener.addr.0, self.public_listener.dual_stack),
+            ("control".to_owned(), self.control_listener.addr.0, self.control_listener.dual_stack),
+        ];
+        if let Some(ref l) = self.socks_listener {
+            listeners.push(("socks".to_owned(), l.addr.0, l.dual_stack));
+        }
+
+        let mut forwards = Vec::new();
+        // A host's addresses aren't known until it's resolved, so it can't be checked.
+        if let Some(Forward::Addr(addr)) = self.private_forward {
+            forwards.push(("public".to_owned(), addr.0));
+        }
+
+        for l in &self.additional_public_listeners {
+            let name = format!("public ({})", l.name);
+            listeners.push((name.clone(), l.listener.addr.0, l.listener.dual_stack));
+            if let Forward::Addr(addr) = l.forward {
+                forwards.push((name, addr.0));
+            }
+        }
+
+        let conflicts = conflicts(&listeners, &forwards);
+
+        let mut timeouts = vec![
+            (ENV_NO_ENDPOINTS_TIMEOUT, Some(self.no_endpoints_timeout)),
+            (ENV_PUBLIC_CONNECT_TIMEOUT, self.public_connect_timeout),
+            (ENV_PRIVATE_CONNECT_TIMEOUT, self.private_connect_timeout),
+            (ENV_PUBLIC_IDLE_TIMEOUT, self.public_idle_timeout),
+            (ENV_PRIVATE_IDLE_TIMEOUT, self.private_idle_timeout),
+            (ENV_TCP_KEEPALIVE, self.tcp_keepalive),
+            (ENV_TCP_KEEPALIVE_INTERVAL, self.tcp_keepalive_interval),
+            (ENV_METRICS_FLUSH_INTERVAL, Some(self.metrics_flush_interval)),
+            (ENV_PRIVATE_FORWARD_TTL, Some(self.private_forward_ttl)),
+            (ENV_IDLE_TRIM_PERIOD, self.idle_trim_period),
+            (ENV_REPLAY_BACKOFF, Some(self.replay_backoff)),
+            (ENV_REPLAY_MAX_BACKOFF, Some(self.replay_max_backoff)),
+        ].into_iter()
+            .map(|(name, t)| (name.to_owned(), t))
+            .collect::<Vec<_>>();
+        let mut listeners = vec![
+            (ENV_PRIVATE_LISTENER.to_owned(), &self.private_listener),
+            (ENV_PUBLIC_LISTENER.to_owned(), &self.public_listener),
+        ];
+        if let Some(ref l) = self.socks_listener {
+            listeners.push((ENV_SOCKS_LISTENER.to_owned(), l));
+        }
+        for l in &self.additional_public_listeners {
+            listeners.push((public_listener_prefix(&l.name), &l.listener));
+        }
+        for (prefix, l) in listeners {
+            timeouts.push((format!("{}{}", prefix, ACCEPT_TIMEOUT_SUFFIX), l.accept_timeout));
+            timeouts.push((format!("{}{}", prefix, CONNECT_TIMEOUT_SUFFIX), l.connect_timeout));
+        }
+        let zero_timeouts = timeouts
+            .into_iter()
+            .filter(|&(_, t)| t == Some(Duration::from_secs(0)))
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        if conflicts.is_empty() && zero_timeouts.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Invalid {
+                conflicts,
+                zero_timeouts,
+            })
+        }
+    }
+
+    pub fn private_listener(&self) -> &Listener {
+        &self.private_listener
+    }
+
+    pub fn public_listener(&self) -> &Listener {
+        &self.public_listener
+    }
+
+    pub fn control_listener(&self) -> &Listener {
+        &self.control_listener
+    }
+
+    pub fn control_listener_sources(&self) -> &[Network] {
+        &self.control_listener_sources
+    }
+
+    pub fn control_listener_credentials(&self) -> Option<&str> {
+        self.control_listener_credentials.as_ref().map(|s| s.as_str())
+    }
+
+    pub fn socks_listener(&self) -> Option<&Listener> {
+        self.socks_listener.as_ref()
+    }
+
+    pub fn private_forward(&self) -> Option<&Forward> {
+        self.private_forward.as_ref()
+    }
+
+    pub fn private_forward_ttl(&self) -> Duration {
+        self.private_forward_ttl
+    }
+
+    pub fn additional_public_listeners(&self) -> &[PublicListener] {
+        &self.additional_public_listeners
+    }
+
+    pub fn ingress_mode(&self) -> bool {
+        self.ingress_mode
+    }
+
+    pub fn ingress_hosts(&self) -> &[String] {
+        &self.ingress_hosts
+    }
+
+    pub fn ingress_response_headers(&self) -> &[ResponseHeader] {
+        &self.ingress_response_headers
+    }
+
+    pub fn inbound_normalization(&self) -> Normalization {
+        self.inbound_normalization
+    }
+
+    pub fn no_endpoints(&self) -> NoEndpoints {
+        self.no_endpoints
+    }
+
+    pub fn no_endpoints_timeout(&self) -> Duration {
+        self.no_endpoints_timeout
+    }
+
+    pub fn no_endpoints_startup_fallback(&self) -> bool {
+        self.no_endpoints_startup_fallback
+    }
+
+    pub fn cluster_gateways(&self) -> &[ClusterGateway] {
+        &self.cluster_gateways
+    }
+
+    pub fn redirect_hosts(&self) -> &[String] {
+        &self.redirect_hosts
+    }
+
+    pub fn redirect_limit(&self) -> usize {
+        self.redirect_limit
+    }
+
+    pub fn error_translations(&self) -> &[ErrorTranslation] {
+        &self.error_translations
+    }
+
+    pub fn maintenance_pages(&self) -> &[HostPage] {
+        &self.maintenance_pages
+    }
+
+    pub fn unavailable_pages(&self) -> &[HostPage] {
+        &self.unavailable_pages
+    }
+
+    pub fn cors_rules(&self) -> &[CorsRule] {
+        &self.cors_rules
+    }
+
+    pub fn allowed_methods(&self) -> &[MethodRule] {
+        &self.allowed_methods
+    }
+
+    pub fn allowed_content_types(&self) -> &[ContentTypeRule] {
+        &self.allowed_content_types
+    }
+
+    pub fn checksum_hosts(&self) -> &[String] {
+        &self.checksum_hosts
+    }
+
+    pub fn buffer_hosts(&self) -> &[String] {
+        &self.buffer_hosts
+    }
+
+    pub fn buffer_max_bytes(&self) -> usize {
+        self.buffer_max_bytes
+    }
+
+    pub fn coalesce_hosts(&self) -> &[String] {
+        &self.coalesce_hosts
+    }
+
+    pub fn coalesce_max_bytes(&self) -> usize {
+        self.coalesce_max_bytes
+    }
+
+    pub fn audit_hosts(&self) -> &[String] {
+        &self.audit_hosts
+    }
+
+    pub fn audit_spool_path(&self) -> Option<&Path> {
+        self.audit_spool_path.as_ref().map(|p| p.as_path())
+    }
+
+    pub fn audit_max_body_bytes(&self) -> usize {
+        self.audit_max_body_bytes
+    }
+
+    pub fn audit_redacted_headers(&self) -> &[String] {
+        &self.audit_redacted_headers
+    }
+
+    pub fn replay_hosts(&self) -> &[String] {
+        &self.replay_hosts
+    }
+
+    pub fn replay_journal_path(&self) -> Option<&Path> {
+        self.replay_journal_path.as_ref().map(|p| p.as_path())
+    }
+
+    pub fn replay_journal_max_entries(&self) -> usize {
+        self.replay_journal_max_entries
+    }
+
+    pub fn replay_backoff(&self) -> Duration {
+        self.replay_backoff
+    }
+
+    pub fn replay_max_backoff(&self) -> Duration {
+        self.replay_max_backoff
+    }
+
+    pub fn public_connect_timeout(&self) -> Option<Duration> {
+        self.public_connect_timeout
+    }
+
+    pub fn private_connect_timeout(&self) -> Option<Duration> {
+        self.private_connect_timeout
+    }
+
+    pub fn outbound_max_concurrent_connects(&self) -> Option<usize> {
+        self.outbound_max_concurrent_connects
+    }
+
+    pub fn inbound_skip_ports(&self) -> &HashSet<u16> {
+        &self.inbound_skip_ports
+    }
+
+    pub fn outbound_skip_ports(&self) -> &HashSet<u16> {
+        &self.outbound_skip_ports
+    }
+
+    pub fn public_max_concurrent_streams(&self) -> Option<usize> {
+        self.public_max_concurrent_streams
+    }
+
+    pub fn public_idle_timeout(&self) -> Option<Duration> {
+        self.public_idle_timeout
+    }
+
+    pub fn private_idle_timeout(&self) -> Option<Duration> {
+        self.private_idle_timeout
+    }
+
+    pub fn tcp_nodelay(&self) -> bool {
+        self.tcp_nodelay
+    }
+
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.tcp_keepalive
+    }
+
+    pub fn tcp_keepalive_interval(&self) -> Option<Duration> {
+        self.tcp_keepalive_interval
+    }
+
+    pub fn tcp_keepalive_probes(&self) -> Option<u32> {
+        self.tcp_keepalive_probes
+    }
+
+    pub fn connect_recv_buffer_size(&self) -> Option<usize> {
+        self.connect_recv_buffer_size
+    }
+
+    pub fn connect_send_buffer_size(&self) -> Option<usize> {
+        self.connect_send_buffer_size
+    }
+
+    pub fn h2_initial_window_size(&self) -> Option<u32> {
+        self.h2_initial_window_size
+    }
+
+    pub fn resolv_conf_path(&self) -> &Path {
+        &self.resolv_conf_path
+    }
+
+    pub fn crash_report_path(&self) -> Option<&Path> {
+        self.crash_report_path.as_ref().map(|p| p.as_path())
+    }
+
+    pub fn idle_trim_period(&self) -> Option<Duration> {
+        self.idle_trim_period
+    }
+
+    pub fn profile(&self) -> Option<Profile> {
+        self.profile
+    }
+
+    pub fn log_filter(&self) -> Option<&str> {
+        self.log_filter.as_ref().map(|s| s.as_str())
+    }
+
+    pub fn endpoint_cache_path(&self) -> Option<&Path> {
+        self.endpoint_cache_path.as_ref().map(|p| p.as_path())
+    }
+
+    pub fn ports_file_path(&self) -> Option<&Path> {
+        self.ports_file_path.as_ref().map(|p| p.as_path())
+    }
+
+    pub fn dns_intercept_listener(&self) -> Option<&Listener> {
+        self.dns_intercept_listener.as_ref()
+    }
+
+    pub fn udp_forwards(&self) -> &[UdpForward] {
+        &self.udp_forwards
+    }
+
+    pub fn control_host_and_port(&self) -> &HostAndPort {
+        &self.control_host_and_port
+    }
+
+    pub fn control_token_path(&self) -> Option<&Path> {
+        self.control_token_path.as_ref().map(|p| p.as_path())
+    }
+
+    pub fn event_buffer_capacity(&self) -> usize {
+        self.event_buffer_capacity
+    }
+
+    pub fn metrics_flush_interval(&self) -> Duration {
+        self.metrics_flush_interval
+    }
+
+    pub fn max_report_bytes(&self) -> usize {
+        self.max_report_bytes
+    }
+
+    pub fn slow_request_threshold(&self) -> Option<Duration> {
+        self.slow_request_threshold
+    }
+
+    pub fn statsd_addr(&self) -> Option<Addr> {
+        self.statsd_addr
+    }
+
+    pub fn telemetry_disabled(&self) -> bool {
+        self.telemetry_disabled
+    }
+
+    pub fn telemetry_headers(&self) -> &[http::header::HeaderName] {
+        &self.telemetry_headers
+    }
+
+    pub fn tap_request_progress_interval(&self) -> Option<Duration> {
+        self.tap_request_progress_interval
+    }
+
+    pub fn health_check_paths(&self) -> &[String] {
+        &self.health_check_paths
+    }
+
+    pub fn health_check_user_agents(&self) -> &[String] {
+        &self.health_check_user_agents
+    }
+
+    pub fn health_check_sources(&self) -> &[Network] {
+        &self.health_check_sources
+    }
+
+    pub fn synthetic_token(&self) -> Option<&str> {
+        self.synthetic_token.as_ref().map(|s| s.as_str())
+    }
+
+    pub fn admin_read_only(&self) -> bool {
+        self.admin_read_only
+    }
+
+    pub fn debug_headers(&self) -> bool {
+        self.debug_headers
+    }
+
+    /// The files that settings' values were read from, e.g. by
+    /// `CONDUIT_PROXY_SYNTHETIC_TOKEN_FILE`, in no particular order.
+    pub fn value_files(&self) -> &[PathBuf] {
+        &self.value_files
+    }
+
+    /// Renders the effective settings, including defaults, as a JSON object keyed by
+    /// setting name. Secrets are replaced with `REDACTED`.
+    pub fn to_json(&self) -> String {
+        self.json_members().finish()
+    }
+
+    /// Returns the names of the settings whose values differ in `other`, in the order
+    /// they're rendered by `to_json`. Changes to secrets aren't seen.
+    pub fn changed_settings(&self, other: &Config) -> Vec<String> {
+        let other = other.json_members().0;
+        self.json_members()
+            .0
+            .into_iter()
+            .filter(|m| !other.contains(m))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn json_members(&self) -> JsonObject {
+        let addr = |a: &Addr| json_str(&a.to_string());
+        let duration = |d: &Duration| json_str(&format_duration(*d));
+        let opt_duration = |d: &Option<Duration>| json_opt(d.as_ref().map(&duration));
+        let listener = |l: &Listener| {
+            let mut o = JsonObject::default();
+            o.member("addr", addr(&l.addr));
+            o.member("accept_timeout", opt_duration(&l.accept_timeout));
+            o.member("connect_timeout", opt_duration(&l.connect_timeout));
+            o.member("reuse_port", l.reuse_port.to_string());
+            o.member("dual_stack", l.dual_stack.to_string());
+            o.member("backlog", json_opt(l.backlog));
+            o.member("recv_buffer_size", json_opt(l.recv_buffer_size));
+            o.member("send_buffer_size", json_opt(l.send_buffer_size));
+            o.member("proxy_protocol", l.proxy_protocol.to_string());
+            o.finish()
+        };
+        let opt_listener = |l: &Option<Listener>| json_opt(l.as_ref().map(&listener));
+        let path = |p: &PathBuf| json_str(&p.display().to_string());
+        let opt_path = |p: &Option<PathBuf>| json_opt(p.as_ref().map(&path));
+
+        let mut o = JsonObject::default();
+        o.member("private_listener", listener(&self.private_listener));
+        o.member("public_listener", listener(&self.public_listener));
+        o.member("control_listener", listener(&self.control_listener));
+        o.member(
+            "control_listener_sources",
+            json_list(self.control_listener_sources.iter().map(|n| json_str(&n.to_string()))),
+        );
+        o.member(
+            "control_listener_credentials",
+            json_opt(self.control_listener_credentials.as_ref().map(|_| json_str(REDACTED))),
+        );
+        o.member("socks_listener", opt_listener(&self.socks_listener));
+        o.member(
+            "private_forward",
+            json_opt(self.private_forward.as_ref().map(|f| json_str(&f.to_string()))),
+        );
+        o.member("private_forward_ttl", duration(&self.private_forward_ttl));
+        o.member(
+            "additional_public_listeners",
+            json_list(self.additional_public_listeners.iter().map(|l| {
+                let mut o = JsonObject::default();
+                o.member("name", json_str(&l.name));
+                o.member("listener", listener(&l.listener));
+                o.member("forward", json_str(&l.forward.to_string()));
+                o.member("allowed_methods", json_debug(&l.allowed_methods));
+                o.member("allowed_content_types", json_debug(&l.allowed_content_types));
+                o.member("max_concurrent_streams", json_opt(l.max_concurrent_streams));
+                o.finish()
+            })),
+        );
+        o.member("ingress_mode", self.ingress_mode.to_string());
+        o.member("ingress_hosts", json_strs(&self.ingress_hosts));
+        o.member("ingress_response_headers", json_debug(&self.ingress_response_headers));
+        o.member("inbound_normalization", json_str(&self.inbound_normalization.to_string()));
+        o.member("no_endpoints", json_str(&self.no_endpoints.to_string()));
+        o.member("no_endpoints_timeout", duration(&self.no_endpoints_timeout));
+        o.member(
+            "no_endpoints_startup_fallback",
+            self.no_endpoints_startup_fallback.to_string(),
+        );
+        o.member("cluster_gateways", json_debug(&self.cluster_gateways));
+        o.member("redirect_hosts", json_strs(&self.redirect_hosts));
+        o.member("redirect_limit", self.redirect_limit.to_string());
+        o.member("error_translations", json_debug(&self.error_translations));
+        o.member("maintenance_pages", json_debug(&self.maintenance_pages));
+        o.member("unavailable_pages", json_debug(&self.unavailable_pages));
+        o.member("cors_rules", json_debug(&self.cors_rules));
+        o.member("allowed_methods", json_debug(&self.allowed_methods));
+        o.member("allowed_content_types", json_debug(&self.allowed_content_types));
+        o.member("checksum_hosts", json_strs(&self.checksum_hosts));
+        o.member("buffer_hosts", json_strs(&self.buffer_hosts));
+        o.member("buffer_max_bytes", self.buffer_max_bytes.to_string());
+        o.member("coalesce_hosts", json_strs(&self.coalesce_hosts));
+        o.member("coalesce_max_bytes", self.coalesce_max_bytes.to_string());
+        o.member("audit_hosts", json_strs(&self.audit_hosts));
+        o.member("audit_spool_path", opt_path(&self.audit_spool_path));
+        o.member("audit_max_body_bytes", self.audit_max_body_bytes.to_string());
+        o.member("audit_redacted_headers", json_strs(&self.audit_redacted_headers));
+        o.member("replay_hosts", json_strs(&self.replay_hosts));
+        o.member("replay_journal_path", opt_path(&self.replay_journal_path));
+        o.member("replay_journal_max_entries", self.replay_journal_max_entries.to_string());
+        o.member("replay_backoff", duration(&self.replay_backoff));
+        o.member("replay_max_backoff", duration(&self.replay_max_backoff));
+        o.member("public_connect_timeout", opt_duration(&self.public_connect_timeout));
+        o.member("private_connect_timeout", opt_duration(&self.private_connect_timeout));
+        o.member(
+            "outbound_max_concurrent_connects",
+            json_opt(self.outbound_max_concurrent_connects),
+        );
+        o.member("inbound_skip_ports", json_ports(&self.inbound_skip_ports));
+        o.member("outbound_skip_ports", json_ports(&self.outbound_skip_ports));
+        o.member(
+            "public_max_concurrent_streams",
+            json_opt(self.public_max_concurrent_streams),
+        );
+        o.member("public_idle_timeout", opt_duration(&self.public_idle_timeout));
+        o.member("private_idle_timeout", opt_duration(&self.private_idle_timeout));
+        o.member("tcp_nodelay", self.tcp_nodelay.to_string());
+        o.member("tcp_keepalive", opt_duration(&self.tcp_keepalive));
+        o.member("tcp_keepalive_interval", opt_duration(&self.tcp_keepalive_interval));
+        o.member("tcp_keepalive_probes", json_opt(self.tcp_keepalive_probes));
+        o.member("connect_recv_buffer_size", json_opt(self.connect_recv_buffer_size));
+        o.member("connect_send_buffer_size", json_opt(self.connect_send_buffer_size));
+        o.member("h2_initial_window_size", json_opt(self.h2_initial_window_size));
+        o.member("resolv_conf_path", path(&self.resolv_conf_path));
+        o.member("crash_report_path", opt_path(&self.crash_report_path));
+        o.member("idle_trim_period", opt_duration(&self.idle_trim_period));
+        o.member("profile", json_opt(self.profile.map(|p| json_str(&p.to_string()))));
+        o.member("log_filter", json_opt(self.log_filter.as_ref().map(|f| json_str(f))));
+        o.member("endpoint_cache_path", opt_path(&self.endpoint_cache_path));
+        o.member("ports_file_path", opt_path(&self.ports_file_path));
+        o.member("dns_intercept_listener", opt_listener(&self.dns_intercept_listener));
+        o.member(
+            "udp_forwards",
+            json_list(self.udp_forwards.iter().map(|f| {
+                let mut o = JsonObject::default();
+                o.member("listen", addr(&f.listen));
+                o.member("target", addr(&f.target));
+                o.finish()
+            })),
+        );
+        o.member("control_url", json_str(&format!("tcp://{}", self.control_host_and_port)));
+        o.member("control_token_path", opt_path(&self.control_token_path));
+        o.member("event_buffer_capacity", self.event_buffer_capacity.to_string());
+        o.member("metrics_flush_interval", duration(&self.metrics_flush_interval));
+        o.member("max_report_bytes", self.max_report_bytes.to_string());
+        o.member("slow_request_threshold", opt_duration(&self.slow_request_threshold));
+        o.member("statsd_addr", json_opt(self.statsd_addr.as_ref().map(&addr)));
+        o.member("telemetry_disabled", self.telemetry_disabled.to_string());
+        o.member(
+            "telemetry_headers",
+            json_list(self.telemetry_headers.iter().map(|h| json_str(h.as_str()))),
+        );
+        o.member(
+            "tap_request_progress_interval",
+            opt_duration(&self.tap_request_progress_interval),
+        );
+        o.member("health_check_paths", json_strs(&self.health_check_paths));
+        o.member("health_check_user_agents", json_strs(&self.health_check_user_agents));
+        o.member(
+            "health_check_sources",
+            json_list(self.health_check_sources.iter().map(|n| json_str(&n.to_string()))),
+        );
+        o.member(
+            "synthetic_token",
+            json_opt(self.synthetic_token.as_ref().map(|_| json_str(REDACTED))),
+        );
+        o.member("admin_read_only", self.admin_read_only.to_string());
+        o.member("debug_headers", self.debug_headers.to_string());
+        o
+    }
+}
+
+/// Finds the listeners that would bind the same address, and the listeners whose
+/// connections would be forwarded to one of the proxy's own listeners.
+///
+/// Each listener is given with whether it's dual-stack, as an unspecified IPv6 address
+/// only overlaps IPv4 addresses when it is.
+fn conflicts(
+    listeners: &[(String, SocketAddr, bool)],
+    forwards: &[(String, SocketAddr)],
+) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+
+    for (i, &(ref first, a, dual_a)) in listeners.iter().enumerate() {
+        for &(ref second, b, dual_b) in &listeners[i + 1..] {
+            let (ip_a, ip_b) = (unmapped(a.ip()), unmapped(b.ip()));
+            if a.port() != 0 && a.port() == b.port()
+                && (ip_a == ip_b || covers(ip_a, dual_a, ip_b) || covers(ip_b, dual_b, ip_a))
+            {
+                conflicts.push(Conflict::Listeners {
+                    first: first.clone(),
+                    second: second.clone(),
+                    addr: b,
+                });
+            }
+        }
+    }
+
+    for &(ref listener, target) in forwards {
+        for &(ref to, addr, dual_stack) in listeners {
+            let (ip, target_ip) = (unmapped(addr.ip()), unmapped(target.ip()));
+            let local = target_ip.is_loopback() || target_ip.is_unspecified();
+            if addr.port() != 0 && addr.port() == target.port()
+                && (ip == target_ip || (local && covers(ip, dual_stack, target_ip)))
+            {
+                conflicts.push(Conflict::ForwardToListener {
+                    listener: listener.clone(),
+                    to: to.clone(),
+                    addr: target,
+                });
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// Returns true if a socket bound to the unspecified address `ip` also accepts connections
+/// to `other`, i.e. if `other` is of the same family or `ip` is a dual-stack IPv6 address.
+fn covers(ip: IpAddr, dual_stack: bool, other: IpAddr) -> bool {
+    if !ip.is_unspecified() {
+        return false;
+    }
+    match (ip, other) {
+        (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => true,
+        (IpAddr::V6(_), IpAddr::V4(_)) => dual_stack,
+        (IpAddr::V4(_), IpAddr::V6(_)) => false,
+    }
+}
+
+/// Converts an IPv4-mapped IPv6 address, e.g. `::ffff:127.0.0.1`, to the IPv4 address
+/// that a socket bound to it actually uses.
+fn unmapped(ip: IpAddr) -> IpAddr {
+    if let IpAddr::V6(v6) = ip {
+        let s = v6.segments();
+        if s[..5].iter().all(|&x| x == 0) && s[5] == 0xffff {
+            let (hi, lo) = (s[6], s[7]);
+            let v4 = Ipv4Addr::new((hi >> 8) as u8, hi as u8, (lo >> 8) as u8, lo as u8);
+            return IpAddr::V4(v4);
+        }
+    }
+    ip
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Sets where to listen for connections that are initiated on the host.
+    pub fn private_listener(mut self, private_listener: Listener) -> Self {
+        self.config.private_listener = private_listener;
+        self
+    }
+
+    /// Sets where to listen for connections initiated by external sources.
+    pub fn public_listener(mut self, public_listener: Listener) -> Self {
+        self.config.public_listener = public_listener;
+        self
+    }
+
+    /// Sets where to listen for connections initiated by the control plane.
+    pub fn control_listener(mut self, control_listener: Listener) -> Self {
+        self.config.control_listener = control_listener;
+        self
+    }
+
+    /// Sets the credentials, formatted as `<user>:<password>`, that requests to the
+    /// control listener must present, if any.
+    pub fn control_listener_credentials(mut self, credentials: Option<String>) -> Self {
+        self.config.control_listener_credentials = credentials;
+        self
+    }
+
+    /// Sets where to accept SOCKS5 connections, if at all.
+    pub fn socks_listener(mut self, socks_listener: Option<Listener>) -> Self {
+        self.config.socks_listener = socks_listener;
+        self
+    }
+
+    /// Sets where to forward externally received connections.
+    pub fn private_forward(mut self, private_forward: Option<Addr>) -> Self {
+        self.config.private_forward = private_forward.map(Forward::Addr);
+        self
+    }
+
+    /// Sets where to talk to the control plane.
+    pub fn control_host_and_port(mut self, control_host_and_port: HostAndPort) -> Self {
+        self.config.control_host_and_port = control_host_and_port;
+        self
+    }
+
+    /// Sets what to do with outbound requests for destinations that have no endpoints.
+    pub fn no_endpoints(mut self, no_endpoints: NoEndpoints, timeout: Duration) -> Self {
+        self.config.no_endpoints = no_endpoints;
+        self.config.no_endpoints_timeout = timeout;
+        self
+    }
+
+    /// Sets whether outbound requests fall back to their original destinations until
+    /// the proxy has first connected to the controller.
+    pub fn no_endpoints_startup_fallback(mut self, fallback: bool) -> Self {
+        self.config.no_endpoints_startup_fallback = fallback;
+        self
+    }
+
+    /// Sets how long to wait for connections to the public and private peers.
+    pub fn connect_timeouts(
+        mut self,
+        public: Option<Duration>,
+        private: Option<Duration>,
+    ) -> Self {
+        self.config.public_connect_timeout = public;
+        self.config.private_connect_timeout = private;
+        self
+    }
+
+    /// Sets how long connections accepted on the public and private listeners may be
+    /// idle.
+    pub fn idle_timeouts(mut self, public: Option<Duration>, private: Option<Duration>) -> Self {
+        self.config.public_idle_timeout = public;
+        self.config.private_idle_timeout = private;
+        self
+    }
+
+    /// Sets the path to "/etc/resolv.conf".
+    pub fn resolv_conf_path(mut self, resolv_conf_path: PathBuf) -> Self {
+        self.config.resolv_conf_path = resolv_conf_path;
+        self
+    }
+
+    /// Sets where to write the addresses to which the listeners are bound, if anywhere.
+    pub fn ports_file_path(mut self, ports_file_path: Option<PathBuf>) -> Self {
+        self.config.ports_file_path = ports_file_path;
+        self
+    }
+
+    /// Sets the event queue capacity.
+    pub fn event_buffer_capacity(mut self, event_buffer_capacity: usize) -> Self {
+        self.config.event_buffer_capacity = event_buffer_capacity;
+        self
+    }
+
+    /// Sets the interval after which to flush metrics.
+    pub fn metrics_flush_interval(mut self, metrics_flush_interval: Duration) -> Self {
+        self.config.metrics_flush_interval = metrics_flush_interval;
+        self
+    }
+
+    /// Sets whether telemetry is disabled.
+    pub fn telemetry_disabled(mut self, telemetry_disabled: bool) -> Self {
+        self.config.telemetry_disabled = telemetry_disabled;
+        self
+    }
+
+    /// Checks the settings for conflicts, as `Config::load_from_env` does.
+    pub fn build(self) -> Result<Config, Error> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+// ===== impl NoEndpoints =====
+
+impl FromStr for NoEndpoints {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(NoEndpoints::Fail),
+            "fallback" => Ok(NoEndpoints::Fallback),
+            "queue" => Ok(NoEndpoints::Queue),
+            _ => Err(ParseError::NotANoEndpointsBehavior),
+        }
+    }
+}
+
+impl fmt::Display for NoEndpoints {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            NoEndpoints::Fail => "fail",
+            NoEndpoints::Fallback => "fallback",
+            NoEndpoints::Queue => "queue",
+        })
+    }
+}
+
+// ===== impl Normalization =====
+
+impl FromStr for Normalization {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Normalization::Off),
+            "lenient" => Ok(Normalization::Lenient),
+            "strict" => Ok(Normalization::Strict),
+            _ => Err(ParseError::NotANormalization),
+        }
+    }
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Normalization::Off => "off",
+            Normalization::Lenient => "lenient",
+            Normalization::Strict => "strict",
+        })
+    }
+}
+
+// ===== impl Profile =====
+
+impl Profile {
+    /// Returns the profile's default for the setting `name`, if it has one.
+    fn default_value(&self, name: &str) -> Option<&'static str> {
+        match (*self, name) {
+            (Profile::Dev, ENV_LOG) => Some("info,conduit_proxy=debug"),
+            (Profile::Dev, ENV_NO_ENDPOINTS) => Some("fallback"),
+            (Profile::Dev, ENV_PRIVATE_CONNECT_TIMEOUT) => Some("1s"),
+            (Profile::Dev, ENV_PUBLIC_CONNECT_TIMEOUT) => Some("10s"),
+            (Profile::Dev, ENV_DEBUG_HEADERS) => Some("true"),
+
+            (Profile::Test, ENV_NO_ENDPOINTS) => Some("fail"),
+
+            (Profile::Prod, ENV_LOG) => Some("warn,conduit_proxy=info"),
+            (Profile::Prod, ENV_INBOUND_NORMALIZATION) => Some("strict"),
+            (Profile::Prod, ENV_ADMIN_READ_ONLY) => Some("true"),
+
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dev" => Ok(Profile::Dev),
+            "test" => Ok(Profile::Test),
+            "prod" => Ok(Profile::Prod),
+            _ => Err(ParseError::NotAProfile),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Profile::Dev => "dev",
+            Profile::Test => "test",
+            Profile::Prod => "prod",
+        })
+    }
+}
+
+// ===== impl Listener =====
+
+impl Listener {
+    pub fn new(addr: Addr) -> Self {
+        Listener {
+            addr,
+            accept_timeout: None,
+            connect_timeout: None,
+            reuse_port: false,
+            dual_stack: true,
+            backlog: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            proxy_protocol: false,
+        }
+    }
+
+    pub fn with_accept_timeout(self, accept_timeout: Option<Duration>) -> Self {
+        Listener {
+            accept_timeout,
+            ..self
+        }
+    }
+
+    pub fn with_connect_timeout(self, connect_timeout: Option<Duration>) -> Self {
+        Listener {
+            connect_timeout,
+            ..self
+        }
+    }
+
+    pub fn with_reuse_port(self, reuse_port: bool) -> Self {
+        Listener {
+            reuse_port,
+            ..self
+        }
+    }
+
+    pub fn addr(&self) -> Addr {
+        self.addr
+    }
+
+    pub fn accept_timeout(&self) -> Option<Duration> {
+        self.accept_timeout
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+
+    pub fn reuse_port(&self) -> bool {
+        self.reuse_port
+    }
+
+    pub fn dual_stack(&self) -> bool {
+        self.dual_stack
+    }
+
+    pub fn backlog(&self) -> Option<usize> {
+        self.backlog
+    }
+
+    pub fn recv_buffer_size(&self) -> Option<usize> {
+        self.recv_buffer_size
+    }
+
+    pub fn send_buffer_size(&self) -> Option<usize> {
+        self.send_buffer_size
+    }
+
+    pub fn proxy_protocol(&self) -> bool {
+        self.proxy_protocol
+    }
+
+    /// Reads the listener's timeouts and socket options from the variables named after
+    /// it, e.g. `CONDUIT_PROXY_PUBLIC_LISTENER_ACCEPT_TIMEOUT`.
+    fn load_settings(self, env: &mut Env, prefix: &str) -> Self {
+        let accept = format!("{}{}", prefix, ACCEPT_TIMEOUT_SUFFIX);
+        let connect = format!("{}{}", prefix, CONNECT_TIMEOUT_SUFFIX);
+        let reuse_port = format!("{}{}", prefix, REUSE_PORT_SUFFIX);
+        let dual_stack = format!("{}{}", prefix, DUAL_STACK_SUFFIX);
+        let backlog = format!("{}{}", prefix, BACKLOG_SUFFIX);
+        let recv_buffer_size = format!("{}{}", prefix, RECV_BUFFER_SIZE_SUFFIX);
+        let send_buffer_size = format!("{}{}", prefix, SEND_BUFFER_SIZE_SUFFIX);
+        let proxy_protocol = format!("{}{}", prefix, PROXY_PROTOCOL_SUFFIX);
+        Listener {
+            accept_timeout: env.parse(&accept, parse_millis),
+            connect_timeout: env.parse(&connect, parse_millis),
+            reuse_port: env.parse(&reuse_port, parse_bool).unwrap_or(false),
+            dual_stack: env.parse(&dual_stack, parse_bool).unwrap_or(true),
+            backlog: env.parse(&backlog, parse_positive),
+            recv_buffer_size: env.parse(&recv_buffer_size, parse_positive),
+            send_buffer_size: env.parse(&send_buffer_size, parse_positive),
+            proxy_protocol: env.parse(&proxy_protocol, parse_bool).unwrap_or(false),
+            ..self
+        }
+    }
+}
+
+/// Returns the prefix of the names of the variables that configure an additional public
+/// listener, e.g. `CONDUIT_PROXY_PUBLIC_LISTENER_ADMIN`.
+fn public_listener_prefix(name: &str) -> String {
+    format!("{}_{}", ENV_PUBLIC_LISTENER, name.to_uppercase().replace('-', "_"))
+}
+
+// ===== impl Addr =====
+
+impl FromStr for Addr {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("unix:") || s.starts_with("unix-abstract:") {
+            return Err(ParseError::UnixSocketsUnsupported);
+        }
+
+        // A URL without a host, e.g. `tcp://:4143` or just `:4143`, names every IPv4
+        // interface. `tcp://:password@host:port` has a host, and is rejected below.
+        let hostless = if s.contains('@') {
+            None
+        } else if s.starts_with("tcp://:") {
+            Some(&s["tcp://:".len()..])
+        } else if s.starts_with(':') {
+            Some(&s[1..])
+        } else {
+            None
+        };
+        if let Some(port) = hostless {
+            let port = port.parse::<u16>()
+                .map_err(|_| ParseError::UrlError(UrlError::InvalidPort))?;
+            return Ok(Addr(SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), port)));
+        }
+
+        match parse_url(s)? {
+            HostAndPort {
+                host: Host::Ipv4(ip),
+                port,
+            } => Ok(Addr(SocketAddr::new(ip.into(), port))),
+            HostAndPort {
+                host: Host::Ipv6(ip),
+                port,
+            } => Ok(Addr(SocketAddr::new(ip.into(), port))),
+            HostAndPort {
+                host: Host::Domain(_),
+                ..
+            } => Err(ParseError::HostIsNotAnIpAddress),
+        }
+    }
+}
+
+impl From<Addr> for SocketAddr {
+    fn from(addr: Addr) -> SocketAddr {
+        addr.0
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tcp://{}", self.0)
+    }
+}
+
+// ===== impl Forward =====
+
+impl FromStr for Forward {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(addr) => Ok(Forward::Addr(addr)),
+            Err(ParseError::HostIsNotAnIpAddress) => parse_url(s).map(Forward::Host),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl fmt::Display for Forward {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Forward::Addr(ref addr) => fmt::Display::fmt(addr, f),
+            Forward::Host(ref host_and_port) => write!(f, "tcp://{}", host_and_port),
+        }
+    }
+}
+
+// ===== impl Network =====
+
+impl Network {
+    /// Returns whether `ip` is in the network. IPv4-mapped IPv6 addresses, as reported for
+    /// IPv4 peers of dual-stack sockets, are treated as the IPv4 addresses they map.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (*self, unmapped(*ip)) {
+            (Network::V4(ref net), IpAddr::V4(ref ip)) => net.contains(ip),
+            (Network::V6(ref net), IpAddr::V6(ref ip)) => net.contains(ip),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Network::V4(ref net) => fmt::Display::fmt(net, f),
+            Network::V6(ref net) => fmt::Display::fmt(net, f),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(net) = s.parse() {
+            return Ok(Network::V4(net));
+        }
+        s.parse()
+            .map(Network::V6)
+            .map_err(|_| ParseError::NotANetwork)
+    }
+}
+
+// ===== impl Error =====
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidEnvVars(ref vars) => {
+                writeln!(f, "invalid settings:")?;
+                for var in vars {
+                    writeln!(f, "  {}", var)?;
+                }
+            }
+            Error::Invalid {
+                ref conflicts,
+                ref zero_timeouts,
+            } => {
+                if !conflicts.is_empty() {
+                    writeln!(f, "conflicting settings:")?;
+                    for conflict in conflicts {
+                        writeln!(f, "  {}", conflict)?;
+                    }
+                }
+                if !zero_timeouts.is_empty() {
+                    let names = zero_timeouts.join(", ");
+                    writeln!(f, "timeouts must be greater than zero: {}", names)?;
+                }
+            }
+            Error::InvalidArgs(ref args) => {
+                writeln!(f, "invalid arguments: {}", args.join(" "))?;
+            }
+            Error::InvalidFile {
+                ref path,
+                ref error,
+            } => {
+                writeln!(f, "invalid config file {}: {}", path.display(), error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FileError::Unreadable(ref e) => f.write_str(e),
+            FileError::Syntax(line) => {
+                write!(f, "line {}: expected a `key = value` pair", line)
+            }
+            FileError::UnknownKeys(ref keys) => write!(f, "unknown keys: {}", keys.join(", ")),
+        }
+    }
+}
+
+impl fmt::Display for InvalidEnvVar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Some(ref value) => write!(f, "{}={:?}: {}", self.name, value, self.parse_error),
+            None => write!(f, "{}: {}", self.name, self.parse_error),
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Conflict::Listeners {
+                ref first,
+                ref second,
+                addr,
+            } => write!(f, "the {} and {} listeners both bind {}", first, second, addr),
+            Conflict::ForwardToListener {
+                ref listener,
+                ref to,
+                addr,
+            } => write!(
+                f,
+                "the {} listener forwards to {}, the {} listener",
+                listener, addr, to
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::NotANumber => f.write_str("expected a number"),
+            ParseError::NotAPositiveNumber => f.write_str("expected a number greater than zero"),
+            ParseError::NotABool => f.write_str("expected `true` or `false`"),
+            ParseError::NotADuration => f.write_str("expected a duration, e.g. `500ms` or `1m30s`"),
+            ParseError::NotANoEndpointsBehavior => {
+                f.write_str("expected `fail`, `fallback`, or `queue`")
+            }
+            ParseError::NotANormalization => f.write_str("expected `off`, `lenient`, or `strict`"),
+            ParseError::NotAProfile => f.write_str("expected `dev`, `test`, or `prod`"),
+            ParseError::NotAHeaderName => f.write_str("expected comma-separated header names"),
+            ParseError::NotAHeader => {
+                f.write_str("expected `|`-separated `<name>: <value>` headers")
+            }
+            ParseError::NotAHostPage => {
+                f.write_str("expected comma-separated `<host>=<path>` pages")
+            }
+            ParseError::NotAMethodRule => {
+                f.write_str("expected comma-separated `<host>[<path>]=<method>|...` rules")
+            }
+            ParseError::NotAContentTypeRule => {
+                f.write_str("expected comma-separated `<host>[<path>]=<media type>|...` rules")
+            }
+            ParseError::NotAPublicListener => f.write_str(
+                "expected comma-separated `<name>=tcp://<listen>=tcp://<forward>` listeners",
+            ),
+            ParseError::HostIsNotAnIpAddress => {
+                f.write_str("expected an IP address rather than a host name")
+            }
+            ParseError::UnixSocketsUnsupported => {
+                f.write_str("Unix domain sockets aren't supported; expected `tcp://<ip>:<port>`")
+            }
+            ParseError::NotANetwork => {
+                f.write_str("expected comma-separated networks, e.g. `10.0.0.0/8`")
+            }
+            ParseError::NotAUserAndPassword => f.write_str("expected `<user>:<password>`"),
+            ParseError::NotUnicode => f.write_str("expected valid Unicode"),
+            ParseError::NotAReadableFile(ref e) => write!(f, "could not read the file: {}", e),
+            ParseError::UrlError(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            UrlError::SyntaxError => "expected a URL, e.g. `tcp://127.0.0.1:4140`",
+            UrlError::UnsupportedScheme => "the URL's scheme is not supported",
+            UrlError::MissingHost => "the URL has no host",
+            UrlError::InvalidHost => "the URL's host is invalid",
+            UrlError::MissingPort => "the URL has no port",
+            UrlError::InvalidPort => "the URL's port is not a number from 0 to 65535",
+            UrlError::UserInfoNotAllowed => "the URL may not have a username or password",
+            UrlError::QueryNotAllowed => "the URL may not have a query",
+            UrlError::PathNotAllowed => "the URL may not have a path",
+            UrlError::FragmentNotAllowed => "the URL may not have a fragment",
+        })
+    }
+}
+
+/// Parses a `udp://` URL naming a socket address.
+fn parse_udp_addr(s: &str) -> Result<Addr, ParseError> {
+    match parse_url_with_scheme(s, "udp")? {
+        HostAndPort {
+            host: Host::Ipv4(ip),
+            port,
+        } => Ok(Addr(SocketAddr::new(ip.into(), port))),
+        HostAndPort {
+            host: Host::Ipv6(ip),
+            port,
+        } => Ok(Addr(SocketAddr::new(ip.into(), port))),
+        HostAndPort {
+            host: Host::Domain(_),
+            ..
+        } => Err(ParseError::HostIsNotAnIpAddress),
+    }
+}
+
+/// Parses a comma-separated list of `udp://<listen>=udp://<target>` pairs.
+fn parse_udp_forwards(s: &str) -> Result<Vec<UdpForward>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let listen = parse_udp_addr(parts.next().unwrap_or(""))?;
+            let target = parts
+                .next()
+                .ok_or(ParseError::UrlError(UrlError::SyntaxError))
+                .and_then(parse_udp_addr)?;
+            Ok(UdpForward {
+                listen,
+                target,
+            })
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `<name>=tcp://<listen>=tcp://<forward>` listeners,
+/// e.g. `admin=tcp://0.0.0.0:4144=tcp://127.0.0.1:9000`.
+///
+/// Names may only contain lowercase letters, digits, and `-`.
+fn parse_public_listeners(s: &str) -> Result<Vec<PublicListener>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            let mut parts = l.splitn(3, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let valid_name = !name.is_empty() && name.chars().all(|c| match c {
+                'a'...'z' | '0'...'9' | '-' => true,
+                _ => false,
+            });
+            if !valid_name {
+                return Err(ParseError::NotAPublicListener);
+            }
+            let listen = parts.next().ok_or(ParseError::NotAPublicListener)?.parse()?;
+            let forward = parts.next().ok_or(ParseError::NotAPublicListener)?.parse()?;
+            Ok(PublicListener {
+                name: name.to_owned(),
+                listener: Listener::new(listen),
+                forward,
+                allowed_methods: Vec::new(),
+                allowed_content_types: Vec::new(),
+                max_concurrent_streams: None,
+            })
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `<cluster>=tcp://<gateway>` pairs.
+fn parse_cluster_gateways(s: &str) -> Result<Vec<ClusterGateway>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let cluster = parts.next().unwrap_or("").trim();
+            if cluster.is_empty() {
+                return Err(ParseError::UrlError(UrlError::SyntaxError));
+            }
+            let addr = parts
+                .next()
+                .ok_or(ParseError::UrlError(UrlError::SyntaxError))
+                .and_then(str::parse)?;
+            Ok(ClusterGateway {
+                cluster: cluster.to_owned(),
+                addr,
+            })
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `<host>=<status>|<status>...` rules, e.g.
+/// `*.example.com=502|504`.
+fn parse_error_translations(s: &str) -> Result<Vec<ErrorTranslation>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let mut parts = rule.splitn(2, '=');
+            let host = parts.next().unwrap_or("").trim();
+            let statuses = parts.next().ok_or(ParseError::NotANumber)?;
+            let statuses = statuses
+                .split('|')
+                .map(|s| {
+                    let code = parse_number::<u16>(s.trim())?;
+                    http::StatusCode::from_u16(code).map_err(|_| ParseError::NotANumber)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ErrorTranslation {
+                host: host.to_owned(),
+                statuses,
+            })
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `<host>=<path>` pairs.
+fn parse_host_pages(s: &str) -> Result<Vec<HostPage>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let host = parts.next().unwrap_or("").trim();
+            match parts.next().map(str::trim) {
+                Some(path) if !host.is_empty() && !path.is_empty() => Ok(HostPage {
+                    host: host.to_owned(),
+                    path: PathBuf::from(path),
+                }),
+                _ => Err(ParseError::NotAHostPage),
+            }
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `<host>=<origin>|<origin>...` rules, e.g.
+/// `*.example.com=https://app.example.com|https://admin.example.com`.
+fn parse_cors_rules(s: &str) -> Result<Vec<CorsRule>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let mut parts = rule.splitn(2, '=');
+            let host = parts.next().unwrap_or("").trim();
+            let origins = parts.next().ok_or(ParseError::UrlError(UrlError::SyntaxError))?;
+            Ok(CorsRule {
+                host: host.to_owned(),
+                origins: origins
+                    .split('|')
+                    .map(str::trim)
+                    .filter(|o| !o.is_empty())
+                    .map(String::from)
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `<host>[<path>]=<method>|<method>...` rules, e.g.
+/// `web.example.com/admin=GET,*.example.com=GET|POST`.
+fn parse_method_rules(s: &str) -> Result<Vec<MethodRule>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let mut parts = rule.splitn(2, '=');
+            let route = parts.next().unwrap_or("").trim();
+            let methods = parts.next().ok_or(ParseError::NotAMethodRule)?;
+            let (host, path) = parse_route(route).ok_or(ParseError::NotAMethodRule)?;
+            let methods = methods
+                .split('|')
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(|m| http::Method::from_bytes(m.as_bytes()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| ParseError::NotAMethodRule)?;
+            Ok(MethodRule {
+                host,
+                path,
+                methods,
+            })
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `<host>[<path>]=<media type>|<media type>...` rules,
+/// e.g. `api.example.com/v1=application/json|application/grpc`.
+fn parse_content_type_rules(s: &str) -> Result<Vec<ContentTypeRule>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let mut parts = rule.splitn(2, '=');
+            let route = parts.next().unwrap_or("").trim();
+            let media_types = parts.next().ok_or(ParseError::NotAContentTypeRule)?;
+            let (host, path) = parse_route(route).ok_or(ParseError::NotAContentTypeRule)?;
+            Ok(ContentTypeRule {
+                host,
+                path,
+                media_types: media_types
+                    .split('|')
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(String::from)
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Splits a route, e.g. `web.example.com/admin`, into its host and optional path prefix.
+fn parse_route(route: &str) -> Option<(String, Option<String>)> {
+    let (host, path) = match route.find('/') {
+        Some(i) => (&route[..i], Some(route[i..].to_owned())),
+        None => (route, None),
+    };
+    if host.is_empty() {
+        None
+    } else {
+        Some((host.to_owned(), path))
+    }
+}
+
+/// Parses a `|`-separated list of `<name>: <value>` headers, e.g.
+/// `strict-transport-security: max-age=31536000; includeSubDomains|x-frame-options: DENY`.
+///
+/// Header values commonly contain commas, so they can't be used as the separator.
+fn parse_response_headers(s: &str) -> Result<Vec<ResponseHeader>, ParseError> {
+    s.split('|')
+        .map(str::trim)
+        .filter(|header| !header.is_empty())
+        .map(|header| {
+            let mut parts = header.splitn(2, ':');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts.next().ok_or(ParseError::NotAHeader)?.trim();
+            Ok(ResponseHeader {
+                name: name.parse().map_err(|_| ParseError::NotAHeader)?,
+                value: value.parse().map_err(|_| ParseError::NotAHeader)?,
+            })
+        })
+        .collect()
+}
+
+fn parse_bool(s: &str) -> Result<bool, ParseError> {
+    match s {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(ParseError::NotABool),
+    }
+}
+
+/// Parses a comma-separated list of networks, e.g. `10.0.0.0/8,fd00::/8`.
+fn parse_networks(s: &str) -> Result<Vec<Network>, ParseError> {
+    parse_list(s)?.iter().map(|net| net.parse()).collect()
+}
+
+/// Parses a comma-separated list of header names.
+fn parse_header_names(s: &str) -> Result<Vec<http::header::HeaderName>, ParseError> {
+    parse_list(s)?
+        .iter()
+        .map(|name| name.parse().map_err(|_| ParseError::NotAHeaderName))
+        .collect()
+}
+
+/// Parses a comma-separated list, ignoring empty items.
+fn parse_list(s: &str) -> Result<Vec<String>, ParseError> {
+    Ok(s.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+fn parse_number<T>(s: &str) -> Result<T, ParseError> where T: FromStr {
+    s.parse().map_err(|_| ParseError::NotANumber)
+}
+
+fn parse_positive(s: &str) -> Result<usize, ParseError> {
+    match parse_number(s)? {
+        0 => Err(ParseError::NotAPositiveNumber),
+        n => Ok(n),
+    }
+}
+
+fn parse_millis(s: &str) -> Result<Duration, ParseError> {
+    parse_duration(s, Duration::from_millis)
+}
+
+fn parse_secs(s: &str) -> Result<Duration, ParseError> {
+    parse_duration(s, Duration::from_secs)
+}
+
+/// Parses a duration made of `<number><unit>` parts, e.g. `500ms` or `1m30s`, where each
+/// unit is `ms`, `s`, `m`, or `h`.
+///
+/// A bare number is in `default_unit`, so that values set before units were accepted
+/// keep their meaning.
+fn parse_duration(s: &str, default_unit: fn(u64) -> Duration) -> Result<Duration, ParseError> {
+    if let Ok(n) = s.parse() {
+        return Ok(default_unit(n));
+    }
+    if s.is_empty() {
+        return Err(ParseError::NotADuration);
+    }
+
+    let mut total = Duration::from_secs(0);
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_digit(10)).unwrap_or_else(|| rest.len());
+        let n = rest[..digits].parse::<u64>().map_err(|_| ParseError::NotADuration)?;
+        rest = &rest[digits..];
+
+        let unit = rest.find(|c: char| c.is_digit(10)).unwrap_or_else(|| rest.len());
+        let part = match &rest[..unit] {
+            "ms" => Some(Duration::from_millis(n)),
+            "s" => Some(Duration::from_secs(n)),
+            "m" => n.checked_mul(60).map(Duration::from_secs),
+            "h" => n.checked_mul(60 * 60).map(Duration::from_secs),
+            _ => None,
+        };
+        total = part.and_then(|p| total.checked_add(p)).ok_or(ParseError::NotADuration)?;
+        rest = &rest[unit..];
+    }
+    Ok(total)
+}
+
+/// Parses a default address, which the `defaults_are_valid` test checks.
+fn default_addr(s: &str) -> Addr {
+    s.parse().expect("default address must be valid")
+}
+
+fn parse_port(s: &str) -> Result<u16, ParseError> {
+    match parse_number(s)? {
+        0 => Err(ParseError::NotAPositiveNumber),
+        n => Ok(n),
+    }
+}
+
+/// Parses a comma-separated list of ports, e.g. `3306,5432`.
+fn parse_ports(s: &str) -> Result<HashSet<u16>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(parse_port)
+        .collect()
+}
+
+fn parse_url(s: &str) -> Result<HostAndPort, ParseError> {
+    parse_url_with_scheme(s, "tcp")
+}
+
+fn parse_url_with_scheme(s: &str, scheme: &str) -> Result<HostAndPort, ParseError> {
+    parse_url_with_default_port(s, scheme, None)
+}
+
+/// Parses a URL, which must name a port unless `default_port` is given.
+fn parse_url_with_default_port(
+    s: &str,
+    scheme: &str,
+    default_port: Option<u16>,
+) -> Result<HostAndPort, ParseError> {
+    let url = Url::parse(&s).map_err(|e| {
+        ParseError::UrlError(match e {
+            url::ParseError::EmptyHost => UrlError::MissingHost,
+            url::ParseError::InvalidPort => UrlError::InvalidPort,
+            url::ParseError::IdnaError |
+            url::ParseError::InvalidIpv4Address |
+            url::ParseError::InvalidIpv6Address |
+            url::ParseError::InvalidDomainCharacter => UrlError::InvalidHost,
+            _ => UrlError::SyntaxError,
+        })
+    })?;
+    if url.scheme() != scheme {
+        return Err(ParseError::UrlError(UrlError::UnsupportedScheme));
+    }
+    let host = url.host()
+        .ok_or_else(|| ParseError::UrlError(UrlError::MissingHost))?
+        .to_owned();
+    let port = url.port()
+        .or(default_port)
+        .ok_or_else(|| ParseError::UrlError(UrlError::MissingPort))?;
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(ParseError::UrlError(UrlError::UserInfoNotAllowed));
+    }
+    if !url.path().is_empty() && url.path() != "/" {
+        return Err(ParseError::UrlError(UrlError::PathNotAllowed));
+    }
+    if url.query().is_some() {
+        return Err(ParseError::UrlError(UrlError::QueryNotAllowed));
+    }
+    if url.fragment().is_some() {
+        return Err(ParseError::UrlError(UrlError::FragmentNotAllowed));
+    }
+    Ok(HostAndPort {
+        host,
+        port,
+    })
+}
+
+fn read_file(path: &Path) -> Result<HashMap<String, String>, Error> {
+    let invalid = |error| Error::InvalidFile {
+        path: path.to_owned(),
+        error,
+    };
+
+    let mut contents = String::new();
+    fs::File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| invalid(FileError::Unreadable(e.to_string())))?;
+    parse_file(&contents).map_err(|line| invalid(FileError::Syntax(line)))
+}
+
+/// Parses `--<key>=<value>` and `--<key> <value>` arguments, keyed by ENV variable name,
+/// along with the argument that set each.
+///
+/// On failure, returns the first invalid argument.
+fn parse_args<I>(args: I) -> Result<HashMap<String, (String, String)>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut settings = HashMap::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            return Err(arg);
+        }
+        let (flag, value) = match arg.find('=') {
+            Some(i) => (arg[..i].to_owned(), arg[i + 1..].to_owned()),
+            None => match args.next() {
+                Some(value) => (arg.clone(), value),
+                None => return Err(arg),
+            },
+        };
+        let name = match parse_key(&flag[2..]) {
+            Some(name) => name,
+            None => return Err(arg),
+        };
+        if settings.insert(name, (flag, value)).is_some() {
+            return Err(arg);
+        }
+    }
+    Ok(settings)
+}
+
+/// Parses the `key = value` pairs of a config file, keyed by ENV variable name.
+///
+/// On failure, returns the number of the first invalid line.
+fn parse_file(s: &str) -> Result<HashMap<String, String>, usize> {
+    let mut settings = HashMap::new();
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().and_then(|k| parse_key(k.trim()));
+        let value = parts.next().and_then(|v| parse_file_value(v.trim()));
+        match (key, value) {
+            (Some(key), Some(value)) => {
+                if settings.insert(key, value).is_some() {
+                    return Err(i + 1);
+                }
+            }
+            _ => return Err(i + 1),
+        }
+    }
+    Ok(settings)
+}
+
+/// Maps a config file key or argument name, e.g. `private-listener`, to the name of
+/// its ENV variable.
+fn parse_key(key: &str) -> Option<String> {
+    let valid = !key.is_empty() && key.chars().all(|c| match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '-' => true,
+        _ => false,
+    });
+    if !valid {
+        return None;
+    }
+
+    let key = key.to_uppercase().replace('-', "_");
+    if key.starts_with("CONDUIT_") {
+        return Some(key);
+    }
+    let unprefixed = format!("CONDUIT_{}", key);
+    if UNPREFIXED_ENV_VARS.contains(&unprefixed.as_str()) {
+        Some(unprefixed)
+    } else {
+        Some(format!("{}{}", ENV_PREFIX, key))
+    }
+}
+
+/// Parses a value, followed by an optional comment.
+fn parse_file_value(value: &str) -> Option<String> {
+    let (value, rest) = if value.starts_with('"') || value.starts_with('\'') {
+        match parse_file_string(value) {
+            Some(parsed) => parsed,
+            None => return None,
+        }
+    } else if value.starts_with('[') {
+        let mut items = Vec::new();
+        let mut rest = value[1..].trim_left();
+        if rest.starts_with(']') {
+            rest = &rest[1..];
+        } else {
+            loop {
+                let (item, after) = match parse_file_string(rest) {
+                    Some(parsed) => parsed,
+                    None => return None,
+                };
+                items.push(item);
+                let after = after.trim_left();
+                if after.starts_with(',') {
+                    rest = after[1..].trim_left();
+                    if rest.starts_with(']') {
+                        rest = &rest[1..];
+                        break;
+                    }
+                } else if after.starts_with(']') {
+                    rest = &after[1..];
+                    break;
+                } else {
+                    return None;
+                }
+            }
+        }
+        (items.join(","), rest)
+    } else {
+        // Numbers and booleans.
+        let end = value.find('#').unwrap_or_else(|| value.len());
+        let bare = value[..end].trim_right();
+        let valid = !bare.is_empty() && bare.chars().all(|c| match c {
+            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '.' | '+' | '-' => true,
+            _ => false,
+        });
+        if !valid {
+            return None;
+        }
+        (bare.to_owned(), &value[end..])
+    };
+
+    let rest = rest.trim_left();
+    if rest.is_empty() || rest.starts_with('#') {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Parses a basic (`"..."`) or literal (`'...'`) string, returning it and the text that
+/// follows it.
+fn parse_file_string(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.char_indices();
+    let quote = match chars.next() {
+        Some((_, q)) if q == '"' || q == '\'' => q,
+        _ => return None,
+    };
+
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        if c == quote {
+            return Some((value, &s[i + 1..]));
+        }
+        if c == '\\' && quote == '"' {
+            match chars.next() {
+                Some((_, '"')) => value.push('"'),
+                Some((_, '\\')) => value.push('\\'),
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                _ => return None,
+            }
+        } else {
+            value.push(c);
+        }
+    }
+    None
+}
+
+/// Returns the name of the process's environment variable that sets `name`.
+///
+/// Names start with `CONDUIT_PROXY_` unless `CONDUIT_PROXY_ENV_PREFIX` names another
+/// prefix, e.g. `ACME_MESH_` renames `CONDUIT_PROXY_PRIVATE_LISTENER` to
+/// `ACME_MESH_PRIVATE_LISTENER`.
+pub fn env_var_name(name: &str) -> String {
+    match env::var(ENV_ENV_PREFIX) {
+        Ok(prefix) => with_prefix(name, &prefix),
+        Err(_) => name.to_owned(),
+    }
+}
+
+fn with_prefix(name: &str, prefix: &str) -> String {
+    if name.starts_with(ENV_PREFIX) {
+        format!("{}{}", prefix, &name[ENV_PREFIX.len()..])
+    } else {
+        name.to_owned()
+    }
+}
+
+// ===== impl JsonObject =====
+
+impl JsonObject {
+    /// Adds a member, where `value` is already rendered as JSON.
+    fn member(&mut self, name: &str, value: String) {
+        self.0.push((name.to_owned(), value));
+    }
+
+    fn finish(self) -> String {
+        let members = self.0
+            .into_iter()
+            .map(|(name, value)| format!("{}:{}", json_str(&name), value))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", members.join(","))
+    }
+}
+
+/// Renders `null`, or a value that is already rendered as JSON.
+fn json_opt<T: fmt::Display>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "null".into())
+}
+
+/// Renders a set of ports as a sorted array of numbers.
+fn json_ports(ports: &HashSet<u16>) -> String {
+    let mut ports = ports.iter().collect::<Vec<_>>();
+    ports.sort();
+    json_list(ports.into_iter().map(|p| p.to_string()))
+}
+
+/// Renders an array of values that are already rendered as JSON.
+fn json_list<I: Iterator<Item = String>>(values: I) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(","))
+}
+
+fn json_strs(values: &[String]) -> String {
+    json_list(values.iter().map(|s| json_str(s)))
+}
+
+/// Renders settings that have no simpler form by their `Debug` representation.
+fn json_debug<T: fmt::Debug>(value: &T) -> String {
+    json_str(&format!("{:?}", value))
+}
+
+/// Formats a duration so that `parse_duration` reads it back.
+fn format_duration(d: Duration) -> String {
+    let millis = u64::from(d.subsec_nanos() / 1_000_000);
+    if millis == 0 {
+        format!("{}s", d.as_secs())
+    } else {
+        format!("{}ms", d.as_secs() * 1_000 + millis)
+    }
+}
+
+// ===== impl Env =====
+
+impl Env {
+    fn var(&mut self, name: &str) -> Option<String> {
+        // The file variable is always looked up, so that it's known to a config file.
+        let file_name = format!("{}{}", name, FILE_SUFFIX);
+        match (self.lookup(name), self.lookup(&file_name)) {
+            (Some(value), Some(_)) => {
+                warn!("{} is set, so {} is ignored", name, file_name);
+                return Some(value);
+            }
+            (Some(value), None) => return Some(value),
+            (None, Some(path)) => return self.read_value_file(name, &file_name, path),
+            (None, None) => {}
+        }
+
+        for &(old, new) in DEPRECATED_ENV_VARS {
+            if new == name {
+                if let Some(value) = self.lookup(old) {
+                    warn!("{} is deprecated; use {} instead", old, new);
+                    return Some(value);
+                }
+            }
+        }
+
+        self.profile.and_then(|p| p.default_value(name)).map(str::to_owned)
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<String> {
+        self.read.insert(name.to_owned());
+        if let Some(&(_, ref value)) = self.args.get(name) {
+            return Some(value.clone());
+        }
+        if self.isolated {
+            return self.file.get(name).cloned();
+        }
+        match env::var(env_var_name(name)) {
+            Ok(value) => Some(value),
+            Err(env::VarError::NotPresent) => self.file.get(name).cloned(),
+            Err(env::VarError::NotUnicode(_)) => {
+                self.errors.push(InvalidEnvVar {
+                    name: name.to_owned(),
+                    value: None,
+                    parse_error: ParseError::NotUnicode,
+                });
+                None
+            }
+        }
+    }
+
+    /// Reads the value of `name` from the file named by `file_name`.
+    fn read_value_file(&mut self, name: &str, file_name: &str, path: String) -> Option<String> {
+        let mut value = String::new();
+        let read = fs::File::open(&path).and_then(|mut f| f.read_to_string(&mut value));
+        if let Err(e) = read {
+            self.errors.push(InvalidEnvVar {
+                name: file_name.to_owned(),
+                value: Some(path),
+                parse_error: ParseError::NotAReadableFile(e.to_string()),
+            });
+            return None;
+        }
+        self.from_files.insert(name.to_owned(), PathBuf::from(path));
+        Some(value.trim().to_owned())
+    }
+
+    fn parse<T, Parse>(&mut self, name: &str, parse: Parse) -> Option<T>
+    where
+        Parse: FnOnce(&str) -> Result<T, ParseError>,
+    {
+        let value = match self.var(name) {
+            Some(value) => value,
+            None => return None,
+        };
+        match parse(&value) {
+            Ok(r) => Some(r),
+            Err(parse_error) => {
+                let secret = SECRET_ENV_VARS.contains(&name);
+                let value = if secret || self.from_files.contains_key(name) {
+                    None
+                } else {
+                    Some(value)
+                };
+                self.errors.push(InvalidEnvVar {
+                    name: name.to_owned(),
+                    value,
+                    parse_error,
+                });
+                None
+            }
+        }
+    }
+
+    /// Fails if the config file has keys that don't name a setting that's been read.
+    fn check_file_keys(&self, path: &Path) -> Result<(), Error> {
+        let mut unknown = self.file
+            .keys()
+            .filter(|k| !self.read.contains(k.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        unknown.sort();
+        Err(Error::InvalidFile {
+            path: path.to_owned(),
+            error: FileError::UnknownKeys(unknown),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{
+        conflicts, parse_args, parse_duration, parse_file, parse_public_listeners, parse_url,
+        with_prefix, Addr, Config, Conflict, Env, Error, Forward, Listener, Network, NoEndpoints,
+        Normalization, ParseError, Profile, Source, UrlError,
+    };
+
+    fn addrs(addrs: &[(&str, &str)]) -> Vec<(String, SocketAddr)> {
+        addrs
+            .iter()
+            .map(|&(name, addr)| (name.to_owned(), addr.parse().unwrap()))
+            .collect()
+    }
+
+    /// Listeners with the default, dual-stack, setting.
+    fn dual_stack(addrs: &[(&str, &str)]) -> Vec<(String, SocketAddr, bool)> {
+        addrs
+            .iter()
+            .map(|&(name, addr)| (name.to_owned(), addr.parse().unwrap(), true))
+            .collect()
+    }
+
+    /// A path in the temporary directory that no other test, or test run, uses.
+    fn temp_path(test: &str) -> PathBuf {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let name = format!(
+            "conduit-proxy-{}-{}.{:09}",
+            test,
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos()
+        );
+        env::temp_dir().join(name)
+    }
+
+    /// Loads a configuration from only `vars`, ignoring the process's environment.
+    fn load(vars: &[(&str, &str)]) -> Result<Config, Error> {
+        let mut env = Env {
+            isolated: true,
+            ..Env::default()
+        };
+        for &(k, v) in vars {
+            env.file.insert(k.into(), v.into());
+        }
+        Config::load(&mut env, Source::Builder)
+    }
+
+    #[test]
+    fn reports_all_invalid_env_vars() {
+        env::set_var("CONDUIT_PROXY_TEST_INVALID_NUMBER", "ten");
+        env::set_var("CONDUIT_PROXY_TEST_INVALID_BOOL", "yes");
+        env::set_var("CONDUIT_PROXY_TEST_VALID_NUMBER", "10");
+
+        let mut env = Env::default();
+        let n: Option<u64> = env.parse("CONDUIT_PROXY_TEST_INVALID_NUMBER", super::parse_number);
+        let b = env.parse("CONDUIT_PROXY_TEST_INVALID_BOOL", super::parse_bool);
+        let m: Option<u64> = env.parse("CONDUIT_PROXY_TEST_VALID_NUMBER", super::parse_number);
+        let u = env.parse("CONDUIT_PROXY_TEST_UNSET", super::parse_bool);
+        assert_eq!((n, b, m, u), (None, None, Some(10), None));
+
+        let names = env.errors.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["CONDUIT_PROXY_TEST_INVALID_NUMBER", "CONDUIT_PROXY_TEST_INVALID_BOOL"]
+        );
+        match env.errors[1].parse_error {
+            ParseError::NotABool => {}
+            ref e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn reports_all_conflicts() {
+        let listeners = dual_stack(&[
+            ("private", "127.0.0.1:4140"),
+            ("public", "0.0.0.0:4143"),
+            ("control", "127.0.0.1:4143"),
+        ]);
+        let forwards = addrs(&[("public", "127.0.0.1:4140")]);
+
+        assert_eq!(
+            conflicts(&listeners, &forwards),
+            vec![
+                Conflict::Listeners {
+                    first: "public".into(),
+                    second: "control".into(),
+                    addr: "127.0.0.1:4143".parse().unwrap(),
+                },
+                Conflict::ForwardToListener {
+                    listener: "public".into(),
+                    to: "private".into(),
+                    addr: "127.0.0.1:4140".parse().unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_conflicts_with_ipv4_mapped_addrs() {
+        let listeners = dual_stack(&[
+            ("private", "127.0.0.1:4140"),
+            ("public", "[::ffff:127.0.0.1]:4140"),
+            ("control", "[::ffff:0.0.0.0]:4190"),
+        ]);
+        let forwards = addrs(&[("public", "[::ffff:127.0.0.1]:4190")]);
+
+        assert_eq!(
+            conflicts(&listeners, &forwards),
+            vec![
+                Conflict::Listeners {
+                    first: "private".into(),
+                    second: "public".into(),
+                    addr: "[::ffff:127.0.0.1]:4140".parse().unwrap(),
+                },
+                Conflict::ForwardToListener {
+                    listener: "public".into(),
+                    to: "control".into(),
+                    addr: "[::ffff:127.0.0.1]:4190".parse().unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn allows_distinct_and_ephemeral_addrs() {
+        let listeners = dual_stack(&[
+            ("private", "127.0.0.1:0"),
+            ("public", "127.0.0.1:0"),
+            ("control", "0.0.0.0:4190"),
+            ("public (admin)", "10.0.0.1:4143"),
+        ]);
+        let forwards = addrs(&[("public", "127.0.0.1:8080"), ("public (admin)", "10.0.0.2:4190")]);
+
+        assert!(conflicts(&listeners, &forwards).is_empty());
+    }
+
+    #[test]
+    fn unspecified_addrs_only_overlap_their_own_family() {
+        let listeners = dual_stack(&[("public", "0.0.0.0:4143"), ("control", "[::1]:4143")]);
+        assert!(conflicts(&listeners, &[]).is_empty());
+
+        let listeners = dual_stack(&[("public", "[::]:4143"), ("control", "127.0.0.1:4143")]);
+        assert_eq!(
+            conflicts(&listeners, &[]),
+            vec![Conflict::Listeners {
+                first: "public".into(),
+                second: "control".into(),
+                addr: "127.0.0.1:4143".parse().unwrap(),
+            }]
+        );
+
+        let listeners: Vec<(String, SocketAddr, bool)> = vec![
+            ("public".to_owned(), "[::]:4143".parse().unwrap(), false),
+            ("control".to_owned(), "0.0.0.0:4143".parse().unwrap(), true),
+        ];
+        assert!(conflicts(&listeners, &[]).is_empty());
+
+        let forwards = addrs(&[("public", "127.0.0.1:4143")]);
+        assert!(conflicts(&listeners[..1], &forwards).is_empty());
+    }
+
+    #[test]
+    fn parses_file_settings() {
+        let file = r#"
+            # Listeners
+            private_listener = "tcp://127.0.0.1:4140"  # loopback only
+            CONDUIT_RESOLV_CONF = '/etc/resolv.conf'
+            ingress-hosts = ["a.example.com", "b.example.com",]
+            redirect_limit = 3
+            ingress_mode = true
+            synthetic_token = "a \"quoted\" token"
+        "#;
+        let settings = parse_file(file).unwrap();
+
+        let get = |k: &str| settings.get(k).map(|v| v.as_str());
+        assert_eq!(get("CONDUIT_PROXY_PRIVATE_LISTENER"), Some("tcp://127.0.0.1:4140"));
+        assert_eq!(get("CONDUIT_RESOLV_CONF"), Some("/etc/resolv.conf"));
+        assert_eq!(get("CONDUIT_PROXY_INGRESS_HOSTS"), Some("a.example.com,b.example.com"));
+        assert_eq!(get("CONDUIT_PROXY_REDIRECT_LIMIT"), Some("3"));
+        assert_eq!(get("CONDUIT_PROXY_INGRESS_MODE"), Some("true"));
+        assert_eq!(get("CONDUIT_PROXY_SYNTHETIC_TOKEN"), Some("a \"quoted\" token"));
+        assert_eq!(settings.len(), 6);
+
+        assert_eq!(parse_file("a = 1\n[table]\n"), Err(2));
+        assert_eq!(parse_file("a = \"unterminated\n"), Err(1));
+        assert_eq!(parse_file("a = 1\nA = 2\n"), Err(2));
+        assert_eq!(parse_file("a = 1 2\n"), Err(1));
+    }
+
+    #[test]
+    fn renders_json_with_secrets_redacted() {
+        let config = load(&[
+            ("CONDUIT_PROXY_SYNTHETIC_TOKEN", "s3cret"),
+            ("CONDUIT_PROXY_CONTROL_LISTENER_CREDENTIALS", "ops:hunter2"),
+            ("CONDUIT_PROXY_METRICS_FLUSH_INTERVAL", "1500ms"),
+        ]).unwrap();
+
+        let json = config.to_json();
+        assert!(json.starts_with(
+            "{\"private_listener\":{\"addr\":\"tcp://127.0.0.1:4140\",\"accept_timeout\":null,"
+        ));
+        assert!(json.contains("\"private_forward\":null"));
+        assert!(json.contains("\"metrics_flush_interval\":\"1500ms\""));
+        assert!(json.contains("\"no_endpoints_timeout\":\"10s\""));
+        assert!(json.contains("\"synthetic_token\":\"<redacted>\""));
+        assert!(json.contains("\"control_listener_credentials\":\"<redacted>\""));
+        assert!(!json.contains("s3cret"));
+        assert!(!json.contains("hunter2"));
+    }
+
+    #[test]
+    fn loads_listener_timeouts() {
+        let config = load(&[
+            ("CONDUIT_PROXY_PUBLIC_LISTENER_ACCEPT_TIMEOUT", "5s"),
+            ("CONDUIT_PROXY_PRIVATE_LISTENER_CONNECT_TIMEOUT", "1s"),
+        ]).unwrap();
+        let public = config.public_listener();
+        assert_eq!(public.accept_timeout(), Some(Duration::from_secs(5)));
+        assert_eq!(public.connect_timeout(), None);
+        let private = config.private_listener();
+        assert_eq!(private.connect_timeout(), Some(Duration::from_secs(1)));
+
+        match load(&[
+            ("CONDUIT_PROXY_PUBLIC_LISTENERS", "admin=tcp://0.0.0.0:4144=tcp://127.0.0.1:9000"),
+            ("CONDUIT_PROXY_PUBLIC_LISTENER_ADMIN_ACCEPT_TIMEOUT", "0"),
+        ]) {
+            Err(Error::Invalid {
+                zero_timeouts: ref names,
+                ..
+            }) => {
+                assert_eq!(names, &["CONDUIT_PROXY_PUBLIC_LISTENER_ADMIN_ACCEPT_TIMEOUT"])
+            }
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn loads_listener_reuse_port() {
+        let config = load(&[("CONDUIT_PROXY_PUBLIC_LISTENER_REUSE_PORT", "true")]).unwrap();
+        assert!(config.public_listener().reuse_port());
+        assert!(!config.private_listener().reuse_port());
+        assert!(config.to_json().contains("\"reuse_port\":true"));
+    }
+
+    #[test]
+    fn loads_listener_dual_stack() {
+        let config = load(&[
+            ("CONDUIT_PROXY_PUBLIC_LISTENER", "tcp://[::]:4143"),
+            ("CONDUIT_PROXY_PRIVATE_LISTENER_DUAL_STACK", "false"),
+        ]).unwrap();
+        assert_eq!(config.public_listener().addr().to_string(), "tcp://[::]:4143");
+        assert!(config.public_listener().dual_stack());
+        assert!(!config.private_listener().dual_stack());
+    }
+
+    #[test]
+    fn loads_listener_proxy_protocol() {
+        let config = load(&[("CONDUIT_PROXY_PUBLIC_LISTENER_PROXY_PROTOCOL", "true")]).unwrap();
+        assert!(config.public_listener().proxy_protocol());
+        assert!(!config.private_listener().proxy_protocol());
+        assert!(config.to_json().contains("\"proxy_protocol\":true"));
+    }
+
+    #[test]
+    fn loads_listener_backlog() {
+        let backlog = ("CONDUIT_PROXY_PUBLIC_LISTENER_BACKLOG", "1024");
+        let config = load(&[backlog]).unwrap();
+        assert_eq!(config.public_listener().backlog(), Some(1024));
+        assert_eq!(config.private_listener().backlog(), None);
+        assert!(config.to_json().contains("\"backlog\":1024"));
+
+        match load(&[backlog, ("CONDUIT_PROXY_PRIVATE_LISTENER_BACKLOG", "0")]) {
+            Err(Error::InvalidEnvVars(ref vars)) => {
+                assert_eq!(vars[0].name, "CONDUIT_PROXY_PRIVATE_LISTENER_BACKLOG")
+            }
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn loads_no_endpoints_startup_fallback() {
+        let config = load(&[]).unwrap();
+        assert!(!config.no_endpoints_startup_fallback());
+
+        let config = load(&[("CONDUIT_PROXY_NO_ENDPOINTS_STARTUP_FALLBACK", "true")]).unwrap();
+        assert!(config.no_endpoints_startup_fallback());
+        assert!(config.to_json().contains("\"no_endpoints_startup_fallback\":true"));
+    }
+
+    #[test]
+    fn loads_buffer_sizes() {
+        let config = load(&[
+            ("CONDUIT_PROXY_PUBLIC_LISTENER_RECV_BUFFER_SIZE", "262144"),
+            ("CONDUIT_PROXY_CONNECT_SEND_BUFFER_SIZE", "131072"),
+        ]).unwrap();
+        assert_eq!(config.public_listener().recv_buffer_size(), Some(262144));
+        assert_eq!(config.public_listener().send_buffer_size(), None);
+        assert_eq!(config.private_listener().recv_buffer_size(), None);
+        assert_eq!(config.connect_recv_buffer_size(), None);
+        assert_eq!(config.connect_send_buffer_size(), Some(131072));
+
+        assert!(load(&[("CONDUIT_PROXY_CONNECT_RECV_BUFFER_SIZE", "0")]).is_err());
+    }
+
+    #[test]
+    fn loads_replay_journal() {
+        let config = load(&[]).unwrap();
+        assert!(config.replay_hosts().is_empty());
+        assert_eq!(config.replay_journal_path(), None);
+        assert_eq!(config.replay_journal_max_entries(), 1_000);
+        assert_eq!(config.replay_backoff(), Duration::from_secs(1));
+        assert_eq!(config.replay_max_backoff(), Duration::from_secs(60));
+
+        let mut vars = vec![
+            ("CONDUIT_PROXY_REPLAY_HOSTS", "hooks.example.com"),
+            ("CONDUIT_PROXY_REPLAY_JOURNAL_PATH", "/var/replay"),
+            ("CONDUIT_PROXY_REPLAY_JOURNAL_MAX_ENTRIES", "50"),
+            ("CONDUIT_PROXY_REPLAY_BACKOFF", "250"),
+        ];
+        let config = load(&vars).unwrap();
+        assert_eq!(config.replay_hosts(), &["hooks.example.com".to_owned()]);
+        assert_eq!(config.replay_journal_path(), Some(Path::new("/var/replay")));
+        assert_eq!(config.replay_journal_max_entries(), 50);
+        assert_eq!(config.replay_backoff(), Duration::from_millis(250));
+        assert!(config.to_json().contains("\"replay_journal_max_entries\":50"));
+
+        vars.push(("CONDUIT_PROXY_REPLAY_MAX_BACKOFF", "0"));
+        match load(&vars) {
+            Err(Error::Invalid {
+                zero_timeouts: ref names,
+                ..
+            }) => {
+                assert_eq!(names, &["CONDUIT_PROXY_REPLAY_MAX_BACKOFF"])
+            }
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn loads_tcp_nodelay() {
+        let config = load(&[]).unwrap();
+        assert!(config.tcp_nodelay());
+
+        let config = load(&[("CONDUIT_PROXY_TCP_NODELAY", "false")]).unwrap();
+        assert!(!config.tcp_nodelay());
+        assert!(config.to_json().contains("\"tcp_nodelay\":false"));
+    }
+
+    #[test]
+    fn loads_tcp_keepalive() {
+        let config = load(&[]).unwrap();
+        assert_eq!(config.tcp_keepalive(), None);
+
+        let config = load(&[
+            ("CONDUIT_PROXY_TCP_KEEPALIVE", "60"),
+            ("CONDUIT_PROXY_TCP_KEEPALIVE_INTERVAL", "10s"),
+            ("CONDUIT_PROXY_TCP_KEEPALIVE_PROBES", "3"),
+        ]).unwrap();
+        assert_eq!(config.tcp_keepalive(), Some(Duration::from_secs(60)));
+        assert_eq!(config.tcp_keepalive_interval(), Some(Duration::from_secs(10)));
+        assert_eq!(config.tcp_keepalive_probes(), Some(3));
+
+        match load(&[("CONDUIT_PROXY_TCP_KEEPALIVE", "0")]) {
+            Err(Error::Invalid {
+                zero_timeouts: ref names,
+                ..
+            }) => {
+                assert_eq!(names, &["CONDUIT_PROXY_TCP_KEEPALIVE"])
+            }
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infers_control_port() {
+        let control = |vars: &[(&str, &str)]| load(vars).map(|c| c.control_host_and_port().port);
+        let url = ("CONDUIT_PROXY_CONTROL_URL", "tcp://proxy-api.conduit.svc.cluster.local");
+        assert_eq!(control(&[url]).ok(), Some(8086));
+        let port = ("CONDUIT_PROXY_CONTROL_DEFAULT_PORT", "9000");
+        assert_eq!(control(&[url, port]).ok(), Some(9000));
+        assert_eq!(
+            control(&[
+                ("CONDUIT_PROXY_CONTROL_URL", "tcp://localhost:8087"),
+                port,
+            ]).ok(),
+            Some(8087)
+        );
+        assert!(control(&[url, ("CONDUIT_PROXY_CONTROL_DEFAULT_PORT", "0")]).is_err());
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        env::set_var("CONDUIT_PROXY_TEST_OVERRIDDEN", "env");
+
+        let mut env = Env {
+            file: parse_file("test_overridden = 'file'\ntest_from_file = 'file'").unwrap(),
+            ..Env::default()
+        };
+        assert_eq!(env.var("CONDUIT_PROXY_TEST_OVERRIDDEN"), Some("env".to_owned()));
+        assert_eq!(env.var("CONDUIT_PROXY_TEST_FROM_FILE"), Some("file".to_owned()));
+        assert!(env.read.contains("CONDUIT_PROXY_TEST_FROM_FILE"));
+    }
+
+    #[test]
+    fn maps_keys_to_env_vars() {
+        assert_eq!(parse_key("private-listener"), Some("CONDUIT_PROXY_PRIVATE_LISTENER".into()));
+        assert_eq!(parse_key("resolv_conf"), Some("CONDUIT_RESOLV_CONF".into()));
+        assert_eq!(parse_key("resolv-conf"), Some("CONDUIT_RESOLV_CONF".into()));
+        assert_eq!(parse_key("CONDUIT_RESOLV_CONF"), Some("CONDUIT_RESOLV_CONF".into()));
+        assert_eq!(parse_key("log"), Some("CONDUIT_PROXY_LOG".into()));
+        assert_eq!(parse_key("bad key"), None);
+    }
+
+    #[test]
+    fn parses_args() {
+        let args = |args: &[&str]| parse_args(args.iter().map(|a| a.to_string()));
+
+        let settings = args(&[
+            "--private-listener=tcp://127.0.0.1:4140",
+            "--control-url",
+            "tcp://localhost:8086",
+            "--CONDUIT_RESOLV_CONF=/tmp/resolv.conf",
+        ]).unwrap();
+        let get = |k: &str| {
+            settings
+                .get(k)
+                .map(|&(ref arg, ref value)| (arg.as_str(), value.as_str()))
+        };
+        assert_eq!(
+            get("CONDUIT_PROXY_PRIVATE_LISTENER"),
+            Some(("--private-listener", "tcp://127.0.0.1:4140"))
+        );
+        assert_eq!(
+            get("CONDUIT_PROXY_CONTROL_URL"),
+            Some(("--control-url", "tcp://localhost:8086"))
+        );
+        assert_eq!(
+            get("CONDUIT_RESOLV_CONF"),
+            Some(("--CONDUIT_RESOLV_CONF", "/tmp/resolv.conf"))
+        );
+
+        assert_eq!(args(&["private-listener=x"]), Err("private-listener=x".to_owned()));
+        assert_eq!(args(&["--control-url"]), Err("--control-url".to_owned()));
+        assert_eq!(args(&["--a=1", "--a=2"]), Err("--a=2".to_owned()));
+    }
+
+    #[test]
+    fn args_override_env() {
+        env::set_var("CONDUIT_PROXY_TEST_ARG_OVERRIDDEN", "env");
+
+        let mut env = Env {
+            args: parse_args(vec!["--test-arg-overridden=arg".to_owned()]).unwrap(),
+            ..Env::default()
+        };
+        assert_eq!(env.var("CONDUIT_PROXY_TEST_ARG_OVERRIDDEN"), Some("arg".to_owned()));
+    }
+
+    #[test]
+    fn parses_durations() {
+        let millis = |s: &str| parse_duration(s, Duration::from_millis).ok();
+        assert_eq!(millis("500"), Some(Duration::from_millis(500)));
+        assert_eq!(millis("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(millis("10s"), Some(Duration::from_secs(10)));
+        assert_eq!(millis("1m30s"), Some(Duration::from_secs(90)));
+        assert_eq!(millis("1h1ms"), Some(Duration::from_millis(3_600_001)));
+        assert_eq!(parse_duration("10", Duration::from_secs).ok(), Some(Duration::from_secs(10)));
+
+        for invalid in &["", "s", "10x", "1.5s", "-1s", "10 s", "ms10"] {
+            assert_eq!(millis(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        for addr in &[
+            super::DEFAULT_PRIVATE_LISTENER,
+            super::DEFAULT_PUBLIC_LISTENER,
+            super::DEFAULT_CONTROL_LISTENER,
+        ] {
+            assert!(addr.parse::<Addr>().is_ok(), "{}", addr);
+        }
+        assert!(parse_url(super::DEFAULT_CONTROL_URL).is_ok());
+    }
+
+    #[test]
+    fn parses_addrs() {
+        let addr = |s: &str| s.parse::<Addr>().ok().map(|a| a.0);
+        let any: SocketAddr = "0.0.0.0:4143".parse().unwrap();
+        assert_eq!(addr("tcp://0.0.0.0:4143"), Some(any));
+        assert_eq!(addr("tcp://0.0.0.0:4143/"), Some(any));
+        assert_eq!(addr("tcp://:4143"), Some(any));
+        assert_eq!(addr(":4143"), Some(any));
+        assert_eq!(addr("tcp://[::1]:4143"), Some("[::1]:4143".parse().unwrap()));
+    }
+
+    #[test]
+    fn rejects_invalid_addrs() {
+        let err = |s: &str| match s.parse::<Addr>() {
+            Err(ParseError::UrlError(e)) => Some(e),
+            _ => None,
+        };
+        assert_eq!(err("0.0.0.0:4143"), Some(UrlError::UnsupportedScheme));
+        assert_eq!(err("http://0.0.0.0:4143"), Some(UrlError::UnsupportedScheme));
+        assert_eq!(err("tcp://0.0.0.0"), Some(UrlError::MissingPort));
+        assert_eq!(err("tcp://0.0.0.0:99999"), Some(UrlError::InvalidPort));
+        assert_eq!(err(":99999"), Some(UrlError::InvalidPort));
+        assert_eq!(err("tcp://:"), Some(UrlError::InvalidPort));
+        assert_eq!(err("tcp://[::1:4143"), Some(UrlError::InvalidHost));
+        assert_eq!(err("tcp://user@0.0.0.0:4143"), Some(UrlError::UserInfoNotAllowed));
+        assert_eq!(err("tcp://:pass@0.0.0.0:4143"), Some(UrlError::UserInfoNotAllowed));
+        assert_eq!(err("tcp://0.0.0.0:4143/path"), Some(UrlError::PathNotAllowed));
+        assert_eq!(err("tcp://0.0.0.0:4143?q"), Some(UrlError::QueryNotAllowed));
+        assert_eq!(err("tcp://0.0.0.0:4143#f"), Some(UrlError::FragmentNotAllowed));
+        assert!(match "tcp://localhost:4143".parse::<Addr>() {
+            Err(ParseError::HostIsNotAnIpAddress) => true,
+            _ => false,
+        });
+        for s in &["unix:///var/run/app.sock", "unix-abstract:app"] {
+            assert!(match s.parse::<Addr>() {
+                Err(ParseError::UnixSocketsUnsupported) => true,
+                _ => false,
+            });
+        }
+    }
+
+    quickcheck! {
+        fn addr_parsing_never_panics(s: String) -> bool {
+            let _ = s.parse::<Addr>();
+            let _ = format!("tcp://{}", s).parse::<Addr>();
+            true
+        }
+    }
+
+    #[test]
+    fn parses_ports() {
+        let ports = super::parse_ports(" 3306, 5432,,3306 ").unwrap();
+        assert_eq!(ports.len(), 2);
+        assert!(ports.contains(&3306) && ports.contains(&5432));
+        assert_eq!(super::json_ports(&ports), "[3306,5432]");
+
+        for invalid in &["0", "65536", "mysql", "3306;5432"] {
+            assert!(super::parse_ports(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn profiles_change_defaults() {
+        let config = load(&[]).unwrap();
+        assert_eq!(config.profile(), None);
+        assert_eq!(config.no_endpoints(), NoEndpoints::Queue);
+        assert_eq!(config.log_filter(), None);
+        assert!(!config.admin_read_only());
+        assert!(!config.debug_headers());
+
+        let config = load(&[("CONDUIT_PROXY_PROFILE", "dev")]).unwrap();
+        assert_eq!(config.profile(), Some(Profile::Dev));
+        assert_eq!(config.no_endpoints(), NoEndpoints::Fallback);
+        assert_eq!(config.private_connect_timeout(), Some(Duration::from_secs(1)));
+        assert_eq!(config.log_filter(), Some("info,conduit_proxy=debug"));
+        assert!(config.debug_headers());
+
+        let config = load(&[
+            ("CONDUIT_PROXY_PROFILE", "prod"),
+            ("CONDUIT_PROXY_INBOUND_NORMALIZATION", "lenient"),
+        ]).unwrap();
+        assert_eq!(config.inbound_normalization(), Normalization::Lenient);
+        assert_eq!(config.log_filter(), Some("warn,conduit_proxy=info"));
+        assert!(config.admin_read_only());
+        assert!(config.to_json().contains("\"profile\":\"prod\""));
+    }
+
+    #[test]
+    fn networks_contain_mapped_addresses() {
+        let net = "10.0.0.0/8".parse::<Network>().unwrap();
+        assert!(net.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(net.contains(&"::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!net.contains(&"::ffff:11.1.2.3".parse().unwrap()));
+        assert!(!net.contains(&"fd00::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn parses_forwards() {
+        let forward = |s: &str| s.parse::<Forward>().ok().map(|f| f.to_string());
+        assert_eq!(forward("tcp://127.0.0.1:8080"), Some("tcp://127.0.0.1:8080".into()));
+        assert_eq!(forward("tcp://helper.local:8080"), Some("tcp://helper.local:8080".into()));
+        assert_eq!(forward("tcp://helper.local"), None);
+        assert_eq!(forward("tcp://helper.local:8080/path"), None);
+    }
+
+    #[test]
+    fn public_listeners_forward_to_hosts() {
+        let listeners =
+            parse_public_listeners("admin=tcp://0.0.0.0:4144=tcp://admin.local:9000").unwrap();
+        assert_eq!(listeners[0].forward.to_string(), "tcp://admin.local:9000");
+        assert!(parse_public_listeners("admin=tcp://admin.local:4144=tcp://0.0.0.0:9000").is_err());
+    }
+
+    #[test]
+    fn builder_checks_conflicts() {
+        let listener = |addr: &str| Listener::new(addr.parse().unwrap());
+
+        let config = Config::builder()
+            .private_listener(listener("tcp://127.0.0.1:5140"))
+            .build()
+            .unwrap();
+        assert_eq!(SocketAddr::from(config.private_listener().addr()).port(), 5140);
+
+        let conflicting = Config::builder()
+            .private_listener(listener("tcp://127.0.0.1:4190"))
+            .build();
+        match conflicting {
+            Err(Error::Invalid {
+                ref conflicts,
+                ..
+            }) => assert_eq!(conflicts.len(), 1),
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn builder_rejects_zero_timeouts() {
+        let zero = Duration::from_secs(0);
+        let config = Config::builder()
+            .idle_timeouts(Some(zero), None)
+            .metrics_flush_interval(zero)
+            .build();
+        match config {
+            Err(Error::Invalid {
+                zero_timeouts: ref names,
+                ..
+            }) => assert_eq!(
+                names,
+                &[super::ENV_PUBLIC_IDLE_TIMEOUT, super::ENV_METRICS_FLUSH_INTERVAL]
+            ),
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn reports_changed_settings() {
+        let secs = Duration::from_secs;
+        let config = Config::builder().build().unwrap();
+        let changed = Config::builder()
+            .connect_timeouts(Some(secs(1)), None)
+            .metrics_flush_interval(secs(30))
+            .build()
+            .unwrap();
+        assert!(config.changed_settings(&config.clone()).is_empty());
+        assert_eq!(
+            config.changed_settings(&changed),
+            &["public_connect_timeout", "metrics_flush_interval"]
+        );
+    }
+
+    #[test]
+    fn reports_conflicts_and_zero_timeouts_together() {
+        let config = Config::builder()
+            .private_listener(Listener::new("tcp://127.0.0.1:4190".parse().unwrap()))
+            .metrics_flush_interval(Duration::from_secs(0))
+            .build();
+        match config {
+            Err(Error::Invalid {
+                ref conflicts,
+                ref zero_timeouts,
+            }) => {
+                assert_eq!(conflicts.len(), 1);
+                assert_eq!(zero_timeouts, &[super::ENV_METRICS_FLUSH_INTERVAL]);
+            }
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn reload_reads_file_again() {
+        use std::fs::{self, File};
+        use std::io::Write;
+
+        let path = temp_path("reload_reads_file_again");
+        let write = |contents: &str| {
+            File::create(&path).and_then(|mut f| f.write_all(contents.as_bytes())).unwrap()
+        };
+
+        write("metrics_flush_interval = \"10s\"\n");
+        let config = Config::load_from_file(&path).unwrap();
+        assert_eq!(config.metrics_flush_interval(), Duration::from_secs(10));
+
+        write("metrics_flush_interval = \"20s\"\n");
+        let reloaded = config.reload().unwrap();
+        assert_eq!(reloaded.metrics_flush_interval(), Duration::from_secs(20));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn renames_env_vars_with_prefix() {
+        assert_eq!(
+            with_prefix("CONDUIT_PROXY_PRIVATE_LISTENER", "ACME_MESH_"),
+            "ACME_MESH_PRIVATE_LISTENER"
+        );
+        assert_eq!(with_prefix("CONDUIT_RESOLV_CONF", "ACME_MESH_"), "CONDUIT_RESOLV_CONF");
+    }
+
+    #[test]
+    fn omits_secret_values_from_errors() {
+        match load(&[("CONDUIT_PROXY_CONTROL_LISTENER_CREDENTIALS", "hunter2")]) {
+            Err(Error::InvalidEnvVars(ref vars)) => {
+                let error = vars[0].to_string();
+                assert!(error.starts_with("CONDUIT_PROXY_CONTROL_LISTENER_CREDENTIALS: "));
+                assert!(!error.contains("hunter2"));
+            }
+            ref r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn reads_values_from_files() {
+        let file = temp_path("reads_values_from_files");
+        fs::File::create(&file).and_then(|mut f| f.write_all(b"  s3cret\n")).unwrap();
+
+        let mut env = Env {
+            isolated: true,
+            ..Env::default()
+        };
+        let path = file.to_str().unwrap().to_owned();
+        env.file.insert("CONDUIT_PROXY_TEST_SECRET_FILE".into(), path.clone());
+        env.file.insert("CONDUIT_PROXY_TEST_NUMBER_FILE".into(), path);
+        env.file.insert("CONDUIT_PROXY_TEST_MISSING_FILE".into(), "/nonexistent".into());
+        env.file.insert("CONDUIT_PROXY_TEST_BOTH".into(), "value".into());
+        env.file.insert("CONDUIT_PROXY_TEST_BOTH_FILE".into(), "/nonexistent".into());
+
+        assert_eq!(env.var("CONDUIT_PROXY_TEST_SECRET"), Some("s3cret".into()));
+        assert_eq!(env.var("CONDUIT_PROXY_TEST_BOTH"), Some("value".into()));
+        assert!(env.read.contains("CONDUIT_PROXY_TEST_BOTH_FILE"));
+        assert_eq!(env.var("CONDUIT_PROXY_TEST_MISSING"), None);
+        let n: Option<u64> = env.parse("CONDUIT_PROXY_TEST_NUMBER", super::parse_number);
+        assert_eq!(n, None);
+
+        let errors = env.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("CONDUIT_PROXY_TEST_MISSING_FILE=\"/nonexistent\""));
+        assert_eq!(errors[1], "CONDUIT_PROXY_TEST_NUMBER: expected a number");
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn reads_deprecated_env_vars() {
+        let mut env = Env {
+            isolated: true,
+            ..Env::default()
+        };
+        env.file.insert("CONDUIT_PROXY_METRICS_FLUSH_INTERVAL_SECS".into(), "5".into());
+        let interval = env.parse(super::ENV_METRICS_FLUSH_INTERVAL, super::parse_secs);
+        assert_eq!(interval, Some(Duration::from_secs(5)));
+
+        // The deprecated name is known, so it isn't reported as unknown in a file.
+        assert!(env.read.contains("CONDUIT_PROXY_METRICS_FLUSH_INTERVAL_SECS"));
     }
 }
@@ -11,9 +11,26 @@ pub enum Connection {
     Plain(PlaintextSocket),
 }
 
+/// Describes how a connection is negotiated before HTTP/2 is spoken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Negotiate {
+    /// HTTP/2 is spoken immediately.
+    Plain,
+
+    /// A SOCKS5 `CONNECT` precedes HTTP/2.
+    Socks5,
+
+    /// A PROXY protocol header, naming the client behind a load balancer, precedes
+    /// HTTP/2.
+    ProxyProtocol,
+}
+
 /// A connection handshake.
 ///
-/// Resolves to a connection ready to be used at the next layer.
+/// Resolves to a connection ready to be used at the next layer. Only plaintext
+/// connections are supported, so the handshake completes immediately: there's no TLS
+/// handshake to time out or to classify failures of. A TLS variant of `Connection` would
+/// need both, keyed by the peer address.
 pub struct Handshake {
     plaintext_socket: Option<PlaintextSocket>,
 }
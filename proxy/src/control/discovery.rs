@@ -1,6 +1,10 @@
 use std::collections::{HashSet, VecDeque};
 use std::collections::hash_map::{Entry, HashMap};
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use futures::{Async, Future, Poll, Stream};
 use futures::sync::mpsc;
@@ -15,6 +19,8 @@ use super::pb::proxy::destination::Update as PbUpdate;
 use super::pb::proxy::destination::client::Destination as DestinationSvc;
 use super::pb::proxy::destination::client::destination_methods::Get as GetRpc;
 use super::pb::proxy::destination::update::Update as PbUpdate2;
+use super::resolutions::{Class, Resolutions};
+use super::snapshot::{self, Snapshot};
 
 pub type ClientBody = ::tower_grpc::client::codec::EncodingBody<
     Protobuf<Destination, PbUpdate>,
@@ -24,20 +30,47 @@ pub type ClientBody = ::tower_grpc::client::codec::EncodingBody<
 /// A handle to start watching a destination for address changes.
 #[derive(Clone, Debug)]
 pub struct Discovery {
-    tx: mpsc::UnboundedSender<(Authority, mpsc::UnboundedSender<Update>)>,
+    tx: mpsc::UnboundedSender<(Authority, Subscriber)>,
 }
 
 /// A `tower_discover::Discover`, given to a `tower_balance::Balance`.
 #[derive(Debug)]
 pub struct Watch<B> {
     rx: mpsc::UnboundedReceiver<Update>,
+    endpoints: Endpoints,
+    /// Holds the sending half of a fixed watch open, since no background task does.
+    fixed_tx: Option<mpsc::UnboundedSender<Update>>,
     bind: B,
 }
 
+/// Describes whether a watched destination currently has any endpoints.
+///
+/// This is updated by the controller thread as soon as updates are received, whether or
+/// not the `Watch` itself has been polled.
+#[derive(Clone, Debug)]
+pub struct Endpoints(Arc<EndpointsState>);
+
+#[derive(Debug)]
+struct EndpointsState {
+    count: AtomicUsize,
+    /// Whether the controller has sent any updates for the destination.
+    resolved: AtomicBool,
+    since: Instant,
+}
+
+/// The controller thread's handle on a `Watch`.
+#[derive(Debug)]
+struct Subscriber {
+    tx: mpsc::UnboundedSender<Update>,
+    endpoints: Endpoints,
+}
+
 /// A background handle to eventually bind on the controller thread.
 #[derive(Debug)]
 pub struct Background {
-    rx: mpsc::UnboundedReceiver<(Authority, mpsc::UnboundedSender<Update>)>,
+    rx: mpsc::UnboundedReceiver<(Authority, Subscriber)>,
+    snapshot: Option<Snapshot>,
+    resolutions: Resolutions,
 }
 
 type DiscoveryWatch<F> = DestinationSet<
@@ -58,18 +91,37 @@ pub struct DiscoveryWork<F> {
     /// Each poll, records whether the rpc service was till ready.
     rpc_ready: bool,
     /// A receiver of new watch requests.
-    rx: mpsc::UnboundedReceiver<(Authority, mpsc::UnboundedSender<Update>)>,
+    rx: mpsc::UnboundedReceiver<(Authority, Subscriber)>,
+    /// Where the endpoints of all destinations are persisted, if anywhere.
+    snapshot: Option<Snapshot>,
+    /// Whether any destination's endpoints have changed since the snapshot was stored.
+    snapshot_dirty: bool,
+    /// Endpoints loaded from the snapshot that have not yet been watched.
+    stale: snapshot::Endpoints,
+    resolutions: Resolutions,
 }
 
+/// Tracks the addresses for an authority.
+///
+/// A single `Destination.Get` stream is shared by all watches on an authority, so that a
+/// burst of requests for the same authority (i.e. as a service starts) results in a
+/// single query to the controller.
 #[derive(Debug)]
 struct DestinationSet<R> {
     addrs: HashSet<SocketAddr>,
+    class: Class,
+    /// When the current stream was requested, until the controller first responds on it.
+    requested: Option<Instant>,
+    /// Whether the controller has sent any updates since the destination was watched.
+    resolved: bool,
+    /// Whether `addrs` were loaded from a snapshot and not yet confirmed by the controller.
+    stale: bool,
     needs_reconnect: bool,
     rx: R,
-    tx: mpsc::UnboundedSender<Update>,
+    subscribers: Vec<Subscriber>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 enum Update {
     Insert(SocketAddr),
     Remove(SocketAddr),
@@ -99,7 +151,13 @@ pub trait Bind {
 ///
 /// The `Discovery` is used by a listener, the `Background` is consumed
 /// on the controller thread.
-pub fn new() -> (Discovery, Background) {
+///
+/// If `snapshot_path` is set, the endpoints of all destinations are persisted there, and
+/// endpoints persisted by a previous process are used until the controller is reachable.
+pub fn new(
+    snapshot_path: Option<PathBuf>,
+    resolutions: Resolutions,
+) -> (Discovery, Background) {
     let (tx, rx) = mpsc::unbounded();
     (
         Discovery {
@@ -107,6 +165,8 @@ pub fn new() -> (Discovery, Background) {
         },
         Background {
             rx,
+            snapshot: snapshot_path.map(Snapshot::new),
+            resolutions,
         },
     )
 }
@@ -118,12 +178,19 @@ impl Discovery {
     pub fn resolve<B>(&self, authority: &Authority, bind: B) -> Watch<B> {
         trace!("resolve; authority={:?}", authority);
         let (tx, rx) = mpsc::unbounded();
+        let endpoints = Endpoints::new();
+        let subscriber = Subscriber {
+            tx,
+            endpoints: endpoints.clone(),
+        };
         self.tx
-            .unbounded_send((authority.clone(), tx))
+            .unbounded_send((authority.clone(), subscriber))
             .expect("unbounded can't fail");
 
         Watch {
             rx,
+            endpoints,
+            fixed_tx: None,
             bind,
         }
     }
@@ -131,6 +198,65 @@ impl Discovery {
 
 // ==== impl Watch =====
 
+impl<B> Watch<B> {
+    /// Returns a `Watch` that discovers a single, unchanging address without consulting
+    /// the controller.
+    pub fn fixed(addr: SocketAddr, bind: B) -> Self {
+        let (tx, rx) = mpsc::unbounded();
+        tx.unbounded_send(Update::Insert(addr))
+            .expect("unbounded can't fail");
+
+        let endpoints = Endpoints::new();
+        endpoints.update(1, true);
+
+        Watch {
+            rx,
+            endpoints,
+            fixed_tx: Some(tx),
+            bind,
+        }
+    }
+
+    /// Returns a handle describing whether the watched destination has any endpoints.
+    pub fn endpoints(&self) -> Endpoints {
+        self.endpoints.clone()
+    }
+}
+
+// ==== impl Endpoints =====
+
+impl Endpoints {
+    pub(crate) fn new() -> Self {
+        Endpoints(Arc::new(EndpointsState {
+            count: AtomicUsize::new(0),
+            resolved: AtomicBool::new(false),
+            since: Instant::now(),
+        }))
+    }
+
+    pub(crate) fn update(&self, count: usize, resolved: bool) {
+        self.0.count.store(count, Ordering::Release);
+        if resolved {
+            self.0.resolved.store(true, Ordering::Release);
+        }
+    }
+
+    /// Returns true if the destination has no endpoints.
+    ///
+    /// The controller doesn't report that a destination has no endpoints until it has
+    /// had some, so a destination that it hasn't reported on within `grace` of being
+    /// watched is also considered to be empty.
+    pub fn is_empty(&self, grace: Duration) -> bool {
+        self.0.count.load(Ordering::Acquire) == 0
+            && (self.0.resolved.load(Ordering::Acquire) || self.0.since.elapsed() >= grace)
+    }
+
+    /// Returns true if the destination has no endpoints, however recently it was watched.
+    pub fn is_currently_empty(&self) -> bool {
+        self.0.count.load(Ordering::Acquire) == 0
+    }
+}
+
 impl<B> Discover for Watch<B>
 where
     B: Bind,
@@ -168,11 +294,20 @@ where
 impl Background {
     /// Bind this handle to start talking to the controller API.
     pub fn work<F>(self) -> DiscoveryWork<F> {
+        let stale = self.snapshot
+            .as_ref()
+            .map(Snapshot::load)
+            .unwrap_or_default();
+
         DiscoveryWork {
             destinations: HashMap::new(),
             reconnects: VecDeque::new(),
             rpc_ready: false,
             rx: self.rx,
+            snapshot: self.snapshot,
+            snapshot_dirty: false,
+            stale,
+            resolutions: self.resolutions,
         }
     }
 }
@@ -205,6 +340,21 @@ where
                 break;
             }
         }
+
+        self.store_snapshot();
+    }
+
+    fn store_snapshot(&mut self) {
+        if !self.snapshot_dirty {
+            return;
+        }
+        self.snapshot_dirty = false;
+
+        if let Some(ref snapshot) = self.snapshot {
+            // Stale endpoints that haven't been watched yet are kept for the next process.
+            let watched = self.destinations.iter().map(|(auth, set)| (auth, &set.addrs));
+            snapshot.store(watched.chain(self.stale.iter()));
+        }
     }
 
     fn poll_new_watches<S>(&mut self, mut client: &mut S)
@@ -242,11 +392,12 @@ where
             let mut rpc = GetRpc::new(grpc);
             // check for any new watches
             match self.rx.poll() {
-                Ok(Async::Ready(Some((auth, tx)))) => {
+                Ok(Async::Ready(Some((auth, subscriber)))) => {
                     trace!("Destination.Get {:?}", auth);
                     match self.destinations.entry(auth) {
                         Entry::Occupied(mut occ) => {
-                            occ.get_mut().tx = tx;
+                            trace!("coalescing watch on {:?}", occ.key());
+                            occ.get_mut().add_watch(subscriber);
                         }
                         Entry::Vacant(vac) => {
                             let req = Destination {
@@ -254,12 +405,23 @@ where
                                 path: vac.key().as_str().into(),
                             };
                             let stream = DestinationSvc::new(&mut rpc).get(req);
-                            vac.insert(DestinationSet {
-                                addrs: HashSet::new(),
+                            let addrs = self.stale.remove(vac.key()).unwrap_or_default();
+                            if !addrs.is_empty() {
+                                debug!("using stale endpoints for {:?}: {:?}", vac.key(), addrs);
+                            }
+                            let class = Class::from(vac.key());
+                            self.resolutions.started(class);
+                            let set = vac.insert(DestinationSet {
+                                stale: !addrs.is_empty(),
+                                addrs,
+                                class,
+                                requested: Some(Instant::now()),
+                                resolved: false,
                                 needs_reconnect: false,
                                 rx: stream,
-                                tx,
+                                subscribers: Vec::new(),
                             });
+                            set.add_watch(subscriber);
                         }
                     }
                 }
@@ -295,6 +457,7 @@ where
                     path: auth.as_str().into(),
                 };
                 set.rx = DestinationSvc::new(&mut rpc).get(req);
+                set.requested = Some(Instant::now());
                 set.needs_reconnect = false;
                 return true;
             } else {
@@ -305,31 +468,14 @@ where
     }
 
     fn poll_destinations(&mut self) {
+        let mut changed = false;
         for (auth, set) in &mut self.destinations {
             if set.needs_reconnect {
                 continue;
             }
             let needs_reconnect = 'set: loop {
-                match set.rx.poll() {
-                    Ok(Async::Ready(Some(update))) => match update.update {
-                        Some(PbUpdate2::Add(a_set)) => for addr in a_set.addrs {
-                            if let Some(addr) = addr.addr.and_then(pb_to_sock_addr) {
-                                if set.addrs.insert(addr) {
-                                    trace!("update {:?} for {:?}", addr, auth);
-                                    let _ = set.tx.unbounded_send(Update::Insert(addr));
-                                }
-                            }
-                        },
-                        Some(PbUpdate2::Remove(r_set)) => for addr in r_set.addrs {
-                            if let Some(addr) = pb_to_sock_addr(addr) {
-                                if set.addrs.remove(&addr) {
-                                    trace!("remove {:?} for {:?}", addr, auth);
-                                    let _ = set.tx.unbounded_send(Update::Remove(addr));
-                                }
-                            }
-                        },
-                        None => (),
-                    },
+                let update = match set.rx.poll() {
+                    Ok(Async::Ready(Some(update))) => update,
                     Ok(Async::Ready(None)) => {
                         trace!(
                             "Destination.Get stream ended for {:?}, must reconnect",
@@ -342,13 +488,93 @@ where
                         warn!("Destination.Get stream errored for {:?}: {:?}", auth, err);
                         break 'set true;
                     }
+                };
+
+                if let Some(requested) = set.requested.take() {
+                    self.resolutions.resolved(set.class, requested.elapsed());
+                }
+                let (mut added, mut removed) = (0, 0);
+                match update.update {
+                    Some(PbUpdate2::Add(a_set)) => {
+                        set.resolved = true;
+                        let addrs = a_set.addrs
+                            .into_iter()
+                            .filter_map(|a| a.addr.and_then(pb_to_sock_addr))
+                            .collect::<Vec<_>>();
+                        if set.stale {
+                            // The controller's first update describes all of the
+                            // destination's endpoints, so any stale endpoints that
+                            // it doesn't include are gone.
+                            set.stale = false;
+                            let fresh = addrs.iter().cloned().collect::<HashSet<_>>();
+                            let gone = set.addrs
+                                .difference(&fresh)
+                                .cloned()
+                                .collect::<Vec<_>>();
+                            for addr in gone {
+                                trace!("remove stale {:?} for {:?}", addr, auth);
+                                set.addrs.remove(&addr);
+                                set.notify(Update::Remove(addr));
+                                removed += 1;
+                            }
+                        }
+                        for addr in addrs {
+                            if set.addrs.insert(addr) {
+                                trace!("update {:?} for {:?}", addr, auth);
+                                set.notify(Update::Insert(addr));
+                                added += 1;
+                            }
+                        }
+                    }
+                    Some(PbUpdate2::Remove(r_set)) => {
+                        set.resolved = true;
+                        set.stale = false;
+                        for addr in r_set.addrs {
+                            if let Some(addr) = pb_to_sock_addr(addr) {
+                                if set.addrs.remove(&addr) {
+                                    trace!("remove {:?} for {:?}", addr, auth);
+                                    set.notify(Update::Remove(addr));
+                                    removed += 1;
+                                }
+                            }
+                        }
+                    }
+                    None => (),
                 }
+                self.resolutions.updated(set.class, added, removed);
+                changed |= added + removed > 0;
             };
             if needs_reconnect {
+                self.resolutions.reset(set.class);
                 set.needs_reconnect = true;
                 self.reconnects.push_back(Authority::clone(auth));
             }
         }
+        self.snapshot_dirty |= changed;
+    }
+}
+
+// ===== impl DestinationSet =====
+
+impl<R> DestinationSet<R> {
+    /// Adds a watch, informing it of all addresses that are already known.
+    fn add_watch(&mut self, subscriber: Subscriber) {
+        for addr in &self.addrs {
+            if subscriber.tx.unbounded_send(Update::Insert(*addr)).is_err() {
+                return;
+            }
+        }
+        subscriber.endpoints.update(self.addrs.len(), self.resolved);
+        self.subscribers.push(subscriber);
+    }
+
+    /// Sends an update to all watches, forgetting those that have been dropped.
+    fn notify(&mut self, update: Update) {
+        let (count, resolved) = (self.addrs.len(), self.resolved);
+        self.subscribers.retain(|s| {
+            s.endpoints.update(count, resolved);
+            s.tx.unbounded_send(update).is_ok()
+        });
     }
 }
 
@@ -422,3 +648,23 @@ fn pb_to_sock_addr(pb: TcpAddress) -> Option<SocketAddr> {
         None => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_empty_after_grace_or_removal() {
+        let endpoints = Endpoints::new();
+        assert!(endpoints.is_currently_empty());
+        assert!(!endpoints.is_empty(Duration::from_secs(60)));
+        assert!(endpoints.is_empty(Duration::from_secs(0)));
+
+        endpoints.update(2, true);
+        assert!(!endpoints.is_currently_empty());
+        assert!(!endpoints.is_empty(Duration::from_secs(0)));
+
+        endpoints.update(0, true);
+        assert!(endpoints.is_empty(Duration::from_secs(60)));
+    }
+}
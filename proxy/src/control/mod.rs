@@ -1,4 +1,9 @@
+use std::fs;
+use std::io::Read;
 use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
 use bytes::Bytes;
@@ -18,31 +23,52 @@ mod codec;
 pub mod discovery;
 mod observe;
 pub mod pb;
+mod resolutions;
+mod snapshot;
 mod telemetry;
 
 use self::discovery::{Background as DiscoBg, Discovery, Watch};
 pub use self::discovery::Bind;
 pub use self::observe::Observe;
+pub use self::resolutions::Resolutions;
 use self::pb::proxy::telemetry::ReportRequest;
 use self::telemetry::Telemetry;
 
+#[derive(Clone)]
 pub struct Control {
     disco: Discovery,
+    resolutions: Resolutions,
+    connectivity: Connectivity,
 }
 
 pub struct Background {
     disco: DiscoBg,
+    connectivity: Connectivity,
 }
 
-pub fn new() -> (Control, Background) {
-    let (tx, rx) = self::discovery::new();
+/// Indicates whether the proxy is connected to the controller, and whether it ever has
+/// been.
+#[derive(Clone, Debug, Default)]
+pub struct Connectivity {
+    connected: Arc<AtomicBool>,
+    has_connected: Arc<AtomicBool>,
+}
+
+pub fn new(endpoint_cache_path: Option<PathBuf>) -> (Control, Background) {
+    let resolutions = Resolutions::default();
+    let (tx, rx) = self::discovery::new(endpoint_cache_path, resolutions.clone());
+
+    let connectivity = Connectivity::default();
 
     let c = Control {
         disco: tx,
+        resolutions,
+        connectivity: connectivity.clone(),
     };
 
     let b = Background {
         disco: rx,
+        connectivity,
     };
 
     (c, b)
@@ -54,6 +80,36 @@ impl Control {
     pub fn resolve<B>(&self, auth: &http::uri::Authority, bind: B) -> Watch<B> {
         self.disco.resolve(auth, bind)
     }
+
+    /// Returns a handle on metrics describing destination resolution.
+    pub fn resolutions(&self) -> &Resolutions {
+        &self.resolutions
+    }
+
+    /// Returns a handle indicating whether the proxy is connected to the controller.
+    pub fn connectivity(&self) -> &Connectivity {
+        &self.connectivity
+    }
+}
+
+// ===== impl Connectivity =====
+
+impl Connectivity {
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the proxy has connected to the controller since it started.
+    pub fn has_connected(&self) -> bool {
+        self.has_connected.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+        if connected {
+            self.has_connected.store(true, Ordering::Relaxed);
+        }
+    }
 }
 
 // ===== impl Background =====
@@ -62,7 +118,9 @@ impl Background {
     pub fn bind<S>(
         self,
         events: S,
+        max_report_bytes: usize,
         host_and_port: HostAndPort,
+        token_path: Option<PathBuf>,
         dns_config: dns::Config,
         executor: &Handle,
     ) -> Box<Future<Item = (), Error = ()>>
@@ -90,13 +148,26 @@ impl Background {
 
 
             let reconnect = Reconnect::new(h2_client);
-            let backoff = Backoff::new(reconnect, Duration::from_secs(5), executor);
-            AddOrigin::new(scheme, authority, backoff)
+            let backoff = Backoff::new(
+                reconnect,
+                Duration::from_secs(5),
+                self.connectivity.clone(),
+                executor,
+            );
+            let credentials = AddCredentials::new(token_path, backoff);
+            AddOrigin::new(scheme, authority, credentials)
         };
 
         let mut disco = self.disco.work();
-        let mut telemetry = Telemetry::new(events);
-
+        let mut telemetry = Telemetry::new(events, max_report_bytes);
+
+        // All controller RPCs are multiplexed as streams over the single HTTP/2
+        // connection held by `client`, so the proxy holds at most one connection to the
+        // controller regardless of how many destinations it resolves.
+        //
+        // h2 doesn't yet expose stream priorities, so destination RPCs are prioritized by
+        // polling them first: new resolutions are never held behind telemetry reports
+        // when the connection is only ready for one more stream.
         let fut = future::poll_fn(move || {
             trace!("poll rpc services");
             disco.poll_rpc(&mut EnumService(&mut client, PhantomData));
@@ -117,15 +188,18 @@ struct Backoff<S> {
     timer: Timeout,
     waiting: bool,
     wait_dur: Duration,
+    /// Records whether `inner` is connected.
+    connectivity: Connectivity,
 }
 
 impl<S> Backoff<S> {
-    fn new(inner: S, wait_dur: Duration, handle: &Handle) -> Self {
+    fn new(inner: S, wait_dur: Duration, connectivity: Connectivity, handle: &Handle) -> Self {
         Backoff {
             inner,
             timer: Timeout::new(wait_dur, handle).unwrap(),
             waiting: false,
             wait_dur,
+            connectivity,
         }
     }
 }
@@ -152,11 +226,16 @@ where
         match self.inner.poll_ready() {
             Err(err) => {
                 warn!("controller error: {:?}", err);
+                self.connectivity.set(false);
                 self.waiting = true;
                 self.timer.reset(Instant::now() + self.wait_dur);
                 Ok(Async::NotReady)
             }
-            ok => ok,
+            Ok(Async::Ready(())) => {
+                self.connectivity.set(true);
+                Ok(Async::Ready(()))
+            }
+            not_ready => not_ready,
         }
     }
 
@@ -206,6 +285,68 @@ where
     }
 }
 
+/// Wraps an HTTP service, presenting the token in a file as a bearer credential on every
+/// request.
+///
+/// The file is read for each request, so that a token that's refreshed before it
+/// expires, as the kubelet does for projected service account tokens, is presented as
+/// soon as it's written. Requests are sent without credentials if the file can't be
+/// read.
+struct AddCredentials<S> {
+    token_path: Option<PathBuf>,
+    inner: S,
+}
+
+impl<S> AddCredentials<S> {
+    fn new(token_path: Option<PathBuf>, inner: S) -> Self {
+        AddCredentials {
+            token_path,
+            inner,
+        }
+    }
+
+    fn authorization(&self) -> Option<http::header::HeaderValue> {
+        let path = match self.token_path {
+            Some(ref path) => path,
+            None => return None,
+        };
+        let mut token = String::new();
+        if let Err(e) = fs::File::open(path).and_then(|mut f| f.read_to_string(&mut token)) {
+            warn!("could not read controller token from {}: {}", path.display(), e);
+            return None;
+        }
+        let value = format!("Bearer {}", token.trim());
+        match http::header::HeaderValue::from_str(&value) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("controller token in {} is not a valid header value", path.display());
+                None
+            }
+        }
+    }
+}
+
+impl<S, B> Service for AddCredentials<S>
+where
+    S: Service<Request = http::Request<B>>,
+{
+    type Request = http::Request<B>;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Self::Request) -> Self::Future {
+        if let Some(authorization) = self.authorization() {
+            req.headers_mut().insert(http::header::AUTHORIZATION, authorization);
+        }
+        self.inner.call(req)
+    }
+}
+
 // ===== impl  EnumService =====
 
 struct EnumService<S, B>(S, PhantomData<B>);
@@ -276,3 +417,30 @@ impl From<self::discovery::ClientBody> for GrpcEncodingBody {
         GrpcEncodingBody::DestinationGet(body)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    use super::AddCredentials;
+
+    #[test]
+    fn presents_current_token() {
+        let path = env::temp_dir().join("conduit-proxy-controller-token-test");
+        let write = |token: &[u8]| {
+            fs::File::create(&path).and_then(|mut f| f.write_all(token)).unwrap();
+        };
+        let credentials = AddCredentials::new(Some(path.clone()), ());
+
+        write(b"first\n");
+        assert_eq!(credentials.authorization().unwrap(), "Bearer first");
+        write(b"second");
+        assert_eq!(credentials.authorization().unwrap(), "Bearer second");
+        write(b"bad\ntoken");
+        assert!(credentials.authorization().is_none());
+
+        assert!(AddCredentials::new(None, ()).authorization().is_none());
+    }
+}
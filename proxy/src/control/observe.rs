@@ -115,6 +115,11 @@ impl Stream for TapEvents {
                             self.remaining -= 1;
                             let _ = self.current.insert(req.clone(), ());
                         }
+                        Event::StreamRequestProgress(ref req, _) => {
+                            if !self.current.contains_key(req) {
+                                continue;
+                            }
+                        }
                         Event::StreamRequestFail(ref req, _) => {
                             if self.current.remove(req).is_none() {
                                 continue;
@@ -37,6 +37,7 @@ fn pb_response_end(
     ctx: &Arc<ctx::http::Request>,
     since_request_init: ::std::time::Duration,
     since_response_init: Option<::std::time::Duration>,
+    since_request_init_to_first_byte: Option<::std::time::Duration>,
     response_bytes: u64,
     grpc_status: u32,
 ) -> common::TapEvent {
@@ -49,6 +50,9 @@ fn pb_response_end(
         }),
         since_request_init: Some(pb_duration(&since_request_init)),
         since_response_init: since_response_init.as_ref().map(pb_duration),
+        since_request_init_to_first_byte: since_request_init_to_first_byte
+            .as_ref()
+            .map(pb_duration),
         response_bytes,
         grpc_status,
     };
@@ -164,14 +168,36 @@ impl<'a> TryFrom<&'a Event> for common::TapEvent {
                 }
             }
 
+            Event::StreamRequestProgress(ref ctx, ref progress) => {
+                let progress = tap_event::http::RequestProgress {
+                    id: Some(tap_event::http::StreamId {
+                        base: 0,
+                        // TODO FIXME
+                        stream: ctx.id as u64,
+                    }),
+                    since_request_init: Some(pb_duration(&progress.since_request_open)),
+                    request_bytes: progress.bytes_sent,
+                    request_bytes_per_second: progress.bytes_per_second,
+                };
+
+                common::TapEvent {
+                    source: Some((&ctx.server.remote).into()),
+                    target: Some((&ctx.client.remote).into()),
+                    event: Some(tap_event::Event::Http(tap_event::Http {
+                        event: Some(tap_event::http::Event::RequestProgress(progress)),
+                    })),
+                }
+            }
+
             Event::StreamRequestFail(ref ctx, ref fail) => {
-                pb_response_end(ctx, fail.since_request_open, None, 0, 0)
+                pb_response_end(ctx, fail.since_request_open, None, None, 0, 0)
             }
 
             Event::StreamResponseEnd(ref ctx, ref end) => pb_response_end(
                 &ctx.request,
                 end.since_request_open,
                 Some(end.since_response_open),
+                end.since_request_open_to_first_byte,
                 end.bytes_sent,
                 end.grpc_status.unwrap_or(0),
             ),
@@ -180,6 +206,7 @@ impl<'a> TryFrom<&'a Event> for common::TapEvent {
                 &ctx.request,
                 fail.since_request_open,
                 Some(fail.since_response_open),
+                fail.since_request_open_to_first_byte,
                 fail.bytes_sent,
                 0,
             ),
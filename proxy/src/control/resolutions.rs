@@ -0,0 +1,235 @@
+//! Metrics describing the lifecycle of destination resolutions.
+//!
+//! Metrics are labeled by the class of the resolved authority rather than by the
+//! authority itself, so that the number of series is bounded. They are rendered in the
+//! Prometheus text format.
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use http::uri::Authority;
+use ordermap::OrderMap;
+
+/// Upper bounds, in milliseconds, of the resolution latency histogram's buckets.
+const LATENCY_BUCKETS_MS: [u64; 8] = [1, 5, 10, 50, 100, 500, 1_000, 5_000];
+
+/// A shared handle on resolution metrics.
+#[derive(Clone, Debug, Default)]
+pub struct Resolutions(Arc<Mutex<OrderMap<Class, Stats>>>);
+
+/// A coarse classification of authorities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Class {
+    /// A Kubernetes service, e.g. `web.ns.svc.cluster.local`.
+    Kubernetes,
+    /// A name without any dots, e.g. `web`.
+    Unqualified,
+    /// Any other name.
+    External,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    active: u64,
+    started: u64,
+    updates: u64,
+    added: u64,
+    removed: u64,
+    resets: u64,
+    /// Counts of resolution latencies by bucket; the last bucket is unbounded.
+    latency_buckets: [u64; 9],
+    latency_sum_ms: u64,
+}
+
+// ===== impl Resolutions =====
+
+impl Resolutions {
+    /// Records that resolution of a new authority has started.
+    pub fn started(&self, class: Class) {
+        self.with(class, |s| {
+            s.active += 1;
+            s.started += 1;
+        });
+    }
+
+    /// Records how long the controller took to first respond to a resolution.
+    pub fn resolved(&self, class: Class, latency: Duration) {
+        let ms = latency.as_secs() * 1_000 + u64::from(latency.subsec_nanos() / 1_000_000);
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&le| ms <= le)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.with(class, |s| {
+            s.latency_buckets[bucket] += 1;
+            s.latency_sum_ms += ms;
+        });
+    }
+
+    /// Records an update received from the controller.
+    pub fn updated(&self, class: Class, added: usize, removed: usize) {
+        self.with(class, |s| {
+            s.updates += 1;
+            s.added += added as u64;
+            s.removed += removed as u64;
+        });
+    }
+
+    /// Records that a resolution's stream ended or failed and must be reestablished.
+    pub fn reset(&self, class: Class) {
+        self.with(class, |s| s.resets += 1);
+    }
+
+    fn with<F: FnOnce(&mut Stats)>(&self, class: Class, f: F) {
+        if let Ok(mut stats) = self.0.lock() {
+            f(stats.entry(class).or_insert_with(Stats::default));
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let stats = match self.0.lock() {
+            Ok(stats) => stats,
+            Err(_) => return String::new(),
+        };
+
+        let mut out = String::new();
+        let metrics: [(&str, &str, &str, fn(&Stats) -> u64); 6] = [
+            (
+                "destination_resolutions_active",
+                "gauge",
+                "Destinations currently being resolved.",
+                |s| s.active,
+            ),
+            (
+                "destination_resolutions_total",
+                "counter",
+                "Destinations for which resolution was started.",
+                |s| s.started,
+            ),
+            (
+                "destination_updates_total",
+                "counter",
+                "Updates received from the controller.",
+                |s| s.updates,
+            ),
+            (
+                "destination_endpoints_added_total",
+                "counter",
+                "Endpoints added by the controller.",
+                |s| s.added,
+            ),
+            (
+                "destination_endpoints_removed_total",
+                "counter",
+                "Endpoints removed by the controller.",
+                |s| s.removed,
+            ),
+            (
+                "destination_stream_resets_total",
+                "counter",
+                "Resolution streams that ended or failed and were reestablished.",
+                |s| s.resets,
+            ),
+        ];
+        for &(name, kind, help, value) in &metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (class, s) in stats.iter() {
+                let _ = writeln!(out, "{}{{class=\"{}\"}} {}", name, class, value(s));
+            }
+        }
+
+        let name = "destination_resolution_latency_ms";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time until the controller first responded to a resolution.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (class, s) in stats.iter() {
+            let mut count = 0;
+            for (i, n) in s.latency_buckets.iter().enumerate() {
+                count += n;
+                let le = LATENCY_BUCKETS_MS
+                    .get(i)
+                    .map(|le| le.to_string())
+                    .unwrap_or_else(|| "+Inf".into());
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{class=\"{}\",le=\"{}\"}} {}",
+                    name, class, le, count
+                );
+            }
+            let _ = writeln!(out, "{}_sum{{class=\"{}\"}} {}", name, class, s.latency_sum_ms);
+            let _ = writeln!(out, "{}_count{{class=\"{}\"}} {}", name, class, count);
+        }
+
+        out
+    }
+}
+
+// ===== impl Class =====
+
+impl<'a> From<&'a Authority> for Class {
+    fn from(authority: &'a Authority) -> Self {
+        let host = authority.host().trim_right_matches('.').to_lowercase();
+        if host.ends_with(".svc") || host.contains(".svc.") {
+            Class::Kubernetes
+        } else if !host.contains('.') {
+            Class::Unqualified
+        } else {
+            Class::External
+        }
+    }
+}
+
+impl ::std::fmt::Display for Class {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str(match *self {
+            Class::Kubernetes => "kubernetes",
+            Class::Unqualified => "unqualified",
+            Class::External => "external",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(s: &str) -> Class {
+        Class::from(&s.parse::<Authority>().unwrap())
+    }
+
+    #[test]
+    fn classifies_authorities() {
+        assert_eq!(class("web.ns.svc.cluster.local:8080"), Class::Kubernetes);
+        assert_eq!(class("web.ns.svc:8080"), Class::Kubernetes);
+        assert_eq!(class("web:8080"), Class::Unqualified);
+        assert_eq!(class("example.com"), Class::External);
+    }
+
+    #[test]
+    fn renders_latency_histogram() {
+        let r = Resolutions::default();
+        r.started(Class::Kubernetes);
+        r.resolved(Class::Kubernetes, Duration::from_millis(7));
+        r.resolved(Class::Kubernetes, Duration::from_secs(10));
+        r.updated(Class::Kubernetes, 3, 0);
+
+        let out = r.render();
+        assert!(out.contains("destination_resolutions_active{class=\"kubernetes\"} 1\n"));
+        assert!(out.contains("destination_endpoints_added_total{class=\"kubernetes\"} 3\n"));
+        assert!(out.contains(
+            "destination_resolution_latency_ms_bucket{class=\"kubernetes\",le=\"5\"} 0\n"
+        ));
+        assert!(out.contains(
+            "destination_resolution_latency_ms_bucket{class=\"kubernetes\",le=\"10\"} 1\n"
+        ));
+        assert!(out.contains(
+            "destination_resolution_latency_ms_bucket{class=\"kubernetes\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(out.contains("destination_resolution_latency_ms_count{class=\"kubernetes\"} 2\n"));
+    }
+}
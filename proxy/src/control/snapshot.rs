@@ -0,0 +1,142 @@
+//! Persists the last-known endpoints of each destination.
+//!
+//! When the proxy restarts while the controller is unavailable, it would otherwise be
+//! unable to route any outbound requests. Endpoints loaded from a snapshot are served
+//! (as stale) until the controller provides an update for their destination.
+//!
+//! The snapshot is a text file with one line per destination: its authority followed by
+//! its endpoint addresses, separated by spaces. Lines that cannot be parsed are ignored.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use http::uri::Authority;
+
+pub type Endpoints = HashMap<Authority, HashSet<SocketAddr>>;
+
+#[derive(Debug)]
+pub struct Snapshot {
+    path: PathBuf,
+}
+
+// ===== impl Snapshot =====
+
+impl Snapshot {
+    pub fn new(path: PathBuf) -> Self {
+        Snapshot {
+            path,
+        }
+    }
+
+    /// Reads the endpoints stored in the snapshot, if it exists.
+    pub fn load(&self) -> Endpoints {
+        let mut s = String::new();
+        let read = fs::File::open(&self.path).and_then(|mut f| f.read_to_string(&mut s));
+        match read {
+            Ok(_) => {
+                let endpoints = parse(&s);
+                info!(
+                    "loaded stale endpoints for {} destinations from {}",
+                    endpoints.len(),
+                    self.path.display()
+                );
+                endpoints
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Endpoints::new(),
+            Err(e) => {
+                warn!("could not read endpoint snapshot {}: {}", self.path.display(), e);
+                Endpoints::new()
+            }
+        }
+    }
+
+    /// Replaces the snapshot with the given endpoints.
+    ///
+    /// The snapshot is written to a temporary file that is then renamed over the existing
+    /// snapshot, so that a crash never leaves a partially written snapshot behind.
+    pub fn store<'a, I>(&self, endpoints: I)
+    where
+        I: IntoIterator<Item = (&'a Authority, &'a HashSet<SocketAddr>)>,
+    {
+        let tmp = self.path.with_extension("tmp");
+        let written = fs::File::create(&tmp)
+            .and_then(|mut f| f.write_all(render(endpoints).as_bytes()))
+            .and_then(|_| fs::rename(&tmp, &self.path));
+        match written {
+            Ok(()) => trace!("stored endpoint snapshot to {}", self.path.display()),
+            Err(e) => warn!("could not store endpoint snapshot {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+fn parse(s: &str) -> Endpoints {
+    let mut endpoints = Endpoints::new();
+    for line in s.lines() {
+        let mut words = line.split_whitespace();
+        let auth = match words.next().map(|w| w.parse::<Authority>()) {
+            Some(Ok(auth)) => auth,
+            _ => continue,
+        };
+        let addrs = words
+            .filter_map(|w| w.parse::<SocketAddr>().ok())
+            .collect::<HashSet<_>>();
+        if !addrs.is_empty() {
+            endpoints.insert(auth, addrs);
+        }
+    }
+    endpoints
+}
+
+fn render<'a, I>(endpoints: I) -> String
+where
+    I: IntoIterator<Item = (&'a Authority, &'a HashSet<SocketAddr>)>,
+{
+    let mut out = String::new();
+    for (auth, addrs) in endpoints {
+        if addrs.is_empty() {
+            continue;
+        }
+        out.push_str(auth.as_str());
+        for addr in addrs {
+            let _ = write!(out, " {}", addr);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let mut endpoints = Endpoints::new();
+        endpoints.insert(
+            "web.ns.svc.cluster.local:8080".parse().unwrap(),
+            vec!["10.1.1.1:8080".parse().unwrap(), "[fd00::1]:8080".parse().unwrap()]
+                .into_iter()
+                .collect(),
+        );
+        endpoints.insert(
+            "empty.ns.svc.cluster.local:80".parse().unwrap(),
+            HashSet::new(),
+        );
+
+        let parsed = parse(&render(&endpoints));
+        assert_eq!(parsed.len(), 1);
+        let auth = "web.ns.svc.cluster.local:8080".parse::<Authority>().unwrap();
+        assert_eq!(parsed.get(&auth), endpoints.get(&auth));
+    }
+
+    #[test]
+    fn ignores_invalid_lines() {
+        let parsed = parse("web:80 10.1.1.1:80 nope\n\nbad authority\nother:80 nope\n");
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed.values().next().unwrap().len(), 1);
+    }
+}
@@ -1,11 +1,20 @@
+use std::collections::VecDeque;
+use std::mem;
 use std::time::Instant;
 
 use futures::{Async, Future, Stream};
+use prost::Message;
 use tower::Service;
 use tower_grpc;
 
 use super::codec::Protobuf;
-use super::pb::proxy::telemetry::{ReportRequest, ReportResponse};
+use super::pb::proxy::telemetry::{
+    ClientTransport,
+    ReportRequest,
+    ReportResponse,
+    RequestScope,
+    ServerTransport,
+};
 use super::pb::proxy::telemetry::client::Telemetry as TelemetrySvc;
 use super::pb::proxy::telemetry::client::telemetry_methods::Report as ReportRpc;
 
@@ -25,6 +34,12 @@ type TelemetryStream<F> = tower_grpc::client::BodyFuture<
 pub struct Telemetry<T, F> {
     reports: T,
     in_flight: Option<(Instant, TelemetryStream<F>)>,
+
+    /// The maximum encoded size of a report message sent to the controller.
+    max_report_bytes: usize,
+
+    /// Parts of the latest report that have yet to be sent.
+    pending: VecDeque<ReportRequest>,
 }
 
 impl<T, F> Telemetry<T, F>
@@ -34,10 +49,12 @@ where
     F: Future<Item = ::http::Response<::tower_h2::RecvBody>>,
     F::Error: ::std::fmt::Debug,
 {
-    pub fn new(reports: T) -> Self {
+    pub fn new(reports: T, max_report_bytes: usize) -> Self {
         Telemetry {
             reports,
             in_flight: None,
+            max_report_bytes,
+            pending: VecDeque::new(),
         }
     }
 
@@ -83,6 +100,22 @@ where
                 }
             };
 
+            // Parts of a split report are sent one at a time, as the controller becomes
+            // ready.
+            if controller_ready {
+                if let Some(report) = self.pending.pop_front() {
+                    trace!(
+                        "report sent; requests={} accepts={} connects={}",
+                        report.requests.len(),
+                        report.server_transports.len(),
+                        report.client_transports.len(),
+                    );
+                    let rep = TelemetrySvc::new(&mut rpc).report(report);
+                    self.in_flight = Some((Instant::now(), rep));
+                    continue;
+                }
+            }
+
             match self.reports.poll() {
                 Ok(Async::NotReady) => {
                     return;
@@ -95,27 +128,158 @@ where
                     warn!("report stream error: {:?}", err);
                 }
                 Ok(Async::Ready(Some(report))) => {
-                    // Attempt to send the report.  Continue looping so that `reports` is
-                    // polled until it's not ready.
-                    if !controller_ready {
+                    // Any unsent parts of the previous report are superseded. Continue
+                    // looping so that `reports` is polled until it's not ready.
+                    for report in self.pending.drain(..) {
                         info!(
                             "report dropped; requests={} accepts={} connects={}",
                             report.requests.len(),
                             report.server_transports.len(),
                             report.client_transports.len(),
                         );
-                    } else {
-                        trace!(
-                            "report sent; requests={} accepts={} connects={}",
-                            report.requests.len(),
-                            report.server_transports.len(),
-                            report.client_transports.len(),
-                        );
-                        let rep = TelemetrySvc::new(&mut rpc).report(report);
-                        self.in_flight = Some((Instant::now(), rep));
+                    }
+                    self.pending = split(report, self.max_report_bytes);
+                    if self.pending.len() > 1 {
+                        debug!("report split into {} parts", self.pending.len());
                     }
                 }
             }
         }
     }
 }
+
+/// Splits a report into parts whose encoded sizes don't exceed `max_bytes`.
+///
+/// Each part describes the same process and proxy. Transports and requests are never
+/// split, so a part holding a single, very large request scope may still exceed
+/// `max_bytes`.
+fn split(report: ReportRequest, max_bytes: usize) -> VecDeque<ReportRequest> {
+    let mut parts = VecDeque::new();
+    if report.encoded_len() <= max_bytes {
+        parts.push_back(report);
+        return parts;
+    }
+
+    let ReportRequest {
+        process,
+        proxy,
+        server_transports,
+        client_transports,
+        requests,
+    } = report;
+    let empty = || ReportRequest {
+        process: process.clone(),
+        proxy,
+        server_transports: Vec::new(),
+        client_transports: Vec::new(),
+        requests: Vec::new(),
+    };
+
+    let base_len = empty().encoded_len();
+    let mut part = empty();
+    let mut part_len = base_len;
+    let items = server_transports
+        .into_iter()
+        .map(Item::Server)
+        .chain(client_transports.into_iter().map(Item::Client))
+        .chain(requests.into_iter().map(Item::Request));
+    for item in items {
+        let len = item.field_len();
+        if part_len + len > max_bytes && part_len > base_len {
+            parts.push_back(mem::replace(&mut part, empty()));
+            part_len = base_len;
+        }
+        if base_len + len > max_bytes {
+            warn!("report item exceeds {} bytes; sending it anyway", max_bytes);
+        }
+        part_len += len;
+        item.push(&mut part);
+    }
+    parts.push_back(part);
+    parts
+}
+
+/// A repeated field of a `ReportRequest`.
+enum Item {
+    Server(ServerTransport),
+    Client(ClientTransport),
+    Request(RequestScope),
+}
+
+impl Item {
+    /// Returns the number of bytes the item adds to an encoded report: its key, its
+    /// length, and its contents.
+    fn field_len(&self) -> usize {
+        let len = match *self {
+            Item::Server(ref t) => t.encoded_len(),
+            Item::Client(ref t) => t.encoded_len(),
+            Item::Request(ref r) => r.encoded_len(),
+        };
+        let mut varint_len = 1;
+        let mut n = len >> 7;
+        while n > 0 {
+            varint_len += 1;
+            n >>= 7;
+        }
+        1 + varint_len + len
+    }
+
+    fn push(self, report: &mut ReportRequest) {
+        match self {
+            Item::Server(t) => report.server_transports.push(t),
+            Item::Client(t) => report.client_transports.push(t),
+            Item::Request(r) => report.requests.push(r),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::pb::proxy::telemetry::{Process, RequestCtx};
+
+    fn report(requests: usize) -> ReportRequest {
+        ReportRequest {
+            process: Some(Process {
+                node: "node".into(),
+                scheduled_instance: "pod".into(),
+                scheduled_namespace: "ns".into(),
+            }),
+            proxy: 0,
+            server_transports: vec![ServerTransport::default(); 3],
+            client_transports: vec![ClientTransport::default(); 3],
+            requests: (0..requests)
+                .map(|i| RequestScope {
+                    ctx: Some(RequestCtx {
+                        path: format!("/path/{}", i),
+                        ..RequestCtx::default()
+                    }),
+                    ..RequestScope::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn small_reports_are_not_split() {
+        let parts = split(report(10), 1024 * 1024);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0], report(10));
+    }
+
+    #[test]
+    fn large_reports_are_split() {
+        let max = 256;
+        let parts = split(report(100), max);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(part.encoded_len() <= max, "{} > {}", part.encoded_len(), max);
+            assert_eq!(part.process, report(0).process);
+        }
+
+        let requests = parts.iter().flat_map(|p| p.requests.clone()).collect::<Vec<_>>();
+        assert_eq!(requests, report(100).requests);
+        let servers = parts.iter().map(|p| p.server_transports.len()).sum::<usize>();
+        assert_eq!(servers, 3);
+    }
+}
@@ -0,0 +1,87 @@
+//! Records panics so that crashes in the field can be diagnosed after the fact.
+//!
+//! When a report path is configured, each panic appends a report describing the panic
+//! (its message, location, and thread) to that file before the default panic handler
+//! runs. Backtraces are not captured in the report; set `RUST_BACKTRACE=1` to have the
+//! default handler write one to stderr. Aborts (and other signals) are not recorded.
+
+use std::fmt::Write as FmtWrite;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::panic::{self, PanicInfo};
+use std::path::PathBuf;
+use std::thread;
+
+use chrono::Utc;
+
+/// Installs a panic hook that records panics to `path`, if one is configured.
+///
+/// All panics are logged, whether or not a report path is configured.
+pub fn init(path: Option<PathBuf>) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let report = report(info);
+        error!("panic: {}", report.trim_right().replace('\n', "; "));
+
+        if let Some(ref path) = path {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut f| f.write_all(report.as_bytes()));
+            if let Err(e) = written {
+                error!("could not write crash report to {}: {}", path.display(), e);
+            }
+        }
+
+        default_hook(info);
+    }));
+}
+
+fn report(info: &PanicInfo) -> String {
+    let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+        *s
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.as_str()
+    } else {
+        "<unknown>"
+    };
+    let location = info.location().map(|loc| (loc.file(), loc.line()));
+    format_report(thread::current().name(), message, location)
+}
+
+fn format_report(thread: Option<&str>, message: &str, location: Option<(&str, u32)>) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "time: {}", Utc::now().to_rfc3339());
+    let _ = writeln!(report, "version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "thread: {}", thread.unwrap_or("<unnamed>"));
+    let _ = writeln!(report, "message: {}", message);
+    if let Some((file, line)) = location {
+        let _ = writeln!(report, "location: {}:{}", file, line);
+    }
+    report.push('\n');
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_reports() {
+        let report = format_report(Some("main"), "oh no", Some(("src/lib.rs", 42)));
+        let lines = report.lines().collect::<Vec<_>>();
+        let version = format!("version: {}", env!("CARGO_PKG_VERSION"));
+        assert!(lines[0].starts_with("time: "));
+        assert_eq!(
+            lines[1..].to_vec(),
+            vec![version.as_str(), "thread: main", "message: oh no", "location: src/lib.rs:42"]
+        );
+        // Reports appended to the same file are separated by a blank line.
+        assert!(report.ends_with("\n\n"));
+
+        let report = format_report(None, "oh no", None);
+        assert!(report.contains("thread: <unnamed>\n"));
+        assert!(!report.contains("location:"));
+    }
+}
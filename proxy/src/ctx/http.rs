@@ -3,15 +3,35 @@ use std::sync::Arc;
 
 use ctx;
 
+/// The names of the request headers that a `Request` may carry.
+///
+/// Other headers are only counted, so that events can't expose their values (which may
+/// hold personal data or credentials) to the control plane.
+pub type HeaderAllowlist = Arc<Vec<http::header::HeaderName>>;
+
 /// Describes a stream's request headers.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Request {
     // A numeric ID useful for debugging & correlation.
+    //
+    // The proxy does not retry requests, so each ID identifies exactly one attempt and
+    // request counts derived from these events are attempt counts. If retries are
+    // added, attempts should share their logical request's ID and carry an attempt
+    // number, so that retries don't inflate request totals.
     pub id: usize,
 
     pub uri: http::Uri,
     pub method: http::Method,
 
+    /// The request's headers that are on the `HeaderAllowlist`.
+    pub headers: Vec<(http::header::HeaderName, http::header::HeaderValue)>,
+
+    /// The number of the request's headers that are not on the `HeaderAllowlist`.
+    pub omitted_headers: usize,
+
+    /// The total size, in bytes, of the names and values of the omitted headers.
+    pub omitted_header_bytes: usize,
+
     /// Identifies the proxy server that received the request.
     pub server: Arc<ctx::transport::Server>,
 
@@ -39,18 +59,39 @@ impl Request {
         request: &http::Request<B>,
         server: &Arc<ctx::transport::Server>,
         client: &Arc<ctx::transport::Client>,
+        allowed_headers: &HeaderAllowlist,
         id: usize,
     ) -> Arc<Self> {
+        let mut headers = Vec::new();
+        let mut omitted_headers = 0;
+        let mut omitted_header_bytes = 0;
+        for (name, value) in request.headers().iter() {
+            if allowed_headers.contains(name) {
+                headers.push((name.clone(), value.clone()));
+            } else {
+                omitted_headers += 1;
+                omitted_header_bytes += name.as_str().len() + value.len();
+            }
+        }
+
         let r = Self {
             id,
             uri: request.uri().clone(),
             method: request.method().clone(),
+            headers,
+            omitted_headers,
+            omitted_header_bytes,
             server: Arc::clone(server),
             client: Arc::clone(client),
         };
 
         Arc::new(r)
     }
+
+    /// Returns the first value of the header `name`, if it's allowed and present.
+    pub fn header(&self, name: &http::header::HeaderName) -> Option<&http::header::HeaderValue> {
+        self.headers.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref v)| v)
+    }
 }
 
 impl Response {
@@ -63,3 +104,40 @@ impl Response {
         Arc::new(r)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use http;
+
+    use ctx;
+
+    #[test]
+    fn only_allowed_headers_are_carried() {
+        let process = ctx::Process::new("test", "test", "test");
+        let proxy = ctx::Proxy::inbound(&process);
+        let addr: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        let server = ctx::transport::Server::new(&proxy, &addr, &addr, &None);
+        let client = ctx::transport::Client::new(&proxy, &addr);
+        let req = http::Request::builder()
+            .uri("/")
+            .header(http::header::USER_AGENT, "curl/7.58")
+            .header(http::header::AUTHORIZATION, "Bearer secret")
+            .header(http::header::COOKIE, "session=1")
+            .body(())
+            .unwrap();
+
+        let allowed = Arc::new(vec![http::header::USER_AGENT]);
+        let r = super::Request::new(&req, &server, &client, &allowed, 0);
+        assert_eq!(r.header(&http::header::USER_AGENT).unwrap(), "curl/7.58");
+        assert_eq!(r.header(&http::header::AUTHORIZATION), None);
+        assert_eq!(r.headers.len(), 1);
+        assert_eq!(r.omitted_headers, 2);
+        assert_eq!(
+            r.omitted_header_bytes,
+            "authorization".len() + "Bearer secret".len() + "cookie".len() + "session=1".len()
+        );
+    }
+}
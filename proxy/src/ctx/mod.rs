@@ -7,6 +7,10 @@
 //! As a rule, context types should implement `Clone + Send + Sync`. This allows them to
 //! be stored in `http::Extensions`, for instance. Furthermore, because these contexts
 //! will be sent to a telemetry processing thread, we want to avoid excessive cloning.
+//!
+//! Contexts are reference-counted rather than allocated from a per-connection arena:
+//! events, taps, and slow request logs may still refer to a request's context on the
+//! telemetry thread after the connection it was received on has closed.
 use control::pb::proxy::telemetry as proto;
 use std::env;
 use std::sync::Arc;
@@ -31,6 +35,16 @@ pub struct Process {
     ///
     /// Empty if unknown.
     pub scheduled_namespace: String,
+
+    /// Identifies the availability zone that the `node` is in.
+    ///
+    /// Empty if unknown.
+    pub zone: String,
+
+    /// Identifies the cluster that the `node` is in.
+    ///
+    /// Empty if unknown.
+    pub cluster: String,
 }
 
 /// Indicates the orientation of traffic, relative to a sidecar proxy.
@@ -52,13 +66,16 @@ impl Process {
             node: node.into(),
             scheduled_instance: instance.into(),
             scheduled_namespace: ns.into(),
+            zone: String::new(),
+            cluster: String::new(),
         })
     }
 
     /// Construct a new `Process` from environment variables.
     pub fn from_env() -> Arc<Self> {
         fn get_var(key: &str) -> String {
-            env::var(key).unwrap_or_else(|why| {
+            let key = ::config::env_var_name(key);
+            env::var(&key).unwrap_or_else(|why| {
                 warn!(
                     "Process::from_env(): Failed to get value of {} environment variable: {:?}",
                     key,
@@ -68,13 +85,22 @@ impl Process {
             })
         }
 
+        // Not every scheduler knows about zones and clusters, so they're optional.
+        fn get_optional_var(key: &str) -> String {
+            env::var(::config::env_var_name(key)).unwrap_or_default()
+        }
+
         let node = get_var(::config::ENV_NODE_NAME);
         let scheduled_instance = get_var(::config::ENV_POD_NAME);
         let scheduled_namespace = get_var(::config::ENV_POD_NAMESPACE);
+        let zone = get_optional_var(::config::ENV_ZONE_NAME);
+        let cluster = get_optional_var(::config::ENV_CLUSTER_NAME);
         Arc::new(Self {
             node,
             scheduled_instance,
             scheduled_namespace,
+            zone,
+            cluster,
         })
     }
 }
@@ -0,0 +1,196 @@
+//! Annotates responses with headers describing how the proxy handled their requests, so
+//! that the mesh's behavior can be debugged with nothing but `curl -v`.
+//!
+//! When enabled, each response received from an endpoint carries:
+//!
+//! - `conduit-debug-endpoint`: the address of the endpoint that served the request;
+//! - `conduit-debug-attempts`: the number of times the request was sent, which is always
+//!   1, since the proxy doesn't retry requests;
+//! - `conduit-debug-queue-time`: how long the request waited, after it was received,
+//!   before it was dispatched to the endpoint, e.g. for a connection to be established
+//!   or for the balancer to become ready;
+//! - `conduit-debug-connect-timeout`: the timeout applied to connecting to the endpoint.
+//!
+//! The headers reveal the addresses of endpoints to clients, so they're only meant to
+//! be enabled while debugging.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll};
+use http;
+use http::header::{HeaderName, HeaderValue};
+use tower::Service;
+
+const ENDPOINT: &str = "conduit-debug-endpoint";
+const ATTEMPTS: &str = "conduit-debug-attempts";
+const QUEUE_TIME: &str = "conduit-debug-queue-time";
+const CONNECT_TIMEOUT: &str = "conduit-debug-connect-timeout";
+
+/// When a request was received, carried in its extensions.
+#[derive(Clone, Copy, Debug)]
+pub struct Received(Instant);
+
+/// Annotates the responses of a single endpoint, if enabled.
+#[derive(Debug)]
+pub struct Annotate<S> {
+    inner: S,
+    annotations: Option<Arc<Annotations>>,
+}
+
+/// The headers that are the same for each of an endpoint's responses.
+#[derive(Debug)]
+struct Annotations {
+    endpoint: HeaderValue,
+    connect_timeout: HeaderValue,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    annotations: Option<Arc<Annotations>>,
+    queue_time: Option<Duration>,
+}
+
+/// Records that `req` was received now, so that its queue time can be reported.
+pub fn received<B>(req: &mut http::Request<B>) {
+    req.extensions_mut().insert(Received(Instant::now()));
+}
+
+// ===== impl Annotate =====
+
+impl<S> Annotate<S> {
+    pub fn new(inner: S, endpoint: &SocketAddr, connect_timeout: Duration) -> Self {
+        let annotations = Annotations {
+            endpoint: header_value(endpoint.to_string()),
+            connect_timeout: header_value(millis(connect_timeout)),
+        };
+        Annotate {
+            inner,
+            annotations: Some(Arc::new(annotations)),
+        }
+    }
+
+    /// Returns an `Annotate` that leaves responses untouched.
+    pub fn disabled(inner: S) -> Self {
+        Annotate {
+            inner,
+            annotations: None,
+        }
+    }
+}
+
+impl<S, A, B> Service for Annotate<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let queue_time = match self.annotations {
+            Some(_) => req.extensions().get::<Received>().map(|r| r.0.elapsed()),
+            None => None,
+        };
+        ResponseFuture {
+            inner: self.inner.call(req),
+            annotations: self.annotations.clone(),
+            queue_time,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut rsp = try_ready!(self.inner.poll());
+        if let Some(ref annotations) = self.annotations {
+            let headers = rsp.headers_mut();
+            headers.insert(HeaderName::from_static(ENDPOINT), annotations.endpoint.clone());
+            headers.insert(HeaderName::from_static(ATTEMPTS), HeaderValue::from_static("1"));
+            if let Some(queue_time) = self.queue_time {
+                headers.insert(
+                    HeaderName::from_static(QUEUE_TIME),
+                    header_value(millis(queue_time)),
+                );
+            }
+            headers.insert(
+                HeaderName::from_static(CONNECT_TIMEOUT),
+                annotations.connect_timeout.clone(),
+            );
+        }
+        Ok(Async::Ready(rsp))
+    }
+}
+
+fn millis(d: Duration) -> String {
+    format!("{}ms", d.as_secs() * 1_000 + u64::from(d.subsec_nanos() / 1_000_000))
+}
+
+fn header_value(s: String) -> HeaderValue {
+    HeaderValue::from_str(&s).expect("formatted values are valid headers")
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+
+    use super::*;
+
+    struct Respond;
+
+    impl Service for Respond {
+        type Request = http::Request<()>;
+        type Response = http::Response<()>;
+        type Error = ();
+        type Future = FutureResult<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            future::ok(http::Response::new(()))
+        }
+    }
+
+    #[test]
+    fn annotates_responses() {
+        let endpoint = "10.1.2.3:8080".parse().unwrap();
+        let mut svc = Annotate::new(Respond, &endpoint, Duration::from_millis(1500));
+
+        let mut req = http::Request::new(());
+        received(&mut req);
+        let rsp = svc.call(req).wait().unwrap();
+        assert_eq!(rsp.headers()[ENDPOINT], "10.1.2.3:8080");
+        assert_eq!(rsp.headers()[ATTEMPTS], "1");
+        assert!(rsp.headers()[QUEUE_TIME].to_str().unwrap().ends_with("ms"));
+        assert_eq!(rsp.headers()[CONNECT_TIMEOUT], "1500ms");
+
+        // Requests that weren't received by a listener have no queue time.
+        let rsp = svc.call(http::Request::new(())).wait().unwrap();
+        assert!(rsp.headers().get(QUEUE_TIME).is_none());
+    }
+
+    #[test]
+    fn leaves_responses_untouched_when_disabled() {
+        let mut svc = Annotate::disabled(Respond);
+        let mut req = http::Request::new(());
+        received(&mut req);
+        let rsp = svc.call(req).wait().unwrap();
+        assert!(rsp.headers().is_empty());
+    }
+}
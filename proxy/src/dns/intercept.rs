@@ -0,0 +1,396 @@
+//! Answers DNS queries sent by the local application.
+//!
+//! When the application's DNS traffic is redirected to the proxy (i.e. by iptables), the
+//! proxy answers `A` and `AAAA` queries using its own resolver, so that the addresses the
+//! application sees match the ones the proxy would resolve for the same name. Answers are
+//! cached briefly. Other query types are answered with `NOTIMP`.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket as StdUdpSocket};
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll};
+use tokio_core::net::UdpSocket;
+use tokio_core::reactor::Handle;
+use url;
+
+use super::{Config, IpAddrFuture, Resolver};
+
+/// How long answers are cached, and the TTL advertised to the application.
+const TTL_SECS: u32 = 5;
+
+/// The maximum number of cached answers.
+const CACHE_CAPACITY: usize = 1_024;
+
+/// The maximum number of queries that may be resolving at once.
+const MAX_PENDING: usize = 256;
+
+const MAX_MESSAGE_LEN: usize = 512;
+const HEADER_LEN: usize = 12;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+const RCODE_NOERROR: u8 = 0;
+const RCODE_FORMERR: u8 = 1;
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NOTIMP: u8 = 4;
+
+/// Serves DNS queries on a UDP socket.
+pub struct Intercept {
+    socket: UdpSocket,
+    resolver: Resolver,
+    cache: HashMap<(String, u16), (Option<IpAddr>, Instant)>,
+    pending: Vec<Pending>,
+    replies: VecDeque<(Vec<u8>, SocketAddr)>,
+    buf: Vec<u8>,
+}
+
+struct Pending {
+    query: Query,
+    peer: SocketAddr,
+    future: IpAddrFuture,
+}
+
+/// A parsed single-question DNS query.
+#[derive(Debug, PartialEq)]
+struct Query {
+    id: u16,
+    flags: u16,
+    name: String,
+    qtype: u16,
+    qclass: u16,
+    /// The raw question section, echoed back in the reply.
+    question: Vec<u8>,
+}
+
+// ===== impl Intercept =====
+
+impl Intercept {
+    /// Answers queries received on `socket`, which has already been bound.
+    pub fn new(socket: StdUdpSocket, config: Config, executor: &Handle) -> io::Result<Self> {
+        let socket = UdpSocket::from_socket(socket, executor)?;
+        info!("intercepting DNS on {}", socket.local_addr()?);
+        Ok(Intercept {
+            socket,
+            resolver: Resolver::new(config, executor),
+            cache: HashMap::new(),
+            pending: Vec::new(),
+            replies: VecDeque::new(),
+            buf: vec![0; MAX_MESSAGE_LEN],
+        })
+    }
+
+    fn recv(&mut self) -> io::Result<()> {
+        while self.pending.len() < MAX_PENDING {
+            let (n, peer) = match self.socket.recv_from(&mut self.buf) {
+                Ok(r) => r,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            let query = match Query::parse(&self.buf[..n]) {
+                Ok(q) => q,
+                Err(Some(id)) => {
+                    trace!("malformed DNS query from {}", peer);
+                    self.replies.push_back((error_reply(id, RCODE_FORMERR), peer));
+                    continue;
+                }
+                Err(None) => {
+                    trace!("ignoring DNS message from {}", peer);
+                    continue;
+                }
+            };
+            trace!("DNS query from {}: {:?}", peer, query);
+
+            if query.qclass != CLASS_IN || (query.qtype != TYPE_A && query.qtype != TYPE_AAAA) {
+                self.replies.push_back((query.reply(RCODE_NOTIMP, None), peer));
+                continue;
+            }
+
+            let key = (query.name.to_lowercase(), query.qtype);
+            if let Some(&(addr, expiry)) = self.cache.get(&key) {
+                if expiry > Instant::now() {
+                    self.replies.push_back((query.reply(RCODE_NOERROR, addr), peer));
+                    continue;
+                }
+            }
+
+            let future = self.resolver.resolve_host(&url::Host::Domain(query.name.clone()));
+            self.pending.push(Pending {
+                query,
+                peer,
+                future,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn poll_pending(&mut self) {
+        let mut i = 0;
+        while i < self.pending.len() {
+            let result = match self.pending[i].future.poll() {
+                Ok(Async::NotReady) => {
+                    i += 1;
+                    continue;
+                }
+                Ok(Async::Ready(addr)) => Ok(addr),
+                Err(_) => Err(()),
+            };
+
+            let Pending { query, peer, .. } = self.pending.swap_remove(i);
+            let reply = match result {
+                Ok(addr) => {
+                    let addr = match (query.qtype, addr) {
+                        (TYPE_A, IpAddr::V4(_)) | (TYPE_AAAA, IpAddr::V6(_)) => Some(addr),
+                        _ => None,
+                    };
+                    self.cache_insert(&query, addr);
+                    query.reply(RCODE_NOERROR, addr)
+                }
+                Err(()) => {
+                    debug!("failed to resolve {} for DNS query", query.name);
+                    query.reply(RCODE_SERVFAIL, None)
+                }
+            };
+            self.replies.push_back((reply, peer));
+        }
+    }
+
+    fn cache_insert(&mut self, query: &Query, addr: Option<IpAddr>) {
+        let now = Instant::now();
+        if self.cache.len() >= CACHE_CAPACITY {
+            self.cache.retain(|_, &mut (_, expiry)| expiry > now);
+            if self.cache.len() >= CACHE_CAPACITY {
+                self.cache.clear();
+            }
+        }
+        let expiry = now + Duration::from_secs(u64::from(TTL_SECS));
+        self.cache
+            .insert((query.name.to_lowercase(), query.qtype), (addr, expiry));
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        while let Some((reply, peer)) = self.replies.pop_front() {
+            match self.socket.send_to(&reply, &peer) {
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.replies.push_front((reply, peer));
+                    return Ok(());
+                }
+                Err(e) => {
+                    // Failing to reply to one peer shouldn't stop the server.
+                    debug!("failed to send DNS reply to {}: {}", peer, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Future for Intercept {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<(), io::Error> {
+        loop {
+            self.recv()?;
+            self.poll_pending();
+            self.send()?;
+
+            // Resolutions completed while polling may have made room for more queries.
+            if self.pending.len() >= MAX_PENDING || self.socket.poll_read().is_not_ready() {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+// ===== impl Query =====
+
+impl Query {
+    /// Parses a query, returning its ID on error if it can be replied to.
+    fn parse(msg: &[u8]) -> Result<Self, Option<u16>> {
+        if msg.len() < HEADER_LEN {
+            return Err(None);
+        }
+
+        let id = read_u16(msg, 0);
+        let flags = read_u16(msg, 2);
+        let is_response = flags & 0x8000 != 0;
+        if is_response {
+            return Err(None);
+        }
+
+        let opcode = (flags >> 11) & 0xf;
+        let qdcount = read_u16(msg, 4);
+        if opcode != 0 || qdcount != 1 {
+            return Err(Some(id));
+        }
+
+        let mut name = String::new();
+        let mut pos = HEADER_LEN;
+        loop {
+            let len = *msg.get(pos).ok_or(Some(id))? as usize;
+            pos += 1;
+            if len == 0 {
+                break;
+            }
+            // Compression pointers aren't valid in a lone question.
+            if len > 63 {
+                return Err(Some(id));
+            }
+            let label = msg.get(pos..pos + len).ok_or(Some(id))?;
+            if !name.is_empty() {
+                name.push('.');
+            }
+            for &b in label {
+                if !is_label_byte(b) {
+                    return Err(Some(id));
+                }
+                name.push(b as char);
+            }
+            pos += len;
+        }
+        if msg.len() < pos + 4 {
+            return Err(Some(id));
+        }
+
+        Ok(Query {
+            id,
+            flags,
+            name,
+            qtype: read_u16(msg, pos),
+            qclass: read_u16(msg, pos + 2),
+            question: msg[HEADER_LEN..pos + 4].to_vec(),
+        })
+    }
+
+    fn reply(&self, rcode: u8, addr: Option<IpAddr>) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(MAX_MESSAGE_LEN);
+
+        // QR=1, preserve opcode and RD, RA=1.
+        let flags = 0x8000 | (self.flags & 0x7900) | 0x0080 | u16::from(rcode);
+        let ancount = if addr.is_some() { 1 } else { 0 };
+        write_u16(&mut msg, self.id);
+        write_u16(&mut msg, flags);
+        write_u16(&mut msg, 1);
+        write_u16(&mut msg, ancount);
+        write_u16(&mut msg, 0);
+        write_u16(&mut msg, 0);
+        msg.extend_from_slice(&self.question);
+
+        if let Some(addr) = addr {
+            // A pointer to the name in the question section.
+            write_u16(&mut msg, 0xc000 | HEADER_LEN as u16);
+            write_u16(&mut msg, self.qtype);
+            write_u16(&mut msg, CLASS_IN);
+            write_u16(&mut msg, (TTL_SECS >> 16) as u16);
+            write_u16(&mut msg, TTL_SECS as u16);
+            match addr {
+                IpAddr::V4(ip) => {
+                    write_u16(&mut msg, 4);
+                    msg.extend_from_slice(&ip.octets());
+                }
+                IpAddr::V6(ip) => {
+                    write_u16(&mut msg, 16);
+                    msg.extend_from_slice(&ip.octets());
+                }
+            }
+        }
+
+        msg
+    }
+}
+
+fn error_reply(id: u16, rcode: u8) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(HEADER_LEN);
+    write_u16(&mut msg, id);
+    write_u16(&mut msg, 0x8080 | u16::from(rcode));
+    for _ in 0..4 {
+        write_u16(&mut msg, 0);
+    }
+    msg
+}
+
+fn is_label_byte(b: u8) -> bool {
+    (b >= b'a' && b <= b'z') || (b >= b'A' && b <= b'Z') || (b >= b'0' && b <= b'9') ||
+        b == b'-' || b == b'_'
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> u16 {
+    (u16::from(buf[pos]) << 8) | u16::from(buf[pos + 1])
+}
+
+fn write_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.push((v >> 8) as u8);
+    buf.push(v as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut msg = Vec::new();
+        write_u16(&mut msg, 0xbeef);
+        write_u16(&mut msg, 0x0100); // RD
+        write_u16(&mut msg, 1);
+        write_u16(&mut msg, 0);
+        write_u16(&mut msg, 0);
+        write_u16(&mut msg, 0);
+        for label in name.split('.') {
+            msg.push(label.len() as u8);
+            msg.extend_from_slice(label.as_bytes());
+        }
+        msg.push(0);
+        write_u16(&mut msg, qtype);
+        write_u16(&mut msg, CLASS_IN);
+        msg
+    }
+
+    #[test]
+    fn parse_query() {
+        let msg = query("web.default.svc.cluster.local", TYPE_A);
+        let q = Query::parse(&msg).unwrap();
+        assert_eq!(q.id, 0xbeef);
+        assert_eq!(q.name, "web.default.svc.cluster.local");
+        assert_eq!(q.qtype, TYPE_A);
+        assert_eq!(q.qclass, CLASS_IN);
+        assert_eq!(&q.question[..], &msg[HEADER_LEN..]);
+    }
+
+    #[test]
+    fn parse_rejects_truncated() {
+        let msg = query("web.default", TYPE_A);
+        assert_eq!(Query::parse(&msg[..4]), Err(None));
+        assert_eq!(Query::parse(&msg[..msg.len() - 1]), Err(Some(0xbeef)));
+    }
+
+    #[test]
+    fn reply_with_answer() {
+        let msg = query("web", TYPE_A);
+        let q = Query::parse(&msg).unwrap();
+        let reply = q.reply(RCODE_NOERROR, Some(IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3))));
+
+        assert_eq!(read_u16(&reply, 0), 0xbeef);
+        assert_eq!(read_u16(&reply, 2), 0x8180);
+        assert_eq!(read_u16(&reply, 6), 1);
+        assert_eq!(&reply[HEADER_LEN..msg.len()], &msg[HEADER_LEN..]);
+        assert_eq!(&reply[reply.len() - 4..], &[10, 1, 2, 3]);
+    }
+
+    #[test]
+    fn reply_without_answer() {
+        let msg = query("web", TYPE_AAAA);
+        let q = Query::parse(&msg).unwrap();
+        let reply = q.reply(RCODE_SERVFAIL, None);
+        assert_eq!(read_u16(&reply, 2) & 0xf, u16::from(RCODE_SERVFAIL));
+        assert_eq!(read_u16(&reply, 6), 0);
+        assert_eq!(reply.len(), msg.len());
+    }
+}
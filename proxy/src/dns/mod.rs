@@ -9,6 +9,10 @@ use std::str::FromStr;
 use tokio_core::reactor::Handle;
 use url;
 
+mod intercept;
+
+pub use self::intercept::Intercept;
+
 #[derive(Clone, Debug)]
 pub struct Config(domain::resolv::ResolvConf);
 
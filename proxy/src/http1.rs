@@ -0,0 +1,224 @@
+//! Serves the control listener's admin endpoints to HTTP/1.1 clients.
+//!
+//! The control listener's gRPC services require HTTP/2, but its admin endpoints, e.g.
+//! the status page, are more convenient to reach with clients that default to HTTP/1.1.
+//! Each connection is routed on its first bytes: connections that begin with the HTTP/2
+//! connection preface are served as HTTP/2, and any other connection is read as a single
+//! HTTP/1.1 request, which is answered by the admin endpoints and then closed.
+
+use std::io;
+
+use bytes::Bytes;
+use futures::Future;
+use futures::future::{self, Loop};
+use http;
+use tokio_io::{io as tio, AsyncRead, AsyncWrite};
+
+use local::Responders;
+use transport::Prefixed;
+
+/// Begins every HTTP/2 connection (RFC 7540, Section 3.5).
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The longest request head that's read before the connection is closed.
+const MAX_HEAD_LEN: usize = 8 * 1024;
+
+/// The protocol a connection speaks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Http1,
+    Http2,
+}
+
+/// Reads from `io` until it's clear whether it begins with the HTTP/2 preface.
+///
+/// Resolves to the detected protocol and to `io`, from which the bytes that were read
+/// will be read again.
+pub fn detect<T>(io: T) -> Box<Future<Item = (Protocol, Prefixed<T>), Error = io::Error>>
+where
+    T: AsyncRead + 'static,
+{
+    let f = future::loop_fn((io, Vec::new()), |(io, mut read)| {
+        let want = H2_PREFACE.len() - read.len();
+        tio::read(io, vec![0u8; want]).and_then(move |(io, buf, n)| {
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no preface"));
+            }
+            read.extend_from_slice(&buf[..n]);
+
+            if !H2_PREFACE.starts_with(&read) {
+                return Ok(Loop::Break((Protocol::Http1, Prefixed::new(read, io))));
+            }
+            if read.len() == H2_PREFACE.len() {
+                return Ok(Loop::Break((Protocol::Http2, Prefixed::new(read, io))));
+            }
+            Ok(Loop::Continue((io, read)))
+        })
+    });
+    Box::new(f)
+}
+
+/// Answers a single HTTP/1.1 request on `io` with `responders`, then closes `io`.
+pub fn serve<T>(io: T, responders: Responders<()>) -> Box<Future<Item = (), Error = io::Error>>
+where
+    T: AsyncRead + AsyncWrite + 'static,
+{
+    let f = read_head(io)
+        .and_then(move |(io, head)| tio::write_all(io, respond(&head, &responders)))
+        // `io` is closed once it's dropped.
+        .map(|_| ());
+    Box::new(f)
+}
+
+/// Reads a request head, up to but excluding the empty line that ends it.
+fn read_head<T>(io: T) -> Box<Future<Item = (T, Vec<u8>), Error = io::Error>>
+where
+    T: AsyncRead + 'static,
+{
+    let f = future::loop_fn((io, Vec::new()), |(io, mut head)| {
+        tio::read(io, vec![0u8; 1024]).and_then(move |(io, buf, n)| {
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete request"));
+            }
+            head.extend_from_slice(&buf[..n]);
+
+            if let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+                head.truncate(end);
+                return Ok(Loop::Break((io, head)));
+            }
+            if head.len() > MAX_HEAD_LEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "request too large"));
+            }
+            Ok(Loop::Continue((io, head)))
+        })
+    });
+    Box::new(f)
+}
+
+/// Returns the encoded response to the request with the given `head`.
+fn respond(head: &[u8], responders: &Responders<()>) -> Vec<u8> {
+    let rsp = match parse_head(head) {
+        Ok(req) => match responders.respond(&req) {
+            Some(rsp) => rsp.into_http(),
+            None => {
+                debug!("no admin endpoint for HTTP/1.1 request to {}", req.uri());
+                status(http::StatusCode::NOT_FOUND)
+            }
+        },
+        Err(reason) => {
+            debug!("invalid HTTP/1.1 request: {}", reason);
+            status(http::StatusCode::BAD_REQUEST)
+        }
+    };
+    encode(rsp)
+}
+
+fn parse_head(head: &[u8]) -> Result<http::Request<()>, &'static str> {
+    let head = ::std::str::from_utf8(head).map_err(|_| "request head isn't UTF-8")?;
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next().unwrap_or("").splitn(3, ' ');
+    let method = request_line.next().unwrap_or("");
+    let target = request_line.next().ok_or("missing request target")?;
+    let version = request_line.next().ok_or("missing HTTP version")?;
+    if !version.starts_with("HTTP/1.") {
+        return Err("unsupported HTTP version");
+    }
+
+    let mut req = http::Request::builder();
+    req.method(method).uri(target);
+    for line in lines {
+        let colon = line.find(':').ok_or("malformed header")?;
+        let (name, value) = line.split_at(colon);
+        req.header(name, value[1..].trim());
+    }
+    req.body(()).map_err(|_| "invalid request")
+}
+
+fn status(status: http::StatusCode) -> http::Response<Bytes> {
+    let mut rsp = http::Response::new(Bytes::new());
+    *rsp.status_mut() = status;
+    rsp
+}
+
+fn encode(rsp: http::Response<Bytes>) -> Vec<u8> {
+    let status = rsp.status();
+    let mut out = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    ).into_bytes();
+    for (name, value) in rsp.headers() {
+        out.extend_from_slice(name.as_str().as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    let body = rsp.body();
+    out.extend_from_slice(format!("content-length: {}\r\n", body.len()).as_bytes());
+    out.extend_from_slice(b"connection: close\r\n\r\n");
+    out.extend_from_slice(body);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+    use local::{self, Respond};
+
+    struct Hello;
+
+    impl<B> Respond<B> for Hello {
+        fn respond(&self, req: &http::Request<B>) -> Option<local::Response> {
+            if req.uri().path() != "/hello" {
+                return None;
+            }
+            let mut rsp = http::Response::new(Bytes::from("hi"));
+            rsp.headers_mut().insert("x-host", req.headers()["host"].clone());
+            Some(local::Response::new(rsp))
+        }
+    }
+
+    #[test]
+    fn detects_http2() {
+        let mut conn = H2_PREFACE.to_vec();
+        conn.extend_from_slice(b"\0\0\0\x04");
+        let (protocol, mut io) = detect(Cursor::new(conn.clone())).wait().unwrap();
+        assert_eq!(protocol, Protocol::Http2);
+        let mut replayed = Vec::new();
+        io.read_to_end(&mut replayed).unwrap();
+        assert_eq!(replayed, conn);
+
+        let conn = b"POST /hello HTTP/1.1\r\n\r\n".to_vec();
+        let (protocol, mut io) = detect(Cursor::new(conn.clone())).wait().unwrap();
+        assert_eq!(protocol, Protocol::Http1);
+        let mut replayed = Vec::new();
+        io.read_to_end(&mut replayed).unwrap();
+        assert_eq!(replayed, conn);
+
+        assert!(detect(Cursor::new(b"PRI *".to_vec())).wait().is_err());
+    }
+
+    #[test]
+    fn answers_admin_requests() {
+        let responders = Responders::new(vec![Box::new(Hello) as Box<Respond<()>>]);
+
+        let rsp = respond(b"GET /hello HTTP/1.1\r\nHost: proxy:4190", &responders);
+        assert_eq!(
+            ::std::str::from_utf8(&rsp).unwrap(),
+            "HTTP/1.1 200 OK\r\n\
+             x-host: proxy:4190\r\n\
+             content-length: 2\r\n\
+             connection: close\r\n\r\n\
+             hi"
+        );
+
+        let rsp = respond(b"GET /tap HTTP/1.1", &responders);
+        assert!(rsp.starts_with(b"HTTP/1.1 404 Not Found\r\n"));
+
+        let rsp = respond(b"GET /hello SPDY/3", &responders);
+        assert!(rsp.starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+    }
+}
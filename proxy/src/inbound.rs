@@ -1,38 +1,40 @@
 use std::io;
 use std::net::{IpAddr, SocketAddr};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
+use futures::{Future, Stream};
 use http;
-use tokio_core::reactor::Handle;
+use tokio_core::reactor::{Handle, Interval};
 use tower_buffer::{self, Buffer};
 use tower_h2;
-use tower_reconnect::{self, Reconnect};
+use tower_reconnect;
 use tower_router::Recognize;
+use url::{Host, HostAndPort};
 
 use bind;
 use ctx;
+use dns;
 use telemetry;
 use transport;
 
 type Bind<B> = bind::Bind<Arc<ctx::Proxy>, B>;
 
 pub struct Inbound<B> {
-    default_addr: Option<SocketAddr>,
+    default_addr: DefaultAddr,
     bind: Bind<B>,
 }
 
-type Client<B> = tower_h2::client::Client<
-    telemetry::sensor::Connect<transport::TimeoutConnect<transport::Connect>>,
-    CtxtExec,
-    B,
->;
-
-type CtxtExec = ::logging::ContextualExecutor<(&'static str, SocketAddr), Handle>;
+/// Where requests without an original destination are forwarded, if anywhere.
+///
+/// All clones share the address, which changes as a forwarded host is resolved again.
+#[derive(Clone, Debug)]
+pub struct DefaultAddr(Arc<RwLock<Option<SocketAddr>>>);
 
 // ===== impl Inbound =====
 
 impl<B> Inbound<B> {
-    pub fn new(default_addr: Option<SocketAddr>, bind: Bind<B>) -> Self {
+    pub fn new(default_addr: DefaultAddr, bind: Bind<B>) -> Self {
         Self {
             default_addr,
             bind,
@@ -62,7 +64,7 @@ where
     >;
     type Key = SocketAddr;
     type RouteError = ();
-    type Service = Buffer<Reconnect<telemetry::sensor::NewHttp<Client<B>, B, tower_h2::RecvBody>>>;
+    type Service = Buffer<bind::Service<B>>;
 
     fn recognize(&self, req: &Self::Request) -> Option<Self::Key> {
         let key = req.extensions()
@@ -82,7 +84,7 @@ where
                     }
                 }
             })
-            .or_else(|| self.default_addr);
+            .or_else(|| self.default_addr.get());
 
         trace!("recognize key={:?}", key);
 
@@ -106,6 +108,64 @@ where
     }
 }
 
+// ===== impl DefaultAddr =====
+
+impl DefaultAddr {
+    /// Resolves `host_and_port` now, and again every `ttl`.
+    ///
+    /// Until the host is first resolved, there's no default address. If the host can't
+    /// be resolved again, its last address is kept. The returned future does the
+    /// resolving and must be spawned.
+    pub fn resolve(
+        host_and_port: HostAndPort,
+        ttl: Duration,
+        resolver: dns::Resolver,
+        executor: &Handle,
+    ) -> io::Result<(Self, Box<Future<Item = (), Error = ()>>)> {
+        let addr = DefaultAddr::from(None);
+        let refresh = {
+            let addr = addr.clone();
+            move || {
+                let addr = addr.clone();
+                let host = host_and_port.host.clone();
+                let port = host_and_port.port;
+                resolver.resolve_host(&host_and_port.host).then(move |result| {
+                    match result {
+                        Ok(ip) => addr.set(&host, SocketAddr::new(ip, port)),
+                        Err(_) => warn!("could not resolve {}; keeping {:?}", host, addr.get()),
+                    }
+                    Ok::<(), io::Error>(())
+                })
+            }
+        };
+
+        let interval = Interval::new(ttl, executor)?;
+        let f = refresh()
+            .and_then(move |()| interval.for_each(move |()| refresh()))
+            .map_err(|e| error!("forward resolution timer failed: {}", e));
+        Ok((addr, Box::new(f)))
+    }
+
+    pub fn get(&self) -> Option<SocketAddr> {
+        self.0.read().ok().and_then(|a| *a)
+    }
+
+    fn set(&self, host: &Host, addr: SocketAddr) {
+        if let Ok(mut current) = self.0.write() {
+            if *current != Some(addr) {
+                info!("forwarding to {} at {}", host, addr);
+                *current = Some(addr);
+            }
+        }
+    }
+}
+
+impl From<Option<SocketAddr>> for DefaultAddr {
+    fn from(addr: Option<SocketAddr>) -> Self {
+        DefaultAddr(Arc::new(RwLock::new(addr)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::net;
@@ -115,15 +175,16 @@ mod tests {
     use quickcheck::TestResult;
     use tokio_core::reactor::Core;
     use tower_router::Recognize;
+    use url::Host;
 
-    use super::Inbound;
+    use super::{DefaultAddr, Inbound};
     use bind::Bind;
     use ctx;
 
     fn new_inbound(default: Option<net::SocketAddr>, ctx: &Arc<ctx::Proxy>) -> Inbound<()> {
         let core = Core::new().unwrap();
         let bind = Bind::new(core.handle()).with_ctx(ctx.clone());
-        Inbound::new(default, bind)
+        Inbound::new(DefaultAddr::from(default), bind)
     }
 
     quickcheck! {
@@ -235,4 +296,15 @@ mod tests {
             inbound.recognize(&req) == default
         }
     }
+
+    #[test]
+    fn default_addr_changes_every_clone() {
+        let default = DefaultAddr::from(None);
+        let clone = default.clone();
+        assert_eq!(clone.get(), None);
+
+        let addr = "10.1.2.3:8080".parse().unwrap();
+        default.set(&Host::Domain("helper.local".into()), addr);
+        assert_eq!(clone.get(), Some(addr));
+    }
 }
@@ -42,8 +42,12 @@ extern crate url;
 
 use futures::*;
 
-use std::io;
-use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener as StdTcpListener, UdpSocket as StdUdpSocket};
+use std::path::Path;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
@@ -56,20 +60,38 @@ use tower_h2::*;
 use tower_router::{Recognize, Router};
 
 pub mod app;
+mod audit;
 mod bind;
+mod buffer;
+mod checksum;
+mod coalesce;
 pub mod config;
 mod connection;
+mod crash;
 pub mod control;
 mod convert;
 mod ctx;
+mod debug_headers;
 mod dns;
+mod http1;
 mod inbound;
+mod local;
 mod logging;
 mod map_err;
+mod no_endpoints;
+mod normalize;
 mod outbound;
+mod redirect;
+mod reload;
+mod replay;
+mod response_headers;
+mod status;
+mod stream_limit;
 mod telemetry;
 mod transport;
+mod trim;
 mod tower_fn; // TODO: move to tower-fn
+mod udp;
 
 use bind::Bind;
 use control::pb::proxy::tap;
@@ -94,28 +116,50 @@ pub struct Main {
 
     control_listener: StdTcpListener,
     inbound_listener: StdTcpListener,
+    additional_inbound_listeners: Vec<StdTcpListener>,
     outbound_listener: StdTcpListener,
+    socks_listener: Option<StdTcpListener>,
+    dns_intercept_socket: Option<StdUdpSocket>,
+    udp_forward_sockets: Vec<StdUdpSocket>,
 }
 
 impl Main {
     pub fn new(config: config::Config) -> Self {
-        let control_listener = StdTcpListener::bind(SocketAddr::from(config.control_listener.addr))
-            .expect("controller listener bind");
-        let inbound_listener = StdTcpListener::bind(SocketAddr::from(config.public_listener.addr))
-            .expect("public listener bind");
-        let outbound_listener = StdTcpListener::bind(
-            SocketAddr::from(config.private_listener.addr),
-        ).expect("private listener bind");
+        let control_listener =
+            bind_listener(config.control_listener()).expect("controller listener bind");
+        let inbound_listener =
+            bind_listener(config.public_listener()).expect("public listener bind");
+        let additional_inbound_listeners = config
+            .additional_public_listeners()
+            .iter()
+            .map(|l| bind_listener(&l.listener).expect("additional public listener bind"))
+            .collect();
+        let outbound_listener =
+            bind_listener(config.private_listener()).expect("private listener bind");
+        let socks_listener = config
+            .socks_listener()
+            .map(|l| bind_listener(l).expect("socks listener bind"));
+        let dns_intercept_socket = config.dns_intercept_listener().map(|l| {
+            StdUdpSocket::bind(SocketAddr::from(l.addr())).expect("dns intercept bind")
+        });
+        let udp_forward_sockets = config
+            .udp_forwards()
+            .iter()
+            .map(|f| StdUdpSocket::bind(SocketAddr::from(f.listen)).expect("udp relay bind"))
+            .collect();
 
         Self {
             config,
             control_listener,
             inbound_listener,
+            additional_inbound_listeners,
             outbound_listener,
+            socks_listener,
+            dns_intercept_socket,
+            udp_forward_sockets,
         }
     }
 
-
     pub fn control_addr(&self) -> SocketAddr {
         self.control_listener.local_addr().expect("control_addr")
     }
@@ -124,10 +168,60 @@ impl Main {
         self.inbound_listener.local_addr().expect("inbound_addr")
     }
 
+    /// Returns the addresses of the additional public listeners, in the order in which
+    /// they're configured.
+    pub fn additional_inbound_addrs(&self) -> Vec<SocketAddr> {
+        self.additional_inbound_listeners
+            .iter()
+            .map(|l| l.local_addr().expect("additional_inbound_addr"))
+            .collect()
+    }
+
     pub fn outbound_addr(&self) -> SocketAddr {
         self.outbound_listener.local_addr().expect("outbound_addr")
     }
 
+    pub fn socks_addr(&self) -> Option<SocketAddr> {
+        self.socks_listener
+            .as_ref()
+            .map(|l| l.local_addr().expect("socks_addr"))
+    }
+
+    pub fn dns_intercept_addr(&self) -> Option<SocketAddr> {
+        self.dns_intercept_socket
+            .as_ref()
+            .map(|s| s.local_addr().expect("dns_intercept_addr"))
+    }
+
+    /// Returns the addresses on which UDP is relayed, in the order in which the relays are
+    /// configured.
+    pub fn udp_forward_addrs(&self) -> Vec<SocketAddr> {
+        self.udp_forward_sockets
+            .iter()
+            .map(|s| s.local_addr().expect("udp_forward_addr"))
+            .collect()
+    }
+
+    /// Returns the name and bound address of each of the proxy's TCP listeners.
+    ///
+    /// Listeners configured with port 0 are bound to an ephemeral port, which is only
+    /// known once they've been bound.
+    pub fn listener_addrs(&self) -> Vec<(String, SocketAddr)> {
+        let mut addrs = vec![
+            ("public".to_owned(), self.inbound_addr()),
+            ("private".to_owned(), self.outbound_addr()),
+            ("control".to_owned(), self.control_addr()),
+        ];
+        if let Some(addr) = self.socks_addr() {
+            addrs.push(("socks".to_owned(), addr));
+        }
+        let additional = self.config.additional_public_listeners();
+        for (l, addr) in additional.iter().zip(self.additional_inbound_addrs()) {
+            addrs.push((format!("public ({})", l.name), addr));
+        }
+        addrs
+    }
+
     pub fn run(self) {
         self.run_until(::futures::future::empty());
     }
@@ -136,38 +230,281 @@ impl Main {
     where
         F: Future<Item = (), Error = ()>,
     {
+        let listener_addrs = self.listener_addrs();
+        if let Some(path) = self.config.ports_file_path() {
+            write_ports_file(path, &listener_addrs);
+        }
+
         let Main {
             config,
             control_listener,
             inbound_listener,
+            additional_inbound_listeners,
             outbound_listener,
+            socks_listener,
+            dns_intercept_socket,
+            udp_forward_sockets,
         } = self;
 
-        let control_host_and_port = config.control_host_and_port.clone();
+        let control_host_and_port = config.control_host_and_port().clone();
+        let max_report_bytes = config.max_report_bytes();
+        let control_token_path = config.control_token_path().map(Path::to_path_buf);
 
         info!("using controller at {:?}", control_host_and_port);
         info!("routing on {:?}", outbound_listener.local_addr().unwrap(),);
         info!(
             "proxying on {:?} to {:?}",
             inbound_listener.local_addr().unwrap(),
-            config.private_forward
+            config.private_forward()
         );
 
+        // Settings that may change when the configuration is reloaded.
+        let settings = reload::Settings::new(&config);
+
         let process_ctx = ctx::Process::from_env();
+
+        // Metrics exported for dashboards are labeled with where the proxy runs.
+        let topology = telemetry::topology::Topology::new(&process_ctx);
+
         let (sensors, telemetry) = telemetry::new(
             &process_ctx,
-            config.event_buffer_capacity,
-            config.metrics_flush_interval,
+            config.event_buffer_capacity(),
+            settings.metrics_flush_interval().clone(),
+            config.slow_request_threshold(),
+            telemetry::health::HealthChecks::new(
+                config.health_check_paths().iter().cloned(),
+                config.health_check_user_agents().iter().cloned(),
+                config.health_check_sources().to_vec(),
+            ),
+            config.statsd_addr().map(|addr| {
+                telemetry::statsd::Statsd::new(addr.into(), &topology).expect("statsd socket")
+            }),
+            config.telemetry_headers().to_vec(),
+            config.tap_request_progress_interval(),
         );
 
-        let (control, control_bg) = control::new();
+        // With telemetry disabled, sensors record nothing. The enabled sensors are held
+        // so that the telemetry channel isn't closed.
+        let (sensors, _enabled_sensors) = if config.telemetry_disabled() {
+            info!("telemetry is disabled");
+            (telemetry::Sensors::null(), Some(sensors))
+        } else {
+            (sensors, None)
+        };
+
+        let endpoint_cache_path = config.endpoint_cache_path().map(|p| p.to_path_buf());
+        let (control, control_bg) = control::new(endpoint_cache_path);
+
+        // Summarizes the proxy's state on the control listener.
+        let status_page = status::Page::new(
+            &process_ctx,
+            listener_addrs,
+            control.connectivity(),
+            telemetry.authorities(),
+            telemetry.health_checks(),
+        );
+
+        let config_dump = status::ConfigDump::new(&config);
+
+        // The control listener exposes operational data, so access to it may be limited to
+        // some networks and to clients that present credentials.
+        let control_sources = config.control_listener_sources().to_vec();
+        let control_credentials =
+            status::Credentials::new(settings.control_authorization().clone());
 
         let mut core = Core::new().expect("executor");
         let executor = core.handle();
 
-        let dns_config = dns::Config::from_file(&config.resolv_conf_path);
+        // Events are buffered on this thread and sent to the telemetry thread in batches.
+        sensors.spawn_flush(&executor).expect("telemetry flush");
+
+        let dns_config = dns::Config::from_file(config.resolv_conf_path());
+
+        // Nagle's algorithm is disabled on proxied connections, unless bulk transfers'
+        // throughput matters more than latency.
+        let nodelay = config.tcp_nodelay();
+
+        // Half-open connections, e.g. to peers behind a NAT that has dropped them, are
+        // detected with TCP keepalive, if configured.
+        let keepalive = transport::Keepalive::new(
+            config.tcp_keepalive(),
+            config.tcp_keepalive_interval(),
+            config.tcp_keepalive_probes(),
+        );
+
+        let debug_headers = config.debug_headers();
+        let bind = Bind::new(executor.clone())
+            .with_sensors(sensors.clone())
+            .with_nodelay(nodelay)
+            .with_keepalive(keepalive)
+            .with_debug_headers(debug_headers)
+            .with_buffer_sizes(transport::BufferSizes::new(
+                config.connect_recv_buffer_size(),
+                config.connect_send_buffer_size(),
+            ));
+        let bind = match config.h2_initial_window_size() {
+            Some(size) => bind.with_initial_window_size(size),
+            None => bind,
+        };
+
+        // Every proxied HTTP/2 connection is served with these settings.
+        let h2_initial_window_size = config.h2_initial_window_size();
+        let h2_server = || {
+            let mut builder = h2::server::Builder::default();
+            if let Some(size) = h2_initial_window_size {
+                builder.initial_window_size(size);
+            }
+            builder
+        };
+
+        // Outbound requests to these hosts may have their redirects followed.
+        let redirects = redirect::Policy::new(
+            config.redirect_hosts().iter().cloned(),
+            config.redirect_limit(),
+        );
+
+        // Failed responses to these hosts are replaced with a standard error envelope.
+        let translations = local::translate::Rules::new(
+            config
+                .error_translations()
+                .iter()
+                .map(|t| local::translate::Rule::new(&t.host, t.statuses.clone()))
+                .collect(),
+        );
+
+        // Cross-origin requests to these inbound hosts are handled on the application's
+        // behalf.
+        let cors = local::cors::Rules::new(
+            config
+                .cors_rules()
+                .iter()
+                .map(|r| local::cors::Rule::new(&r.host, r.origins.clone()))
+                .collect(),
+        );
+
+        // Only these methods and content types are forwarded to these inbound routes.
+        let methods = allowed_methods(config.allowed_methods());
+        let content_types = allowed_content_types(config.allowed_content_types());
 
-        let bind = Bind::new(executor.clone()).with_sensors(sensors.clone());
+        // Responses from these inbound hosts are replaced with static pages, either always
+        // or when the application is unavailable.
+        let pages = {
+            use local::maintenance::{Page, Rule, When};
+            let always = config.maintenance_pages().iter().map(|p| (p, When::Always));
+            let unavailable = config.unavailable_pages().iter().map(|p| (p, When::Unavailable));
+            let rules = always
+                .chain(unavailable)
+                .filter_map(|(p, when)| match Page::load(&p.path) {
+                    Ok(page) => Some(Rule::new(&p.host, page, when)),
+                    Err(e) => {
+                        error!("could not load page {}: {}", p.path.display(), e);
+                        None
+                    }
+                })
+                .collect();
+            local::maintenance::Rules::new(rules)
+        };
+
+        // Response bodies from these hosts are validated against their checksums.
+        let checksums = checksum::Routes::new(config.checksum_hosts().iter().cloned());
+
+        // Response bodies from these hosts are buffered before their headers are forwarded.
+        let buffers = buffer::Routes::new(
+            config.buffer_hosts().iter().cloned(),
+            config.buffer_max_bytes(),
+        );
+
+        // Exchanges with these hosts are recorded to the audit spool, if one is configured.
+        let audits = audit::Routes::new(
+            config.audit_hosts().iter().cloned(),
+            config.audit_max_body_bytes(),
+            config.audit_redacted_headers().iter().cloned(),
+            config
+                .audit_spool_path()
+                .map(|p| audit::Spool::new(p.to_path_buf()).expect("audit spool")),
+        );
+
+        // Failed requests that the application sends to these hosts are journaled and
+        // replayed through the outbound listener, if a journal is configured.
+        let replays = replay::Routes::new(
+            config.replay_hosts().iter().cloned(),
+            config.replay_journal_path().map(|p| {
+                let settings = replay::Settings {
+                    dir: p.to_path_buf(),
+                    max_entries: config.replay_journal_max_entries(),
+                    backoff: config.replay_backoff(),
+                    max_backoff: config.replay_max_backoff(),
+                };
+                let outbound = outbound_listener.local_addr().expect("outbound addr");
+                replay::Journal::new(settings, outbound).expect("replay journal")
+            }),
+        );
+
+        // Identical outbound GET requests to these hosts, received on the same listener,
+        // share a single upstream request.
+        let coalesces = coalesce::Routes::new(
+            config.coalesce_hosts().iter().cloned(),
+            config.coalesce_max_bytes(),
+        );
+
+        // Outbound requests for destinations without endpoints are handled per this policy.
+        let no_endpoints =
+            no_endpoints::Policy::new(config.no_endpoints(), config.no_endpoints_timeout());
+
+        // Until the controller is first reached, e.g. while it's starting alongside the
+        // proxy, such requests may go to their original destinations instead.
+        let startup_fallback = config.no_endpoints_startup_fallback();
+
+        // Requests for services in other clusters are routed through those clusters'
+        // gateways rather than resolved through the local controller.
+        let cluster_gateways = config
+            .cluster_gateways()
+            .iter()
+            .map(|gw| outbound::ClusterGateway::new(&gw.cluster, gw.addr.into()))
+            .collect::<Vec<_>>();
+
+        // Connection attempts to each outbound authority beyond this limit wait.
+        let max_concurrent_connects = config.outbound_max_concurrent_connects();
+
+        // Every listener's routes are dropped once the proxy has been idle, if configured.
+        let idle = trim::Idle::default();
+
+        // Connections to these original destination ports are forwarded opaquely, e.g. to
+        // databases that don't speak HTTP.
+        let inbound_skip_ports = Arc::new(config.inbound_skip_ports().clone());
+        let outbound_skip_ports = Arc::new(config.outbound_skip_ports().clone());
+
+        // Streams opened on each public connection beyond this limit are refused.
+        let stream_limit = stream_limit::Limit::new(config.public_max_concurrent_streams());
+
+        // Requests received on public listeners are normalized, if configured.
+        let normalize = normalize::Policy::new(config.inbound_normalization());
+
+        // The policies that every listener applies. Each listener sets its own responders,
+        // timeouts, and negotiation, and enables any of the other policies it applies.
+        let shared = ListenerPolicy {
+            responders: local::Responders::new(Vec::new()),
+            redirects: redirect::Policy::disabled(),
+            pages: local::maintenance::Rules::empty(),
+            translations,
+            cors: local::cors::Rules::empty(),
+            checksums,
+            buffers,
+            audits,
+            replays: replay::Routes::disabled(),
+            coalesces: coalesce::Routes::disabled(),
+            headers: response_headers::Policy::disabled(),
+            normalize: normalize::Policy::disabled(),
+            stream_limit: stream_limit::Limit::default(),
+            idle_timeout: settings.private_idle_timeout().clone(),
+            accept_timeout: None,
+            nodelay,
+            keepalive,
+            debug_headers,
+            idle: idle.clone(),
+            negotiate: connection::Negotiate::Plain,
+            skip_ports: Arc::default(),
+        };
 
         // Setup the public listener. This will listen on a publicly accessible
         // address and listen for inbound connections that should be forwarded
@@ -175,46 +512,289 @@ impl Main {
         let inbound = {
             let ctx = ctx::Proxy::inbound(&process_ctx);
 
-            let timeout = config
-                .private_connect_timeout
-                .unwrap_or_else(|| Duration::from_millis(20));
-            let bind = bind.clone()
-                .with_connect_timeout(timeout)
-                .with_ctx(ctx.clone());
+            let mut responders: Vec<Box<local::Respond<RecvBody>>> = Vec::new();
+            responders.push(Box::new(local::Synthetic::new(settings.synthetic_token().clone())));
+            responders.push(Box::new(pages.clone()));
+            responders.push(Box::new(cors.clone()));
+            responders.push(Box::new(methods));
+            responders.push(Box::new(content_types));
 
-            let default_addr = config.private_forward.map(|a| a.into());
+            let fut = if config.ingress_mode() {
+                // In ingress mode, requests are routed by authority just like outbound
+                // requests, so connect timeouts to remote peers apply.
+                let bind = config
+                    .public_listener()
+                    .connect_timeout()
+                    .or(config.public_connect_timeout())
+                    .map_or_else(|| bind.clone(), |t| bind.clone().with_connect_timeout(t))
+                    .with_ctx(ctx.clone());
 
-            let fut = serve(
-                inbound_listener,
-                h2::server::Builder::default(),
-                Inbound::new(default_addr, bind),
-                ctx,
-                sensors.clone(),
-                executor.clone(),
-            );
+                if !config.ingress_hosts().is_empty() {
+                    let hosts = config.ingress_hosts().iter().cloned();
+                    responders.push(Box::new(local::ingress::Hosts::new(hosts)));
+                }
+
+                // Responses leaving through the ingress carry the platform's headers.
+                let headers = response_headers::Policy::new(
+                    config
+                        .ingress_response_headers()
+                        .iter()
+                        .map(|h| (h.name.clone(), h.value.clone())),
+                );
+
+                serve(
+                    inbound_listener,
+                    h2_server(),
+                    Outbound::new(bind, control.clone())
+                        .with_cluster_gateways(cluster_gateways.clone())
+                        .with_no_endpoints(no_endpoints)
+                        .with_startup_fallback(startup_fallback)
+                        .with_max_concurrent_connects(max_concurrent_connects),
+                    ListenerPolicy {
+                        responders: local::Responders::new(responders),
+                        pages: pages.clone(),
+                        cors: cors.clone(),
+                        headers,
+                        normalize,
+                        stream_limit: stream_limit.clone(),
+                        idle_timeout: settings.public_idle_timeout().clone(),
+                        accept_timeout: config.public_listener().accept_timeout(),
+                        negotiate: public_negotiate(config.public_listener()),
+                        skip_ports: inbound_skip_ports.clone(),
+                        ..shared.clone()
+                    },
+                    ctx,
+                    sensors.clone(),
+                    executor.clone(),
+                )
+            } else {
+                let timeout = config
+                    .public_listener()
+                    .connect_timeout()
+                    .or(config.private_connect_timeout())
+                    .unwrap_or_else(|| Duration::from_millis(20));
+                let bind = bind.clone()
+                    .with_connect_timeout(timeout)
+                    .with_ctx(ctx.clone());
+
+                let default_addr = match config.private_forward() {
+                    None => inbound::DefaultAddr::from(None),
+                    Some(forward) => forward_addr(
+                        "private_forward",
+                        forward,
+                        config.private_forward_ttl(),
+                        &dns_config,
+                        &executor,
+                    ),
+                };
+
+                serve(
+                    inbound_listener,
+                    h2_server(),
+                    Inbound::new(default_addr, bind),
+                    ListenerPolicy {
+                        responders: local::Responders::new(responders),
+                        pages: pages.clone(),
+                        cors: cors.clone(),
+                        normalize,
+                        stream_limit: stream_limit.clone(),
+                        idle_timeout: settings.public_idle_timeout().clone(),
+                        accept_timeout: config.public_listener().accept_timeout(),
+                        negotiate: public_negotiate(config.public_listener()),
+                        skip_ports: inbound_skip_ports.clone(),
+                        ..shared.clone()
+                    },
+                    ctx,
+                    sensors.clone(),
+                    executor.clone(),
+                )
+            };
             ::logging::context_future("inbound", fut)
         };
 
+        // Setup any additional public listeners. Each forwards to its own target with its
+        // own policies, so that e.g. an application's admin port can be restricted
+        // independently of its API port.
+        let additional_inbound = {
+            let ctx = ctx::Proxy::inbound(&process_ctx);
+
+            let servers = config
+                .additional_public_listeners()
+                .iter()
+                .zip(additional_inbound_listeners)
+                .map(|(l, listener)| {
+                    info!(
+                        "proxying {} on {:?} to {:?}",
+                        l.name,
+                        listener.local_addr().unwrap(),
+                        l.forward
+                    );
+
+                    let mut responders: Vec<Box<local::Respond<RecvBody>>> = Vec::new();
+                    let token = settings.synthetic_token().clone();
+                    responders.push(Box::new(local::Synthetic::new(token)));
+                    responders.push(Box::new(pages.clone()));
+                    responders.push(Box::new(cors.clone()));
+                    responders.push(Box::new(allowed_methods(&l.allowed_methods)));
+                    responders.push(Box::new(allowed_content_types(&l.allowed_content_types)));
+
+                    let timeout = l.listener
+                        .connect_timeout()
+                        .or(config.private_connect_timeout())
+                        .unwrap_or_else(|| Duration::from_millis(20));
+                    let bind = bind.clone()
+                        .with_connect_timeout(timeout)
+                        .with_ctx(ctx.clone());
+
+                    let default_addr = forward_addr(
+                        ("forward", l.name.clone()),
+                        &l.forward,
+                        config.private_forward_ttl(),
+                        &dns_config,
+                        &executor,
+                    );
+
+                    let fut = serve(
+                        listener,
+                        h2_server(),
+                        Inbound::new(default_addr, bind),
+                        ListenerPolicy {
+                            responders: local::Responders::new(responders),
+                            pages: pages.clone(),
+                            cors: cors.clone(),
+                            normalize,
+                            stream_limit: stream_limit::Limit::new(l.max_concurrent_streams),
+                            idle_timeout: settings.public_idle_timeout().clone(),
+                            accept_timeout: l.listener.accept_timeout(),
+                            negotiate: public_negotiate(&l.listener),
+                            ..shared.clone()
+                        },
+                        ctx.clone(),
+                        sensors.clone(),
+                        executor.clone(),
+                    );
+                    ::logging::context_future(("inbound", l.name.clone()), fut)
+                })
+                .collect::<Vec<_>>();
+            future::join_all(servers)
+        };
+
         // Setup the private listener. This will listen on a locally accessible
         // address and listen for outbound requests that should be routed
         // to a remote service (public destination).
         let outbound = {
             let ctx = ctx::Proxy::outbound(&process_ctx);
 
-            let bind = config
-                .public_connect_timeout
-                .map_or_else(|| bind.clone(), |t| bind.clone().with_connect_timeout(t))
-                .with_ctx(ctx.clone());
+            // Requests from the local application to the proxy itself.
+            let resolutions = control.resolutions().clone();
+            let grpc_messages = sensors.grpc_messages().clone();
+            let admin_read_only = config.admin_read_only();
+            let local_responders = || {
+                let mut responders: Vec<Box<local::Respond<RecvBody>>> = Vec::new();
+                if admin_read_only {
+                    responders.push(Box::new(local::ReadOnly::new()));
+                }
+                responders.push(Box::new(local::Info::new(&process_ctx)));
+                responders.push(Box::new(local::LogScope::new()));
+                responders.push(Box::new(local::Resolutions::new(&resolutions, &topology)));
+                responders.push(Box::new(local::GrpcMessages::new(&grpc_messages, &topology)));
+                responders.push(Box::new(local::StreamLimit::new(&stream_limit, &topology)));
+                local::Responders::new(responders)
+            };
+
+            // Each listener may override the timeout for connecting to public peers.
+            let bind_for = |listener: &config::Listener| {
+                listener
+                    .connect_timeout()
+                    .or(config.public_connect_timeout())
+                    .map_or_else(|| bind.clone(), |t| bind.clone().with_connect_timeout(t))
+            };
+
+            // Clients connecting over SOCKS5 are routed exactly like clients that
+            // connect to the private listener directly.
+            let socks = match socks_listener {
+                None => future::Either::A(future::ok(())),
+                Some(listener) => {
+                    let socks_config = config.socks_listener().expect("socks listener config");
+                    info!("accepting SOCKS5 on {:?}", listener.local_addr().unwrap());
+                    let fut = serve(
+                        listener,
+                        h2_server(),
+                        Outbound::new(bind_for(socks_config).with_ctx(ctx.clone()), control.clone())
+                            .with_cluster_gateways(cluster_gateways.clone())
+                            .with_no_endpoints(no_endpoints)
+                            .with_startup_fallback(startup_fallback)
+                            .with_max_concurrent_connects(max_concurrent_connects),
+                        ListenerPolicy {
+                            responders: local_responders(),
+                            redirects: redirects.clone(),
+                            replays: replays.clone(),
+                            coalesces: coalesces.for_listener(),
+                            idle_timeout: settings.private_idle_timeout().clone(),
+                            accept_timeout: socks_config.accept_timeout(),
+                            negotiate: connection::Negotiate::Socks5,
+                            ..shared.clone()
+                        },
+                        ctx.clone(),
+                        sensors.clone(),
+                        executor.clone(),
+                    );
+                    future::Either::B(::logging::context_future("socks", fut))
+                }
+            };
 
             let fut = serve(
                 outbound_listener,
-                h2::server::Builder::default(),
-                Outbound::new(bind, control),
+                h2_server(),
+                Outbound::new(bind_for(config.private_listener()).with_ctx(ctx.clone()), control)
+                    .with_cluster_gateways(cluster_gateways)
+                    .with_no_endpoints(no_endpoints)
+                    .with_startup_fallback(startup_fallback)
+                    .with_max_concurrent_connects(max_concurrent_connects),
+                ListenerPolicy {
+                    responders: local_responders(),
+                    redirects,
+                    replays,
+                    coalesces: coalesces.for_listener(),
+                    idle_timeout: settings.private_idle_timeout().clone(),
+                    accept_timeout: config.private_listener().accept_timeout(),
+                    skip_ports: outbound_skip_ports,
+                    ..shared
+                },
                 ctx,
                 sensors,
-                executor,
+                executor.clone(),
             );
             ::logging::context_future("outbound", fut)
+                .join(socks)
+                .map(|_| ())
+        };
+
+        // Optionally answer DNS queries that the local application's resolver has been
+        // redirected to send to the proxy.
+        let dns_intercept = match dns_intercept_socket {
+            None => future::Either::A(future::ok(())),
+            Some(socket) => {
+                let intercept = dns::Intercept::new(socket, dns_config.clone(), &executor)
+                    .expect("dns intercept socket");
+                future::Either::B(::logging::context_future("dns-intercept", intercept))
+            }
+        };
+
+        // Relay any configured UDP ports.
+        let udp_relays = {
+            let relays = config
+                .udp_forwards()
+                .iter()
+                .zip(udp_forward_sockets)
+                .map(|(fwd, socket)| {
+                    let listen = socket.local_addr().expect("udp relay addr");
+                    let relay = udp::Relay::new(socket, fwd.target.into(), &executor)
+                        .expect("udp relay socket");
+                    ::logging::context_future(("udp", listen), relay)
+                })
+                .collect::<Vec<_>>();
+            future::join_all(relays)
         };
 
         trace!("running");
@@ -229,12 +809,26 @@ impl Main {
 
                     let (taps, observe) = control::Observe::new(100);
 
-                    let new_service = tap::server::Tap::new_service().observe(observe);
+                    // HTTP/1.1 clients may reach the admin endpoints, but not tap.
+                    let admin = status::responders(
+                        control_credentials.clone(),
+                        status_page.clone(),
+                        config_dump.clone(),
+                    );
+                    let new_service = status::NewStatus::new(
+                        tap::server::Tap::new_service().observe(observe),
+                        control_credentials,
+                        status_page,
+                        config_dump,
+                        &executor,
+                    );
 
                     let server = serve_control(
                         control_listener,
                         h2::server::Builder::default(),
                         new_service,
+                        admin,
+                        control_sources,
                         &executor,
                     );
 
@@ -242,8 +836,14 @@ impl Main {
                         .make_control(&taps, &executor)
                         .expect("bad news in telemetry town");
 
-                    let client =
-                        control_bg.bind(telemetry, control_host_and_port, dns_config, &executor);
+                    let client = control_bg.bind(
+                        telemetry,
+                        max_report_bytes,
+                        control_host_and_port,
+                        control_token_path,
+                        dns_config,
+                        &executor,
+                    );
 
                     let fut = client.join(server.map_err(|_| {})).map(|_| {});
                     executor.spawn(::logging::context_future("controller-client", fut));
@@ -255,19 +855,152 @@ impl Main {
         }
 
         let fut = inbound
+            .join(additional_inbound)
             .join(outbound)
+            .join(dns_intercept)
+            .join(udp_relays)
             .map(|_| ())
             .map_err(|err| error!("main error: {:?}", err));
 
         core.handle().spawn(fut);
+
+        let reloads = reload::watch(config.clone(), settings, &executor).expect("reload timer");
+        core.handle().spawn(reloads);
+
+        if let Some(period) = config.idle_trim_period() {
+            let trims = idle.trim_after(period, &executor).expect("idle trim timer");
+            core.handle().spawn(trims);
+        }
+
         core.run(shutdown_signal).expect("executor");
     }
 }
 
+/// Binds `listener`'s address, with its socket options.
+fn bind_listener(listener: &config::Listener) -> io::Result<StdTcpListener> {
+    let addr = SocketAddr::from(listener.addr());
+    let listen = transport::bind_listener(
+        &addr,
+        listener.reuse_port(),
+        listener.dual_stack(),
+        listener.backlog(),
+    )?;
+    transport::BufferSizes::new(listener.recv_buffer_size(), listener.send_buffer_size())
+        .apply_to_listener(&listen);
+    Ok(listen)
+}
+
+/// Returns how connections accepted by the public `listener` are negotiated.
+fn public_negotiate(listener: &config::Listener) -> connection::Negotiate {
+    if listener.proxy_protocol() {
+        connection::Negotiate::ProxyProtocol
+    } else {
+        connection::Negotiate::Plain
+    }
+}
+
+/// Returns the default address of connections forwarded to `forward`.
+///
+/// A host name is resolved again every `ttl` by a task spawned on `executor`.
+fn forward_addr<C>(
+    context: C,
+    forward: &config::Forward,
+    ttl: Duration,
+    dns_config: &dns::Config,
+    executor: &Handle,
+) -> inbound::DefaultAddr
+where
+    C: ::std::fmt::Debug + 'static,
+{
+    match *forward {
+        config::Forward::Addr(addr) => inbound::DefaultAddr::from(Some(addr.into())),
+        config::Forward::Host(ref host_and_port) => {
+            let resolver = dns::Resolver::new(dns_config.clone(), executor);
+            let (addr, resolve) =
+                inbound::DefaultAddr::resolve(host_and_port.clone(), ttl, resolver, executor)
+                    .expect("forward resolution timer");
+            executor.spawn(::logging::context_future(context, resolve));
+            addr
+        }
+    }
+}
+
+/// Writes each listener's name and address, as `<name>=<addr>` lines, to `path`.
+///
+/// The file is written to a temporary file that is then renamed into place, so that
+/// readers never observe a partially written file.
+fn write_ports_file(path: &Path, addrs: &[(String, SocketAddr)]) {
+    let mut contents = String::new();
+    for &(ref name, addr) in addrs {
+        contents.push_str(&format!("{}={}\n", name, addr));
+    }
+
+    let tmp = path.with_extension("tmp");
+    let written = fs::File::create(&tmp)
+        .and_then(|mut f| f.write_all(contents.as_bytes()))
+        .and_then(|_| fs::rename(&tmp, path));
+    match written {
+        Ok(()) => debug!("wrote listener addresses to {}", path.display()),
+        Err(e) => warn!("could not write listener addresses to {}: {}", path.display(), e),
+    }
+}
+
+fn allowed_methods(rules: &[config::MethodRule]) -> local::methods::Rules {
+    local::methods::Rules::new(
+        rules
+            .iter()
+            .map(|r| {
+                let path = r.path.as_ref().map(String::as_str);
+                local::methods::Rule::new(&r.host, path, r.methods.clone())
+            })
+            .collect(),
+    )
+}
+
+fn allowed_content_types(rules: &[config::ContentTypeRule]) -> local::content_types::Rules {
+    local::content_types::Rules::new(
+        rules
+            .iter()
+            .map(|r| {
+                let path = r.path.as_ref().map(String::as_str);
+                local::content_types::Rule::new(&r.host, path, r.media_types.clone())
+            })
+            .collect(),
+    )
+}
+
+/// The policies that a listener applies to the connections it accepts and the requests
+/// it receives on them.
+#[derive(Clone)]
+struct ListenerPolicy {
+    responders: local::Responders<RecvBody>,
+    redirects: redirect::Policy,
+    pages: local::maintenance::Rules,
+    translations: local::translate::Rules,
+    cors: local::cors::Rules,
+    checksums: checksum::Routes,
+    buffers: buffer::Routes,
+    audits: audit::Routes,
+    replays: replay::Routes,
+    coalesces: coalesce::Routes,
+    headers: response_headers::Policy,
+    normalize: normalize::Policy,
+    stream_limit: stream_limit::Limit,
+    idle_timeout: reload::DurationSetting,
+    accept_timeout: Option<Duration>,
+    nodelay: bool,
+    keepalive: transport::Keepalive,
+    debug_headers: bool,
+    idle: trim::Idle,
+    negotiate: connection::Negotiate,
+    skip_ports: Arc<HashSet<u16>>,
+}
+
 fn serve<R, B, E, F>(
     listen: StdTcpListener,
     h2_builder: h2::server::Builder,
     recognize: R,
+    policy: ListenerPolicy,
     proxy_ctx: Arc<ctx::Proxy>,
     sensors: telemetry::Sensors,
     executor: Handle,
@@ -284,55 +1017,197 @@ where
     >
         + 'static,
 {
+    let ListenerPolicy {
+        responders,
+        redirects,
+        pages,
+        translations,
+        cors,
+        checksums,
+        buffers,
+        audits,
+        replays,
+        coalesces,
+        headers,
+        normalize,
+        stream_limit,
+        idle_timeout,
+        accept_timeout,
+        nodelay,
+        keepalive,
+        debug_headers,
+        idle,
+        negotiate,
+        skip_ports,
+    } = policy;
+
     let listen_addr = listen.local_addr().expect("local addr");
 
     let bind = TcpListener::from_listener(listen, &listen_addr, &executor).expect("bind");
 
     let router = Router::new(recognize);
+    {
+        let router = router.clone();
+        idle.on_idle(move || router.clear());
+    }
+    let activity = idle.activity();
+    let local_executor = executor.clone();
     let stack = NewServiceFn::new(move || {
         // Clone the router handle
         let router = router.clone();
 
+        // Journal failed requests to configured routes for replay, if configured
+        let replay = replay::Replay::new(router, replays.clone());
+
+        // Follow redirects on behalf of the application, if configured
+        let redirect = redirect::Redirect::new(replay, redirects.clone());
+
+        // Answer some requests without routing them
+        let local = local::Local::new(redirect, responders.clone(), &local_executor);
+
+        // Serve static pages when the upstream is unavailable, if configured
+        let unavailable = local::Unavailable::new(local, pages.clone());
+
         // Map errors to 500 responses
-        MapErr::new(router)
+        let map_err = MapErr::new(unavailable);
+
+        // Give failures a consistent shape, if configured
+        let translate = local::Translate::new(map_err, translations.clone());
+
+        // Allow configured cross-origin requests
+        let cors = local::Cors::new(translate, cors.clone());
+
+        // Share responses between identical GET requests, if configured. Errors have
+        // already been mapped to responses, so failures are shared too.
+        let coalesce = coalesce::Coalesce::new(cors, coalesces.clone());
+
+        // Validate response bodies against their advertised checksums, if configured
+        let checksum = checksum::Checksum::new(coalesce, checksums.clone());
+
+        // Every layer above forwards response headers as soon as they're received; only
+        // configured routes have their response bodies buffered first.
+        let buffer = buffer::Buffer::new(checksum, buffers.clone());
+
+        // Record exchanges with audited hosts, if configured
+        let audit = audit::Audit::new(buffer, audits.clone());
+
+        // Enforce headers on every response leaving the listener, if configured
+        let headers = response_headers::ResponseHeaders::new(audit, headers.clone());
+
+        // Refuse requests with ambiguous targets before any policy sees them, if
+        // configured
+        let normalize = normalize::Normalize::new(headers, normalize);
+
+        // Refuse streams beyond the connection's limit, if configured. A new service is
+        // built for each connection, so each connection is limited independently.
+        stream_limit::StreamLimit::new(normalize, stream_limit.clone())
     });
 
-    let server = Server::new(
+    let server = Rc::new(Server::new(
         stack,
         h2_builder,
         ::logging::context_executor(("serve", listen_addr), executor.clone()),
-    );
+    ));
     let f = bind.incoming().fold(
         (server, proxy_ctx, sensors, executor),
         move |(server, proxy_ctx, sensors, executor), (socket, remote_addr)| {
-            if let Err(e) = socket.set_nodelay(true) {
-                warn!(
-                    "could not set TCP_NODELAY on {:?}/{:?}: {}",
-                    socket.local_addr(),
-                    socket.peer_addr(),
-                    e
-                );
-            }
+            transport::set_nodelay(&socket, nodelay);
+            keepalive.apply(&socket);
 
             let opened_at = Instant::now();
             let orig_dst = transport::get_original_dst(&socket);
             let local_addr = socket.local_addr().unwrap_or(listen_addr);
-            let srv_ctx =
-                ctx::transport::Server::new(&proxy_ctx, &local_addr, &remote_addr, &orig_dst);
 
-            connection::Connection::handshake(socket).map(move |session| {
-                let io = sensors.accept(session, opened_at, &srv_ctx);
+            // Connections to skipped ports aren't handled as HTTP at all.
+            if let Some(dst) = orig_dst {
+                if skip_ports.contains(&dst.port()) {
+                    let fwd = transport::forward(socket, &dst, nodelay, keepalive, &executor)
+                        .map(|(sent, received)| {
+                            debug!("forwarded {}B and received {}B", sent, received)
+                        })
+                        .map_err(|e| debug!("forward failed: {}", e));
+                    let ctx = ("forward", local_addr, remote_addr, dst);
+                    executor.spawn(::logging::context_future(ctx, fwd));
+                    return Ok((server, proxy_ctx, sensors, executor));
+                }
+            }
 
-                // TODO session context
-                let set_ctx = move |request: &mut http::Request<()>| {
-                    request.extensions_mut().insert(Arc::clone(&srv_ctx));
-                };
+            // Negotiation may require a round trip with the client, so it's done on the
+            // connection's own task rather than holding up the accept loop.
+            // Each resolves to the socket, its original destination, and its client's
+            // address, which a PROXY header may name in place of the peer's.
+            let negotiated = match negotiate {
+                connection::Negotiate::Plain => {
+                    future::Either::A(future::ok((socket, orig_dst, remote_addr)))
+                }
+                connection::Negotiate::Socks5 => future::Either::B(future::Either::A(
+                    transport::socks_handshake(socket)
+                        .map(move |(socket, dst)| (socket, dst.or(orig_dst), remote_addr)),
+                )),
+                connection::Negotiate::ProxyProtocol => future::Either::B(future::Either::B(
+                    transport::proxy_protocol_handshake(socket).map(move |(socket, src)| {
+                        let client_addr = src.unwrap_or(remote_addr);
+                        debug!("PROXY header names client {}", client_addr);
+                        (socket, orig_dst, client_addr)
+                    }),
+                )),
+            };
 
-                let s = server.serve_modified(io, set_ctx).map_err(|_| ());
-                executor.spawn(::logging::context_future(("serve", local_addr), s));
+            // Connections that don't send a request in time are closed, if configured.
+            let (accept_expired, first_request) =
+                transport::AcceptTimeout::new(accept_timeout, &executor);
 
-                (server, proxy_ctx, sensors, executor)
-            })
+            let conn = {
+                let server = server.clone();
+                let proxy_ctx = proxy_ctx.clone();
+                let sensors = sensors.clone();
+                let executor = executor.clone();
+                let idle_timeout = idle_timeout.clone();
+                let activity = activity.clone();
+                negotiated
+                    .and_then(|(socket, orig_dst, client_addr)| {
+                        connection::Connection::handshake(socket)
+                            .map(move |s| (s, orig_dst, client_addr))
+                    })
+                    .map_err(move |e| debug!("handshake failed from {}: {}", remote_addr, e))
+                    .and_then(move |(session, orig_dst, client_addr)| {
+                        let srv_ctx = ctx::transport::Server::new(
+                            &proxy_ctx,
+                            &local_addr,
+                            &client_addr,
+                            &orig_dst,
+                        );
+                        let io = sensors.accept(session, opened_at, &srv_ctx);
+
+                        // Connections that go unused for too long are closed.
+                        let (io, idle) =
+                            transport::Idle::new(io, idle_timeout.get_timeout(), &executor);
+
+                        // TODO session context
+                        let set_ctx = move |request: &mut http::Request<()>| {
+                            first_request.received();
+                            activity.request();
+                            if debug_headers {
+                                debug_headers::received(request);
+                            }
+                            request.extensions_mut().insert(Arc::clone(&srv_ctx));
+                        };
+
+                        let idle = idle.map(|()| debug!("closing idle connection"));
+                        server
+                            .serve_modified(io, set_ctx)
+                            .map_err(|_| ())
+                            .select(idle)
+                            .map(|_| ())
+                            .map_err(|_| ())
+                    })
+            };
+            let accept_expired =
+                accept_expired.map(|()| debug!("closing connection without a request"));
+            let conn = conn.select(accept_expired).map(|_| ()).map_err(|_| ());
+            executor.spawn(::logging::context_future(("serve", local_addr, remote_addr), conn));
+
+            Ok::<_, io::Error>((server, proxy_ctx, sensors, executor))
         },
     );
 
@@ -343,6 +1218,8 @@ fn serve_control<N, B>(
     listen: StdTcpListener,
     h2_builder: h2::server::Builder,
     new_service: N,
+    admin: local::Responders<()>,
+    sources: Vec<config::Network>,
     executor: &Handle,
 ) -> Box<Future<Item = (), Error = io::Error> + 'static>
 where
@@ -355,23 +1232,35 @@ where
     let server = Server::new(new_service, h2_builder, executor.clone());
     let f = bind.incoming().fold(
         (server, executor.clone()),
-        move |(server, executor), (socket, _)| {
-            if let Err(e) = socket.set_nodelay(true) {
-                warn!(
-                    "could not set TCP_NODELAY on {:?}/{:?}: {}",
-                    socket.local_addr(),
-                    socket.peer_addr(),
-                    e
-                );
+        move |(server, executor), (socket, remote_addr)| {
+            // Connections from other networks are closed without being read, if configured.
+            let allowed = sources.is_empty()
+                || sources.iter().any(|net| net.contains(&remote_addr.ip()));
+            if !allowed {
+                debug!("refusing control connection from {}", remote_addr);
+                return future::ok::<_, io::Error>((server, executor));
             }
 
-            connection::Connection::handshake(socket).map(move |session| {
-                let s = server.serve(session).map_err(|_| ());
+            transport::set_nodelay(&socket, true);
 
-                executor.spawn(::logging::context_future("serve_control", s));
+            // Connections are routed on their first bytes, which may take a round trip
+            // with the client, so it's done on the connection's own task.
+            let conn = {
+                let server = server.clone();
+                let admin = admin.clone();
+                http1::detect(socket).and_then(move |(protocol, socket)| match protocol {
+                    http1::Protocol::Http2 => {
+                        future::Either::A(server.serve(socket).map_err(|_| {
+                            io::Error::new(io::ErrorKind::Other, "HTTP/2 connection failed")
+                        }))
+                    }
+                    http1::Protocol::Http1 => future::Either::B(http1::serve(socket, admin)),
+                })
+            };
+            let conn = conn.map_err(move |e| debug!("control connection failed: {}", e));
+            executor.spawn(::logging::context_future(("serve_control", remote_addr), conn));
 
-                (server, executor)
-            })
+            future::ok::<_, io::Error>((server, executor))
         },
     );
 
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http;
+use http::header::CONTENT_TYPE;
+
+use super::{host_matches, path_matches, request_host, Respond, Response};
+
+/// Restricts which request payload types are forwarded to the application.
+///
+/// Each rule names a host pattern (as in `ingress::Hosts`), an optional path prefix, and
+/// the media types (e.g. `application/json`) accepted by matching routes. The first
+/// matching rule applies; requests that match no rule are forwarded regardless of their
+/// content type.
+///
+/// Requests whose `Content-Type` isn't accepted, ignoring any parameters such as the
+/// charset, are answered locally with a `415 Unsupported Media Type`. Requests without a
+/// `Content-Type` carry no payload to check and are forwarded.
+#[derive(Clone, Debug)]
+pub struct Rules(Arc<Vec<Rule>>);
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    host: String,
+    path: Option<String>,
+    media_types: Vec<String>,
+}
+
+// ===== impl Rules =====
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Rules(Arc::new(rules))
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<B> Respond<B> for Rules {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        let content_type = match req.headers().get(CONTENT_TYPE) {
+            Some(ct) => ct,
+            None => return None,
+        };
+        let host = match request_host(req) {
+            Some(host) => host,
+            None => return None,
+        };
+        let rule = match self.0.iter().find(|r| r.matches(host, req.uri().path())) {
+            Some(rule) => rule,
+            None => return None,
+        };
+
+        let accepted = content_type
+            .to_str()
+            .map(|ct| rule.accepts(ct))
+            .unwrap_or(false);
+        if accepted {
+            return None;
+        }
+
+        debug!("content type {:?} not accepted for {}", content_type, req.uri());
+        let mut rsp = http::Response::new(Bytes::new());
+        *rsp.status_mut() = http::StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        Some(Response::new(rsp))
+    }
+}
+
+// ===== impl Rule =====
+
+impl Rule {
+    /// Accepts `media_types` on routes to `host` under `path`, or on all of `host`'s
+    /// routes if `path` is `None`.
+    pub fn new(host: &str, path: Option<&str>, media_types: Vec<String>) -> Self {
+        Rule {
+            host: host.to_lowercase(),
+            path: path.map(String::from),
+            media_types: media_types.into_iter().map(|t| t.to_lowercase()).collect(),
+        }
+    }
+
+    fn matches(&self, host: &str, path: &str) -> bool {
+        host_matches(&self.host, host)
+            && self.path.as_ref().map(|p| path_matches(p, path)).unwrap_or(true)
+    }
+
+    fn accepts(&self, content_type: &str) -> bool {
+        let media_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        self.media_types.contains(&media_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Rules {
+        Rules::new(vec![Rule::new(
+            "api.example.com",
+            Some("/v1"),
+            vec!["application/json".into(), "application/grpc".into()],
+        )])
+    }
+
+    fn req(uri: &str, content_type: Option<&str>) -> http::Request<()> {
+        let mut req = http::Request::builder();
+        req.method("POST").uri(uri);
+        if let Some(ct) = content_type {
+            req.header(CONTENT_TYPE, ct);
+        }
+        req.body(()).unwrap()
+    }
+
+    #[test]
+    fn rejects_unaccepted_content_types() {
+        for ct in &["application/xml", "text/plain; charset=utf-8", "application/grpc+proto"] {
+            let rsp = rules()
+                .respond(&req("http://api.example.com/v1/users", Some(ct)))
+                .expect("request rejected")
+                .response;
+            assert_eq!(rsp.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+    }
+
+    #[test]
+    fn forwards_accepted_content_types() {
+        let uri = "http://api.example.com/v1/users";
+        assert!(rules().respond(&req(uri, Some("application/json"))).is_none());
+        assert!(rules().respond(&req(uri, Some("Application/JSON; charset=utf-8"))).is_none());
+        assert!(rules().respond(&req(uri, None)).is_none());
+
+        // Other routes are left alone.
+        let other = Some("application/xml");
+        assert!(rules().respond(&req("http://api.example.com/v2/users", other)).is_none());
+        assert!(rules().respond(&req("http://web.example.com/v1/users", other)).is_none());
+    }
+}
@@ -0,0 +1,311 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http;
+use http::header::{
+    HeaderValue,
+    ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_HEADERS,
+    ACCESS_CONTROL_REQUEST_METHOD,
+    ORIGIN,
+    VARY,
+};
+use tower::Service;
+
+use super::{host_matches, request_host, Respond, Response};
+
+/// How long, in seconds, clients may cache the result of a preflight request.
+const MAX_AGE_SECS: &str = "600";
+
+/// Handles cross-origin requests on behalf of the application.
+///
+/// Each rule names a host pattern (as in `ingress::Hosts`) and the origins that may
+/// make cross-origin requests to matching hosts; an origin of `*` allows any origin.
+/// The first matching rule applies.
+///
+/// Preflight requests to matching hosts are answered locally, allowing whichever method
+/// and headers were requested if the origin is allowed. Other responses to requests from
+/// allowed origins have `Access-Control-Allow-Origin` set by `Cors`.
+#[derive(Clone, Debug)]
+pub struct Rules(Arc<Vec<Rule>>);
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    host: String,
+    origins: Vec<String>,
+}
+
+/// Wraps a service so that responses to allowed origins carry CORS headers.
+pub struct Cors<S> {
+    inner: S,
+    rules: Rules,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    /// The value of `Access-Control-Allow-Origin`, if the request's origin is allowed.
+    allow_origin: Option<HeaderValue>,
+}
+
+// ===== impl Rules =====
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Rules(Arc::new(rules))
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Vec::new())
+    }
+
+    fn find(&self, host: &str) -> Option<&Rule> {
+        self.0.iter().find(|r| host_matches(&r.host, host))
+    }
+
+    /// Returns the `Access-Control-Allow-Origin` value for a request, if it is a
+    /// cross-origin request from an allowed origin.
+    fn allow_origin<B>(&self, req: &http::Request<B>) -> Option<HeaderValue> {
+        let origin = req.headers().get(ORIGIN);
+        let rule = request_host(req).and_then(|h| self.find(h));
+        match (origin, rule) {
+            (Some(origin), Some(rule)) => rule.allow(origin),
+            _ => None,
+        }
+    }
+}
+
+impl<B> Respond<B> for Rules {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        if *req.method() != http::Method::OPTIONS {
+            return None;
+        }
+        let method = match req.headers().get(ACCESS_CONTROL_REQUEST_METHOD) {
+            Some(m) if req.headers().contains_key(ORIGIN) => m,
+            _ => return None,
+        };
+        if request_host(req).and_then(|h| self.find(h)).is_none() {
+            return None;
+        }
+
+        let mut rsp = http::Response::new(Bytes::new());
+        match self.allow_origin(req) {
+            Some(origin) => {
+                trace!("answering preflight for {:?}", origin);
+                *rsp.status_mut() = http::StatusCode::NO_CONTENT;
+                let headers = rsp.headers_mut();
+                set_allow_origin(headers, origin);
+                headers.insert(ACCESS_CONTROL_ALLOW_METHODS, method.clone());
+                if let Some(h) = req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS) {
+                    headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, h.clone());
+                }
+                headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static(MAX_AGE_SECS));
+            }
+            None => {
+                debug!("rejecting preflight from {:?}", req.headers().get(ORIGIN));
+                *rsp.status_mut() = http::StatusCode::FORBIDDEN;
+            }
+        }
+        Some(Response::new(rsp))
+    }
+}
+
+// ===== impl Rule =====
+
+impl Rule {
+    pub fn new(host: &str, origins: Vec<String>) -> Self {
+        Rule {
+            host: host.to_lowercase(),
+            origins: origins.into_iter().map(|o| o.to_lowercase()).collect(),
+        }
+    }
+
+    fn allow(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        if self.origins.iter().any(|o| o == "*") {
+            return Some(HeaderValue::from_static("*"));
+        }
+        let allowed = origin
+            .to_str()
+            .map(|o| self.origins.contains(&o.to_lowercase()))
+            .unwrap_or(false);
+        if allowed {
+            Some(origin.clone())
+        } else {
+            None
+        }
+    }
+}
+
+// ===== impl Cors =====
+
+impl<S> Cors<S> {
+    pub fn new(inner: S, rules: Rules) -> Self {
+        Cors {
+            inner,
+            rules,
+        }
+    }
+}
+
+impl<S, A, B> Service for Cors<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let allow_origin = self.rules.allow_origin(&req);
+        ResponseFuture {
+            inner: self.inner.call(req),
+            allow_origin,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut rsp = try_ready!(self.inner.poll());
+        if let Some(origin) = self.allow_origin.take() {
+            set_allow_origin(rsp.headers_mut(), origin);
+        }
+        Ok(Async::Ready(rsp))
+    }
+}
+
+/// Sets `Access-Control-Allow-Origin`, noting that the response varies by origin unless
+/// all origins are allowed.
+fn set_allow_origin(headers: &mut http::HeaderMap, origin: HeaderValue) {
+    if origin != "*" {
+        headers.append(VARY, HeaderValue::from_static("origin"));
+    }
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+
+    use super::*;
+
+    struct Empty;
+
+    impl Service for Empty {
+        type Request = http::Request<()>;
+        type Response = http::Response<()>;
+        type Error = ();
+        type Future = FutureResult<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            future::ok(http::Response::new(()))
+        }
+    }
+
+    fn rules() -> Rules {
+        Rules::new(vec![
+            Rule::new("web.example.com", vec!["https://app.example.com".into()]),
+            Rule::new("*.public.example.com", vec!["*".into()]),
+        ])
+    }
+
+    fn preflight(uri: &str, origin: &str) -> http::Request<()> {
+        http::Request::builder()
+            .method("OPTIONS")
+            .uri(uri)
+            .header(ORIGIN, origin)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .header(ACCESS_CONTROL_REQUEST_HEADERS, "content-type, x-token")
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn answers_preflights() {
+        let rsp = rules()
+            .respond(&preflight("http://web.example.com/", "https://App.example.com"))
+            .expect("preflight answered")
+            .response;
+        assert_eq!(rsp.status(), http::StatusCode::NO_CONTENT);
+        let h = rsp.headers();
+        assert_eq!(h[ACCESS_CONTROL_ALLOW_ORIGIN], "https://App.example.com");
+        assert_eq!(h[ACCESS_CONTROL_ALLOW_METHODS], "PUT");
+        assert_eq!(h[ACCESS_CONTROL_ALLOW_HEADERS], "content-type, x-token");
+        assert_eq!(h[VARY], "origin");
+
+        let rsp = rules()
+            .respond(&preflight("http://a.public.example.com/", "https://evil.example"))
+            .expect("preflight answered")
+            .response;
+        assert_eq!(rsp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(rsp.headers().get(VARY).is_none());
+    }
+
+    #[test]
+    fn rejects_preflights_from_other_origins() {
+        let rsp = rules()
+            .respond(&preflight("http://web.example.com/", "https://evil.example"))
+            .expect("preflight answered")
+            .response;
+        assert_eq!(rsp.status(), http::StatusCode::FORBIDDEN);
+        assert!(rsp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[test]
+    fn forwards_other_requests() {
+        // Unconfigured hosts are left to the application.
+        assert!(rules()
+            .respond(&preflight("http://other.example.com/", "https://app.example.com"))
+            .is_none());
+
+        // So are plain OPTIONS requests.
+        let req = http::Request::builder()
+            .method("OPTIONS")
+            .uri("http://web.example.com/")
+            .header(ORIGIN, "https://app.example.com")
+            .body(())
+            .unwrap();
+        assert!(rules().respond(&req).is_none());
+    }
+
+    #[test]
+    fn sets_allowed_origin_on_responses() {
+        let mut svc = Cors::new(Empty, rules());
+
+        let req = http::Request::builder()
+            .uri("http://web.example.com/")
+            .header(ORIGIN, "https://app.example.com")
+            .body(())
+            .unwrap();
+        let rsp = svc.call(req).wait().unwrap();
+        assert_eq!(rsp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+
+        let req = http::Request::builder()
+            .uri("http://web.example.com/")
+            .header(ORIGIN, "https://evil.example")
+            .body(())
+            .unwrap();
+        let rsp = svc.call(req).wait().unwrap();
+        assert!(rsp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+}
@@ -0,0 +1,58 @@
+use bytes::Bytes;
+use http;
+
+use super::{request_host, Respond, Response};
+use telemetry::grpc;
+use telemetry::topology::Topology;
+
+const AUTHORITY: &str = "conduit.local";
+const PATH: &str = "/grpc-metrics";
+
+/// Exposes metrics describing the messages of gRPC streams to the local application.
+///
+/// `GET http://conduit.local/grpc-metrics` returns the metrics in the Prometheus
+/// text format.
+#[derive(Clone, Debug)]
+pub struct GrpcMessages {
+    metrics: grpc::Messages,
+    topology: Topology,
+}
+
+// ===== impl GrpcMessages =====
+
+impl GrpcMessages {
+    pub fn new(metrics: &grpc::Messages, topology: &Topology) -> Self {
+        GrpcMessages {
+            metrics: metrics.clone(),
+            topology: topology.clone(),
+        }
+    }
+
+    fn matches<B>(req: &http::Request<B>) -> bool {
+        request_host(req) == Some(AUTHORITY) && req.uri().path() == PATH
+    }
+}
+
+impl<B> Respond<B> for GrpcMessages {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        if !Self::matches(req) {
+            return None;
+        }
+
+        let rsp = if *req.method() == http::Method::GET {
+            let body = self.topology.label_prometheus(&self.metrics.render());
+            let mut rsp = http::Response::new(Bytes::from(body));
+            rsp.headers_mut().insert(
+                http::header::CONTENT_TYPE,
+                http::header::HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+            rsp
+        } else {
+            let mut rsp = http::Response::new(Bytes::new());
+            *rsp.status_mut() = http::StatusCode::METHOD_NOT_ALLOWED;
+            rsp
+        };
+
+        Some(Response::new(rsp))
+    }
+}
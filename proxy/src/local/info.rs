@@ -0,0 +1,141 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http;
+use url::form_urlencoded;
+
+use super::{request_host, Respond, Response};
+use ctx;
+
+/// The authority the local application uses to reach the proxy itself.
+const AUTHORITY: &str = "conduit.local";
+const PATH: &str = "/connection-info";
+
+/// Describes the proxy to the local application.
+///
+/// `GET http://conduit.local/connection-info` returns a JSON document describing the
+/// proxy's version and the identity of the pod it runs in. If a `peer` query parameter is
+/// given, the document also describes how connections to that peer are secured.
+#[derive(Clone, Debug)]
+pub struct Info {
+    process: Arc<ctx::Process>,
+}
+
+// ===== impl Info =====
+
+impl Info {
+    pub fn new(process: &Arc<ctx::Process>) -> Self {
+        Info {
+            process: Arc::clone(process),
+        }
+    }
+
+    fn matches<B>(req: &http::Request<B>) -> bool {
+        request_host(req) == Some(AUTHORITY) && req.uri().path() == PATH
+    }
+
+    fn to_json(&self, peer: Option<&str>) -> String {
+        let mut s = String::new();
+        s.push('{');
+        let _ = write!(s, "\"version\":{}", json_str(env!("CARGO_PKG_VERSION")));
+        let _ = write!(s, ",\"node\":{}", json_str(&self.process.node));
+        let _ = write!(s, ",\"pod\":{}", json_str(&self.process.scheduled_instance));
+        let _ = write!(s, ",\"namespace\":{}", json_str(&self.process.scheduled_namespace));
+        if let Some(peer) = peer {
+            // The proxy does not yet originate or terminate TLS, so no peer is ever
+            // reached over mTLS.
+            let _ = write!(s, ",\"peer\":{{\"authority\":{},\"tls\":false}}", json_str(peer));
+        }
+        s.push('}');
+        s
+    }
+}
+
+impl<B> Respond<B> for Info {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        if !Self::matches(req) {
+            return None;
+        }
+
+        let mut rsp = if *req.method() == http::Method::GET {
+            let peer = req.uri().query().and_then(|q| {
+                form_urlencoded::parse(q.as_bytes())
+                    .find(|&(ref k, _)| *k == "peer")
+                    .map(|(_, v)| v.into_owned())
+            });
+            let json = self.to_json(peer.as_ref().map(|p| p.as_str()));
+            let mut rsp = http::Response::new(Bytes::from(json));
+            rsp.headers_mut().insert(
+                http::header::CONTENT_TYPE,
+                http::header::HeaderValue::from_static("application/json"),
+            );
+            rsp
+        } else {
+            let mut rsp = http::Response::new(Bytes::new());
+            *rsp.status_mut() = http::StatusCode::METHOD_NOT_ALLOWED;
+            rsp
+        };
+        rsp.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            http::header::HeaderValue::from_static("no-cache"),
+        );
+
+        Some(Response::new(rsp))
+    }
+}
+
+/// Formats `s` as a JSON string literal.
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_str_escapes() {
+        assert_eq!(json_str("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[test]
+    fn responds_to_connection_info() {
+        let info = Info::new(&ctx::Process::new("node", "pod", "ns"));
+
+        let req = http::Request::builder()
+            .uri("http://conduit.local/connection-info?peer=foo.ns.svc:8080")
+            .body(())
+            .unwrap();
+        let rsp = info.respond(&req).expect("info response").response;
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+        let expected = format!(
+            "{{\"version\":\"{}\",\"node\":\"node\",\"pod\":\"pod\",\"namespace\":\"ns\",\
+             \"peer\":{{\"authority\":\"foo.ns.svc:8080\",\"tls\":false}}}}",
+            env!("CARGO_PKG_VERSION"),
+        );
+        assert_eq!(&rsp.body()[..], expected.as_bytes());
+
+        let req = http::Request::builder()
+            .uri("http://example.com/connection-info")
+            .body(())
+            .unwrap();
+        assert!(info.respond(&req).is_none());
+    }
+}
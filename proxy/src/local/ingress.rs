@@ -0,0 +1,77 @@
+use bytes::Bytes;
+use http;
+
+use super::{host_matches, request_host, Respond, Response};
+
+/// Restricts which hosts may be routed when the public listener acts as an ingress.
+///
+/// Each pattern is either an exact host name or a `*.`-prefixed suffix. Requests whose
+/// authority (or `Host` header) matches no pattern are answered with a 404 rather than
+/// being routed into the mesh.
+#[derive(Clone, Debug)]
+pub struct Hosts {
+    patterns: Vec<String>,
+}
+
+// ===== impl Hosts =====
+
+impl Hosts {
+    pub fn new<I: IntoIterator<Item = String>>(patterns: I) -> Self {
+        Hosts {
+            patterns: patterns.into_iter().map(|p| p.to_lowercase()).collect(),
+        }
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        self.patterns.iter().any(|p| host_matches(p, host))
+    }
+}
+
+impl<B> Respond<B> for Hosts {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        let host = request_host(req);
+        match host {
+            Some(h) if self.is_allowed(h) => None,
+            _ => {
+                debug!("ingress host not allowed: {:?}", host);
+                let mut rsp = http::Response::new(Bytes::new());
+                *rsp.status_mut() = http::StatusCode::NOT_FOUND;
+                Some(Response::new(rsp))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_and_suffix() {
+        let hosts = Hosts::new(vec!["web.example.com".into(), "*.api.example.com".into()]);
+        assert!(hosts.is_allowed("web.example.com"));
+        assert!(hosts.is_allowed("WEB.example.com"));
+        assert!(hosts.is_allowed("users.api.example.com"));
+        assert!(!hosts.is_allowed("api.example.com"));
+        assert!(!hosts.is_allowed("www.example.com"));
+    }
+
+    #[test]
+    fn rejects_unknown_hosts() {
+        let hosts = Hosts::new(vec!["web.example.com".into()]);
+
+        let req = http::Request::builder()
+            .uri("http://web.example.com/")
+            .body(())
+            .unwrap();
+        assert!(hosts.respond(&req).is_none());
+
+        let req = http::Request::builder()
+            .uri("/")
+            .header("host", "other.example.com:80")
+            .body(())
+            .unwrap();
+        let rsp = hosts.respond(&req).expect("rejected");
+        assert_eq!(rsp.response.status(), http::StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,119 @@
+use bytes::Bytes;
+use http;
+use log::LogLevelFilter;
+use url::form_urlencoded;
+
+use super::{request_host, Respond, Response};
+use logging;
+
+const AUTHORITY: &str = "conduit.local";
+const PATH: &str = "/log-scope";
+
+/// Lets the local application change the proxy's log scope at runtime.
+///
+/// - `PUT http://conduit.local/log-scope?match=<pattern>&level=<level>` logs records at
+///   `level` or above in any context (i.e. a connection or endpoint address) containing
+///   `pattern`;
+/// - `DELETE http://conduit.local/log-scope` restores the configured logging;
+/// - `GET http://conduit.local/log-scope` describes the current scope.
+#[derive(Clone, Debug, Default)]
+pub struct LogScope;
+
+// ===== impl LogScope =====
+
+impl LogScope {
+    pub fn new() -> Self {
+        LogScope
+    }
+
+    fn matches<B>(req: &http::Request<B>) -> bool {
+        request_host(req) == Some(AUTHORITY) && req.uri().path() == PATH
+    }
+}
+
+impl<B> Respond<B> for LogScope {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        if !Self::matches(req) {
+            return None;
+        }
+
+        let method = req.method();
+        let rsp = if *method == http::Method::GET {
+            let body = logging::scope()
+                .map(|s| format!("match={} level={}\n", s.pattern, s.level))
+                .unwrap_or_default();
+            http::Response::new(Bytes::from(body))
+        } else if *method == http::Method::PUT {
+            match parse_scope(req.uri().query().unwrap_or("")) {
+                Ok(scope) => {
+                    info!("setting log scope: {:?}", scope);
+                    status(update(Some(scope)))
+                }
+                Err(e) => {
+                    let mut rsp = http::Response::new(Bytes::from(e));
+                    *rsp.status_mut() = http::StatusCode::BAD_REQUEST;
+                    rsp
+                }
+            }
+        } else if *method == http::Method::DELETE {
+            info!("clearing log scope");
+            status(update(None))
+        } else {
+            status(http::StatusCode::METHOD_NOT_ALLOWED)
+        };
+
+        Some(Response::new(rsp))
+    }
+}
+
+fn update(scope: Option<logging::Scope>) -> http::StatusCode {
+    if logging::set_scope(scope) {
+        http::StatusCode::NO_CONTENT
+    } else {
+        http::StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+fn status(status: http::StatusCode) -> http::Response<Bytes> {
+    let mut rsp = http::Response::new(Bytes::new());
+    *rsp.status_mut() = status;
+    rsp
+}
+
+fn parse_scope(query: &str) -> Result<logging::Scope, &'static str> {
+    let mut pattern = None;
+    let mut level = LogLevelFilter::Debug;
+    for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+        match &*k {
+            "match" => pattern = Some(v.into_owned()),
+            "level" => level = v.parse().map_err(|_| "invalid level")?,
+            _ => {}
+        }
+    }
+
+    match pattern {
+        Some(ref p) if p.is_empty() => Err("match must not be empty"),
+        Some(pattern) => Ok(logging::Scope {
+            pattern,
+            level,
+        }),
+        None => Err("missing match"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scope() {
+        let scope = parse_scope("match=10.1.2.3%3A8080&level=trace").unwrap();
+        assert_eq!(scope.pattern, "10.1.2.3:8080");
+        assert_eq!(scope.level, LogLevelFilter::Trace);
+
+        assert_eq!(parse_scope("match=foo").unwrap().level, LogLevelFilter::Debug);
+        assert!(parse_scope("level=debug").is_err());
+        assert!(parse_scope("match=&level=debug").is_err());
+        assert!(parse_scope("match=foo&level=loud").is_err());
+    }
+}
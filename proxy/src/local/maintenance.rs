@@ -0,0 +1,317 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http;
+use http::header::{HeaderValue, CONTENT_TYPE};
+use tower::Service;
+
+use super::translate::next_correlation_id;
+use super::{host_matches, request_host, Body, Respond, Response};
+
+/// Replaced with the request's ID when a page is rendered.
+const REQUEST_ID_PLACEHOLDER: &str = "{{request_id}}";
+
+/// Used as the request's ID, if present.
+const REQUEST_ID: &str = "x-request-id";
+
+/// Serves static pages in place of the application's responses.
+///
+/// Each rule names a host pattern (as in `ingress::Hosts`), a page, and when the page is
+/// served: either always, e.g. while a service is under maintenance, or only when the
+/// upstream is unavailable, i.e. when the request fails or the upstream responds with
+/// a `502`, `503`, or `504`. The first matching rule applies.
+///
+/// Pages are served with a `503` status. Each occurrence of `{{request_id}}` in a page is
+/// replaced with the request's `x-request-id`, or a generated ID if it has none.
+#[derive(Clone, Debug)]
+pub struct Rules(Arc<Vec<Rule>>);
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    host: String,
+    page: Page,
+    when: When,
+}
+
+/// Determines when a rule's page is served.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum When {
+    Always,
+    Unavailable,
+}
+
+/// A page template and its content type.
+#[derive(Clone, Debug)]
+pub struct Page {
+    template: Arc<String>,
+    content_type: HeaderValue,
+}
+
+/// Wraps a service so that pages are served when the upstream is unavailable.
+pub struct Unavailable<S> {
+    inner: S,
+    rules: Rules,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    /// The page to serve and the request's ID, if the request matched a rule.
+    page: Option<(Page, String)>,
+}
+
+// ===== impl Rules =====
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Rules(Arc::new(rules))
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Vec::new())
+    }
+
+    fn find<B>(&self, req: &http::Request<B>, when: When) -> Option<&Page> {
+        request_host(req)
+            .and_then(|h| self.0.iter().find(|r| host_matches(&r.host, h)))
+            .and_then(|r| if r.when == when { Some(&r.page) } else { None })
+    }
+}
+
+impl<B> Respond<B> for Rules {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        self.find(req, When::Always).map(|page| {
+            debug!("serving maintenance page for {}", req.uri());
+            Response::new(page.render(http::StatusCode::SERVICE_UNAVAILABLE, &request_id(req)))
+        })
+    }
+}
+
+fn request_id<B>(req: &http::Request<B>) -> String {
+    req.headers()
+        .get(REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(next_correlation_id)
+}
+
+// ===== impl Rule =====
+
+impl Rule {
+    pub fn new(host: &str, page: Page, when: When) -> Self {
+        Rule {
+            host: host.to_lowercase(),
+            page,
+            when,
+        }
+    }
+}
+
+// ===== impl Page =====
+
+impl Page {
+    pub fn new(template: String, content_type: HeaderValue) -> Self {
+        Page {
+            template: Arc::new(template),
+            content_type,
+        }
+    }
+
+    /// Reads a page from a file, inferring its content type from the file's extension.
+    ///
+    /// Pages are read once, at startup. A page projected from a Kubernetes volume is
+    /// updated by atomically swapping its `..data` symlink, so it's never read partially
+    /// written, but the update only takes effect when the proxy restarts.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut template = String::new();
+        fs::File::open(path)?.read_to_string(&mut template)?;
+
+        let content_type = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => "application/json",
+            Some("html") | Some("htm") => "text/html; charset=utf-8",
+            _ => "text/plain; charset=utf-8",
+        };
+        Ok(Self::new(template, HeaderValue::from_static(content_type)))
+    }
+
+    fn render(&self, status: http::StatusCode, request_id: &str) -> http::Response<Bytes> {
+        let body = self.template.replace(REQUEST_ID_PLACEHOLDER, request_id);
+        let mut rsp = http::Response::new(Bytes::from(body));
+        *rsp.status_mut() = status;
+        rsp.headers_mut().insert(CONTENT_TYPE, self.content_type.clone());
+        rsp
+    }
+}
+
+// ===== impl Unavailable =====
+
+impl<S> Unavailable<S> {
+    pub fn new(inner: S, rules: Rules) -> Self {
+        Unavailable {
+            inner,
+            rules,
+        }
+    }
+}
+
+impl<S, A, B> Service for Unavailable<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<Body<B>>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let page = self.rules
+            .find(&req, When::Unavailable)
+            .map(|page| (page.clone(), request_id(&req)));
+        ResponseFuture {
+            inner: self.inner.call(req),
+            page,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<Body<B>>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let unavailable = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(rsp)) => {
+                let unavailable = match rsp.status() {
+                    http::StatusCode::BAD_GATEWAY
+                    | http::StatusCode::SERVICE_UNAVAILABLE
+                    | http::StatusCode::GATEWAY_TIMEOUT => true,
+                    _ => false,
+                };
+                if !unavailable || self.page.is_none() {
+                    return Ok(Async::Ready(rsp));
+                }
+                rsp.status()
+            }
+            Err(e) => {
+                if self.page.is_none() {
+                    return Err(e);
+                }
+                http::StatusCode::SERVICE_UNAVAILABLE
+            }
+        };
+
+        debug!("upstream unavailable ({}); serving page", unavailable);
+        let (page, id) = self.page.take().expect("page must be set");
+        let rsp = page.render(http::StatusCode::SERVICE_UNAVAILABLE, &id);
+        let (parts, body) = rsp.into_parts();
+        Ok(Async::Ready(http::Response::from_parts(parts, Body::Local(Some(body)))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+
+    use super::*;
+
+    /// Responds to every request with a fixed status, or fails if there is none.
+    struct Status(Option<u16>);
+
+    impl Service for Status {
+        type Request = http::Request<()>;
+        type Response = http::Response<Body<()>>;
+        type Error = ();
+        type Future = FutureResult<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            match self.0 {
+                Some(status) => {
+                    let mut rsp = http::Response::new(Body::Proxied(()));
+                    *rsp.status_mut() = http::StatusCode::from_u16(status).unwrap();
+                    future::ok(rsp)
+                }
+                None => future::err(()),
+            }
+        }
+    }
+
+    fn page() -> Page {
+        Page::new(
+            "{\"maintenance\":true,\"request_id\":\"{{request_id}}\"}".into(),
+            HeaderValue::from_static("application/json"),
+        )
+    }
+
+    fn req(uri: &str) -> http::Request<()> {
+        http::Request::builder()
+            .uri(uri)
+            .header(REQUEST_ID, "abc123")
+            .body(())
+            .unwrap()
+    }
+
+    fn local_body(rsp: &http::Response<Body<()>>) -> &[u8] {
+        match *rsp.body() {
+            Body::Local(Some(ref b)) => &b[..],
+            _ => panic!("expected a page"),
+        }
+    }
+
+    #[test]
+    fn always_serves_maintenance_pages() {
+        let rules = Rules::new(vec![
+            Rule::new("web.example.com", page(), When::Always),
+            Rule::new("api.example.com", page(), When::Unavailable),
+        ]);
+
+        let rsp = rules.respond(&req("http://web.example.com/")).expect("page served");
+        assert_eq!(rsp.response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(rsp.response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(
+            &rsp.response.body()[..],
+            &b"{\"maintenance\":true,\"request_id\":\"abc123\"}"[..]
+        );
+
+        assert!(rules.respond(&req("http://api.example.com/")).is_none());
+        assert!(rules.respond(&req("http://other.example.com/")).is_none());
+    }
+
+    #[test]
+    fn serves_pages_when_unavailable() {
+        let rules = Rules::new(vec![Rule::new("*.example.com", page(), When::Unavailable)]);
+
+        for status in vec![Some(502), Some(503), Some(504), None] {
+            let mut svc = Unavailable::new(Status(status), rules.clone());
+            let rsp = svc.call(req("http://web.example.com/")).wait().unwrap();
+            assert_eq!(rsp.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(
+                local_body(&rsp),
+                &b"{\"maintenance\":true,\"request_id\":\"abc123\"}"[..]
+            );
+        }
+
+        let mut svc = Unavailable::new(Status(Some(500)), rules.clone());
+        let rsp = svc.call(req("http://web.example.com/")).wait().unwrap();
+        assert_eq!(rsp.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        let mut svc = Unavailable::new(Status(None), rules);
+        assert!(svc.call(req("http://web.test/")).wait().is_err());
+    }
+}
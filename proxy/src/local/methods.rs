@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http;
+use http::header::{HeaderValue, ALLOW};
+
+use super::{host_matches, path_matches, request_host, Respond, Response};
+
+/// Restricts which request methods are forwarded to the application.
+///
+/// Each rule names a host pattern (as in `ingress::Hosts`), an optional path prefix, and
+/// the methods allowed on matching routes. The first matching rule applies; requests
+/// that match no rule are forwarded regardless of their method.
+///
+/// Requests with a method that isn't allowed are answered locally with a
+/// `405 Method Not Allowed` listing the allowed methods in its `Allow` header.
+#[derive(Clone, Debug)]
+pub struct Rules(Arc<Vec<Rule>>);
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    host: String,
+    path: Option<String>,
+    methods: Vec<http::Method>,
+    allow: HeaderValue,
+}
+
+// ===== impl Rules =====
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Rules(Arc::new(rules))
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<B> Respond<B> for Rules {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        let host = match request_host(req) {
+            Some(host) => host,
+            None => return None,
+        };
+        let rule = match self.0.iter().find(|r| r.matches(host, req.uri().path())) {
+            Some(rule) => rule,
+            None => return None,
+        };
+        if rule.methods.contains(req.method()) {
+            return None;
+        }
+
+        debug!("method {} not allowed for {}", req.method(), req.uri());
+        let mut rsp = http::Response::new(Bytes::new());
+        *rsp.status_mut() = http::StatusCode::METHOD_NOT_ALLOWED;
+        rsp.headers_mut().insert(ALLOW, rule.allow.clone());
+        Some(Response::new(rsp))
+    }
+}
+
+// ===== impl Rule =====
+
+impl Rule {
+    /// Allows `methods` on routes to `host` under `path`, or on all of `host`'s routes
+    /// if `path` is `None`.
+    pub fn new(host: &str, path: Option<&str>, methods: Vec<http::Method>) -> Self {
+        let allow = methods
+            .iter()
+            .map(|m| m.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Rule {
+            host: host.to_lowercase(),
+            path: path.map(String::from),
+            allow: HeaderValue::from_str(&allow).expect("methods are valid header values"),
+            methods,
+        }
+    }
+
+    fn matches(&self, host: &str, path: &str) -> bool {
+        host_matches(&self.host, host)
+            && self.path.as_ref().map(|p| path_matches(p, path)).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Rules {
+        Rules::new(vec![
+            Rule::new("web.example.com", Some("/admin/"), vec![http::Method::GET]),
+            Rule::new(
+                "web.example.com",
+                None,
+                vec![http::Method::GET, http::Method::POST],
+            ),
+        ])
+    }
+
+    fn req(method: &str, uri: &str) -> http::Request<()> {
+        http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn rejects_disallowed_methods() {
+        let rsp = rules()
+            .respond(&req("DELETE", "http://web.example.com/users/1"))
+            .expect("request rejected")
+            .response;
+        assert_eq!(rsp.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(rsp.headers()[ALLOW], "GET, POST");
+
+        let rsp = rules()
+            .respond(&req("POST", "http://web.example.com/admin/users"))
+            .expect("request rejected")
+            .response;
+        assert_eq!(rsp.headers()[ALLOW], "GET");
+    }
+
+    #[test]
+    fn forwards_allowed_methods() {
+        assert!(rules().respond(&req("POST", "http://web.example.com/users")).is_none());
+        assert!(rules().respond(&req("GET", "http://web.example.com/admin")).is_none());
+
+        // Prefixes only match whole path segments.
+        assert!(rules().respond(&req("POST", "http://web.example.com/administrators")).is_none());
+
+        // Unconfigured hosts are left alone.
+        assert!(rules().respond(&req("DELETE", "http://other.example.com/")).is_none());
+    }
+}
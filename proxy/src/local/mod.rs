@@ -0,0 +1,309 @@
+//! Responses produced by the proxy itself rather than by a remote peer.
+//!
+//! A `Local` service sits in front of a listener's router. Each request is first offered
+//! to a list of `Respond` implementations; the first one to produce a response short
+//! circuits the router entirely. All other requests are forwarded to the router and its
+//! responses are passed through untouched.
+
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, IntoBuf};
+use futures::{Async, Future, Poll};
+use h2;
+use http;
+use tokio_core::reactor::{Handle, Timeout};
+use tower::Service;
+use tower_h2;
+
+pub mod content_types;
+pub mod cors;
+pub mod grpc_messages;
+pub mod info;
+pub mod ingress;
+pub mod log_scope;
+pub mod maintenance;
+pub mod methods;
+pub mod read_only;
+pub mod resolutions;
+pub mod stream_limit;
+pub mod synthetic;
+pub mod translate;
+
+pub use self::cors::Cors;
+pub use self::grpc_messages::GrpcMessages;
+pub use self::info::Info;
+pub use self::log_scope::LogScope;
+pub use self::maintenance::Unavailable;
+pub use self::read_only::ReadOnly;
+pub use self::resolutions::Resolutions;
+pub use self::stream_limit::StreamLimit;
+pub use self::synthetic::Synthetic;
+pub use self::translate::Translate;
+
+/// Produces a response for requests that should not be routed.
+pub trait Respond<B> {
+    /// Returns a response if `req` should be answered locally.
+    fn respond(&self, req: &http::Request<B>) -> Option<Response>;
+}
+
+/// A locally-generated response.
+#[derive(Debug)]
+pub struct Response {
+    response: http::Response<Bytes>,
+    delay: Option<Duration>,
+}
+
+/// An ordered list of `Respond` implementations shared by all connections on a listener.
+pub struct Responders<B>(Arc<Vec<Box<Respond<B>>>>);
+
+/// Answers requests locally when a `Respond` matches, otherwise forwards them to `inner`.
+pub struct Local<S, B> {
+    inner: S,
+    responders: Responders<B>,
+    executor: Handle,
+}
+
+pub enum ResponseFuture<F> {
+    Inner(F),
+    Local(Option<http::Response<Bytes>>, Option<Timeout>),
+}
+
+/// A response body that is either proxied from a peer or produced locally.
+#[derive(Debug)]
+pub enum Body<B> {
+    Proxied(B),
+    Local(Option<Bytes>),
+}
+
+/// The chunk type of a `Body`.
+#[derive(Debug)]
+pub enum Data<B> {
+    Proxied(B),
+    Local(Cursor<Bytes>),
+}
+
+/// Returns the host a request is addressed to, without any port.
+///
+/// The `:authority` is preferred, falling back to the `Host` header.
+pub fn request_host<B>(req: &http::Request<B>) -> Option<&str> {
+    req.uri().authority_part().map(|a| a.host()).or_else(|| {
+        req.headers()
+            .get(http::header::HOST)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.split(':').next().unwrap_or(h))
+    })
+}
+
+/// Returns true if `host` matches `pattern`, which is either an exact, lowercase host
+/// name or a `*.`-prefixed suffix.
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    let host = host.to_lowercase();
+    if pattern.starts_with("*.") {
+        host.ends_with(&pattern[1..])
+    } else {
+        pattern == host
+    }
+}
+
+/// Returns true if `path` is `prefix` or one of the paths beneath it.
+///
+/// Prefixes only match whole path segments, so `/admin` matches `/admin/users` but not
+/// `/administrators`.
+pub fn path_matches(prefix: &str, path: &str) -> bool {
+    let prefix = prefix.trim_right_matches('/');
+    path.starts_with(prefix)
+        && (path.len() == prefix.len() || path[prefix.len()..].starts_with('/'))
+}
+
+// ===== impl Response =====
+
+impl Response {
+    pub fn new(response: http::Response<Bytes>) -> Self {
+        Response {
+            response,
+            delay: None,
+        }
+    }
+
+    /// Delays sending the response until `delay` has elapsed.
+    pub fn with_delay(self, delay: Duration) -> Self {
+        Response {
+            delay: Some(delay),
+            ..self
+        }
+    }
+
+    /// Returns the response to be sent immediately, ignoring any delay.
+    pub fn into_http(self) -> http::Response<Bytes> {
+        self.response
+    }
+}
+
+// ===== impl Responders =====
+
+impl<B> Responders<B> {
+    pub fn new(responders: Vec<Box<Respond<B>>>) -> Self {
+        Responders(Arc::new(responders))
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Vec::new())
+    }
+
+    /// Returns the response of the first responder that answers `req`, if any.
+    pub fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        self.0.iter().filter_map(|r| r.respond(req)).next()
+    }
+}
+
+impl<B> Clone for Responders<B> {
+    fn clone(&self) -> Self {
+        Responders(self.0.clone())
+    }
+}
+
+// ===== impl Local =====
+
+impl<S, B> Local<S, B> {
+    pub fn new(inner: S, responders: Responders<B>, executor: &Handle) -> Self {
+        Local {
+            inner,
+            responders,
+            executor: executor.clone(),
+        }
+    }
+}
+
+impl<S, A, B> Service for Local<S, A>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+{
+    type Request = http::Request<A>;
+    type Response = http::Response<Body<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        match self.responders.respond(&req) {
+            None => ResponseFuture::Inner(self.inner.call(req)),
+            Some(Response { response, delay }) => {
+                trace!("responding locally: {} {}", req.method(), req.uri());
+                let timeout = delay.and_then(|d| match Timeout::new(d, &self.executor) {
+                    Ok(t) => Some(t),
+                    Err(e) => {
+                        warn!("could not delay local response: {}", e);
+                        None
+                    }
+                });
+                ResponseFuture::Local(Some(response), timeout)
+            }
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = http::Response<Body<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::Inner(ref mut f) => {
+                let rsp = try_ready!(f.poll());
+                let (parts, body) = rsp.into_parts();
+                let rsp = http::Response::from_parts(parts, Body::Proxied(body));
+                Ok(Async::Ready(rsp))
+            }
+            ResponseFuture::Local(ref mut rsp, ref mut timeout) => {
+                if let Some(ref mut t) = *timeout {
+                    // A failed timer is treated as having fired.
+                    if let Ok(Async::NotReady) = t.poll() {
+                        return Ok(Async::NotReady);
+                    }
+                }
+
+                let (parts, body) = rsp.take().expect("poll after complete").into_parts();
+                let body = if body.is_empty() { None } else { Some(body) };
+                let rsp = http::Response::from_parts(parts, Body::Local(body));
+                Ok(Async::Ready(rsp))
+            }
+        }
+    }
+}
+
+// ===== impl Body =====
+
+impl<B> Default for Body<B> {
+    fn default() -> Self {
+        Body::Local(None)
+    }
+}
+
+impl<B> tower_h2::Body for Body<B>
+where
+    B: tower_h2::Body + 'static,
+{
+    type Data = Data<<B::Data as IntoBuf>::Buf>;
+
+    fn is_end_stream(&self) -> bool {
+        match *self {
+            Body::Proxied(ref b) => b.is_end_stream(),
+            Body::Local(ref b) => b.is_none(),
+        }
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        match *self {
+            Body::Proxied(ref mut b) => {
+                let data = try_ready!(b.poll_data());
+                Ok(Async::Ready(data.map(|d| Data::Proxied(d.into_buf()))))
+            }
+            Body::Local(ref mut b) => {
+                let data = b.take().map(|b| Data::Local(b.into_buf()));
+                Ok(Async::Ready(data))
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        match *self {
+            Body::Proxied(ref mut b) => b.poll_trailers(),
+            Body::Local(_) => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+// ===== impl Data =====
+
+impl<B: Buf> Buf for Data<B> {
+    fn remaining(&self) -> usize {
+        match *self {
+            Data::Proxied(ref b) => b.remaining(),
+            Data::Local(ref b) => b.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match *self {
+            Data::Proxied(ref b) => b.bytes(),
+            Data::Local(ref b) => b.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match *self {
+            Data::Proxied(ref mut b) => b.advance(cnt),
+            Data::Local(ref mut b) => b.advance(cnt),
+        }
+    }
+}
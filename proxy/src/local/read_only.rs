@@ -0,0 +1,74 @@
+use bytes::Bytes;
+use http;
+
+use super::{request_host, Respond, Response};
+
+const AUTHORITY: &str = "conduit.local";
+
+/// Refuses requests to `conduit.local` that could change the proxy's state.
+///
+/// Only `GET` and `HEAD` requests are passed on to the responders that follow; any other
+/// request, e.g. `PUT http://conduit.local/log-scope`, is answered with
+/// `403 Forbidden`. It should precede every other `conduit.local` responder.
+#[derive(Clone, Debug, Default)]
+pub struct ReadOnly;
+
+// ===== impl ReadOnly =====
+
+impl ReadOnly {
+    pub fn new() -> Self {
+        ReadOnly
+    }
+}
+
+impl<B> Respond<B> for ReadOnly {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        if request_host(req) != Some(AUTHORITY) {
+            return None;
+        }
+        match *req.method() {
+            http::Method::GET | http::Method::HEAD => return None,
+            _ => {}
+        }
+
+        debug!("refusing {} {}: admin endpoints are read-only", req.method(), req.uri());
+        let mut rsp = http::Response::new(Bytes::from("admin endpoints are read-only\n"));
+        *rsp.status_mut() = http::StatusCode::FORBIDDEN;
+        Some(Response::new(rsp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, uri: &str) -> http::Request<()> {
+        http::Request::builder().method(method).uri(uri).body(()).unwrap()
+    }
+
+    #[test]
+    fn refuses_mutating_requests() {
+        let read_only = ReadOnly::new();
+        let refused = read_only
+            .respond(&request("PUT", "http://conduit.local/log-scope?match=foo"))
+            .expect("refused")
+            .into_http();
+        assert_eq!(refused.status(), http::StatusCode::FORBIDDEN);
+        assert!(
+            read_only
+                .respond(&request("DELETE", "http://conduit.local/log-scope"))
+                .is_some()
+        );
+
+        assert!(
+            read_only
+                .respond(&request("GET", "http://conduit.local/log-scope"))
+                .is_none()
+        );
+        assert!(
+            read_only
+                .respond(&request("PUT", "http://web.example.com/log-scope"))
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use bytes::Bytes;
+use http;
+
+use super::{request_host, Respond, Response};
+use control;
+use telemetry::topology::Topology;
+
+const AUTHORITY: &str = "conduit.local";
+const PATH: &str = "/destination-metrics";
+
+/// Exposes metrics describing destination resolution to the local application.
+///
+/// `GET http://conduit.local/destination-metrics` returns the metrics in the Prometheus
+/// text format.
+#[derive(Clone, Debug)]
+pub struct Resolutions {
+    metrics: control::Resolutions,
+    topology: Topology,
+}
+
+// ===== impl Resolutions =====
+
+impl Resolutions {
+    pub fn new(metrics: &control::Resolutions, topology: &Topology) -> Self {
+        Resolutions {
+            metrics: metrics.clone(),
+            topology: topology.clone(),
+        }
+    }
+
+    fn matches<B>(req: &http::Request<B>) -> bool {
+        request_host(req) == Some(AUTHORITY) && req.uri().path() == PATH
+    }
+}
+
+impl<B> Respond<B> for Resolutions {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        if !Self::matches(req) {
+            return None;
+        }
+
+        let rsp = if *req.method() == http::Method::GET {
+            let body = self.topology.label_prometheus(&self.metrics.render());
+            let mut rsp = http::Response::new(Bytes::from(body));
+            rsp.headers_mut().insert(
+                http::header::CONTENT_TYPE,
+                http::header::HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+            rsp
+        } else {
+            let mut rsp = http::Response::new(Bytes::new());
+            *rsp.status_mut() = http::StatusCode::METHOD_NOT_ALLOWED;
+            rsp
+        };
+
+        Some(Response::new(rsp))
+    }
+}
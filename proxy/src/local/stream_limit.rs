@@ -0,0 +1,59 @@
+use bytes::Bytes;
+use http;
+
+use super::{request_host, Respond, Response};
+use stream_limit;
+use telemetry::topology::Topology;
+
+const AUTHORITY: &str = "conduit.local";
+const PATH: &str = "/stream-metrics";
+
+/// Exposes metrics describing streams refused by the public listener to the local
+/// application.
+///
+/// `GET http://conduit.local/stream-metrics` returns the metrics in the Prometheus
+/// text format.
+#[derive(Clone, Debug)]
+pub struct StreamLimit {
+    metrics: stream_limit::Limit,
+    topology: Topology,
+}
+
+// ===== impl StreamLimit =====
+
+impl StreamLimit {
+    pub fn new(metrics: &stream_limit::Limit, topology: &Topology) -> Self {
+        StreamLimit {
+            metrics: metrics.clone(),
+            topology: topology.clone(),
+        }
+    }
+
+    fn matches<B>(req: &http::Request<B>) -> bool {
+        request_host(req) == Some(AUTHORITY) && req.uri().path() == PATH
+    }
+}
+
+impl<B> Respond<B> for StreamLimit {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        if !Self::matches(req) {
+            return None;
+        }
+
+        let rsp = if *req.method() == http::Method::GET {
+            let body = self.topology.label_prometheus(&self.metrics.render());
+            let mut rsp = http::Response::new(Bytes::from(body));
+            rsp.headers_mut().insert(
+                http::header::CONTENT_TYPE,
+                http::header::HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+            rsp
+        } else {
+            let mut rsp = http::Response::new(Bytes::new());
+            *rsp.status_mut() = http::StatusCode::METHOD_NOT_ALLOWED;
+            rsp
+        };
+
+        Some(Response::new(rsp))
+    }
+}
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use http;
+use url::form_urlencoded;
+
+use super::{Respond, Response};
+use reload::SecretSetting;
+
+/// The request header that must carry the configured token.
+const TOKEN_HEADER: &str = "conduit-synthetic-token";
+
+/// Upper bounds on what a synthetic request may ask for, so that a leaked token can't
+/// be used to tie up the proxy.
+const MAX_LATENCY_MS: u64 = 30_000;
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Answers smoke-test requests without forwarding them to the application.
+///
+/// A request is answered only if it carries a `conduit-synthetic-token` header matching
+/// the configured token, which may change when the configuration is reloaded. Without a
+/// token, no request is answered. The response is shaped by the request's query parameters:
+///
+/// - `status`: the response status code (default 200);
+/// - `latency_ms`: how long to wait before responding (default 0);
+/// - `body_bytes`: the size of the response body (default 0).
+#[derive(Clone, Debug)]
+pub struct Synthetic {
+    token: SecretSetting,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Params {
+    status: http::StatusCode,
+    latency: Duration,
+    body_bytes: usize,
+}
+
+// ===== impl Synthetic =====
+
+impl Synthetic {
+    pub fn new(token: SecretSetting) -> Self {
+        Synthetic {
+            token,
+        }
+    }
+
+    fn is_authorized<B>(&self, req: &http::Request<B>) -> bool {
+        req.headers()
+            .get(TOKEN_HEADER)
+            .map(|v| self.token.matches(v.as_bytes()))
+            .unwrap_or(false)
+    }
+}
+
+impl<B> Respond<B> for Synthetic {
+    fn respond(&self, req: &http::Request<B>) -> Option<Response> {
+        if !self.is_authorized(req) {
+            return None;
+        }
+
+        let params = match Params::from_query(req.uri().query().unwrap_or("")) {
+            Ok(p) => p,
+            Err(e) => {
+                debug!("invalid synthetic request {}: {}", req.uri(), e);
+                let mut rsp = http::Response::new(Bytes::from(e));
+                *rsp.status_mut() = http::StatusCode::BAD_REQUEST;
+                return Some(Response::new(rsp));
+            }
+        };
+
+        debug!("synthetic response: {:?}", params);
+        let mut rsp = http::Response::new(Bytes::from(vec![b'.'; params.body_bytes]));
+        *rsp.status_mut() = params.status;
+        Some(Response::new(rsp).with_delay(params.latency))
+    }
+}
+
+// ===== impl Params =====
+
+impl Params {
+    fn from_query(query: &str) -> Result<Self, &'static str> {
+        let mut params = Params {
+            status: http::StatusCode::OK,
+            latency: Duration::from_millis(0),
+            body_bytes: 0,
+        };
+
+        for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+            match &*k {
+                "status" => {
+                    params.status = v.parse::<u16>()
+                        .ok()
+                        .and_then(|s| http::StatusCode::from_u16(s).ok())
+                        .ok_or("invalid status")?;
+                }
+                "latency_ms" => {
+                    let ms = v.parse::<u64>().map_err(|_| "invalid latency_ms")?;
+                    if ms > MAX_LATENCY_MS {
+                        return Err("latency_ms too large");
+                    }
+                    params.latency = Duration::from_millis(ms);
+                }
+                "body_bytes" => {
+                    let n = v.parse::<usize>().map_err(|_| "invalid body_bytes")?;
+                    if n > MAX_BODY_BYTES {
+                        return Err("body_bytes too large");
+                    }
+                    params.body_bytes = n;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_default() {
+        let p = Params::from_query("").unwrap();
+        assert_eq!(p.status, http::StatusCode::OK);
+        assert_eq!(p.latency, Duration::from_millis(0));
+        assert_eq!(p.body_bytes, 0);
+    }
+
+    #[test]
+    fn params_parse() {
+        let p = Params::from_query("status=503&latency_ms=250&body_bytes=10&x=y").unwrap();
+        assert_eq!(p.status, http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(p.latency, Duration::from_millis(250));
+        assert_eq!(p.body_bytes, 10);
+    }
+
+    #[test]
+    fn params_bounded() {
+        assert!(Params::from_query("status=1000").is_err());
+        assert!(Params::from_query("latency_ms=30001").is_err());
+        assert!(Params::from_query("body_bytes=1048577").is_err());
+    }
+
+    #[test]
+    fn requires_token() {
+        let s = Synthetic::new(SecretSetting::new(Some("sekrit".into())));
+
+        let req = http::Request::builder().uri("/").body(()).unwrap();
+        assert!(s.respond(&req).is_none());
+
+        let req = http::Request::builder()
+            .uri("/")
+            .header(TOKEN_HEADER, "nope")
+            .body(())
+            .unwrap();
+        assert!(s.respond(&req).is_none());
+
+        let req = http::Request::builder()
+            .uri("/?status=204")
+            .header(TOKEN_HEADER, "sekrit")
+            .body(())
+            .unwrap();
+        let rsp = s.respond(&req).expect("synthetic response");
+        assert_eq!(rsp.response.status(), http::StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn answers_nothing_without_token() {
+        let s = Synthetic::new(SecretSetting::new(None));
+        let req = http::Request::builder()
+            .uri("/")
+            .header(TOKEN_HEADER, "")
+            .body(())
+            .unwrap();
+        assert!(s.respond(&req).is_none());
+    }
+}
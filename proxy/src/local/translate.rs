@@ -0,0 +1,264 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http;
+use http::header::{HeaderValue, CONTENT_TYPE};
+use tower::Service;
+
+use super::{host_matches, request_host, Body};
+
+/// Identifies the request that produced a translated response.
+const CORRELATION_ID: &str = "x-correlation-id";
+
+/// Used to derive a correlation ID when the request doesn't carry one.
+const REQUEST_ID: &str = "x-request-id";
+
+/// Replaces failed responses with a standard JSON error envelope.
+///
+/// Each rule names a host pattern (as in `ingress::Hosts`) and the response statuses
+/// that are translated for matching requests. The first matching rule applies.
+#[derive(Clone, Debug)]
+pub struct Rules(Arc<Vec<Rule>>);
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    host: String,
+    statuses: Vec<http::StatusCode>,
+}
+
+/// Wraps a service so that its responses are translated according to `Rules`.
+pub struct Translate<S> {
+    inner: S,
+    rules: Rules,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    rule: Option<Rule>,
+    correlation_id: Option<String>,
+}
+
+// ===== impl Rules =====
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Rules(Arc::new(rules))
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Vec::new())
+    }
+
+    fn find(&self, host: &str) -> Option<&Rule> {
+        self.0.iter().find(|r| host_matches(&r.host, host))
+    }
+}
+
+// ===== impl Rule =====
+
+impl Rule {
+    pub fn new(host: &str, statuses: Vec<http::StatusCode>) -> Self {
+        Rule {
+            host: host.to_lowercase(),
+            statuses,
+        }
+    }
+}
+
+// ===== impl Translate =====
+
+impl<S> Translate<S> {
+    pub fn new(inner: S, rules: Rules) -> Self {
+        Translate {
+            inner,
+            rules,
+        }
+    }
+}
+
+impl<S, A, B> Service for Translate<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<Body<B>>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let rule = request_host(&req).and_then(|h| self.rules.find(h)).cloned();
+        let correlation_id = rule.as_ref().and_then(|_| {
+            req.headers()
+                .get(REQUEST_ID)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from)
+        });
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            rule,
+            correlation_id,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<Body<B>>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = try_ready!(self.inner.poll());
+
+        let translate = self.rule
+            .as_ref()
+            .map(|r| r.statuses.contains(&rsp.status()))
+            .unwrap_or(false);
+        if !translate {
+            return Ok(Async::Ready(rsp));
+        }
+
+        let status = rsp.status();
+        let id = self.correlation_id.take().unwrap_or_else(next_correlation_id);
+        debug!("translating {} response; correlation_id={}", status, id);
+
+        let mut translated = http::Response::new(Body::Local(Some(envelope(status, &id))));
+        *translated.status_mut() = status;
+        *translated.version_mut() = rsp.version();
+        translated
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if let Ok(v) = HeaderValue::from_str(&id) {
+            translated.headers_mut().insert(CORRELATION_ID, v);
+        }
+
+        Ok(Async::Ready(translated))
+    }
+}
+
+/// Renders the error envelope returned in place of a translated response.
+fn envelope(status: http::StatusCode, correlation_id: &str) -> Bytes {
+    let mut id = String::with_capacity(correlation_id.len());
+    for c in correlation_id.chars() {
+        match c {
+            '"' | '\\' => {
+                id.push('\\');
+                id.push(c);
+            }
+            c if c.is_control() => {}
+            c => id.push(c),
+        }
+    }
+
+    let json = format!(
+        "{{\"error\":{{\"status\":{},\"reason\":\"{}\",\"correlation_id\":\"{}\"}}}}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or(""),
+        id,
+    );
+    Bytes::from(json)
+}
+
+/// Generates an ID that is unique to this process and unlikely to collide with other
+/// proxies' IDs.
+pub(super) fn next_correlation_id() -> String {
+    static NEXT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{:x}{:08x}-{:x}", now.as_secs(), now.subsec_nanos(), n)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+
+    use super::*;
+
+    /// Responds to every request with a fixed status.
+    struct Status(u16);
+
+    impl Service for Status {
+        type Request = http::Request<()>;
+        type Response = http::Response<Body<()>>;
+        type Error = ();
+        type Future = FutureResult<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            let mut rsp = http::Response::new(Body::Proxied(()));
+            *rsp.status_mut() = http::StatusCode::from_u16(self.0).unwrap();
+            future::ok(rsp)
+        }
+    }
+
+    fn translate(
+        rules: &Rules,
+        req: http::Request<()>,
+        status: u16,
+    ) -> http::Response<Body<()>> {
+        let mut svc = Translate::new(Status(status), rules.clone());
+        svc.call(req).wait().unwrap()
+    }
+
+    fn req(uri: &str) -> http::Request<()> {
+        http::Request::builder()
+            .uri(uri)
+            .header(REQUEST_ID, "abc\"123")
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn translates_matching_statuses() {
+        let rules = Rules::new(vec![
+            Rule::new("*.example.com", vec![http::StatusCode::BAD_GATEWAY]),
+        ]);
+
+        let rsp = translate(&rules, req("http://web.example.com/"), 502);
+        assert_eq!(rsp.status(), http::StatusCode::BAD_GATEWAY);
+        assert_eq!(rsp.headers()[CORRELATION_ID], "abc\"123");
+        match *rsp.body() {
+            Body::Local(Some(ref b)) => assert_eq!(
+                &b[..],
+                &b"{\"error\":{\"status\":502,\"reason\":\"Bad Gateway\",\
+                   \"correlation_id\":\"abc\\\"123\"}}"[..]
+            ),
+            _ => panic!("expected a translated body"),
+        }
+    }
+
+    #[test]
+    fn passes_other_responses_through() {
+        let rules = Rules::new(vec![
+            Rule::new("web.example.com", vec![http::StatusCode::BAD_GATEWAY]),
+        ]);
+
+        let rsp = translate(&rules, req("http://web.example.com/"), 503);
+        assert!(rsp.headers().get(CORRELATION_ID).is_none());
+
+        let rsp = translate(&rules, req("http://api.example.com/"), 502);
+        assert!(rsp.headers().get(CORRELATION_ID).is_none());
+    }
+
+    #[test]
+    fn generates_unique_correlation_ids() {
+        assert_ne!(next_correlation_id(), next_correlation_id());
+    }
+}
@@ -1,25 +1,185 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
 use std::fmt;
+use std::cmp;
+use std::io::{self, Write};
 use std::rc::Rc;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::{Duration, Instant};
 
 use chrono::Utc;
-use env_logger::LogBuilder;
+use env_logger::{LogBuilder, Logger};
 use futures::{Future, Poll};
 use futures::future::{ExecuteError, Executor};
-use log::LogLevel;
+use log::{self, Log, LogLevel, LogLevelFilter, LogMetadata, LogRecord, MaxLogLevelFilter};
 
 const ENV_LOG: &str = "CONDUIT_PROXY_LOG";
+const ENV_LOG_FORMAT: &str = "CONDUIT_PROXY_LOG_FORMAT";
+
+/// The number of identical warnings or errors that may be logged in a burst.
+const BURST: u64 = 10;
+
+/// The rate, per second, at which identical warnings or errors may be logged once a
+/// burst has been exhausted.
+const TOKENS_PER_SEC: u64 = 1;
+
+/// How often to report the number of suppressed log lines.
+const SUMMARY_INTERVAL_SECS: u64 = 10;
 
 thread_local! {
     static CONTEXT: RefCell<Vec<*const fmt::Debug>> = RefCell::new(Vec::new());
 }
 
+/// Points to the process's `Scoping`, once the logger has been initialized.
+static SCOPING: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Enables more verbose logging for a subset of the proxy's work.
+///
+/// A record is logged if it is at least as severe as `level` and any of the contexts it
+/// was logged in (i.e. a listener, connection, or endpoint address) contains `pattern`.
+/// This is in addition to whatever `CONDUIT_PROXY_LOG` enables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scope {
+    pub pattern: String,
+    pub level: LogLevelFilter,
+}
+
+/// Holds the filter and the scope, which may be changed while the proxy runs.
+struct Scoping {
+    /// The filter set by `CONDUIT_PROXY_LOG`.
+    filter: RwLock<Logger>,
+    max: MaxLogLevelFilter,
+    scope: RwLock<Option<Scope>>,
+}
+
+/// How log lines are rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Human-readable lines.
+    Plain,
+
+    /// One JSON object per line, for log aggregation systems.
+    Json,
+}
+
 pub fn init() {
-    LogBuilder::new()
-        .format(|record| {
-            CONTEXT.with(|ctxt| {
-                let level = match record.level() {
+    let format = env::var(::config::env_var_name(ENV_LOG_FORMAT)).ok();
+    let (format, invalid) = match format.as_ref().map(String::as_str) {
+        None | Some("") | Some("plain") => (Format::Plain, None),
+        Some("json") => (Format::Json, None),
+        Some(other) => (Format::Plain, Some(other)),
+    };
+
+    let filter = LogBuilder::new()
+        .parse(&env::var(::config::env_var_name(ENV_LOG)).unwrap_or_default())
+        .build();
+
+    log::set_logger(|max| {
+        max.set(filter.filter());
+
+        // The scoping is never freed, so that it may be referenced for the remainder of
+        // the process.
+        let scoping = Box::into_raw(Box::new(Scoping {
+            filter: RwLock::new(filter),
+            max,
+            scope: RwLock::new(None),
+        }));
+        SCOPING.store(scoping as usize, Ordering::SeqCst);
+
+        Box::new(ProxyLogger::new(format, unsafe { &*scoping }))
+    }).expect("logger");
+
+    if let Some(invalid) = invalid {
+        warn!("unknown {} {:?}; using plain", ENV_LOG_FORMAT, invalid);
+    }
+}
+
+/// Returns the current scope, if one is set.
+pub fn scope() -> Option<Scope> {
+    scoping().and_then(|s| s.scope.read().ok().and_then(|s| s.clone()))
+}
+
+/// Replaces the current scope.
+///
+/// Returns false if logging has not been initialized.
+pub fn set_scope(scope: Option<Scope>) -> bool {
+    let scoping = match scoping() {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let mut current = match scoping.scope.write() {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let base = match scoping.filter.read() {
+        Ok(f) => f.filter(),
+        Err(_) => return false,
+    };
+    let max = scope
+        .as_ref()
+        .map(|s| cmp::max(s.level, base))
+        .unwrap_or(base);
+    scoping.max.set(max);
+    *current = scope;
+    true
+}
+
+/// Replaces the filter set by `CONDUIT_PROXY_LOG` with one parsed from `spec`, which
+/// has the same syntax.
+///
+/// Returns false if logging has not been initialized.
+pub fn set_filter(spec: &str) -> bool {
+    let scoping = match scoping() {
+        Some(s) => s,
+        None => return false,
+    };
+
+    // The scope is locked first, as in `set_scope`, so that the maximum level accounts
+    // for both.
+    let scope = match scoping.scope.read() {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let mut current = match scoping.filter.write() {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+    let filter = LogBuilder::new().parse(spec).build();
+    let max = scope
+        .as_ref()
+        .map(|s| cmp::max(s.level, filter.filter()))
+        .unwrap_or(filter.filter());
+    scoping.max.set(max);
+    *current = filter;
+    true
+}
+
+fn scoping() -> Option<&'static Scoping> {
+    let ptr = SCOPING.load(Ordering::SeqCst) as *const Scoping;
+    if ptr.is_null() {
+        None
+    } else {
+        // The scoping is leaked by `init`, so it lives for the rest of the process.
+        Some(unsafe { &*ptr })
+    }
+}
+
+// ===== impl Format =====
+
+impl Format {
+    fn render(
+        &self,
+        level: LogLevel,
+        target: &str,
+        context: &[*const fmt::Debug],
+        message: &fmt::Display,
+    ) -> String {
+        match *self {
+            Format::Plain => {
+                let level = match level {
                     LogLevel::Trace => "TRCE",
                     LogLevel::Debug => "DBUG",
                     LogLevel::Info => "INFO",
@@ -30,15 +190,200 @@ pub fn init() {
                     "{} {} {} {:?}{}",
                     Utc::now().format("%s%.6f"),
                     level,
-                    record.target(),
-                    Context(&ctxt.borrow()),
-                    record.args()
+                    target,
+                    Context(context),
+                    message
+                )
+            }
+            Format::Json => {
+                let context = context
+                    .iter()
+                    .map(|item| {
+                        // See `fn context()` for comments about this unsafe.
+                        let item = unsafe { &**item };
+                        json_str(&format!("{:?}", item))
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "{{\"timestamp\":{},\"level\":{},\"target\":{},\"context\":[{}],\
+                     \"message\":{}}}",
+                    json_str(&Utc::now().to_rfc3339()),
+                    json_str(&level.to_string()),
+                    json_str(target),
+                    context.join(","),
+                    json_str(&message.to_string()),
                 )
+            }
+        }
+    }
+}
+
+/// Filters, rate-limits, and renders log records.
+///
+/// Records are logged if they are enabled by `CONDUIT_PROXY_LOG` or by the current
+/// `Scope`.
+///
+/// Identical warnings and errors are rate-limited. Log lines are considered identical
+/// when they are logged from the same place, i.e. they share a message template. Each
+/// template may log `BURST` lines at once, and then `TOKENS_PER_SEC` lines per second.
+/// Suppressed lines are counted and periodically summarized, so that, for instance, a
+/// flapping upstream cannot flood the log with connection errors.
+struct ProxyLogger {
+    format: Format,
+    scoping: &'static Scoping,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    by_site: HashMap<(String, u32), Bucket>,
+    last_summary: Instant,
+}
+
+struct Bucket {
+    target: String,
+    tokens: u64,
+    refilled_at: Instant,
+    suppressed: u64,
+}
+
+// ===== impl ProxyLogger =====
+
+impl ProxyLogger {
+    fn new(format: Format, scoping: &'static Scoping) -> Self {
+        ProxyLogger {
+            format,
+            scoping,
+            buckets: Mutex::new(Buckets {
+                by_site: HashMap::new(),
+                last_summary: Instant::now(),
+            }),
+        }
+    }
+
+    fn allow(&self, record: &LogRecord) -> bool {
+        let mut buckets = match self.buckets.lock() {
+            Ok(b) => b,
+            // Never suppress logs because another thread panicked while logging.
+            Err(_) => return true,
+        };
+
+        let now = Instant::now();
+        let interval = Duration::from_secs(SUMMARY_INTERVAL_SECS);
+        if now.duration_since(buckets.last_summary) >= interval {
+            buckets.last_summary = now;
+            let stderr = io::stderr();
+            let mut stderr = stderr.lock();
+            for (&(ref file, line), bucket) in &mut buckets.by_site {
+                if bucket.suppressed > 0 {
+                    let message = format!(
+                        "suppressed {} similar messages from {}:{}",
+                        bucket.suppressed,
+                        file,
+                        line,
+                    );
+                    let line = self.format
+                        .render(LogLevel::Warn, &bucket.target, &[], &message);
+                    let _ = writeln!(stderr, "{}", line);
+                    bucket.suppressed = 0;
+                }
+            }
+        }
+
+        let loc = record.location();
+        let key = (loc.file().to_owned(), loc.line());
+        buckets
+            .by_site
+            .entry(key)
+            .or_insert_with(|| Bucket::new(record.target(), now))
+            .take(now)
+    }
+
+    /// Checks `record` against the filter, without holding the filter's lock while the
+    /// scope is checked.
+    fn matches(&self, record: &LogRecord) -> bool {
+        self.scoping.filter.read().map(|f| f.matches(record)).unwrap_or(false)
+    }
+
+    fn in_scope(&self, record: &LogRecord) -> bool {
+        let scope = match self.scoping.scope.read() {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        let scope = match *scope {
+            Some(ref s) if record.level() <= s.level => s,
+            _ => return false,
+        };
+
+        CONTEXT.with(|ctxt| {
+            ctxt.borrow().iter().any(|item| {
+                // See `fn context()` for comments about this unsafe.
+                let item = unsafe { &**item };
+                format!("{:?}", item).contains(&scope.pattern)
             })
         })
-        .parse(&env::var(ENV_LOG).unwrap_or_default())
-        .init()
-        .expect("logger");
+    }
+}
+
+impl Log for ProxyLogger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        let enabled = self.scoping.filter.read().map(|f| f.enabled(metadata)).unwrap_or(false);
+        enabled || self.scoping
+            .scope
+            .read()
+            .ok()
+            .and_then(|s| s.as_ref().map(|s| metadata.level() <= s.level))
+            .unwrap_or(false)
+    }
+
+    fn log(&self, record: &LogRecord) {
+        if !self.matches(record) && !self.in_scope(record) {
+            return;
+        }
+
+        if record.level() <= LogLevel::Warn && !self.allow(record) {
+            return;
+        }
+
+        let line = CONTEXT.with(|ctxt| {
+            self.format.render(
+                record.level(),
+                record.target(),
+                &ctxt.borrow(),
+                record.args(),
+            )
+        });
+        let _ = writeln!(io::stderr(), "{}", line);
+    }
+}
+
+// ===== impl Bucket =====
+
+impl Bucket {
+    fn new(target: &str, now: Instant) -> Self {
+        Bucket {
+            target: target.to_owned(),
+            tokens: BURST,
+            refilled_at: now,
+            suppressed: 0,
+        }
+    }
+
+    /// Takes a token from the bucket, returning false if none remain.
+    fn take(&mut self, now: Instant) -> bool {
+        let refill = now.duration_since(self.refilled_at).as_secs() * TOKENS_PER_SEC;
+        if refill > 0 {
+            self.tokens = ::std::cmp::min(BURST, self.tokens + refill);
+            self.refilled_at = now;
+        }
+
+        if self.tokens == 0 {
+            self.suppressed += 1;
+            return false;
+        }
+
+        self.tokens -= 1;
+        true
+    }
 }
 
 /// Execute a closure with a `Debug` item attached to allow log messages.
@@ -126,6 +471,25 @@ where
     }
 }
 
+/// Formats `s` as a JSON string literal.
+pub fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
 struct Context<'a>(&'a [*const fmt::Debug]);
 
 impl<'a> fmt::Debug for Context<'a> {
@@ -143,3 +507,46 @@ impl<'a> fmt::Debug for Context<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_json() {
+        let ctx = ("serve", 4143);
+        let context = [&ctx as *const fmt::Debug];
+        let message = "a \"b\"\n";
+        let line = Format::Json.render(LogLevel::Warn, "conduit_proxy", &context, &message);
+        assert!(line.starts_with("{\"timestamp\":\""), "{}", line);
+        assert!(
+            line.ends_with(
+                "\"level\":\"WARN\",\"target\":\"conduit_proxy\",\
+                 \"context\":[\"(\\\"serve\\\", 4143)\"],\"message\":\"a \\\"b\\\"\\n\"}"
+            ),
+            "{}",
+            line
+        );
+    }
+
+    #[test]
+    fn bucket_limits_bursts_and_refills() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new("test", start);
+        for _ in 0..BURST {
+            assert!(bucket.take(start));
+        }
+        assert!(!bucket.take(start));
+        assert!(!bucket.take(start + Duration::from_millis(500)));
+        assert_eq!(bucket.suppressed, 2);
+
+        assert!(bucket.take(start + Duration::from_secs(1)));
+        assert!(!bucket.take(start + Duration::from_secs(1)));
+
+        let later = start + Duration::from_secs(BURST * 100);
+        for _ in 0..BURST {
+            assert!(bucket.take(later));
+        }
+        assert!(!bucket.take(later));
+    }
+}
@@ -1,15 +1,16 @@
 #![deny(warnings)]
 extern crate conduit_proxy;
 
+use std::env;
 use std::process;
 
 // Look in lib.rs.
 fn main() {
     // Load configuration.
-    let config = match conduit_proxy::app::init() {
+    let config = match conduit_proxy::app::init(env::args().skip(1)) {
         Ok(c) => c,
         Err(e) => {
-            eprintln!("configuration error: {:#?}", e);
+            eprint!("configuration error: {}", e);
             process::exit(64)
         }
     };
@@ -1,10 +1,11 @@
 use std::fmt::Debug;
 use std::marker::PhantomData;
 
-use futures::{Future, Poll};
+use futures::{future, Async, Future, Poll};
 use h2;
 use http;
-use tower::Service;
+use tower::{NewService, Service};
+use tower_h2::client;
 
 /// Map an HTTP service's error to an appropriate 500 response.
 pub struct MapErr<T, E> {
@@ -18,6 +19,24 @@ pub struct ResponseFuture<T, E> {
     _p: PhantomData<E>,
 }
 
+/// Builds `Refused` services.
+pub struct NewRefused<N> {
+    inner: N,
+}
+
+/// Answers requests that an HTTP/2 peer refused with `503 Service Unavailable`.
+///
+/// A refused stream, including one beyond the last stream that a server going away
+/// will process, wasn't processed at all, so the request is safe to retry.
+pub struct Refused<S> {
+    inner: S,
+}
+
+/// Maps a refused stream's error to a 503 response.
+pub struct RefusedFuture<F> {
+    inner: F,
+}
+
 // ===== impl MapErr =====
 
 impl<T, E> MapErr<T, E>
@@ -83,3 +102,125 @@ where
         })
     }
 }
+
+// ===== impl NewRefused =====
+
+impl<N> NewRefused<N> {
+    pub fn new(inner: N) -> Self {
+        NewRefused {
+            inner,
+        }
+    }
+}
+
+impl<N, B> NewService for NewRefused<N>
+where
+    N: NewService<Response = http::Response<B>, Error = client::Error>,
+    B: Default,
+{
+    type Request = N::Request;
+    type Response = N::Response;
+    type Error = N::Error;
+    type Service = Refused<N::Service>;
+    type InitError = N::InitError;
+    type Future = future::Map<N::Future, fn(N::Service) -> Refused<N::Service>>;
+
+    fn new_service(&self) -> Self::Future {
+        self.inner
+            .new_service()
+            .map(Refused::new as fn(N::Service) -> Refused<N::Service>)
+    }
+}
+
+// ===== impl Refused =====
+
+impl<S> Refused<S> {
+    pub fn new(inner: S) -> Self {
+        Refused {
+            inner,
+        }
+    }
+}
+
+impl<S, B> Service for Refused<S>
+where
+    S: Service<Response = http::Response<B>, Error = client::Error>,
+    B: Default,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = RefusedFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        RefusedFuture {
+            inner: self.inner.call(request),
+        }
+    }
+}
+
+// ===== impl RefusedFuture =====
+
+impl<F, B> Future for RefusedFuture<F>
+where
+    F: Future<Item = http::Response<B>, Error = client::Error>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner.poll() {
+            Err(ref e) if e.reason() == Some(h2::Reason::REFUSED_STREAM) => {
+                debug!("request refused by peer; responding with 503");
+                let response = http::Response::builder()
+                    .status(http::StatusCode::SERVICE_UNAVAILABLE)
+                    .body(B::default())
+                    .expect("refused response must be valid");
+                Ok(Async::Ready(response))
+            }
+            poll => poll,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::FutureResult;
+
+    use super::*;
+
+    /// Fails every request with a stream error.
+    struct Fail(h2::Reason);
+
+    impl Service for Fail {
+        type Request = http::Request<()>;
+        type Response = http::Response<()>;
+        type Error = client::Error;
+        type Future = FutureResult<Self::Response, client::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), client::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            future::err(self.0.into())
+        }
+    }
+
+    #[test]
+    fn answers_refused_requests_with_503() {
+        let mut svc = Refused::new(Fail(h2::Reason::REFUSED_STREAM));
+        let rsp = svc.call(http::Request::new(())).wait().expect("refused request answered");
+        assert_eq!(rsp.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+
+        // Other failures may have been processed, so they aren't answered.
+        let mut svc = Refused::new(Fail(h2::Reason::INTERNAL_ERROR));
+        let err = svc.call(http::Request::new(())).wait().expect_err("request failed");
+        assert_eq!(err.reason(), Some(h2::Reason::INTERNAL_ERROR));
+    }
+}
@@ -0,0 +1,437 @@
+//! Handles outbound requests for destinations that have no endpoints.
+//!
+//! Requests for a destination without endpoints may be queued until an endpoint is
+//! discovered (failing with a `503` if none is discovered within a timeout), failed
+//! immediately with a `503`, or forwarded to the original destination of the client's
+//! connection, as it would have been without the proxy.
+//!
+//! The controller only reports that a destination has no endpoints once it has removed
+//! the last of them. A destination that hasn't been reported on within the timeout of
+//! first being watched is also considered to have no endpoints.
+//!
+//! While the proxy is starting, i.e. until it first connects to the controller, requests
+//! for destinations without endpoints may instead be forwarded to their original
+//! destinations immediately, so that the application's traffic isn't held up by a slow
+//! or unavailable controller.
+//!
+//! Requests forwarded to an original destination (e.g. an external service) are sent
+//! exactly as the application sent them. The proxy never originates TLS, so verifying
+//! external services' certificates, including their revocation status, is left to the
+//! application.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{Async, Future, Poll};
+use http;
+use tokio_core::reactor::{Handle, Timeout};
+use tower::Service;
+use tower_buffer::Buffer;
+
+use config::NoEndpoints as Behavior;
+use control::Connectivity;
+use control::discovery::{Bind, Endpoints};
+use ctx;
+
+/// Determines how requests for destinations without endpoints are handled.
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    behavior: Behavior,
+    timeout: Duration,
+}
+
+/// Applies a `Policy` to requests for a single destination.
+pub struct NoEndpoints<S, B: Bind> {
+    inner: S,
+    endpoints: Endpoints,
+    policy: Policy,
+    bind: B,
+    /// A client for the original destination, bound when first needed.
+    fallback: Option<(SocketAddr, Buffer<B::Service>)>,
+    /// If set, requests fall back to their original destinations until the controller
+    /// has been connected to.
+    startup_fallback: Option<Connectivity>,
+    executor: Handle,
+}
+
+#[derive(Debug)]
+pub enum Error<A, B> {
+    Inner(A),
+    Fallback(B),
+}
+
+pub enum ResponseFuture<F, G> {
+    Queued {
+        future: F,
+        timeout: Option<Timeout>,
+        endpoints: Endpoints,
+    },
+    Fallback(G),
+    Unavailable,
+}
+
+// ===== impl Policy =====
+
+impl Policy {
+    pub fn new(behavior: Behavior, timeout: Duration) -> Self {
+        Policy {
+            behavior,
+            timeout,
+        }
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self::new(Behavior::Queue, Duration::from_secs(10))
+    }
+}
+
+// ===== impl NoEndpoints =====
+
+impl<S, B: Bind> NoEndpoints<S, B> {
+    pub fn new(
+        inner: S,
+        endpoints: Endpoints,
+        policy: Policy,
+        bind: B,
+        executor: &Handle,
+    ) -> Self {
+        NoEndpoints {
+            inner,
+            endpoints,
+            policy,
+            bind,
+            fallback: None,
+            startup_fallback: None,
+            executor: executor.clone(),
+        }
+    }
+
+    /// Forwards requests to their original destinations until `connectivity` has been
+    /// connected, regardless of the policy.
+    pub fn with_startup_fallback(self, connectivity: Connectivity) -> Self {
+        NoEndpoints {
+            startup_fallback: Some(connectivity),
+            ..self
+        }
+    }
+
+    fn is_starting(&self) -> bool {
+        self.startup_fallback
+            .as_ref()
+            .map_or(false, |c| !c.has_connected())
+    }
+
+    fn fallback(&mut self, addr: SocketAddr) -> Option<&mut Buffer<B::Service>> {
+        let rebind = match self.fallback {
+            Some((a, _)) => a != addr,
+            None => true,
+        };
+        if rebind {
+            debug!("falling back to original destination {}", addr);
+            let svc = self.bind.bind(&addr).map_err(|_| {}).and_then(|svc| {
+                Buffer::new(svc, &self.executor).map_err(|_| {})
+            });
+            match svc {
+                Ok(svc) => self.fallback = Some((addr, svc)),
+                Err(()) => {
+                    warn!("could not bind original destination {}", addr);
+                    self.fallback = None;
+                }
+            }
+        }
+
+        self.fallback.as_mut().map(|&mut (_, ref mut svc)| svc)
+    }
+}
+
+impl<S, B, A, R> Service for NoEndpoints<S, B>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<R>>,
+    B: Bind<Request = http::Request<A>, Response = http::Response<R>>,
+    Buffer<B::Service>: Service<Request = http::Request<A>, Response = http::Response<R>>,
+    R: Default,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = Error<S::Error, <Buffer<B::Service> as Service>::Error>;
+    type Future = ResponseFuture<S::Future, <Buffer<B::Service> as Service>::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Error::Inner)
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        if !self.endpoints.is_currently_empty() {
+            return ResponseFuture::Queued {
+                future: self.inner.call(req),
+                timeout: None,
+                endpoints: self.endpoints.clone(),
+            };
+        }
+
+        if self.is_starting() {
+            if let Some(svc) = orig_dst(&req).and_then(|addr| self.fallback(addr)) {
+                debug!("controller not yet connected; falling back");
+                return ResponseFuture::Fallback(svc.call(req));
+            }
+        }
+
+        let empty = self.endpoints.is_empty(self.policy.timeout);
+        let behavior = self.policy.behavior;
+        match behavior {
+            Behavior::Fail if empty => {
+                debug!("no endpoints; failing request");
+                ResponseFuture::Unavailable
+            }
+            Behavior::Fallback if empty => {
+                let fallback = orig_dst(&req).and_then(|addr| self.fallback(addr));
+                match fallback {
+                    Some(svc) => ResponseFuture::Fallback(svc.call(req)),
+                    None => {
+                        debug!("no endpoints or original destination; failing request");
+                        ResponseFuture::Unavailable
+                    }
+                }
+            }
+            _ => {
+                let timeout = Timeout::new(self.policy.timeout, &self.executor)
+                    .map_err(|e| warn!("could not create no-endpoints timeout: {}", e))
+                    .ok();
+                ResponseFuture::Queued {
+                    future: self.inner.call(req),
+                    timeout,
+                    endpoints: self.endpoints.clone(),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the original destination of the request's connection, unless the connection
+/// was made directly to the proxy.
+fn orig_dst<A>(req: &http::Request<A>) -> Option<SocketAddr> {
+    req.extensions()
+        .get::<Arc<ctx::transport::Server>>()
+        .and_then(|ctx| ctx.orig_dst.and_then(|a| if a == ctx.local { None } else { Some(a) }))
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, G, R> Future for ResponseFuture<F, G>
+where
+    F: Future<Item = http::Response<R>>,
+    G: Future<Item = http::Response<R>>,
+    R: Default,
+{
+    type Item = http::Response<R>;
+    type Error = Error<F::Error, G::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::Queued {
+                ref mut future,
+                ref mut timeout,
+                ref endpoints,
+            } => {
+                if let Async::Ready(rsp) = future.poll().map_err(Error::Inner)? {
+                    return Ok(Async::Ready(rsp));
+                }
+
+                let expired = match *timeout {
+                    Some(ref mut t) => t.poll().map(|a| a.is_ready()).unwrap_or(true),
+                    None => false,
+                };
+                if !expired {
+                    return Ok(Async::NotReady);
+                }
+
+                // Once an endpoint has been discovered, the request may take as long as
+                // it needs to be dispatched.
+                *timeout = None;
+                if endpoints.is_currently_empty() {
+                    debug!("no endpoints discovered before timeout; failing request");
+                    return Ok(Async::Ready(unavailable()));
+                }
+                Ok(Async::NotReady)
+            }
+            ResponseFuture::Fallback(ref mut f) => f.poll().map_err(Error::Fallback),
+            ResponseFuture::Unavailable => Ok(Async::Ready(unavailable())),
+        }
+    }
+}
+
+fn unavailable<R: Default>() -> http::Response<R> {
+    http::Response::builder()
+        .status(http::StatusCode::SERVICE_UNAVAILABLE)
+        .body(R::default())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+    use futures::sync::oneshot;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    type Request = http::Request<()>;
+    type Response = http::Response<()>;
+
+    /// Answers every request with a status.
+    struct Respond(http::StatusCode);
+
+    impl Service for Respond {
+        type Request = Request;
+        type Response = Response;
+        type Error = ();
+        type Future = FutureResult<Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Request) -> Self::Future {
+            let mut rsp = http::Response::new(());
+            *rsp.status_mut() = self.0;
+            future::ok(rsp)
+        }
+    }
+
+    /// Answers a single request once it's sent a response, as a balancer would once an
+    /// endpoint had been discovered.
+    struct Pending(Option<oneshot::Receiver<Response>>);
+
+    impl Service for Pending {
+        type Request = Request;
+        type Response = Response;
+        type Error = oneshot::Canceled;
+        type Future = oneshot::Receiver<Response>;
+
+        fn poll_ready(&mut self) -> Poll<(), oneshot::Canceled> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Request) -> Self::Future {
+            self.0.take().expect("only one request")
+        }
+    }
+
+    /// Binds original destinations to a service that answers `202 Accepted`.
+    struct BindOrigDst;
+
+    impl Bind for BindOrigDst {
+        type Request = Request;
+        type Response = Response;
+        type Error = ();
+        type BindError = ();
+        type Service = Respond;
+
+        fn bind(&self, _: &SocketAddr) -> Result<Respond, ()> {
+            Ok(Respond(http::StatusCode::ACCEPTED))
+        }
+    }
+
+    fn empty() -> Endpoints {
+        let endpoints = Endpoints::new();
+        endpoints.update(0, true);
+        endpoints
+    }
+
+    fn request(orig_dst: Option<&str>) -> Request {
+        let proxy = ctx::Proxy::outbound(&ctx::Process::new("test", "test", "test"));
+        let local = "127.0.0.1:4140".parse().unwrap();
+        let remote = "10.0.0.2:51234".parse().unwrap();
+        let orig_dst = orig_dst.map(|a| a.parse().unwrap());
+        let mut req = http::Request::new(());
+        req.extensions_mut()
+            .insert(ctx::transport::Server::new(&proxy, &local, &remote, &orig_dst));
+        req
+    }
+
+    fn status<F: Future<Item = Response>>(core: &mut Core, rsp: F) -> http::StatusCode {
+        match core.run(rsp) {
+            Ok(rsp) => rsp.status(),
+            Err(_) => panic!("request failed"),
+        }
+    }
+
+    #[test]
+    fn fails_requests_without_endpoints() {
+        let mut core = Core::new().unwrap();
+        let policy = Policy::new(Behavior::Fail, Duration::from_secs(10));
+        let inner = Respond(http::StatusCode::OK);
+        let mut svc = NoEndpoints::new(inner, empty(), policy, BindOrigDst, &core.handle());
+
+        let rsp = svc.call(request(Some("10.0.0.1:8080")));
+        assert_eq!(status(&mut core, rsp), http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn falls_back_to_original_destinations() {
+        let mut core = Core::new().unwrap();
+        let policy = Policy::new(Behavior::Fallback, Duration::from_secs(10));
+        let inner = Respond(http::StatusCode::OK);
+        let mut svc = NoEndpoints::new(inner, empty(), policy, BindOrigDst, &core.handle());
+
+        let rsp = svc.call(request(Some("10.0.0.1:8080")));
+        assert_eq!(status(&mut core, rsp), http::StatusCode::ACCEPTED);
+
+        // Connections made directly to the proxy have nowhere to fall back to.
+        let rsp = svc.call(request(Some("127.0.0.1:4140")));
+        assert_eq!(status(&mut core, rsp), http::StatusCode::SERVICE_UNAVAILABLE);
+        let rsp = svc.call(request(None));
+        assert_eq!(status(&mut core, rsp), http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn falls_back_while_starting() {
+        let mut core = Core::new().unwrap();
+        let policy = Policy::new(Behavior::Fail, Duration::from_secs(10));
+        let inner = Respond(http::StatusCode::OK);
+        let mut svc = NoEndpoints::new(inner, empty(), policy, BindOrigDst, &core.handle())
+            .with_startup_fallback(Connectivity::default());
+
+        let rsp = svc.call(request(Some("10.0.0.1:8080")));
+        assert_eq!(status(&mut core, rsp), http::StatusCode::ACCEPTED);
+    }
+
+    #[test]
+    fn queues_requests_until_the_timeout() {
+        let mut core = Core::new().unwrap();
+        let policy = Policy::new(Behavior::Queue, Duration::from_millis(10));
+        let (_tx, rx) = oneshot::channel();
+        let inner = Pending(Some(rx));
+        let mut svc = NoEndpoints::new(inner, empty(), policy, BindOrigDst, &core.handle());
+
+        let rsp = svc.call(request(Some("10.0.0.1:8080")));
+        assert_eq!(status(&mut core, rsp), http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn drains_queue_once_endpoints_are_discovered() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let policy = Policy::new(Behavior::Queue, Duration::from_millis(10));
+        let endpoints = empty();
+        let (tx, rx) = oneshot::channel();
+        let inner = Pending(Some(rx));
+        let mut svc = NoEndpoints::new(inner, endpoints.clone(), policy, BindOrigDst, &handle);
+
+        let rsp = svc.call(request(Some("10.0.0.1:8080")));
+        endpoints.update(1, true);
+
+        // The request is only answered well after the queue's timeout has expired.
+        let answer = Timeout::new(Duration::from_millis(50), &handle)
+            .unwrap()
+            .then(move |_| {
+                let _ = tx.send(http::Response::new(()));
+                Ok::<(), ()>(())
+            });
+        handle.spawn(answer);
+        assert_eq!(status(&mut core, rsp), http::StatusCode::OK);
+    }
+}
@@ -0,0 +1,314 @@
+//! Normalizes the targets of requests received by a listener.
+//!
+//! A request whose target may be interpreted differently by the proxy's policies and by
+//! the application can be used to reach paths or hosts that the policies would refuse.
+//! Such requests are failed with `PROTOCOL_ERROR` without being forwarded:
+//!
+//! - requests with more than one `Host` header;
+//! - requests whose `Host` header names a different authority than the request target;
+//! - requests whose path climbs above the root with `..` segments.
+//!
+//! In lenient mode, `.` and `..` path segments, including percent-encoded ones, are
+//! otherwise resolved before the request is forwarded. In strict mode, requests with
+//! such segments, or with percent-encoded `/` or `\` characters, are refused instead.
+
+use bytes::Bytes;
+use futures::{Future, Poll};
+use h2;
+use http;
+use http::header::HOST;
+use http::uri::PathAndQuery;
+use tower::Service;
+
+use config::Normalization;
+
+/// How a listener normalizes its requests.
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    mode: Normalization,
+}
+
+/// Applies a `Policy` to requests.
+pub struct Normalize<S> {
+    inner: S,
+    policy: Policy,
+}
+
+pub enum ResponseFuture<F> {
+    Forwarded(F),
+    Refused,
+}
+
+// ===== impl Policy =====
+
+impl Policy {
+    pub fn new(mode: Normalization) -> Self {
+        Policy {
+            mode,
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(Normalization::Off)
+    }
+
+    /// Returns the normalized request, or the reason it's refused.
+    fn apply<B>(&self, mut req: http::Request<B>) -> Result<http::Request<B>, &'static str> {
+        if self.mode == Normalization::Off {
+            return Ok(req);
+        }
+
+        check_host(&req)?;
+
+        let normalized = match req.uri().path_and_query() {
+            Some(pq) => match normalize_path(pq.path(), self.mode)? {
+                Some(path) => match pq.query() {
+                    Some(q) => Some(format!("{}?{}", path, q)),
+                    None => Some(path),
+                },
+                None => None,
+            },
+            None => None,
+        };
+
+        if let Some(pq) = normalized {
+            let mut parts: http::uri::Parts = req.uri().clone().into();
+            parts.path_and_query = Some(
+                PathAndQuery::from_shared(Bytes::from(pq)).map_err(|_| "invalid path")?,
+            );
+            *req.uri_mut() = http::Uri::from_parts(parts).map_err(|_| "invalid path")?;
+        }
+
+        Ok(req)
+    }
+}
+
+/// Ensures that a request names at most one authority.
+fn check_host<B>(req: &http::Request<B>) -> Result<(), &'static str> {
+    let mut hosts = req.headers().get_all(HOST).iter();
+    let host = match hosts.next() {
+        Some(host) => host,
+        None => return Ok(()),
+    };
+    if hosts.next().is_some() {
+        return Err("duplicate host headers");
+    }
+
+    if let Some(authority) = req.uri().authority_part() {
+        let host = host.to_str().map_err(|_| "invalid host header")?;
+        if host.to_lowercase() != authority.as_str().to_lowercase() {
+            return Err("host header conflicts with authority");
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the normalized form of `path`, if it differs from `path`.
+fn normalize_path(path: &str, mode: Normalization) -> Result<Option<String>, &'static str> {
+    // Only origin-form paths have segments; e.g. `*` is left alone.
+    if !path.starts_with('/') {
+        return Ok(None);
+    }
+
+    if mode == Normalization::Strict {
+        let lower = path.to_lowercase();
+        if lower.contains("%2f") || lower.contains("%5c") {
+            return Err("encoded separator in path");
+        }
+    }
+
+    let mut segments: Vec<&str> = Vec::new();
+    let mut changed = false;
+    for segment in path[1..].split('/') {
+        match dots(segment) {
+            0 => {
+                segments.push(segment);
+                continue;
+            }
+            _ if mode == Normalization::Strict => return Err("dot segment in path"),
+            1 => {}
+            _ => {
+                if segments.pop().is_none() {
+                    return Err("path above root");
+                }
+            }
+        }
+        changed = true;
+    }
+
+    if !changed {
+        return Ok(None);
+    }
+
+    // A trailing dot segment refers to a directory, so its slash is kept.
+    let trailing = path.rsplit('/').next().map(|s| dots(s) > 0).unwrap_or(false);
+    let mut normalized = String::with_capacity(path.len());
+    for segment in &segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if trailing || normalized.is_empty() {
+        normalized.push('/');
+    }
+    Ok(Some(normalized))
+}
+
+/// Returns 1 for a `.` segment, 2 for a `..` segment, and 0 for any other segment.
+fn dots(segment: &str) -> usize {
+    match &*segment.to_lowercase() {
+        "." | "%2e" => 1,
+        ".." | ".%2e" | "%2e." | "%2e%2e" => 2,
+        _ => 0,
+    }
+}
+
+// ===== impl Normalize =====
+
+impl<S> Normalize<S> {
+    pub fn new(inner: S, policy: Policy) -> Self {
+        Normalize {
+            inner,
+            policy,
+        }
+    }
+}
+
+impl<S, B> Service for Normalize<S>
+where
+    S: Service<Request = http::Request<B>>,
+    S::Error: From<h2::Reason>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let uri = req.uri().clone();
+        match self.policy.apply(req) {
+            Ok(req) => ResponseFuture::Forwarded(self.inner.call(req)),
+            Err(reason) => {
+                debug!("refusing request for {}: {}", uri, reason);
+                ResponseFuture::Refused
+            }
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F> Future for ResponseFuture<F>
+where
+    F: Future,
+    F::Error: From<h2::Reason>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::Forwarded(ref mut f) => f.poll(),
+            ResponseFuture::Refused => Err(h2::Reason::PROTOCOL_ERROR.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Async;
+    use futures::future::{self, FutureResult};
+
+    use super::*;
+
+    /// Responds with the URI of each request it receives.
+    struct Echo;
+
+    impl Service for Echo {
+        type Request = http::Request<()>;
+        type Response = http::Uri;
+        type Error = h2::Error;
+        type Future = FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, req: Self::Request) -> Self::Future {
+            future::ok(req.uri().clone())
+        }
+    }
+
+    fn call(mode: Normalization, req: http::Request<()>) -> Result<String, h2::Error> {
+        let mut svc = Normalize::new(Echo, Policy::new(mode));
+        svc.call(req).wait().map(|uri| uri.to_string())
+    }
+
+    fn get(mode: Normalization, uri: &str) -> Result<String, h2::Error> {
+        call(mode, http::Request::builder().uri(uri).body(()).unwrap())
+    }
+
+    #[test]
+    fn resolves_dot_segments_leniently() {
+        let lenient = Normalization::Lenient;
+        assert_eq!(get(lenient, "/a/./b/../c?x=../y").unwrap(), "/a/c?x=../y");
+        assert_eq!(get(lenient, "/a/%2E%2e/admin").unwrap(), "/admin");
+        assert_eq!(get(lenient, "/a/b/..").unwrap(), "/a/");
+        assert_eq!(get(lenient, "/a/..").unwrap(), "/");
+
+        let err = get(lenient, "/a/../../etc/passwd").expect_err("above root");
+        assert_eq!(err.reason(), Some(h2::Reason::PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn refuses_dot_segments_strictly() {
+        let strict = Normalization::Strict;
+        assert_eq!(get(strict, "/a/b.c/..d?x=..").unwrap(), "/a/b.c/..d?x=..");
+        assert!(get(strict, "/a/../b").is_err());
+        assert!(get(strict, "/a/%2e/b").is_err());
+        assert!(get(strict, "/a%2Fb").is_err());
+        assert!(get(strict, "/a%5cb").is_err());
+    }
+
+    #[test]
+    fn refuses_ambiguous_hosts() {
+        for &mode in &[Normalization::Lenient, Normalization::Strict] {
+            let req = http::Request::builder()
+                .uri("http://web.example.com/")
+                .header("host", "WEB.example.com")
+                .body(())
+                .unwrap();
+            assert!(call(mode, req).is_ok());
+
+            let req = http::Request::builder()
+                .uri("http://web.example.com/")
+                .header("host", "admin.example.com")
+                .body(())
+                .unwrap();
+            assert!(call(mode, req).is_err());
+
+            let req = http::Request::builder()
+                .uri("/")
+                .header("host", "web.example.com")
+                .header("host", "admin.example.com")
+                .body(())
+                .unwrap();
+            assert!(call(mode, req).is_err());
+        }
+    }
+
+    #[test]
+    fn forwards_unchanged_when_off() {
+        let req = http::Request::builder()
+            .uri("/a/../../b")
+            .header("host", "web.example.com")
+            .header("host", "admin.example.com")
+            .body(())
+            .unwrap();
+        assert_eq!(call(Normalization::Off, req).unwrap(), "/a/../../b");
+    }
+}
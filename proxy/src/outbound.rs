@@ -1,4 +1,5 @@
 use std::io;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use http;
@@ -11,24 +12,39 @@ use tower_router::Recognize;
 use bind::Bind;
 use control;
 use ctx;
+use no_endpoints::{self, NoEndpoints};
 use telemetry;
 use transport;
 
 type Discovery<B> = control::discovery::Watch<Bind<Arc<ctx::Proxy>, B>>;
 
-type Error = tower_buffer::Error<
-    tower_balance::Error<
-        tower_reconnect::Error<
-            tower_h2::client::Error,
-            tower_h2::client::ConnectError<transport::TimeoutError<io::Error>>,
-        >,
-        (),
-    >,
+type ClientError = tower_reconnect::Error<
+    tower_h2::client::Error,
+    tower_h2::client::ConnectError<transport::TimeoutError<io::Error>>,
+>;
+
+type Error = no_endpoints::Error<
+    tower_buffer::Error<tower_balance::Error<ClientError, ()>>,
+    tower_buffer::Error<ClientError>,
 >;
 
 pub struct Outbound<B> {
     bind: Bind<Arc<ctx::Proxy>, B>,
     discovery: control::Control,
+    gateways: Arc<Vec<ClusterGateway>>,
+    no_endpoints: no_endpoints::Policy,
+    startup_fallback: bool,
+    max_concurrent_connects: Option<usize>,
+}
+
+/// Routes requests for services in another cluster through that cluster's gateway.
+///
+/// An authority like `web.default.svc.cluster-b:8080` names a service in `cluster-b` and
+/// is sent to that cluster's gateway, unchanged, rather than being resolved locally.
+#[derive(Clone, Debug)]
+pub struct ClusterGateway {
+    suffix: String,
+    addr: SocketAddr,
 }
 
 // ===== impl Outbound =====
@@ -38,6 +54,60 @@ impl<B> Outbound<B> {
         Self {
             bind,
             discovery,
+            gateways: Arc::new(Vec::new()),
+            no_endpoints: no_endpoints::Policy::default(),
+            startup_fallback: false,
+            max_concurrent_connects: None,
+        }
+    }
+
+    pub fn with_no_endpoints(self, no_endpoints: no_endpoints::Policy) -> Self {
+        Self {
+            no_endpoints,
+            ..self
+        }
+    }
+
+    /// Forwards requests for destinations without endpoints to their original
+    /// destinations until the proxy has first connected to the controller.
+    pub fn with_startup_fallback(self, startup_fallback: bool) -> Self {
+        Self {
+            startup_fallback,
+            ..self
+        }
+    }
+
+    /// Limits the simultaneous connection attempts to each authority's endpoints.
+    pub fn with_max_concurrent_connects(self, max: Option<usize>) -> Self {
+        Self {
+            max_concurrent_connects: max,
+            ..self
+        }
+    }
+
+    pub fn with_cluster_gateways(self, gateways: Vec<ClusterGateway>) -> Self {
+        Self {
+            gateways: Arc::new(gateways),
+            ..self
+        }
+    }
+
+    fn gateway_for(&self, authority: &http::uri::Authority) -> Option<SocketAddr> {
+        let host = authority.host().to_lowercase();
+        self.gateways
+            .iter()
+            .find(|gw| host.ends_with(&gw.suffix))
+            .map(|gw| gw.addr)
+    }
+}
+
+// ===== impl ClusterGateway =====
+
+impl ClusterGateway {
+    pub fn new(cluster: &str, addr: SocketAddr) -> Self {
+        ClusterGateway {
+            suffix: format!(".svc.{}", cluster.to_lowercase()),
+            addr,
         }
     }
 }
@@ -51,7 +121,7 @@ where
     type Error = Error;
     type Key = http::uri::Authority;
     type RouteError = ();
-    type Service = Buffer<Balance<Discovery<B>>>;
+    type Service = NoEndpoints<Buffer<Balance<Discovery<B>>>, Bind<Arc<ctx::Proxy>, B>>;
 
     fn recognize(&self, req: &Self::Request) -> Option<Self::Key> {
         req.uri().authority_part().cloned()
@@ -60,7 +130,16 @@ where
     /// Builds a dynamic, load balancing service.
     ///
     /// Resolves the authority in service discovery and initializes a service that buffers
-    /// and load balances requests across.
+    /// and load balances requests across. Requests are handled according to the
+    /// `no_endpoints::Policy` while the authority has no endpoints.
+    ///
+    /// Destinations are selected by a fixed chain: an authority in another cluster is
+    /// sent to that cluster's gateway; any other authority is resolved by the
+    /// controller; and, while the controller reports no endpoints, the policy may
+    /// forward requests to the connection's original destination. Destinations are
+    /// never resolved with DNS or from static configuration. Both resolution stages
+    /// produce a `control::discovery::Watch`, so making the chain configurable means
+    /// abstracting over that type.
     ///
     /// # TODO
     ///
@@ -72,14 +151,79 @@ where
     ) -> Result<Self::Service, Self::RouteError> {
         debug!("building outbound client to {:?}", authority);
 
-        let resolve = self.discovery.resolve(authority, self.bind.clone());
+        // All of the authority's endpoints share a limit, so that they aren't all dialed
+        // at once when the authority becomes available.
+        let bind = self.bind
+            .clone()
+            .with_dial_limit(self.max_concurrent_connects.map(transport::DialLimit::new));
+
+        let resolve = match self.gateway_for(authority) {
+            Some(gateway) => {
+                debug!("routing {:?} via cluster gateway {}", authority, gateway);
+                control::discovery::Watch::fixed(gateway, bind.clone())
+            }
+            None => self.discovery.resolve(authority, bind.clone()),
+        };
 
+        let endpoints = resolve.endpoints();
+        // The balancer chooses an endpoint for each request on its own. Because the
+        // proxy doesn't retry requests, there's no attempt context that could exclude
+        // endpoints that failed earlier attempts; retries would need to carry that
+        // context into the balancer's choice.
+        //
+        // Endpoints are also weighted only by the balancer's own load estimate, which
+        // doesn't account for failed responses. Weighting by error rate would need a
+        // `tower_balance` load metric fed by response classification, which happens in
+        // telemetry after the balancer has made its choice.
         let balance = Balance::new(resolve);
 
         // Wrap with buffering. This currently is an unbounded buffer,
         // which is not ideal.
         //
         // TODO: Don't use unbounded buffering.
-        Buffer::new(balance, self.bind.executor()).map_err(|_| {})
+        let buffer = Buffer::new(balance, self.bind.executor()).map_err(|_| {})?;
+
+        let no_endpoints = NoEndpoints::new(
+            buffer,
+            endpoints,
+            self.no_endpoints,
+            bind,
+            self.bind.executor(),
+        );
+        if self.startup_fallback {
+            let connectivity = self.discovery.connectivity().clone();
+            return Ok(no_endpoints.with_startup_fallback(connectivity));
+        }
+        Ok(no_endpoints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net;
+
+    use http;
+    use tokio_core::reactor::Core;
+
+    use super::{ClusterGateway, Outbound};
+    use bind::Bind;
+    use control;
+    use ctx;
+
+    #[test]
+    fn gateway_for_cluster_suffix() {
+        let core = Core::new().unwrap();
+        let ctx = ctx::Proxy::outbound(&ctx::Process::new("test", "test", "test"));
+        let bind = Bind::new(core.handle()).with_ctx(ctx);
+        let (control, _bg) = control::new(None);
+        let gw: net::SocketAddr = "10.1.1.1:4143".parse().unwrap();
+        let outbound = Outbound::<()>::new(bind, control)
+            .with_cluster_gateways(vec![ClusterGateway::new("cluster-b", gw)]);
+
+        let remote = "web.ns.svc.cluster-b:8080".parse::<http::uri::Authority>().unwrap();
+        assert_eq!(outbound.gateway_for(&remote), Some(gw));
+
+        let local = "web.ns.svc.cluster.local:8080".parse::<http::uri::Authority>().unwrap();
+        assert_eq!(outbound.gateway_for(&local), None);
     }
 }
@@ -0,0 +1,353 @@
+//! Transparently follows HTTP redirects for configured authorities.
+//!
+//! Some legacy services bounce clients between host names before answering. When a
+//! request's authority is configured, a `3xx` response carrying a `Location` that names
+//! another configured authority is followed by the proxy rather than returned to the
+//! application, up to a fixed number of hops.
+//!
+//! Request bodies are not buffered, so only requests without a body are followed. Per
+//! RFC 7231, `303 See Other` (and, historically, `301` and `302` in response to a `POST`)
+//! is followed with a `GET`; `307` and `308` preserve the request method.
+
+use std::sync::Arc;
+
+use futures::{Async, Future, Poll};
+use http;
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE, HOST, LOCATION};
+use tower::Service;
+use tower_h2::Body;
+
+use ctx;
+
+/// Determines which requests' redirects are followed.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    hosts: Arc<Vec<String>>,
+    limit: usize,
+}
+
+/// Follows redirects returned by an inner service.
+///
+/// The inner service is expected to always be ready, as a `Router` is.
+#[derive(Clone, Debug)]
+pub struct Redirect<S> {
+    inner: S,
+    policy: Policy,
+}
+
+pub struct ResponseFuture<S: Service> {
+    inner: S,
+    policy: Policy,
+    follow: Option<Follow>,
+    future: S::Future,
+}
+
+/// The parts of a request needed to reissue it.
+struct Follow {
+    method: http::Method,
+    uri: http::Uri,
+    version: http::Version,
+    headers: http::HeaderMap,
+    server_ctx: Option<Arc<ctx::transport::Server>>,
+    remaining: usize,
+}
+
+// ===== impl Policy =====
+
+impl Policy {
+    pub fn new<I: IntoIterator<Item = String>>(hosts: I, limit: usize) -> Self {
+        Policy {
+            hosts: Arc::new(hosts.into_iter().map(|h| h.to_lowercase()).collect()),
+            limit,
+        }
+    }
+
+    /// A policy that never follows redirects.
+    pub fn disabled() -> Self {
+        Self::new(Vec::new(), 0)
+    }
+
+    fn allows(&self, uri: &http::Uri) -> bool {
+        if self.limit == 0 {
+            return false;
+        }
+
+        // The proxy doesn't originate TLS, so only plaintext targets may be followed.
+        match uri.scheme() {
+            None | Some("http") => {}
+            Some(_) => return false,
+        }
+
+        uri.host()
+            .map(|h| {
+                let h = h.to_lowercase();
+                self.hosts.iter().any(|p| *p == h)
+            })
+            .unwrap_or(false)
+    }
+}
+
+// ===== impl Redirect =====
+
+impl<S> Redirect<S> {
+    pub fn new(inner: S, policy: Policy) -> Self {
+        Redirect {
+            inner,
+            policy,
+        }
+    }
+}
+
+impl<S, A, B> Service for Redirect<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>> + Clone,
+    A: Body + Default,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let follow = if req.body().is_end_stream() && self.policy.allows(req.uri()) {
+            Some(Follow {
+                method: req.method().clone(),
+                uri: req.uri().clone(),
+                version: req.version(),
+                headers: req.headers().clone(),
+                server_ctx: req.extensions().get::<Arc<ctx::transport::Server>>().cloned(),
+                remaining: self.policy.limit,
+            })
+        } else {
+            None
+        };
+
+        ResponseFuture {
+            future: self.inner.call(req),
+            inner: self.inner.clone(),
+            policy: self.policy.clone(),
+            follow,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, A, B> Future for ResponseFuture<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    A: Body + Default,
+{
+    type Item = S::Response;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let rsp = try_ready!(self.future.poll());
+
+            let follow = match self.follow.take() {
+                Some(f) => f,
+                None => return Ok(Async::Ready(rsp)),
+            };
+
+            let req = match follow.next(&rsp, &self.policy) {
+                Some((req, follow)) => {
+                    self.follow = follow;
+                    req
+                }
+                None => return Ok(Async::Ready(rsp)),
+            };
+
+            debug!("following {} redirect to {}", rsp.status(), req.uri());
+            self.future = self.inner.call(req);
+        }
+    }
+}
+
+// ===== impl Follow =====
+
+impl Follow {
+    /// If `rsp` is a redirect that should be followed, returns the request to issue and,
+    /// if further redirects may be followed, the state to follow them with.
+    fn next<A, B>(
+        self,
+        rsp: &http::Response<B>,
+        policy: &Policy,
+    ) -> Option<(http::Request<A>, Option<Follow>)>
+    where
+        A: Default,
+    {
+        let status = rsp.status();
+        let method = match status.as_u16() {
+            303 if self.method != http::Method::HEAD => http::Method::GET,
+            301 | 302 if self.method == http::Method::POST => http::Method::GET,
+            301 | 302 | 303 | 307 | 308 => self.method.clone(),
+            _ => return None,
+        };
+
+        let uri = rsp.headers()
+            .get(LOCATION)
+            .and_then(|loc| loc.to_str().ok())
+            .and_then(|loc| resolve(&self.uri, loc));
+        let uri = match uri {
+            Some(uri) => uri,
+            None => {
+                debug!("not following {} redirect: invalid location", status);
+                return None;
+            }
+        };
+        if !policy.allows(&uri) {
+            debug!("not following {} redirect to {}", status, uri);
+            return None;
+        }
+
+        let mut headers = self.headers.clone();
+        headers.remove(HOST);
+        if method != self.method {
+            headers.remove(CONTENT_LENGTH);
+            headers.remove(CONTENT_TYPE);
+        }
+
+        let mut req = http::Request::new(A::default());
+        *req.method_mut() = method.clone();
+        *req.uri_mut() = uri.clone();
+        *req.version_mut() = self.version;
+        *req.headers_mut() = headers.clone();
+        if let Some(ref ctx) = self.server_ctx {
+            req.extensions_mut().insert(Arc::clone(ctx));
+        }
+
+        let remaining = self.remaining - 1;
+        let follow = if remaining == 0 {
+            None
+        } else {
+            Some(Follow {
+                method,
+                uri,
+                version: self.version,
+                headers,
+                server_ctx: self.server_ctx,
+                remaining,
+            })
+        };
+
+        Some((req, follow))
+    }
+}
+
+/// Resolves a `Location` value against the URI of the request that was redirected.
+fn resolve(base: &http::Uri, location: &str) -> Option<http::Uri> {
+    if location.starts_with('/') && !location.starts_with("//") {
+        let scheme = base.scheme().unwrap_or("http");
+        return base.authority_part()
+            .and_then(|a| format!("{}://{}{}", scheme, a, location).parse().ok());
+    }
+
+    location.parse::<http::Uri>().ok().and_then(|uri| {
+        if uri.authority_part().is_some() {
+            Some(uri)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(status: u16, location: &str) -> http::Response<()> {
+        http::Response::builder()
+            .status(status)
+            .header(LOCATION, location)
+            .body(())
+            .unwrap()
+    }
+
+    fn follow(method: http::Method, uri: &str) -> Follow {
+        Follow {
+            method,
+            uri: uri.parse().unwrap(),
+            version: http::Version::HTTP_2,
+            headers: http::HeaderMap::new(),
+            server_ctx: None,
+            remaining: 2,
+        }
+    }
+
+    #[test]
+    fn resolves_relative_locations() {
+        let base = "http://a.example.com:8080/x".parse().unwrap();
+        assert_eq!(
+            resolve(&base, "/y?z").unwrap(),
+            "http://a.example.com:8080/y?z"
+        );
+        assert_eq!(
+            resolve(&base, "http://b.example.com/").unwrap(),
+            "http://b.example.com/"
+        );
+        assert!(resolve(&base, "y").is_none());
+    }
+
+    #[test]
+    fn follows_only_configured_plaintext_hosts() {
+        let policy = Policy::new(vec!["a.example.com".into(), "B.example.com".into()], 2);
+        assert!(policy.allows(&"http://a.example.com/".parse().unwrap()));
+        assert!(policy.allows(&"http://b.example.com:8080/".parse().unwrap()));
+        assert!(!policy.allows(&"https://b.example.com/".parse().unwrap()));
+        assert!(!policy.allows(&"http://c.example.com/".parse().unwrap()));
+        assert!(!Policy::disabled().allows(&"http://a.example.com/".parse().unwrap()));
+    }
+
+    #[test]
+    fn rewrites_methods_per_status() {
+        let policy = Policy::new(vec!["a.example.com".into()], 2);
+        let cases = vec![
+            (303, http::Method::POST, http::Method::GET),
+            (303, http::Method::HEAD, http::Method::HEAD),
+            (302, http::Method::POST, http::Method::GET),
+            (301, http::Method::PUT, http::Method::PUT),
+            (307, http::Method::POST, http::Method::POST),
+            (308, http::Method::DELETE, http::Method::DELETE),
+        ];
+        for (status, method, expected) in cases {
+            let f = follow(method, "http://a.example.com/old");
+            let (req, _) = f.next::<(), _>(&redirect(status, "/new"), &policy)
+                .expect("redirect followed");
+            assert_eq!(*req.method(), expected, "status {}", status);
+            assert_eq!(req.uri(), "http://a.example.com/new");
+        }
+    }
+
+    #[test]
+    fn stops_at_limit() {
+        let policy = Policy::new(vec!["a.example.com".into()], 2);
+        let f = follow(http::Method::GET, "http://a.example.com/");
+        let (_, f) = f.next::<(), _>(&redirect(302, "/1"), &policy).unwrap();
+        let f = f.expect("one more hop");
+        let (_, f) = f.next::<(), _>(&redirect(302, "/2"), &policy).unwrap();
+        assert!(f.is_none());
+    }
+
+    #[test]
+    fn ignores_non_redirects() {
+        let policy = Policy::new(vec!["a.example.com".into()], 2);
+        let f = follow(http::Method::GET, "http://a.example.com/");
+        assert!(f.next::<(), _>(&redirect(304, "/"), &policy).is_none());
+    }
+
+    #[test]
+    fn preserves_range_headers() {
+        let policy = Policy::new(vec!["a.example.com".into()], 2);
+        let mut f = follow(http::Method::GET, "http://a.example.com/old");
+        f.headers.insert("range", "bytes=0-99,200-299".parse().unwrap());
+        f.headers.insert("if-range", "\"v1\"".parse().unwrap());
+        let (req, _) = f.next::<(), _>(&redirect(302, "/new"), &policy).unwrap();
+        assert_eq!(req.headers()["range"], "bytes=0-99,200-299");
+        assert_eq!(req.headers()["if-range"], "\"v1\"");
+    }
+}
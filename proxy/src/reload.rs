@@ -0,0 +1,357 @@
+//! Applies changed settings while the proxy runs, when the process receives `SIGHUP` or
+//! when a file that a setting's value was read from changes.
+//!
+//! The `Config` is loaded again from the sources it was originally loaded from, and the
+//! settings that don't require listeners to be bound again are applied: the log filter,
+//! the metrics flush interval, the synthetic token, the control listener's credentials,
+//! and idle timeouts, which apply to connections accepted after the reload. Other
+//! settings only take effect when the proxy restarts, and a warning names any of them
+//! that changed. A configuration that fails to load is logged and ignored.
+//!
+//! The signal handler only sets a flag, which is checked once a second, along with the
+//! modification times of the files named by `<NAME>_FILE` variables. Mounted secrets can
+//! be rotated this way without restarting the proxy.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT};
+use std::time::{Duration, SystemTime};
+
+use futures::{Future, Stream};
+use tokio_core::reactor::{Handle, Interval};
+
+use config::Config;
+use logging;
+use status;
+
+/// Set by the signal handler when the process receives `SIGHUP`.
+static HANGUP: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// How often to check whether the process has received `SIGHUP`.
+const CHECK_INTERVAL_SECS: u64 = 1;
+
+/// The settings, as named by `Config::to_json`, that a reload applies.
+const RELOADABLE: &[&str] = &[
+    "log_filter",
+    "metrics_flush_interval",
+    "public_idle_timeout",
+    "private_idle_timeout",
+    "synthetic_token",
+    "control_listener_credentials",
+];
+
+/// A duration, in milliseconds, that a reload may change.
+#[derive(Clone, Debug)]
+pub struct DurationSetting(Arc<AtomicUsize>);
+
+/// A secret that a reload may change, which is never printed.
+#[derive(Clone)]
+pub struct SecretSetting(Arc<RwLock<Option<String>>>);
+
+/// The settings that a reload changes.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    metrics_flush_interval: DurationSetting,
+    public_idle_timeout: DurationSetting,
+    private_idle_timeout: DurationSetting,
+    synthetic_token: SecretSetting,
+    /// The `authorization` header value that requests to the control listener present.
+    control_authorization: SecretSetting,
+}
+
+// ===== impl DurationSetting =====
+
+impl DurationSetting {
+    pub fn new(duration: Duration) -> Self {
+        DurationSetting(Arc::new(AtomicUsize::new(millis(duration))))
+    }
+
+    /// A timeout, where zero means that there's no timeout.
+    pub fn timeout(timeout: Option<Duration>) -> Self {
+        Self::new(timeout.unwrap_or_default())
+    }
+
+    pub fn get(&self) -> Duration {
+        Duration::from_millis(self.0.load(Ordering::Relaxed) as u64)
+    }
+
+    /// Returns the duration, unless it's zero.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        let d = self.get();
+        if d == Duration::default() {
+            None
+        } else {
+            Some(d)
+        }
+    }
+
+    fn set(&self, duration: Duration) {
+        self.0.store(millis(duration), Ordering::Relaxed);
+    }
+}
+
+fn millis(d: Duration) -> usize {
+    (d.as_secs() as usize) * 1_000 + (d.subsec_nanos() / 1_000_000) as usize
+}
+
+// ===== impl SecretSetting =====
+
+impl SecretSetting {
+    pub fn new(secret: Option<String>) -> Self {
+        SecretSetting(Arc::new(RwLock::new(secret)))
+    }
+
+    /// Returns whether the secret is set and equal to `value`, in time that doesn't
+    /// depend on how much of `value` matches.
+    pub fn matches(&self, value: &[u8]) -> bool {
+        match self.0.read() {
+            Ok(secret) => secret
+                .as_ref()
+                .map(|s| constant_time_eq(s.as_bytes(), value))
+                .unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns whether the secret is set.
+    pub fn is_set(&self) -> bool {
+        self.0.read().map(|s| s.is_some()).unwrap_or(false)
+    }
+
+    fn set(&self, secret: Option<String>) {
+        if let Ok(mut s) = self.0.write() {
+            *s = secret;
+        }
+    }
+}
+
+impl fmt::Debug for SecretSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretSetting(..)")
+    }
+}
+
+/// Compares every byte, rather than stopping at the first that differs, so that a
+/// presented secret can't be guessed a byte at a time by timing failed attempts.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// ===== impl Settings =====
+
+impl Settings {
+    pub fn new(config: &Config) -> Self {
+        Settings {
+            metrics_flush_interval: DurationSetting::new(config.metrics_flush_interval()),
+            public_idle_timeout: DurationSetting::timeout(config.public_idle_timeout()),
+            private_idle_timeout: DurationSetting::timeout(config.private_idle_timeout()),
+            synthetic_token: SecretSetting::new(config.synthetic_token().map(String::from)),
+            control_authorization: SecretSetting::new(control_authorization(config)),
+        }
+    }
+
+    pub fn metrics_flush_interval(&self) -> &DurationSetting {
+        &self.metrics_flush_interval
+    }
+
+    pub fn public_idle_timeout(&self) -> &DurationSetting {
+        &self.public_idle_timeout
+    }
+
+    pub fn private_idle_timeout(&self) -> &DurationSetting {
+        &self.private_idle_timeout
+    }
+
+    pub fn synthetic_token(&self) -> &SecretSetting {
+        &self.synthetic_token
+    }
+
+    pub fn control_authorization(&self) -> &SecretSetting {
+        &self.control_authorization
+    }
+
+    fn apply(&self, config: &Config) {
+        self.metrics_flush_interval.set(config.metrics_flush_interval());
+        self.public_idle_timeout.set(config.public_idle_timeout().unwrap_or_default());
+        self.private_idle_timeout.set(config.private_idle_timeout().unwrap_or_default());
+        self.synthetic_token.set(config.synthetic_token().map(String::from));
+        self.control_authorization.set(control_authorization(config));
+        // An unset filter is the default, as when the proxy started without one.
+        logging::set_filter(config.log_filter().unwrap_or(""));
+    }
+}
+
+fn control_authorization(config: &Config) -> Option<String> {
+    config.control_listener_credentials().map(status::basic_authorization)
+}
+
+/// Reloads `config` and applies it to `settings` each time the process receives
+/// `SIGHUP`, and each time a file that a setting's value was read from changes.
+///
+/// If the signal handler can't be installed, `SIGHUP` keeps its default behavior, and
+/// only changed files cause reloads.
+pub fn watch(
+    config: Config,
+    settings: Settings,
+    handle: &Handle,
+) -> io::Result<Box<Future<Item = (), Error = ()>>> {
+    if !install_handler() {
+        warn!("could not handle SIGHUP; configuration will be reloaded only when files change");
+    }
+
+    // Settings that only apply on restart are compared with those the proxy started with.
+    let started = config.clone();
+    let mut config = config;
+    let mut files = modified(config.value_files());
+    let interval = Interval::new(Duration::from_secs(CHECK_INTERVAL_SECS), handle)?;
+    let reloads = interval
+        .for_each(move |()| {
+            let hangup = HANGUP.swap(false, Ordering::SeqCst);
+            let changed = modified(config.value_files()) != files;
+            if changed {
+                info!("a setting's file changed");
+            }
+            if hangup || changed {
+                if let Some(reloaded) = reload(&config, &started, &settings) {
+                    config = reloaded;
+                }
+                // The files are noted even if the reload failed, e.g. because a file was
+                // briefly missing, so that it's retried only once they change again.
+                files = modified(config.value_files());
+            }
+            Ok(())
+        })
+        .map_err(|e| error!("reload timer failed: {}", e));
+    Ok(Box::new(reloads))
+}
+
+/// Returns when each file was last modified, if it can be read. Symbolic links are
+/// followed, so a mounted secret that's replaced by swapping a link is seen to change.
+fn modified(paths: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    paths
+        .iter()
+        .map(|path| {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+            (path.clone(), modified)
+        })
+        .collect()
+}
+
+/// Returns the reloaded `Config`, if it could be reloaded, warning of any changes to
+/// settings that differ from those that the proxy `started` with and that only apply on
+/// restart.
+fn reload(config: &Config, started: &Config, settings: &Settings) -> Option<Config> {
+    match config.reload() {
+        Ok(reloaded) => {
+            settings.apply(&reloaded);
+            info!("reloaded configuration");
+            let pending = started
+                .changed_settings(&reloaded)
+                .into_iter()
+                .filter(|name| !RELOADABLE.contains(&name.as_str()))
+                .collect::<Vec<_>>();
+            if !pending.is_empty() {
+                warn!("changes to {} apply only on restart", pending.join(", "));
+            }
+            Some(reloaded)
+        }
+        Err(e) => {
+            warn!("could not reload configuration: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn install_handler() -> bool {
+    use libc;
+
+    extern "C" fn on_signal(_: libc::c_int) {
+        HANGUP.store(true, Ordering::SeqCst);
+    }
+
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe { libc::signal(libc::SIGHUP, handler) != libc::SIG_ERR }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn install_handler() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use config::Config;
+    use status::basic_authorization;
+
+    use super::{constant_time_eq, DurationSetting, SecretSetting, Settings, RELOADABLE};
+
+    #[test]
+    fn duration_setting_changes_every_clone() {
+        let setting = DurationSetting::timeout(None);
+        let clone = setting.clone();
+        assert_eq!(clone.get_timeout(), None);
+
+        setting.set(Duration::from_millis(1_500));
+        assert_eq!(clone.get(), Duration::from_millis(1_500));
+        assert_eq!(clone.get_timeout(), Some(Duration::from_millis(1_500)));
+    }
+
+    #[test]
+    fn reloadable_settings_are_rendered() {
+        let json = Config::builder().build().unwrap().to_json();
+        for name in RELOADABLE {
+            assert!(json.contains(&format!("\"{}\":", name)), "{} isn't rendered", name);
+        }
+    }
+
+    #[test]
+    fn secret_setting_changes_every_clone() {
+        let setting = SecretSetting::new(None);
+        let clone = setting.clone();
+        assert!(!clone.matches(b""));
+
+        setting.set(Some("s3cret".into()));
+        assert!(clone.matches(b"s3cret"));
+        assert!(!clone.matches(b"other"));
+        assert_eq!(format!("{:?}", clone), "SecretSetting(..)");
+    }
+
+    #[test]
+    fn compares_every_byte() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"s3cret", b"s3cret"));
+        assert!(!constant_time_eq(b"s3cret", b"s3creT"));
+        assert!(!constant_time_eq(b"s3cret", b"x3cret"));
+        assert!(!constant_time_eq(b"s3cret", b"s3cre"));
+        assert!(!constant_time_eq(b"s3cret", b"s3crets"));
+    }
+
+    #[test]
+    fn applies_rotated_control_credentials() {
+        let config = |credentials: Option<&str>| {
+            Config::builder()
+                .control_listener_credentials(credentials.map(String::from))
+                .build()
+                .unwrap()
+        };
+        let settings = Settings::new(&config(Some("ops:hunter2")));
+        let authorization = settings.control_authorization().clone();
+        assert!(authorization.matches(basic_authorization("ops:hunter2").as_bytes()));
+
+        settings.apply(&config(Some("ops:hunter3")));
+        assert!(!authorization.matches(basic_authorization("ops:hunter2").as_bytes()));
+        assert!(authorization.matches(basic_authorization("ops:hunter3").as_bytes()));
+
+        settings.apply(&config(None));
+        assert!(!authorization.is_set());
+    }
+}
@@ -0,0 +1,646 @@
+//! Journals failed outbound requests to configured hosts and replays them until they're
+//! delivered, e.g. for webhook fan-out, where the sender doesn't retry.
+//!
+//! A request is journaled when it's sent to a configured host with an idempotent method,
+//! since it may be delivered more than once, and when the proxy fails to forward it or
+//! the upstream answers with a `502`, `503`, or `504`. The application still receives
+//! that failure. Only requests without bodies are journaled: request bodies are streamed
+//! to the upstream as they're received and aren't retained.
+//!
+//! Entries are written to a journal directory, which holds at most `max_entries` of
+//! them; failures beyond that are dropped. A dedicated thread owns the directory: it
+//! writes new entries and replays the oldest one through the proxy's own outbound
+//! listener, so that it's routed like any other request. An entry is removed once it's
+//! answered with anything but a server error, and is otherwise retried with exponential
+//! backoff. Replayed requests carry a `conduit-replay` header, and aren't journaled
+//! again when they fail.
+//!
+//! Entries hold requests' headers verbatim, credentials included, so the journal's
+//! directory and entries are created accessible only to the proxy's user.
+
+use std::{cmp, fs, io, thread};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::{future, Async, Future, Poll};
+use h2;
+use http;
+use tokio_core::reactor::Core;
+use tower::{NewService, Service};
+use tower_h2::{self, Body};
+
+use local::{host_matches, request_host};
+use transport;
+
+/// Marks requests that are being replayed.
+const REPLAY_HEADER: &str = "conduit-replay";
+
+/// The number of entries that may be waiting to be written.
+const WRITE_CAPACITY: usize = 1_024;
+
+/// The number of entries in the journal.
+static DEPTH: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The number of replayed requests that were delivered.
+static REPLAYED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The number of replays that failed and will be retried.
+static FAILED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The number of failed requests that weren't journaled because it was full.
+static DROPPED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Returns the number of requests in the journal, waiting to be replayed.
+pub fn journal_depth() -> usize {
+    DEPTH.load(Ordering::Relaxed)
+}
+
+/// Returns the number of replayed requests that were delivered.
+pub fn replayed() -> usize {
+    REPLAYED.load(Ordering::Relaxed)
+}
+
+/// Returns the number of replays that failed, and were retried later.
+pub fn replay_failures() -> usize {
+    FAILED.load(Ordering::Relaxed)
+}
+
+/// Returns the number of failed requests that were dropped because the journal was full.
+pub fn dropped() -> usize {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// The hosts whose failed requests are journaled.
+///
+/// Each host is either an exact host name or a `*.`-prefixed suffix.
+#[derive(Clone, Debug)]
+pub struct Routes {
+    hosts: Arc<Vec<String>>,
+    journal: Option<Journal>,
+}
+
+/// Sends entries to the journal's thread.
+#[derive(Clone, Debug)]
+pub struct Journal {
+    tx: SyncSender<Vec<u8>>,
+}
+
+/// How the journal's thread writes and replays entries.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// The directory in which entries are kept.
+    pub dir: PathBuf,
+    /// The most entries that the journal holds.
+    pub max_entries: usize,
+    /// How long to wait before replaying a failed request for the first time.
+    pub backoff: Duration,
+    /// The longest to wait between replays of a failing request.
+    pub max_backoff: Duration,
+}
+
+/// Journals failed requests to configured routes.
+pub struct Replay<S> {
+    inner: S,
+    routes: Routes,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    /// The journal and the encoded request, if the request is journaled when it fails.
+    entry: Option<(Journal, Vec<u8>)>,
+}
+
+/// The journal's directory, owned by its thread.
+struct Dir {
+    settings: Settings,
+    seq: u64,
+}
+
+// ===== impl Routes =====
+
+impl Routes {
+    /// Creates `Routes` that journal failed requests to `hosts` in `journal`, if any.
+    pub fn new<H>(hosts: H, journal: Option<Journal>) -> Self
+    where
+        H: IntoIterator<Item = String>,
+    {
+        Routes {
+            hosts: Arc::new(hosts.into_iter().map(|h| h.to_lowercase()).collect()),
+            journal,
+        }
+    }
+
+    /// Returns `Routes` that never journal requests.
+    pub fn disabled() -> Self {
+        Self::new(Vec::new(), None)
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.journal.is_some() && self.hosts.iter().any(|p| host_matches(p, host))
+    }
+}
+
+// ===== impl Journal =====
+
+impl Journal {
+    /// Creates the journal's directory, if necessary, and starts a thread that writes
+    /// entries to it and replays them through the outbound listener at `outbound`.
+    pub fn new(settings: Settings, outbound: SocketAddr) -> io::Result<Self> {
+        private_dir().create(&settings.dir)?;
+        let mut dir = Dir {
+            settings,
+            seq: 0,
+        };
+        DEPTH.store(dir.entries()?.len(), Ordering::Relaxed);
+
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(WRITE_CAPACITY);
+        thread::Builder::new()
+            .name("replay-journal".into())
+            .spawn(move || {
+                let mut core = Core::new().expect("replay journal core");
+                let client = {
+                    let executor = core.handle();
+                    let connect = transport::Connect::new(outbound, &executor);
+                    tower_h2::client::Client::<_, _, ()>::new(
+                        connect,
+                        h2::client::Builder::default(),
+                        ::logging::context_executor("replay", executor),
+                    )
+                };
+
+                let backoff = dir.settings.backoff;
+                let mut wait = backoff;
+                // When the next replay is due, if the journal has any entries.
+                let mut next = if journal_depth() > 0 {
+                    Some(Instant::now())
+                } else {
+                    None
+                };
+                loop {
+                    let received = match next {
+                        None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                        Some(at) => {
+                            let now = Instant::now();
+                            let timeout = if at > now { at - now } else { Duration::from_secs(0) };
+                            rx.recv_timeout(timeout)
+                        }
+                    };
+                    match received {
+                        Ok(entry) => {
+                            dir.write(&entry);
+                            // The upstream just failed, so it's given time to recover.
+                            next = next.or_else(|| Some(Instant::now() + wait));
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+
+                    next = match dir.replay_oldest(&mut core, &client) {
+                        None => {
+                            wait = backoff;
+                            None
+                        }
+                        Some(true) => {
+                            wait = backoff;
+                            Some(Instant::now())
+                        }
+                        Some(false) => {
+                            let at = Instant::now() + wait;
+                            wait = cmp::min(wait * 2, dir.settings.max_backoff);
+                            Some(at)
+                        }
+                    };
+                }
+            })?;
+
+        Ok(Journal {
+            tx,
+        })
+    }
+
+    fn send(&self, entry: Vec<u8>) {
+        match self.tx.try_send(entry) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+                warn!("replay journal is busy; dropping request");
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+                warn!("replay journal stopped; dropping request");
+            }
+        }
+    }
+}
+
+// ===== impl Dir =====
+
+impl Dir {
+    /// Returns the paths of the journal's entries, oldest first.
+    fn entries(&self) -> io::Result<Vec<PathBuf>> {
+        let mut entries = fs::read_dir(&self.settings.dir)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().map_or(false, |ext| ext == "replay"))
+            .collect::<Vec<_>>();
+        entries.sort();
+        Ok(entries)
+    }
+
+    fn write(&mut self, entry: &[u8]) {
+        if journal_depth() >= self.settings.max_entries {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+            warn!("replay journal is full; dropping request");
+            return;
+        }
+
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let name = format!(
+            "{}.{:09}-{:010}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos(),
+            self.seq,
+        );
+        self.seq += 1;
+        // Entries are renamed into place so that they're never read partially written.
+        let path = self.settings.dir.join(format!("{}.replay", name));
+        let tmp = self.settings.dir.join(format!("{}.tmp", name));
+        let written = private_file()
+            .open(&tmp)
+            .and_then(|mut f| f.write_all(entry))
+            .and_then(|_| fs::rename(&tmp, &path));
+        match written {
+            Ok(()) => {
+                DEPTH.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => warn!("could not write replay entry {}: {}", path.display(), e),
+        }
+    }
+
+    /// Replays the oldest entry, if there is one, and returns whether it was delivered.
+    fn replay_oldest<C>(&self, core: &mut Core, client: &C) -> Option<bool>
+    where
+        C: NewService<Request = http::Request<()>, Response = http::Response<tower_h2::RecvBody>>,
+        C::Error: ::std::fmt::Debug,
+        C::InitError: ::std::fmt::Debug,
+    {
+        let path = match self.entries() {
+            Ok(entries) => match entries.into_iter().next() {
+                Some(path) => path,
+                None => return None,
+            },
+            Err(e) => {
+                warn!("could not list replay journal: {}", e);
+                return None;
+            }
+        };
+
+        let req = match read_entry(&path) {
+            Ok(req) => req,
+            Err(e) => {
+                warn!("discarding unreadable replay entry {}: {}", path.display(), e);
+                self.remove(&path);
+                return Some(true);
+            }
+        };
+        debug!("replaying {} {}", req.method(), req.uri());
+
+        let replay = client
+            .new_service()
+            .map_err(|e| format!("could not connect: {:?}", e))
+            .and_then(|svc| {
+                let mut svc = Some(svc);
+                future::poll_fn(move || {
+                    let ready = svc.as_mut()
+                        .expect("polled after ready")
+                        .poll_ready()
+                        .map_err(|e| format!("{:?}", e))?;
+                    Ok(ready.map(|()| svc.take().expect("polled after ready")))
+                })
+            })
+            .and_then(move |mut svc| svc.call(req).map_err(|e| format!("{:?}", e)));
+
+        match core.run(replay) {
+            Ok(ref rsp) if !rsp.status().is_server_error() => {
+                debug!("replayed {}: {}", path.display(), rsp.status());
+                REPLAYED.fetch_add(1, Ordering::Relaxed);
+                self.remove(&path);
+                Some(true)
+            }
+            Ok(rsp) => {
+                debug!("replay of {} failed: {}", path.display(), rsp.status());
+                FAILED.fetch_add(1, Ordering::Relaxed);
+                Some(false)
+            }
+            Err(e) => {
+                debug!("replay of {} failed: {}", path.display(), e);
+                FAILED.fetch_add(1, Ordering::Relaxed);
+                Some(false)
+            }
+        }
+    }
+
+    fn remove(&self, path: &Path) {
+        match fs::remove_file(path) {
+            Ok(()) => {
+                DEPTH.fetch_sub(1, Ordering::Relaxed);
+            }
+            Err(e) => warn!("could not remove replay entry {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Returns a builder of directories that only the proxy's user may access, since entries
+/// hold requests' credentials.
+#[cfg(unix)]
+fn private_dir() -> fs::DirBuilder {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true).mode(0o700);
+    builder
+}
+
+#[cfg(not(unix))]
+fn private_dir() -> fs::DirBuilder {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    builder
+}
+
+/// Returns options that create entries only the proxy's user may read.
+#[cfg(unix)]
+fn private_file() -> fs::OpenOptions {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true).mode(0o600);
+    options
+}
+
+#[cfg(not(unix))]
+fn private_file() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    options
+}
+
+// ===== impl Replay =====
+
+impl<S> Replay<S> {
+    pub fn new(inner: S, routes: Routes) -> Self {
+        Replay {
+            inner,
+            routes,
+        }
+    }
+}
+
+impl<S, A, B> Service for Replay<S>
+where
+    S: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    A: Body,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let journaled = request_host(&req)
+            .map(|h| self.routes.matches(h))
+            .unwrap_or(false) && is_replayable(&req);
+
+        let entry = if journaled {
+            self.routes.journal.clone().map(|j| (j, encode(&req)))
+        } else {
+            None
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            entry,
+        }
+    }
+}
+
+/// Returns whether `req` may be replayed: it must be idempotent, have no body, and not
+/// already be a replay.
+fn is_replayable<A: Body>(req: &http::Request<A>) -> bool {
+    let idempotent = match *req.method() {
+        http::Method::GET |
+        http::Method::HEAD |
+        http::Method::PUT |
+        http::Method::DELETE |
+        http::Method::OPTIONS |
+        http::Method::TRACE => true,
+        _ => false,
+    };
+    idempotent && req.body().is_end_stream() && !req.headers().contains_key(REPLAY_HEADER)
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let failed = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(rsp)) => match rsp.status() {
+                http::StatusCode::BAD_GATEWAY |
+                http::StatusCode::SERVICE_UNAVAILABLE |
+                http::StatusCode::GATEWAY_TIMEOUT => Ok(Async::Ready(rsp)),
+                _ => return Ok(Async::Ready(rsp)),
+            },
+            Err(e) => Err(e),
+        };
+
+        if let Some((journal, entry)) = self.entry.take() {
+            journal.send(entry);
+        }
+        failed
+    }
+}
+
+/// Encodes a request's method, target, and headers, one per line.
+fn encode<A>(req: &http::Request<A>) -> Vec<u8> {
+    let mut entry = format!("{} {}\n", req.method(), req.uri()).into_bytes();
+    for (name, value) in req.headers() {
+        entry.extend_from_slice(name.as_str().as_bytes());
+        entry.extend_from_slice(b": ");
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+fn read_entry(path: &Path) -> io::Result<http::Request<()>> {
+    let mut entry = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut entry)?;
+    decode(&entry).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn decode(entry: &[u8]) -> Result<http::Request<()>, &'static str> {
+    let mut lines = entry.split(|&b| b == b'\n').filter(|l| !l.is_empty());
+
+    let request_line = lines.next().ok_or("empty entry")?;
+    let request_line = ::std::str::from_utf8(request_line).map_err(|_| "invalid request line")?;
+    let mut parts = request_line.splitn(2, ' ');
+    let method = parts.next().unwrap_or("");
+    let uri = parts.next().ok_or("missing request target")?;
+
+    let mut req = http::Request::builder();
+    req.method(method).uri(uri).header(REPLAY_HEADER, "true");
+    for line in lines {
+        let colon = line.iter().position(|&b| b == b':').ok_or("malformed header")?;
+        let value = &line[colon + 1..];
+        let start = value.iter().position(|&b| b != b' ').unwrap_or(value.len());
+        req.header(&line[..colon], &value[start..]);
+    }
+    req.body(()).map_err(|_| "invalid request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes() -> (Routes, mpsc::Receiver<Vec<u8>>) {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let routes = Routes::new(
+            vec!["hooks.example.com".to_owned()],
+            Some(Journal {
+                tx,
+            }),
+        );
+        (routes, rx)
+    }
+
+    fn request(method: http::Method) -> http::Request<()> {
+        http::Request::builder()
+            .method(method)
+            .uri("http://hooks.example.com/deliver?id=1")
+            .header("authorization", "Bearer token")
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let entry = encode(&request(http::Method::PUT));
+        assert_eq!(
+            entry,
+            b"PUT http://hooks.example.com/deliver?id=1\nauthorization: Bearer token\n".to_vec()
+        );
+
+        let req = decode(&entry).unwrap();
+        assert_eq!(req.method(), &http::Method::PUT);
+        assert_eq!(req.uri(), "http://hooks.example.com/deliver?id=1");
+        assert_eq!(req.headers()["authorization"], "Bearer token");
+        assert_eq!(req.headers()[REPLAY_HEADER], "true");
+
+        assert!(decode(b"").is_err());
+        assert!(decode(b"GET /\nno colon\n").is_err());
+    }
+
+    #[test]
+    fn journals_only_replayable_requests() {
+        let (routes, _rx) = routes();
+        assert!(routes.matches("hooks.example.com"));
+        assert!(!routes.matches("other.example.com"));
+        assert!(!Routes::disabled().matches("hooks.example.com"));
+
+        assert!(is_replayable(&request(http::Method::DELETE)));
+        assert!(!is_replayable(&request(http::Method::POST)));
+
+        let mut replayed = request(http::Method::GET);
+        replayed.headers_mut().insert(REPLAY_HEADER, "true".parse().unwrap());
+        assert!(!is_replayable(&replayed));
+    }
+
+    #[test]
+    fn journals_failed_responses() {
+        let (routes, rx) = routes();
+        let status = |status: http::StatusCode| {
+            let rsp = http::Response::builder().status(status).body(()).unwrap();
+            let mut fut = ResponseFuture {
+                inner: future::ok::<_, ()>(rsp),
+                entry: routes.journal.clone().map(|j| (j, b"GET /\n".to_vec())),
+            };
+            fut.poll().unwrap();
+        };
+
+        status(http::StatusCode::NOT_FOUND);
+        assert!(rx.try_recv().is_err());
+
+        status(http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(rx.try_recv().unwrap(), b"GET /\n".to_vec());
+    }
+
+    /// Creates a journal directory that no other test uses.
+    fn dir(test: &str) -> Dir {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let name = format!(
+            "conduit-proxy-replay-{}-{}.{:09}",
+            test,
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos()
+        );
+        let path = ::std::env::temp_dir().join(name);
+        private_dir().create(&path).unwrap();
+        Dir {
+            settings: Settings {
+                dir: path,
+                max_entries: 10,
+                backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(1),
+            },
+            seq: 0,
+        }
+    }
+
+    #[test]
+    fn lists_entries_oldest_first() {
+        let mut dir = dir("lists-entries-oldest-first");
+        dir.write(b"GET /first\n");
+        dir.write(b"GET /second\n");
+
+        let entries = dir.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(read_entry(&entries[0]).unwrap().uri(), "/first");
+        dir.remove(&entries[0]);
+        assert_eq!(dir.entries().unwrap().len(), 1);
+
+        fs::remove_dir_all(&dir.settings.dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_entries_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut dir = dir("keeps-entries-private");
+        dir.write(b"GET /\nauthorization: Bearer token\n");
+
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir.settings.dir), 0o700);
+        assert_eq!(mode(&dir.entries().unwrap()[0]), 0o600);
+
+        fs::remove_dir_all(&dir.settings.dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+//! Sets a fixed set of headers on every response sent by a listener.
+//!
+//! This lets platform teams enforce security headers (e.g. `Strict-Transport-Security`
+//! or `X-Content-Type-Options`) centrally, at the ingress, rather than in every
+//! application. Configured headers replace any values set by the application or by the
+//! proxy itself.
+
+use std::sync::Arc;
+
+use futures::{Async, Future, Poll};
+use http;
+use http::header::{HeaderName, HeaderValue};
+use tower::Service;
+
+/// The headers set on a listener's responses.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    headers: Arc<Vec<(HeaderName, HeaderValue)>>,
+}
+
+/// Sets a `Policy`'s headers on responses.
+pub struct ResponseHeaders<S> {
+    inner: S,
+    policy: Policy,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    policy: Policy,
+}
+
+// ===== impl Policy =====
+
+impl Policy {
+    pub fn new<I: IntoIterator<Item = (HeaderName, HeaderValue)>>(headers: I) -> Self {
+        Policy {
+            headers: Arc::new(headers.into_iter().collect()),
+        }
+    }
+
+    /// Returns a policy that leaves responses untouched.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    fn apply(&self, headers: &mut http::HeaderMap) {
+        for &(ref name, ref value) in self.headers.iter() {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+// ===== impl ResponseHeaders =====
+
+impl<S> ResponseHeaders<S> {
+    pub fn new(inner: S, policy: Policy) -> Self {
+        ResponseHeaders {
+            inner,
+            policy,
+        }
+    }
+}
+
+impl<S, B> Service for ResponseHeaders<S>
+where
+    S: Service<Response = http::Response<B>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut rsp = try_ready!(self.inner.poll());
+        self.policy.apply(rsp.headers_mut());
+        Ok(Async::Ready(rsp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+
+    use super::*;
+
+    struct Respond;
+
+    impl Service for Respond {
+        type Request = ();
+        type Response = http::Response<()>;
+        type Error = ();
+        type Future = FutureResult<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            let rsp = http::Response::builder()
+                .header("x-frame-options", "SAMEORIGIN")
+                .header("x-app", "1")
+                .body(())
+                .unwrap();
+            future::ok(rsp)
+        }
+    }
+
+    #[test]
+    fn sets_configured_headers() {
+        let policy = Policy::new(vec![
+            (
+                HeaderName::from_static("strict-transport-security"),
+                HeaderValue::from_static("max-age=31536000; includeSubDomains"),
+            ),
+            (
+                HeaderName::from_static("x-frame-options"),
+                HeaderValue::from_static("DENY"),
+            ),
+        ]);
+        let rsp = ResponseHeaders::new(Respond, policy).call(()).wait().unwrap();
+        assert_eq!(
+            rsp.headers()["strict-transport-security"],
+            "max-age=31536000; includeSubDomains"
+        );
+        assert_eq!(rsp.headers()["x-frame-options"], "DENY");
+        assert_eq!(rsp.headers()["x-app"], "1");
+        assert_eq!(rsp.headers().get_all("x-frame-options").iter().count(), 1);
+    }
+
+    #[test]
+    fn disabled_leaves_responses_untouched() {
+        let rsp = ResponseHeaders::new(Respond, Policy::disabled()).call(()).wait().unwrap();
+        assert_eq!(rsp.headers()["x-frame-options"], "SAMEORIGIN");
+        assert_eq!(rsp.headers().len(), 2);
+    }
+}
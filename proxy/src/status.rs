@@ -0,0 +1,440 @@
+//! A human-readable status page, served on the control listener.
+//!
+//! `GET /` returns a plain HTML page summarizing the proxy's uptime, version,
+//! listeners, connectivity to the controller, and the authorities that it has proxied
+//! the most requests to. It's intended for quick debugging, e.g. through
+//! `kubectl port-forward`. The control listener answers HTTP/1.1 requests to these
+//! endpoints, as well as HTTP/2 requests.
+//!
+//! `GET /config` returns the settings the proxy started with as JSON, with secrets
+//! redacted.
+//!
+//! If credentials are configured, every request to the control listener, including tap
+//! requests, must present them with HTTP basic authentication.
+
+use std::fmt::{self, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http;
+use http::header::{HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, WWW_AUTHENTICATE};
+use tokio_core::reactor::Handle;
+use tower::NewService;
+
+use config::Config;
+use control;
+use ctx;
+use local::{self, Local, Respond, Responders};
+use reload::SecretSetting;
+use replay;
+use telemetry::authorities::Authorities;
+use telemetry::health::HealthChecks;
+use transport;
+
+/// The number of authorities listed on the page.
+const TOP_AUTHORITIES: usize = 10;
+
+/// Renders the status page.
+#[derive(Clone, Debug)]
+pub struct Page {
+    started: Instant,
+    process: Arc<ctx::Process>,
+    listeners: Arc<Vec<(String, SocketAddr)>>,
+    connectivity: control::Connectivity,
+    authorities: Authorities,
+    health_checks: HealthChecks,
+}
+
+/// Serves the effective configuration.
+#[derive(Clone, Debug)]
+pub struct ConfigDump {
+    json: Arc<String>,
+}
+
+/// Refuses requests that don't present the configured basic authentication credentials.
+#[derive(Clone)]
+pub struct Credentials {
+    /// The expected `authorization` header value, if any credentials are configured.
+    expected: SecretSetting,
+}
+
+/// Serves the status page in front of another service.
+pub struct NewStatus<N, B> {
+    inner: N,
+    responders: Responders<B>,
+    executor: Handle,
+}
+
+pub struct NewStatusFuture<F, B> {
+    inner: F,
+    responders: Responders<B>,
+    executor: Handle,
+}
+
+// ===== impl Page =====
+
+impl Page {
+    pub fn new(
+        process: &Arc<ctx::Process>,
+        listeners: Vec<(String, SocketAddr)>,
+        connectivity: &control::Connectivity,
+        authorities: &Authorities,
+        health_checks: &HealthChecks,
+    ) -> Self {
+        Page {
+            started: Instant::now(),
+            process: Arc::clone(process),
+            listeners: Arc::new(listeners),
+            connectivity: connectivity.clone(),
+            authorities: authorities.clone(),
+            health_checks: health_checks.clone(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let uptime = self.started.elapsed();
+        let mut s = String::new();
+        s.push_str("<!DOCTYPE html>\n<html><head><title>conduit-proxy</title></head><body>\n");
+        s.push_str("<h1>conduit-proxy</h1>\n<table>\n");
+        row(&mut s, "Version", env!("CARGO_PKG_VERSION"));
+        row(&mut s, "Uptime", &format!("{}s", uptime.as_secs()));
+        row(&mut s, "Pod", &self.process.scheduled_instance);
+        row(&mut s, "Namespace", &self.process.scheduled_namespace);
+        row(&mut s, "Node", &self.process.node);
+        let controller = if self.connectivity.is_connected() {
+            "connected"
+        } else {
+            "disconnected"
+        };
+        row(&mut s, "Controller", controller);
+        row(&mut s, "Health checks", &self.health_checks.count().to_string());
+        row(
+            &mut s,
+            "Original destination lookup failures",
+            &transport::original_dst_lookup_failures().to_string(),
+        );
+        row(&mut s, "Pending connection attempts", &transport::pending_dials().to_string());
+        let overflows = transport::accept_queue_overflows()
+            .map_or_else(|| "unknown".to_owned(), |n| n.to_string());
+        row(&mut s, "Accept queue overflows", &overflows);
+        row(&mut s, "Replay journal depth", &replay::journal_depth().to_string());
+        row(&mut s, "Replayed requests", &replay::replayed().to_string());
+        row(&mut s, "Failed replays", &replay::replay_failures().to_string());
+        row(&mut s, "Dropped replays", &replay::dropped().to_string());
+        s.push_str("</table>\n");
+
+        s.push_str("<h2>Listeners</h2>\n<table>\n");
+        for &(ref name, ref addr) in self.listeners.iter() {
+            row(&mut s, name, &addr.to_string());
+        }
+        s.push_str("</table>\n");
+
+        s.push_str("<h2>Top authorities</h2>\n<table>\n");
+        s.push_str("<tr><th>Authority</th><th>Requests</th><th>RPS</th><th>Errors</th></tr>\n");
+        let secs = seconds(uptime).max(1.0);
+        for (authority, counts) in self.authorities.top(TOP_AUTHORITIES) {
+            let error_rate = if counts.requests == 0 {
+                0.0
+            } else {
+                100.0 * counts.failures as f64 / counts.requests as f64
+            };
+            let _ = writeln!(
+                s,
+                "<tr><td>{}</td><td>{}</td><td>{:.2}</td><td>{:.1}%</td></tr>",
+                escape(&authority),
+                counts.requests,
+                counts.requests as f64 / secs,
+                error_rate,
+            );
+        }
+        s.push_str("</table>\n</body></html>\n");
+        s
+    }
+}
+
+impl<B> Respond<B> for Page {
+    fn respond(&self, req: &http::Request<B>) -> Option<local::Response> {
+        if req.uri().path() != "/" || *req.method() != http::Method::GET {
+            return None;
+        }
+
+        let mut rsp = http::Response::new(Bytes::from(self.render()));
+        rsp.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        rsp.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        Some(local::Response::new(rsp))
+    }
+}
+
+// ===== impl ConfigDump =====
+
+impl ConfigDump {
+    pub fn new(config: &Config) -> Self {
+        ConfigDump {
+            json: Arc::new(config.to_json()),
+        }
+    }
+}
+
+impl<B> Respond<B> for ConfigDump {
+    fn respond(&self, req: &http::Request<B>) -> Option<local::Response> {
+        if req.uri().path() != "/config" || *req.method() != http::Method::GET {
+            return None;
+        }
+
+        let mut rsp = http::Response::new(Bytes::from(self.json.as_bytes()));
+        rsp.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        rsp.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        Some(local::Response::new(rsp))
+    }
+}
+
+// ===== impl Credentials =====
+
+impl Credentials {
+    /// Requires the `authorization` header value that `expected` holds, while it's set.
+    pub fn new(expected: SecretSetting) -> Self {
+        Credentials {
+            expected,
+        }
+    }
+}
+
+/// Returns the `authorization` header value that presents `user_and_password`, formatted
+/// as `<user>:<password>`, with HTTP basic authentication.
+pub fn basic_authorization(user_and_password: &str) -> String {
+    format!("Basic {}", encode_base64(user_and_password.as_bytes()))
+}
+
+impl<B> Respond<B> for Credentials {
+    fn respond(&self, req: &http::Request<B>) -> Option<local::Response> {
+        if !self.expected.is_set() {
+            return None;
+        }
+        let presented = req.headers().get(AUTHORIZATION).map(|v| v.as_bytes());
+        if presented.map(|p| self.expected.matches(p)).unwrap_or(false) {
+            return None;
+        }
+
+        debug!("refusing control request for {} without credentials", req.uri().path());
+        let mut rsp = http::Response::new(Bytes::new());
+        *rsp.status_mut() = http::StatusCode::UNAUTHORIZED;
+        rsp.headers_mut().insert(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"conduit-proxy\""),
+        );
+        Some(local::Response::new(rsp))
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("required", &self.expected.is_set())
+            .finish()
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn row(s: &mut String, name: &str, value: &str) {
+    let _ = writeln!(s, "<tr><th>{}</th><td>{}</td></tr>", escape(name), escape(value));
+}
+
+fn seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1e9
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// ===== impl NewStatus =====
+
+impl<N, B> NewStatus<N, B> {
+    pub fn new(
+        inner: N,
+        credentials: Credentials,
+        page: Page,
+        config: ConfigDump,
+        executor: &Handle,
+    ) -> Self
+    where
+        B: 'static,
+    {
+        NewStatus {
+            inner,
+            responders: responders(credentials, page, config),
+            executor: executor.clone(),
+        }
+    }
+}
+
+/// Returns the control listener's admin endpoints.
+pub fn responders<B: 'static>(
+    credentials: Credentials,
+    page: Page,
+    config: ConfigDump,
+) -> Responders<B> {
+    // Credentials are checked first, so that they guard every other responder and any
+    // service behind them.
+    Responders::new(vec![
+        Box::new(credentials) as Box<Respond<B>>,
+        Box::new(page),
+        Box::new(config),
+    ])
+}
+
+impl<N, A, B> NewService for NewStatus<N, A>
+where
+    N: NewService<Request = http::Request<A>, Response = http::Response<B>>,
+{
+    type Request = http::Request<A>;
+    type Response = http::Response<local::Body<B>>;
+    type Error = N::Error;
+    type Service = Local<N::Service, A>;
+    type InitError = N::InitError;
+    type Future = NewStatusFuture<N::Future, A>;
+
+    fn new_service(&self) -> Self::Future {
+        NewStatusFuture {
+            inner: self.inner.new_service(),
+            responders: self.responders.clone(),
+            executor: self.executor.clone(),
+        }
+    }
+}
+
+// ===== impl NewStatusFuture =====
+
+impl<F, A, S> Future for NewStatusFuture<F, A>
+where
+    F: Future<Item = S>,
+{
+    type Item = Local<S, A>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        let local = Local::new(inner, self.responders.clone(), &self.executor);
+        Ok(Async::Ready(local))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_status() {
+        let page = Page::new(
+            &ctx::Process::new("node-1", "web-abc", "prod"),
+            vec![("public".into(), "0.0.0.0:4143".parse().unwrap())],
+            &control::Connectivity::default(),
+            &Authorities::default(),
+            &HealthChecks::default(),
+        );
+
+        let req = http::Request::builder().uri("/").body(()).unwrap();
+        assert!(page.respond(&req).is_some());
+
+        let body = page.render();
+        assert!(body.contains("<tr><th>Pod</th><td>web-abc</td></tr>"));
+        assert!(body.contains("<tr><th>Controller</th><td>disconnected</td></tr>"));
+        assert!(body.contains("<tr><th>public</th><td>0.0.0.0:4143</td></tr>"));
+
+        let req = http::Request::builder().uri("/other").body(()).unwrap();
+        assert!(page.respond(&req).is_none());
+    }
+
+    #[test]
+    fn serves_config() {
+        let config = Config::builder().build().unwrap();
+        let dump = ConfigDump::new(&config);
+
+        let req = http::Request::builder().uri("/config").body(()).unwrap();
+        let rsp = dump.respond(&req).expect("config response").response;
+        assert_eq!(rsp.headers()[CONTENT_TYPE], "application/json");
+        let body = String::from_utf8(rsp.body().to_vec()).unwrap();
+        assert!(body.contains("\"public_listener\":{\"addr\":\"tcp://0.0.0.0:4143\""));
+
+        let req = http::Request::builder().uri("/").body(()).unwrap();
+        assert!(dump.respond(&req).is_none());
+    }
+
+    #[test]
+    fn requires_credentials() {
+        let expected = SecretSetting::new(Some(basic_authorization("ops:hunter2")));
+        let credentials = Credentials::new(expected);
+
+        let req = http::Request::builder().uri("/metrics").body(()).unwrap();
+        let rsp = credentials.respond(&req).expect("refused").response;
+        assert_eq!(rsp.status(), http::StatusCode::UNAUTHORIZED);
+        assert_eq!(rsp.headers()[WWW_AUTHENTICATE], "Basic realm=\"conduit-proxy\"");
+
+        let req = http::Request::builder()
+            .uri("/")
+            .header(AUTHORIZATION, "Basic b3BzOmh1bnRlcjI=")
+            .body(())
+            .unwrap();
+        assert!(credentials.respond(&req).is_none());
+
+        let req = http::Request::builder()
+            .uri("/")
+            .header(AUTHORIZATION, "Basic b3BzOndyb25n")
+            .body(())
+            .unwrap();
+        assert!(credentials.respond(&req).is_some());
+
+        let req = http::Request::builder().uri("/").body(()).unwrap();
+        assert!(Credentials::new(SecretSetting::new(None)).respond(&req).is_none());
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        assert_eq!(encode_base64(b"ops:hunter2"), "b3BzOmh1bnRlcjI=");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(escape("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+}
@@ -0,0 +1,219 @@
+//! Limits the number of concurrent streams on each connection.
+//!
+//! A single noisy client may otherwise open as many streams as the HTTP/2 settings
+//! allow, monopolizing the application. Streams opened beyond the limit are failed with
+//! `REFUSED_STREAM` without being forwarded, which causes the server to reset them.
+//!
+//! A stream is active from when its request is received until its response body has been
+//! sent or dropped.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::{Async, Future, Poll};
+use h2;
+use http;
+use tower::Service;
+use tower_h2;
+
+/// The maximum number of concurrent streams per connection on a listener.
+#[derive(Clone, Debug, Default)]
+pub struct Limit {
+    max: Option<usize>,
+    refused: Arc<AtomicUsize>,
+}
+
+/// Enforces a `Limit` on the streams of a single connection.
+pub struct StreamLimit<S> {
+    inner: S,
+    limit: Limit,
+    active: Rc<Cell<usize>>,
+}
+
+pub enum ResponseFuture<F> {
+    Active(F, Option<Active>),
+    Refused,
+}
+
+/// Counts a stream as active until dropped.
+#[derive(Debug)]
+pub struct Active(Rc<Cell<usize>>);
+
+/// A response body that keeps its stream active until it's dropped.
+#[derive(Debug, Default)]
+pub struct Body<B> {
+    inner: B,
+    active: Option<Active>,
+}
+
+// ===== impl Limit =====
+
+impl Limit {
+    pub fn new(max: Option<usize>) -> Self {
+        Limit {
+            max,
+            refused: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Renders the number of refused streams in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let name = "streams_refused_total";
+        format!(
+            "# HELP {} Streams refused because their connection was at its limit.\n\
+             # TYPE {} counter\n\
+             {} {}\n",
+            name,
+            name,
+            name,
+            self.refused.load(Ordering::Relaxed),
+        )
+    }
+}
+
+// ===== impl StreamLimit =====
+
+impl<S> StreamLimit<S> {
+    pub fn new(inner: S, limit: Limit) -> Self {
+        StreamLimit {
+            inner,
+            limit,
+            active: Rc::new(Cell::new(0)),
+        }
+    }
+}
+
+impl<S, B> Service for StreamLimit<S>
+where
+    S: Service<Response = http::Response<B>>,
+    S::Error: From<h2::Reason>,
+{
+    type Request = S::Request;
+    type Response = http::Response<Body<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        let active = self.active.get();
+        if self.limit.max.map(|max| active >= max).unwrap_or(false) {
+            debug!("refusing stream; {} streams active on connection", active);
+            self.limit.refused.fetch_add(1, Ordering::Relaxed);
+            return ResponseFuture::Refused;
+        }
+
+        self.active.set(active + 1);
+        let active = Active(self.active.clone());
+        ResponseFuture::Active(self.inner.call(req), Some(active))
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+    F::Error: From<h2::Reason>,
+{
+    type Item = http::Response<Body<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::Active(ref mut f, ref mut active) => {
+                let (parts, inner) = try_ready!(f.poll()).into_parts();
+                let body = Body {
+                    inner,
+                    active: active.take(),
+                };
+                Ok(Async::Ready(http::Response::from_parts(parts, body)))
+            }
+            ResponseFuture::Refused => Err(h2::Reason::REFUSED_STREAM.into()),
+        }
+    }
+}
+
+// ===== impl Active =====
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
+// ===== impl Body =====
+
+impl<B: tower_h2::Body> tower_h2::Body for Body<B> {
+    type Data = B::Data;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        self.inner.poll_data()
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, FutureResult};
+
+    use super::*;
+
+    struct Respond;
+
+    impl Service for Respond {
+        type Request = ();
+        type Response = http::Response<()>;
+        type Error = h2::Error;
+        type Future = FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            future::ok(http::Response::new(()))
+        }
+    }
+
+    #[test]
+    fn refuses_streams_over_the_limit() {
+        let limit = Limit::new(Some(2));
+        let mut svc = StreamLimit::new(Respond, limit.clone());
+
+        let first = svc.call(()).wait().expect("first stream");
+        let second = svc.call(()).wait().expect("second stream");
+        let err = svc.call(()).wait().expect_err("third stream refused");
+        assert_eq!(err.reason(), Some(h2::Reason::REFUSED_STREAM));
+        assert!(limit.render().contains("streams_refused_total 1\n"));
+
+        // Once a response body is dropped, its stream no longer counts toward the limit.
+        drop(first);
+        let third = svc.call(()).wait().expect("stream after another ended");
+        drop((second, third));
+
+        // Each connection is limited independently.
+        let mut other = StreamLimit::new(Respond, limit);
+        other.call(()).wait().expect("stream on other connection");
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let mut svc = StreamLimit::new(Respond, Limit::default());
+        let rsps = (0..100)
+            .map(|_| svc.call(()).wait().expect("stream"))
+            .collect::<Vec<_>>();
+        assert_eq!(rsps.len(), 100);
+    }
+}
@@ -0,0 +1,137 @@
+//! Summarizes the requests proxied to each authority.
+//!
+//! Unlike the metrics reported to the controller, these counts are never reset; they
+//! describe the proxy's whole lifetime, for display on its status page.
+
+use std::sync::{Arc, Mutex};
+
+use ordermap::OrderMap;
+
+use ctx;
+use telemetry::event::Event;
+
+/// The maximum number of authorities tracked, so that memory use is bounded.
+const MAX_AUTHORITIES: usize = 1_000;
+
+/// A shared handle on per-authority request counts.
+#[derive(Clone, Debug, Default)]
+pub struct Authorities(Arc<Mutex<OrderMap<String, Counts>>>);
+
+/// Counts the requests to an authority.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub requests: u64,
+
+    /// Requests that failed, or whose responses failed or had a 5XX status.
+    pub failures: u64,
+}
+
+// ===== impl Authorities =====
+
+impl Authorities {
+    pub(super) fn record(&self, event: &Event) {
+        match *event {
+            Event::StreamRequestOpen(ref req) => self.with(req, |c| c.requests += 1),
+            Event::StreamRequestFail(ref req, _) => self.with(req, |c| c.failures += 1),
+            Event::StreamResponseFail(ref rsp, _) => {
+                self.with(&rsp.request, |c| c.failures += 1)
+            }
+            Event::StreamResponseEnd(ref rsp, _) if rsp.status.is_server_error() => {
+                self.with(&rsp.request, |c| c.failures += 1)
+            }
+            _ => {}
+        }
+    }
+
+    fn with<F: FnOnce(&mut Counts)>(&self, req: &ctx::http::Request, f: F) {
+        let authority = req.uri
+            .authority_part()
+            .map(|a| a.to_string())
+            .unwrap_or_else(|| "-".into());
+        if let Ok(mut counts) = self.0.lock() {
+            if counts.len() >= MAX_AUTHORITIES && !counts.contains_key(&authority) {
+                return;
+            }
+            f(counts.entry(authority).or_insert_with(Counts::default));
+        }
+    }
+
+    /// Returns up to `n` authorities with the most requests, in descending order.
+    pub fn top(&self, n: usize) -> Vec<(String, Counts)> {
+        let mut top = match self.0.lock() {
+            Ok(counts) => counts
+                .iter()
+                .map(|(a, c)| (a.clone(), *c))
+                .collect::<Vec<_>>(),
+            Err(_) => return Vec::new(),
+        };
+        top.sort_by(|a, b| b.1.requests.cmp(&a.1.requests).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(n);
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use h2;
+    use http;
+
+    use super::*;
+    use telemetry::event;
+
+    fn request(uri: &str) -> Arc<ctx::http::Request> {
+        let process = ctx::Process::new("test", "test", "test");
+        let proxy = ctx::Proxy::inbound(&process);
+        let addr: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        let server = ctx::transport::Server::new(&proxy, &addr, &addr, &None);
+        let client = ctx::transport::Client::new(&proxy, &addr);
+        let req = http::Request::builder().uri(uri).body(()).unwrap();
+        ctx::http::Request::new(&req, &server, &client, &Arc::new(Vec::new()), 0)
+    }
+
+    fn end(req: &Arc<ctx::http::Request>, status: u16) -> Event {
+        let rsp = http::Response::builder().status(status).body(()).unwrap();
+        Event::StreamResponseEnd(
+            ctx::http::Response::new(&rsp, req),
+            event::StreamResponseEnd {
+                grpc_status: None,
+                since_request_open: Duration::from_millis(1),
+                since_response_open: Duration::from_millis(1),
+                since_request_open_to_first_byte: None,
+                bytes_sent: 0,
+                frames_sent: 0,
+            },
+        )
+    }
+
+    #[test]
+    fn counts_requests_and_failures_by_authority() {
+        let authorities = Authorities::default();
+        let web = request("http://web.ns.svc.cluster.local/");
+        let api = request("http://api.ns.svc.cluster.local/");
+
+        for status in &[200, 503, 404] {
+            authorities.record(&Event::StreamRequestOpen(web.clone()));
+            authorities.record(&end(&web, *status));
+        }
+        authorities.record(&Event::StreamRequestOpen(api.clone()));
+        authorities.record(&Event::StreamRequestFail(
+            api.clone(),
+            event::StreamRequestFail {
+                since_request_open: Duration::from_millis(1),
+                error: h2::Reason::REFUSED_STREAM,
+            },
+        ));
+
+        let top = authorities.top(10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0, "web.ns.svc.cluster.local");
+        assert_eq!(top[0].1, Counts { requests: 3, failures: 1 });
+        assert_eq!(top[1].1, Counts { requests: 1, failures: 1 });
+
+        assert_eq!(authorities.top(1).len(), 1);
+    }
+}
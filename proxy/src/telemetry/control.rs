@@ -6,11 +6,16 @@ use futures::{Async, Future, Poll, Stream};
 use futures_mpsc_lossy::Receiver;
 use tokio_core::reactor::{Handle, Timeout};
 
+use super::authorities::Authorities;
 use super::event::Event;
+use super::health::HealthChecks;
 use super::metrics::Metrics;
+use super::slow::SlowRequests;
+use super::statsd::Statsd;
 use super::tap::Taps;
 use control::pb::telemetry::ReportRequest;
 use ctx;
+use reload::DurationSetting;
 
 /// A `Control` which has been configured but not initialized.
 #[derive(Debug)]
@@ -19,8 +24,20 @@ pub struct MakeControl {
     rx: Receiver<Event>,
 
     /// Limits the amount of time metrics may be buffered before being flushed to the
-    /// controller.
-    flush_interval: Duration,
+    /// controller. It may change while the proxy runs.
+    flush_interval: DurationSetting,
+
+    /// Logs requests that exceed a latency threshold, if configured.
+    slow_requests: Option<SlowRequests>,
+
+    /// Counts requests by authority over the proxy's lifetime.
+    authorities: Authorities,
+
+    /// Identifies health checks, which are excluded from other telemetry.
+    health_checks: HealthChecks,
+
+    /// Also receives metrics reports, if configured.
+    statsd: Option<Statsd>,
 
     process_ctx: Arc<ctx::Process>,
 }
@@ -46,9 +63,21 @@ pub struct Control {
     /// Holds the current state of tap observations, as configured by an external source.
     taps: Option<Arc<Mutex<Taps>>>,
 
+    /// Logs requests that exceed a latency threshold, if configured.
+    slow_requests: Option<SlowRequests>,
+
+    /// Counts requests by authority over the proxy's lifetime.
+    authorities: Authorities,
+
+    /// Identifies health checks, which are excluded from other telemetry.
+    health_checks: HealthChecks,
+
+    /// Also receives metrics reports, if configured.
+    statsd: Option<Statsd>,
+
     /// Limits the amount of time metrics may be buffered before being flushed to the
-    /// controller.
-    flush_interval: Duration,
+    /// controller. It may change while the proxy runs.
+    flush_interval: DurationSetting,
 
     /// Ensures liveliness of telemetry by waking the stream to produce reports when
     /// needed.  This timeout is reset as reports are returned.
@@ -64,18 +93,41 @@ impl MakeControl {
     /// - `rx`: the `Receiver` side of the channel on which events are sent.
     /// - `flush_interval`: the maximum amount of time between sending reports to the
     ///   controller.
+    /// - `slow_request_threshold`: if set, requests taking at least this long are logged.
+    /// - `authorities`: counts requests by authority.
+    /// - `health_checks`: identifies health checks, which are excluded from other
+    ///   telemetry.
+    /// - `statsd`: if set, also receives metrics reports.
     pub(super) fn new(
         rx: Receiver<Event>,
-        flush_interval: Duration,
+        flush_interval: DurationSetting,
+        slow_request_threshold: Option<Duration>,
+        authorities: Authorities,
+        health_checks: HealthChecks,
+        statsd: Option<Statsd>,
         process_ctx: &Arc<ctx::Process>,
     ) -> Self {
         Self {
             rx,
             flush_interval,
+            slow_requests: slow_request_threshold.map(SlowRequests::new),
+            authorities,
+            health_checks,
+            statsd,
             process_ctx: Arc::clone(process_ctx),
         }
     }
 
+    /// Returns a handle on the per-authority request counts.
+    pub fn authorities(&self) -> &Authorities {
+        &self.authorities
+    }
+
+    /// Returns a handle on the health check matchers and count.
+    pub fn health_checks(&self) -> &HealthChecks {
+        &self.health_checks
+    }
+
     /// Bind a `Control` with a reactor core.
     ///
     /// # Arguments
@@ -86,14 +138,18 @@ impl MakeControl {
     /// - `Ok(())` if the timeout was successfully created.
     /// - `Err(io::Error)` if the timeout could not be created.
     pub fn make_control(self, taps: &Arc<Mutex<Taps>>, handle: &Handle) -> io::Result<Control> {
-        trace!("telemetry control flush_interval={:?}", self.flush_interval);
+        trace!("telemetry control flush_interval={:?}", self.flush_interval.get());
 
-        let flush_timeout = Timeout::new(self.flush_interval, handle)?;
+        let flush_timeout = Timeout::new(self.flush_interval.get(), handle)?;
 
         Ok(Control {
             metrics: Some(Metrics::new(self.process_ctx)),
             rx: Some(self.rx),
             taps: Some(taps.clone()),
+            slow_requests: self.slow_requests,
+            authorities: self.authorities,
+            health_checks: self.health_checks,
+            statsd: self.statsd,
             flush_interval: self.flush_interval,
             flush_timeout,
         })
@@ -126,20 +182,25 @@ impl Control {
             None
         };
 
-        metrics.map(Self::generate_report)
+        let statsd = self.statsd.as_ref();
+        metrics.map(|m| Self::generate_report(m, statsd))
     }
 
-    fn generate_report(m: &mut Metrics) -> ReportRequest {
+    fn generate_report(m: &mut Metrics, statsd: Option<&Statsd>) -> ReportRequest {
         let mut r = m.generate_report();
         r.proxy = 0; // 0 = Inbound, 1 = Outbound
+        if let Some(statsd) = statsd {
+            statsd.export(&r);
+        }
         r
     }
 
     /// Reset the flush timeout.
     fn reset_timeout(&mut self) {
-        trace!("flushing in {:?}", self.flush_interval);
+        let flush_interval = self.flush_interval.get();
+        trace!("flushing in {:?}", flush_interval);
         self.flush_timeout
-            .reset(Instant::now() + self.flush_interval);
+            .reset(Instant::now() + flush_interval);
     }
 
     fn recv(&mut self) -> Async<Option<Event>> {
@@ -169,16 +230,25 @@ impl Stream for Control {
             let report = match self.recv() {
                 Async::NotReady => break,
                 Async::Ready(Some(ev)) => {
-                    if let Some(taps) = self.taps.as_mut() {
-                        if let Ok(mut t) = taps.lock() {
-                            t.inspect(&ev);
+                    // Health checks are only counted.
+                    if !self.health_checks.inspect(&ev) {
+                        if let Some(taps) = self.taps.as_mut() {
+                            if let Ok(mut t) = taps.lock() {
+                                t.inspect(&ev);
+                            }
                         }
-                    }
 
-                    // XXX Only inbound events are currently aggregated.
-                    if ev.proxy().is_inbound() {
-                        if let Some(metrics) = self.metrics.as_mut() {
-                            metrics.record_event(&ev);
+                        if let Some(ref slow) = self.slow_requests {
+                            slow.inspect(&ev);
+                        }
+
+                        self.authorities.record(&ev);
+
+                        // XXX Only inbound events are currently aggregated.
+                        if ev.proxy().is_inbound() {
+                            if let Some(metrics) = self.metrics.as_mut() {
+                                metrics.record_event(&ev);
+                            }
                         }
                     }
 
@@ -186,9 +256,10 @@ impl Stream for Control {
                 }
                 Async::Ready(None) => {
                     warn!("events finished");
+                    let statsd = self.statsd.as_ref();
                     let report = self.metrics
                         .take()
-                        .map(|mut m| Self::generate_report(&mut m));
+                        .map(|mut m| Self::generate_report(&mut m, statsd));
                     if report.is_none() {
                         return Ok(Async::Ready(None));
                     }
@@ -228,10 +299,14 @@ impl fmt::Debug for Control {
             .field("metrics", &self.metrics)
             .field("rx", &self.rx)
             .field("taps", &self.taps)
-            .field("flush_interval", &self.flush_interval)
+            .field("slow_requests", &self.slow_requests)
+            .field("authorities", &self.authorities)
+            .field("health_checks", &self.health_checks)
+            .field("statsd", &self.statsd)
+            .field("flush_interval", &self.flush_interval.get())
             .field(
                 "flush_timeout",
-                &format!("Timeout({:?})", &self.flush_interval),
+                &format!("Timeout({:?})", self.flush_interval.get()),
             )
             .finish()
     }
@@ -9,8 +9,10 @@ use ctx;
 pub enum Event {
     TransportOpen(Arc<ctx::transport::Ctx>),
     TransportClose(Arc<ctx::transport::Ctx>, TransportClose),
+    ConnectionFail(Arc<ctx::transport::Ctx>, ConnectionFail),
 
     StreamRequestOpen(Arc<ctx::http::Request>),
+    StreamRequestProgress(Arc<ctx::http::Request>, StreamRequestProgress),
     StreamRequestFail(Arc<ctx::http::Request>, StreamRequestFail),
 
     StreamResponseOpen(Arc<ctx::http::Response>, StreamResponseOpen),
@@ -31,6 +33,25 @@ pub struct TransportClose {
     //pub tx_bytes: usize,
 }
 
+/// Describes an HTTP/2 connection to an endpoint that failed, e.g. because the endpoint
+/// sent a GOAWAY frame.
+#[derive(Clone, Debug)]
+pub struct ConnectionFail {
+    /// The error code of the GOAWAY frame, or of the connection error, that ended the
+    /// connection. Streams in flight on the connection fail with it too.
+    pub error: h2::Reason,
+}
+
+/// Describes a request body that's still being sent.
+#[derive(Clone, Debug)]
+pub struct StreamRequestProgress {
+    pub since_request_open: Duration,
+    pub bytes_sent: u64,
+    /// The rate at which bytes were sent since the previous progress event, or since the
+    /// request was opened.
+    pub bytes_per_second: u64,
+}
+
 #[derive(Clone, Debug)]
 pub struct StreamRequestFail {
     pub since_request_open: Duration,
@@ -46,6 +67,9 @@ pub struct StreamResponseOpen {
 pub struct StreamResponseFail {
     pub since_request_open: Duration,
     pub since_response_open: Duration,
+    /// The time from the request's open to the first byte of the response body, if any
+    /// was received.
+    pub since_request_open_to_first_byte: Option<Duration>,
     pub error: h2::Reason,
     pub bytes_sent: u64,
     pub frames_sent: u32,
@@ -56,6 +80,9 @@ pub struct StreamResponseEnd {
     pub grpc_status: Option<u32>,
     pub since_request_open: Duration,
     pub since_response_open: Duration,
+    /// The time from the request's open to the first byte of the response body, if the
+    /// response had one.
+    pub since_request_open_to_first_byte: Option<Duration>,
     pub bytes_sent: u64,
     pub frames_sent: u32,
 }
@@ -66,6 +93,7 @@ impl Event {
     pub fn is_http(&self) -> bool {
         match *self {
             Event::StreamRequestOpen(_) |
+            Event::StreamRequestProgress(_, _) |
             Event::StreamRequestFail(_, _) |
             Event::StreamResponseOpen(_, _) |
             Event::StreamResponseFail(_, _) |
@@ -76,17 +104,36 @@ impl Event {
 
     pub fn is_transport(&self) -> bool {
         match *self {
-            Event::TransportOpen(_) | Event::TransportClose(_, _) => true,
+            Event::TransportOpen(_) |
+            Event::TransportClose(_, _) |
+            Event::ConnectionFail(_, _) => true,
             _ => false,
         }
     }
 
+    /// Returns the request that a stream event describes.
+    pub fn request(&self) -> Option<&Arc<ctx::http::Request>> {
+        match *self {
+            Event::StreamRequestOpen(ref req) |
+            Event::StreamRequestProgress(ref req, _) |
+            Event::StreamRequestFail(ref req, _) => Some(req),
+            Event::StreamResponseOpen(ref rsp, _) |
+            Event::StreamResponseFail(ref rsp, _) |
+            Event::StreamResponseEnd(ref rsp, _) => Some(&rsp.request),
+            Event::TransportOpen(_) |
+            Event::TransportClose(_, _) |
+            Event::ConnectionFail(_, _) => None,
+        }
+    }
+
     pub fn proxy(&self) -> &Arc<ctx::Proxy> {
         match *self {
-            Event::TransportOpen(ref ctx) | Event::TransportClose(ref ctx, _) => ctx.proxy(),
-            Event::StreamRequestOpen(ref req) | Event::StreamRequestFail(ref req, _) => {
-                &req.server.proxy
-            }
+            Event::TransportOpen(ref ctx) |
+            Event::TransportClose(ref ctx, _) |
+            Event::ConnectionFail(ref ctx, _) => ctx.proxy(),
+            Event::StreamRequestOpen(ref req) |
+            Event::StreamRequestProgress(ref req, _) |
+            Event::StreamRequestFail(ref req, _) => &req.server.proxy,
             Event::StreamResponseOpen(ref rsp, _) |
             Event::StreamResponseFail(ref rsp, _) |
             Event::StreamResponseEnd(ref rsp, _) => &rsp.request.server.proxy,
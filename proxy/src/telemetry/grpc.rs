@@ -0,0 +1,303 @@
+//! Metrics describing the messages carried by gRPC streams.
+//!
+//! Request-level metrics say little about the health of long-lived streaming RPCs, so
+//! the messages in each direction of a gRPC stream are counted and their sizes
+//! recorded. Metrics are labeled by route (the request's authority and path, i.e. its
+//! gRPC method) and rendered in the Prometheus text format.
+
+use std::cmp;
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use http;
+use ordermap::OrderMap;
+
+/// Upper bounds, in bytes, of the message size histogram's buckets.
+const SIZE_BUCKETS: [u64; 9] = [
+    64,
+    256,
+    1_024,
+    4_096,
+    16_384,
+    65_536,
+    262_144,
+    1_048_576,
+    4_194_304,
+];
+
+/// Upper bounds of the messages-per-stream histogram's buckets.
+const STREAM_BUCKETS: [u64; 8] = [0, 1, 2, 5, 10, 100, 1_000, 10_000];
+
+/// The length of the prefix of each gRPC message: a compression flag and a 4-byte length.
+const PREFIX_LEN: usize = 5;
+
+/// A shared handle on gRPC message metrics.
+#[derive(Clone, Debug, Default)]
+pub struct Messages(Arc<Mutex<OrderMap<Key, Stats>>>);
+
+/// Counts the messages in one direction of a single gRPC stream.
+///
+/// The number of messages in the stream is recorded when the `Stream` is dropped, so
+/// that streams that fail are also recorded.
+#[derive(Debug)]
+pub struct Stream {
+    messages: Messages,
+    key: Key,
+    /// The prefix of the message currently being read.
+    prefix: [u8; PREFIX_LEN],
+    prefix_len: usize,
+    /// The number of bytes remaining in the message currently being read.
+    remaining: u64,
+    count: u64,
+}
+
+/// Identifies which messages of which route are described.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    authority: String,
+    path: String,
+    direction: Direction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    messages: u64,
+    /// Counts of message sizes by bucket; the last bucket is unbounded.
+    size_buckets: [u64; 10],
+    size_sum: u64,
+    /// Counts of messages per stream by bucket; the last bucket is unbounded.
+    stream_buckets: [u64; 9],
+    stream_sum: u64,
+}
+
+// ===== impl Messages =====
+
+impl Messages {
+    /// Returns a `Stream` counting messages in one direction of `req`'s stream, if it is
+    /// a gRPC request.
+    pub fn stream<B>(&self, req: &http::Request<B>, direction: Direction) -> Option<Stream> {
+        let is_grpc = req.headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|ct| ct.starts_with("application/grpc"))
+            .unwrap_or(false);
+        if !is_grpc {
+            return None;
+        }
+
+        let key = Key {
+            authority: req.uri()
+                .authority_part()
+                .map(|a| a.to_string())
+                .unwrap_or_else(String::new),
+            path: req.uri().path().to_owned(),
+            direction,
+        };
+        Some(Stream {
+            messages: self.clone(),
+            key,
+            prefix: [0; PREFIX_LEN],
+            prefix_len: 0,
+            remaining: 0,
+            count: 0,
+        })
+    }
+
+    fn with<F: FnOnce(&mut Stats)>(&self, key: &Key, f: F) {
+        if let Ok(mut stats) = self.0.lock() {
+            f(stats.entry(key.clone()).or_insert_with(Stats::default));
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let stats = match self.0.lock() {
+            Ok(stats) => stats,
+            Err(_) => return String::new(),
+        };
+
+        let mut out = String::new();
+        let name = "grpc_messages_total";
+        let _ = writeln!(out, "# HELP {} gRPC messages carried by streams.", name);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (key, s) in stats.iter() {
+            let _ = writeln!(out, "{}{{{}}} {}", name, key, s.messages);
+        }
+
+        render_histogram(
+            &mut out,
+            "grpc_message_size_bytes",
+            "Sizes of gRPC messages, excluding their prefixes.",
+            &SIZE_BUCKETS,
+            stats.iter().map(|(k, s)| (k, &s.size_buckets[..], s.size_sum)),
+        );
+        render_histogram(
+            &mut out,
+            "grpc_stream_messages",
+            "Numbers of gRPC messages carried by each stream.",
+            &STREAM_BUCKETS,
+            stats.iter().map(|(k, s)| (k, &s.stream_buckets[..], s.stream_sum)),
+        );
+
+        out
+    }
+}
+
+fn render_histogram<'a, I>(out: &mut String, name: &str, help: &str, bounds: &[u64], series: I)
+where
+    I: Iterator<Item = (&'a Key, &'a [u64], u64)>,
+{
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for (key, buckets, sum) in series {
+        let mut count = 0;
+        for (i, n) in buckets.iter().enumerate() {
+            count += n;
+            let le = bounds
+                .get(i)
+                .map(|le| le.to_string())
+                .unwrap_or_else(|| "+Inf".into());
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, key, le, count);
+        }
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, key, sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, key, count);
+    }
+}
+
+fn bucket(bounds: &[u64], value: u64) -> usize {
+    bounds
+        .iter()
+        .position(|&le| value <= le)
+        .unwrap_or(bounds.len())
+}
+
+// ===== impl Stream =====
+
+impl Stream {
+    /// Reads the next chunk of the stream's data, recording each message it begins.
+    pub fn observe(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let n = cmp::min(self.remaining, data.len() as u64);
+                self.remaining -= n;
+                data = &data[n as usize..];
+                continue;
+            }
+
+            let n = cmp::min(PREFIX_LEN - self.prefix_len, data.len());
+            self.prefix[self.prefix_len..self.prefix_len + n].copy_from_slice(&data[..n]);
+            self.prefix_len += n;
+            data = &data[n..];
+            if self.prefix_len < PREFIX_LEN {
+                return;
+            }
+
+            self.prefix_len = 0;
+            let len = self.prefix[1..]
+                .iter()
+                .fold(0u64, |len, &b| (len << 8) | u64::from(b));
+            self.remaining = len;
+            self.count += 1;
+            self.messages.with(&self.key, |s| {
+                s.messages += 1;
+                s.size_buckets[bucket(&SIZE_BUCKETS, len)] += 1;
+                s.size_sum += len;
+            });
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let count = self.count;
+        self.messages.with(&self.key, |s| {
+            s.stream_buckets[bucket(&STREAM_BUCKETS, count)] += 1;
+            s.stream_sum += count;
+        });
+    }
+}
+
+// ===== impl Key =====
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "authority=\"{}\",path=\"{}\",direction=\"{}\"",
+            self.authority.escape_default(),
+            self.path.escape_default(),
+            match self.direction {
+                Direction::Request => "request",
+                Direction::Response => "response",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grpc_stream(messages: &Messages) -> Stream {
+        let req = http::Request::builder()
+            .uri("http://web.ns.svc.cluster.local:8080/helloworld.Greeter/SayHello")
+            .header("content-type", "application/grpc+proto")
+            .body(())
+            .unwrap();
+        messages.stream(&req, Direction::Response).expect("grpc request")
+    }
+
+    #[test]
+    fn ignores_other_requests() {
+        let req = http::Request::builder()
+            .uri("http://web.example.com/")
+            .header("content-type", "application/json")
+            .body(())
+            .unwrap();
+        assert!(Messages::default().stream(&req, Direction::Request).is_none());
+    }
+
+    #[test]
+    fn counts_messages_split_across_chunks() {
+        let messages = Messages::default();
+        {
+            let mut stream = grpc_stream(&messages);
+            // An empty message, then a 3-byte message split through its prefix, then a
+            // 300-byte message split through its payload.
+            stream.observe(&[0, 0, 0, 0, 0, 0, 0, 0]);
+            stream.observe(&[0, 3, 1, 2, 3, 0, 0, 0, 1, 44]);
+            stream.observe(&[0; 150]);
+            stream.observe(&[0; 150]);
+            assert_eq!(stream.count, 3);
+            assert_eq!(stream.remaining, 0);
+        }
+
+        let out = messages.render();
+        let labels = "authority=\"web.ns.svc.cluster.local:8080\",\
+                      path=\"/helloworld.Greeter/SayHello\",direction=\"response\"";
+        assert!(out.contains(&format!("grpc_messages_total{{{}}} 3\n", labels)));
+        assert!(out.contains(&format!(
+            "grpc_message_size_bytes_bucket{{{},le=\"64\"}} 2\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "grpc_message_size_bytes_bucket{{{},le=\"1024\"}} 3\n",
+            labels
+        )));
+        assert!(out.contains(&format!("grpc_message_size_bytes_sum{{{}}} 303\n", labels)));
+        assert!(out.contains(&format!(
+            "grpc_stream_messages_bucket{{{},le=\"2\"}} 0\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "grpc_stream_messages_bucket{{{},le=\"5\"}} 1\n",
+            labels
+        )));
+    }
+}
@@ -0,0 +1,129 @@
+//! Identifies health checks, e.g. kubelet probes and load balancer health checks.
+//!
+//! Health checks are frequent and almost always succeed, so they'd mask the error rates
+//! of real traffic. Their events are counted, but are otherwise excluded from metrics,
+//! taps, and the other telemetry derived from events.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use http;
+
+use config::Network;
+use ctx;
+use telemetry::event::Event;
+
+/// Matches health check requests by path, `user-agent`, or source network.
+#[derive(Clone, Debug, Default)]
+pub struct HealthChecks {
+    paths: Arc<Vec<String>>,
+    user_agents: Arc<Vec<String>>,
+    sources: Arc<Vec<Network>>,
+
+    /// The number of health check requests that have been opened.
+    count: Arc<AtomicUsize>,
+}
+
+// ===== impl HealthChecks =====
+
+impl HealthChecks {
+    /// Creates a `HealthChecks` that matches requests for any of `paths`, with a
+    /// `user-agent` starting with any of `user_agents`, or from any of `sources`.
+    pub fn new<P, U>(paths: P, user_agents: U, sources: Vec<Network>) -> Self
+    where
+        P: IntoIterator<Item = String>,
+        U: IntoIterator<Item = String>,
+    {
+        HealthChecks {
+            paths: Arc::new(paths.into_iter().collect()),
+            user_agents: Arc::new(user_agents.into_iter().collect()),
+            sources: Arc::new(sources),
+            count: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns true if `event` describes a health check, counting the health check
+    /// when it's opened.
+    pub(super) fn inspect(&self, event: &Event) -> bool {
+        let is_health_check = match event.request() {
+            Some(req) => self.matches(req),
+            None => false,
+        };
+        if let Event::StreamRequestOpen(_) = *event {
+            if is_health_check {
+                self.count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        is_health_check
+    }
+
+    fn matches(&self, req: &ctx::http::Request) -> bool {
+        if self.paths.iter().any(|p| p == req.uri.path()) {
+            return true;
+        }
+
+        let user_agent = req.header(&http::header::USER_AGENT).and_then(|ua| ua.to_str().ok());
+        if let Some(ua) = user_agent {
+            if self.user_agents.iter().any(|prefix| ua.starts_with(prefix.as_str())) {
+                return true;
+            }
+        }
+
+        let source = req.server.remote.ip();
+        self.sources.iter().any(|net| net.contains(&source))
+    }
+
+    /// Returns the number of health check requests that have been opened.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use http;
+
+    use super::*;
+
+    fn request(path: &str, user_agent: &str, source: &str) -> Arc<ctx::http::Request> {
+        let process = ctx::Process::new("test", "test", "test");
+        let proxy = ctx::Proxy::inbound(&process);
+        let local: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        let remote: SocketAddr = source.parse().unwrap();
+        let server = ctx::transport::Server::new(&proxy, &local, &remote, &None);
+        let client = ctx::transport::Client::new(&proxy, &local);
+        let req = http::Request::builder()
+            .uri(path)
+            .header(http::header::USER_AGENT, user_agent)
+            .body(())
+            .unwrap();
+        let allowed = Arc::new(vec![http::header::USER_AGENT]);
+        ctx::http::Request::new(&req, &server, &client, &allowed, 0)
+    }
+
+    #[test]
+    fn matches_paths_user_agents_and_sources() {
+        let checks = HealthChecks::new(
+            vec!["/healthz".to_owned()],
+            vec!["kube-probe/".to_owned()],
+            vec!["10.1.0.0/16".parse().unwrap()],
+        );
+
+        let probes = vec![
+            request("/healthz", "curl/7.58", "10.0.0.2:1234"),
+            request("/ready", "kube-probe/1.9", "10.0.0.2:1234"),
+            request("/", "curl/7.58", "10.1.2.3:1234"),
+        ];
+        for req in &probes {
+            assert!(checks.inspect(&Event::StreamRequestOpen(req.clone())));
+        }
+        assert_eq!(checks.count(), 3);
+
+        let app = request("/healthz/deep", "curl/7.58", "10.0.0.2:1234");
+        assert!(!checks.inspect(&Event::StreamRequestOpen(app)));
+        assert_eq!(checks.count(), 3);
+    }
+}
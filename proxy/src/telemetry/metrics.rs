@@ -10,6 +10,7 @@ use control::pb::common::{HttpMethod, TcpAddress};
 use control::pb::proxy::telemetry::{
     eos_ctx,
     ClientTransport,
+    ConnectionError,
     EosCtx,
     EosScope,
     Latency as PbLatency,
@@ -63,6 +64,12 @@ struct ResponseStats {
     /// Observed latencies are mapped to a count of the times that
     /// latency value was seen.
     latencies: Latencies,
+
+    /// Latencies to the first byte of response bodies.
+    first_byte_latencies: Latencies,
+
+    /// Latencies to the end of response streams.
+    end_latencies: Latencies,
 }
 
 #[derive(Debug)]
@@ -83,6 +90,8 @@ enum End {
 struct TransportStats {
     connects: u32,
     disconnects: Vec<TransportSummary>,
+    /// Counts HTTP/2 connections that failed, by error code.
+    errors: OrderMap<u32, u32>,
 }
 
 impl RequestKey {
@@ -119,10 +128,18 @@ impl Metrics {
                         bytes_sent: 0,
                     });
             }
+            Event::ConnectionFail(ref transport, ref fail) => {
+                *self.transport(transport)
+                    .errors
+                    .entry(fail.error.into())
+                    .or_insert(0) += 1;
+            }
 
             Event::StreamRequestOpen(ref req) => {
                 self.request(req).count += 1;
             }
+            // Progress is only reported to taps.
+            Event::StreamRequestProgress(_, _) => {}
             Event::StreamRequestFail(ref req, ref fail) => {
                 let stats = self.request(req)
                     .responses
@@ -151,6 +168,11 @@ impl Metrics {
                 self.response(res).latencies.add(open.since_request_open);
             }
             Event::StreamResponseFail(ref res, ref fail) => {
+                self.response_latencies(
+                    res,
+                    fail.since_request_open_to_first_byte,
+                    fail.since_request_open,
+                );
                 self.response_end(res, End::Reset(fail.error.into()))
                     .push(EndStats {
                         duration_ms: dur_to_ms(fail.since_response_open),
@@ -159,6 +181,11 @@ impl Metrics {
                     });
             }
             Event::StreamResponseEnd(ref res, ref end) => {
+                self.response_latencies(
+                    res,
+                    end.since_request_open_to_first_byte,
+                    end.since_request_open,
+                );
                 let e = end.grpc_status.map(End::Grpc).unwrap_or(End::Other);
                 self.response_end(res, e).push(EndStats {
                     duration_ms: dur_to_ms(end.since_response_open),
@@ -182,6 +209,19 @@ impl Metrics {
             .or_insert_with(Default::default)
     }
 
+    fn response_latencies<'a>(
+        &mut self,
+        res: &'a Arc<ctx::http::Response>,
+        first_byte: Option<Duration>,
+        end: Duration,
+    ) {
+        let stats = self.response(res);
+        if let Some(first_byte) = first_byte {
+            stats.first_byte_latencies.add(first_byte);
+        }
+        stats.end_latencies.add(end);
+    }
+
     fn response_end<'a>(
         &mut self,
         res: &'a Arc<ctx::http::Response>,
@@ -227,6 +267,14 @@ impl Metrics {
                 }),
                 connects: stats.connects,
                 disconnects: stats.disconnects,
+                connection_errors: stats
+                    .errors
+                    .into_iter()
+                    .map(|(error_code, count)| ConnectionError {
+                        error_code,
+                        count,
+                    })
+                    .collect(),
             });
         }
 
@@ -270,6 +318,8 @@ impl Metrics {
                     }),
                     ends: ends,
                     response_latencies: res_stats.latencies.into(),
+                    first_byte_latencies: res_stats.first_byte_latencies.into(),
+                    end_latencies: res_stats.end_latencies.into(),
                 });
             }
 
@@ -373,7 +423,37 @@ fn dur_to_ms(dur: Duration) -> u64 {
 
 #[cfg(test)]
 mod tests {
+    use h2;
+
     use super::*;
+    use telemetry::event::ConnectionFail;
+
+    #[test]
+    fn counts_connection_errors_by_error_code() {
+        let process = ctx::Process::new("test", "test", "test");
+        let proxy = ctx::Proxy::outbound(&process);
+        let addr: net::SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        let client = ctx::transport::Client::new(&proxy, &addr);
+        let transport = Arc::new(ctx::transport::Ctx::Client(client));
+
+        let mut metrics = Metrics::new(process);
+        for &error in &[h2::Reason::NO_ERROR, h2::Reason::PROTOCOL_ERROR, h2::Reason::NO_ERROR] {
+            metrics.record_event(&Event::ConnectionFail(
+                transport.clone(),
+                ConnectionFail {
+                    error,
+                },
+            ));
+        }
+
+        let report = metrics.generate_report();
+        let errors = report.client_transports[0]
+            .connection_errors
+            .iter()
+            .map(|e| (e.error_code, e.count))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec![(0, 2), (1, 1)]);
+    }
 
     #[test]
     fn latencies_incr() {
@@ -4,14 +4,22 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use futures_mpsc_lossy;
+use http;
 
 use ctx;
+use reload;
 
+pub mod authorities;
 mod control;
 mod event;
+pub mod grpc;
+pub mod health;
 mod metrics;
+mod slow;
 pub mod sensor;
+pub mod statsd;
 pub mod tap;
+pub mod topology;
 
 pub use self::control::{Control, MakeControl};
 pub use self::event::Event;
@@ -28,17 +36,38 @@ pub use self::sensor::Sensors;
 /// # Arguments
 /// - `capacity`: the number of events to aggregate.
 /// - `flush_interval`: the length of time after which a metrics report should be sent,
-///   regardless of how many events have been aggregated.
+///   regardless of how many events have been aggregated. It may change while the proxy
+///   runs.
+/// - `slow_request_threshold`: if set, requests that take at least this long are logged
+///   in detail.
+/// - `health_checks`: identifies health checks, which are excluded from other telemetry.
+/// - `statsd`: if set, metrics reports are also sent to a statsd server.
+/// - `allowed_headers`: the request headers whose values events may carry.
+/// - `progress_interval`: if set, request bodies report their progress at most this
+///   often.
 ///
 /// [`Sensors`]: struct.Sensors.html
 /// [`Control`]: struct.Control.html
 pub fn new(
     process: &Arc<ctx::Process>,
     capacity: usize,
-    flush_interval: Duration,
+    flush_interval: reload::DurationSetting,
+    slow_request_threshold: Option<Duration>,
+    health_checks: health::HealthChecks,
+    statsd: Option<statsd::Statsd>,
+    allowed_headers: Vec<http::header::HeaderName>,
+    progress_interval: Option<Duration>,
 ) -> (Sensors, MakeControl) {
     let (tx, rx) = futures_mpsc_lossy::channel(capacity);
-    let s = Sensors::new(tx);
-    let c = MakeControl::new(rx, flush_interval, process);
+    let s = Sensors::new(tx, allowed_headers, progress_interval);
+    let c = MakeControl::new(
+        rx,
+        flush_interval,
+        slow_request_threshold,
+        authorities::Authorities::default(),
+        health_checks,
+        statsd,
+        process,
+    );
     (s, c)
 }
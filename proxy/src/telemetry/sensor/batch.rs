@@ -0,0 +1,147 @@
+//! Buffers events on the thread on which they're produced.
+//!
+//! Sending an event to the telemetry thread may wake that thread, which is comparatively
+//! expensive to do while a stream is being completed. Instead, sensors append events to
+//! a thread-local buffer, which is drained onto the channel periodically by a `Flush`
+//! task running on the same thread, or as soon as it holds a full batch.
+//!
+//! All sensors on a thread are expected to send events on the same channel.
+
+use std::cell::RefCell;
+use std::io;
+use std::mem;
+use std::time::Duration;
+
+use futures::{Async, Future, Poll, Stream};
+use futures_mpsc_lossy::Sender;
+use tokio_core::reactor::{Handle, Interval};
+
+use telemetry::event::Event;
+
+/// The number of buffered events that causes them to be sent immediately.
+const BATCH_SIZE: usize = 512;
+
+/// The maximum amount of time that an event is buffered.
+const FLUSH_INTERVAL_MS: u64 = 10;
+
+thread_local! {
+    static PENDING: RefCell<Vec<Event>> = RefCell::new(Vec::with_capacity(BATCH_SIZE));
+}
+
+/// Periodically sends the events buffered on its thread.
+pub struct Flush {
+    tx: Sender<Event>,
+    interval: Interval,
+}
+
+/// Buffers `ev` to be sent on `tx`.
+pub fn push(tx: &Sender<Event>, ev: Event) {
+    let full = PENDING.with(|p| {
+        let mut p = p.borrow_mut();
+        p.push(ev);
+        p.len() >= BATCH_SIZE
+    });
+    if full {
+        flush(tx);
+    }
+}
+
+/// Sends all events buffered on the current thread on `tx`.
+fn flush(tx: &Sender<Event>) {
+    let events = PENDING.with(|p| {
+        mem::replace(&mut *p.borrow_mut(), Vec::with_capacity(BATCH_SIZE))
+    });
+
+    let mut dropped = 0;
+    for ev in events {
+        if tx.lossy_send(ev).is_err() {
+            dropped += 1;
+        }
+    }
+    if dropped > 0 {
+        debug!("dropped {} events", dropped);
+    }
+}
+
+// ===== impl Flush =====
+
+impl Flush {
+    /// Creates a `Flush` that must be spawned on the thread on which events are sent.
+    pub fn new(tx: Sender<Event>, executor: &Handle) -> io::Result<Self> {
+        let interval = Interval::new(Duration::from_millis(FLUSH_INTERVAL_MS), executor)?;
+        Ok(Flush {
+            tx,
+            interval,
+        })
+    }
+}
+
+impl Future for Flush {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.interval.poll() {
+                Ok(Async::Ready(Some(()))) => flush(&self.tx),
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => {
+                    error!("telemetry flush interval failed: {}", e);
+                    return Err(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use futures_mpsc_lossy;
+
+    use super::*;
+    use ctx;
+
+    fn event() -> Event {
+        let process = ctx::Process::new("test", "test", "test");
+        let proxy = ctx::Proxy::outbound(&process);
+        let addr: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        let client = ctx::transport::Client::new(&proxy, &addr);
+        Event::TransportOpen(Arc::new(ctx::transport::Ctx::Client(client)))
+    }
+
+    fn pending() -> usize {
+        PENDING.with(|p| p.borrow().len())
+    }
+
+    #[test]
+    fn buffers_events_until_flushed() {
+        let (tx, rx) = futures_mpsc_lossy::channel(BATCH_SIZE * 2);
+
+        push(&tx, event());
+        push(&tx, event());
+        assert_eq!(pending(), 2);
+
+        flush(&tx);
+        assert_eq!(pending(), 0);
+
+        drop(tx);
+        assert_eq!(rx.collect().wait().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn sends_full_batches_immediately() {
+        let (tx, rx) = futures_mpsc_lossy::channel(BATCH_SIZE * 2);
+
+        for _ in 0..BATCH_SIZE + 1 {
+            push(&tx, event());
+        }
+        assert_eq!(pending(), 1);
+
+        drop(tx);
+        assert_eq!(rx.collect().wait().unwrap().len(), BATCH_SIZE);
+    }
+}
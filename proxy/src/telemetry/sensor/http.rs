@@ -11,6 +11,7 @@ use tower_h2::{client, Body};
 
 use ctx;
 use telemetry::event::{self, Event};
+use telemetry::grpc;
 
 const GRPC_STATUS: &str = "grpc-status";
 
@@ -18,6 +19,9 @@ pub struct NewHttp<N, A, B> {
     next_id: Arc<AtomicUsize>,
     new_service: N,
     handle: super::Handle,
+    grpc: grpc::Messages,
+    allowed_headers: ctx::http::HeaderAllowlist,
+    progress_interval: Option<Duration>,
     client_ctx: Arc<ctx::transport::Client>,
     _p: PhantomData<(A, B)>,
 }
@@ -26,6 +30,9 @@ pub struct Init<F, A, B> {
     next_id: Arc<AtomicUsize>,
     future: F,
     handle: super::Handle,
+    grpc: grpc::Messages,
+    allowed_headers: ctx::http::HeaderAllowlist,
+    progress_interval: Option<Duration>,
     client_ctx: Arc<ctx::transport::Client>,
     _p: PhantomData<(A, B)>,
 }
@@ -36,6 +43,9 @@ pub struct Http<S, A, B> {
     next_id: Arc<AtomicUsize>,
     service: S,
     handle: super::Handle,
+    grpc: grpc::Messages,
+    allowed_headers: ctx::http::HeaderAllowlist,
+    progress_interval: Option<Duration>,
     client_ctx: Arc<ctx::transport::Client>,
     _p: PhantomData<(A, B)>,
 }
@@ -44,6 +54,8 @@ pub struct Http<S, A, B> {
 pub struct Respond<F, B> {
     future: F,
     inner: Option<RespondInner>,
+    /// Counts the messages of a gRPC response.
+    grpc: Option<grpc::Stream>,
     _p: PhantomData<(B)>,
 }
 
@@ -54,10 +66,35 @@ struct RespondInner {
     request_open: Instant,
 }
 
+/// Wraps a request body to count the messages of gRPC requests and, if configured, to
+/// report the progress of large request bodies.
+#[derive(Default, Debug)]
+pub struct RequestBody<B> {
+    body: B,
+    grpc: Option<grpc::Stream>,
+    progress: Option<Progress>,
+}
+
+/// Reports how much of a request body has been sent, at most once per `interval`.
+///
+/// Progress is checked as data is sent, so a stalled body isn't reported until it
+/// resumes.
+#[derive(Debug)]
+struct Progress {
+    handle: super::Handle,
+    ctx: Arc<ctx::http::Request>,
+    interval: Duration,
+    request_open: Instant,
+    bytes_sent: u64,
+    reported_at: Instant,
+    reported_bytes: u64,
+}
+
 #[derive(Default, Debug)]
 pub struct ResponseBody<B> {
     body: B,
     inner: Option<ResponseBodyInner>,
+    grpc: Option<grpc::Stream>,
     _p: PhantomData<(B)>,
 }
 
@@ -69,6 +106,8 @@ struct ResponseBodyInner {
     frames_sent: u32,
     request_open: Instant,
     response_open: Instant,
+    /// Set when the first byte of the body is received.
+    first_byte: Option<Duration>,
 }
 
 // === NewHttp ===
@@ -77,19 +116,29 @@ impl<N, A, B> NewHttp<N, A, B>
 where
     A: Body + 'static,
     B: Body + 'static,
-    N: NewService<Request = http::Request<A>, Response = http::Response<B>, Error = client::Error>
+    N: NewService<
+        Request = http::Request<RequestBody<A>>,
+        Response = http::Response<B>,
+        Error = client::Error,
+    >
         + 'static,
 {
     pub(super) fn new(
         next_id: Arc<AtomicUsize>,
         new_service: N,
         handle: &super::Handle,
+        grpc: &grpc::Messages,
+        allowed_headers: &ctx::http::HeaderAllowlist,
+        progress_interval: Option<Duration>,
         client_ctx: &Arc<ctx::transport::Client>,
     ) -> Self {
         Self {
             next_id,
             new_service,
             handle: handle.clone(),
+            grpc: grpc.clone(),
+            allowed_headers: allowed_headers.clone(),
+            progress_interval,
             client_ctx: Arc::clone(client_ctx),
             _p: PhantomData,
         }
@@ -100,10 +149,14 @@ impl<N, A, B> NewService for NewHttp<N, A, B>
 where
     A: Body + 'static,
     B: Body + 'static,
-    N: NewService<Request = http::Request<A>, Response = http::Response<B>, Error = client::Error>
+    N: NewService<
+        Request = http::Request<RequestBody<A>>,
+        Response = http::Response<B>,
+        Error = client::Error,
+    >
         + 'static,
 {
-    type Request = N::Request;
+    type Request = http::Request<A>;
     type Response = http::Response<ResponseBody<B>>;
     type Error = N::Error;
     type InitError = N::InitError;
@@ -115,6 +168,9 @@ where
             next_id: self.next_id.clone(),
             future: self.new_service.new_service(),
             handle: self.handle.clone(),
+            grpc: self.grpc.clone(),
+            allowed_headers: self.allowed_headers.clone(),
+            progress_interval: self.progress_interval,
             client_ctx: Arc::clone(&self.client_ctx),
             _p: PhantomData,
         }
@@ -128,7 +184,7 @@ where
     A: Body + 'static,
     B: Body + 'static,
     F: Future,
-    F::Item: Service<Request = http::Request<A>, Response = http::Response<B>>,
+    F::Item: Service<Request = http::Request<RequestBody<A>>, Response = http::Response<B>>,
 {
     type Item = Http<F::Item, A, B>;
     type Error = F::Error;
@@ -139,6 +195,9 @@ where
         Ok(Async::Ready(Http {
             service,
             handle: self.handle.clone(),
+            grpc: self.grpc.clone(),
+            allowed_headers: self.allowed_headers.clone(),
+            progress_interval: self.progress_interval,
             next_id: self.next_id.clone(),
             client_ctx: self.client_ctx.clone(),
             _p: PhantomData,
@@ -152,16 +211,35 @@ impl<S, A, B> Service for Http<S, A, B>
 where
     A: Body + 'static,
     B: Body + 'static,
-    S: Service<Request = http::Request<A>, Response = http::Response<B>, Error = client::Error>
+    S: Service<
+        Request = http::Request<RequestBody<A>>,
+        Response = http::Response<B>,
+        Error = client::Error,
+    >
         + 'static,
 {
-    type Request = S::Request;
+    type Request = http::Request<A>;
     type Response = http::Response<ResponseBody<B>>;
     type Error = S::Error;
     type Future = Respond<S::Future, B>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
-        self.service.poll_ready()
+        let ready = self.service.poll_ready();
+
+        // Once the connection has failed, e.g. because the endpoint sent a GOAWAY frame,
+        // it isn't polled again.
+        if let Err(ref e) = ready {
+            if let Some(error) = e.reason() {
+                let ctx = Arc::new(ctx::transport::Ctx::Client(Arc::clone(&self.client_ctx)));
+                self.handle.send(|| {
+                    Event::ConnectionFail(ctx, event::ConnectionFail {
+                        error,
+                    })
+                });
+            }
+        }
+
+        ready
     }
 
     fn call(&mut self, mut req: Self::Request) -> Self::Future {
@@ -169,7 +247,13 @@ where
             None => None,
             Some(ctx) => {
                 let id = self.next_id.fetch_add(1, Ordering::SeqCst);
-                let ctx = ctx::http::Request::new(&req, &ctx, &self.client_ctx, id);
+                let ctx = ctx::http::Request::new(
+                    &req,
+                    &ctx,
+                    &self.client_ctx,
+                    &self.allowed_headers,
+                    id,
+                );
 
                 self.handle
                     .send(|| Event::StreamRequestOpen(Arc::clone(&ctx)));
@@ -182,12 +266,31 @@ where
             }
         };
 
+        let progress = match (self.progress_interval, inner.as_ref()) {
+            (Some(interval), Some(inner)) => Some(Progress::new(interval, inner)),
+            _ => None,
+        };
+
+        // The messages of gRPC streams are counted in both directions.
+        let grpc = self.grpc.stream(&req, grpc::Direction::Response);
+        let req = {
+            let request_grpc = self.grpc.stream(&req, grpc::Direction::Request);
+            let (parts, body) = req.into_parts();
+            let body = RequestBody {
+                body,
+                grpc: request_grpc,
+                progress,
+            };
+            http::Request::from_parts(parts, body)
+        };
+
         // TODO measure request lifetime.
         let future = self.service.call(req);
 
         Respond {
             future,
             inner,
+            grpc,
             _p: PhantomData,
         }
     }
@@ -239,6 +342,7 @@ where
                                     grpc_status,
                                     since_request_open: request_open.elapsed(),
                                     since_response_open: Duration::default(),
+                                    since_request_open_to_first_byte: None,
                                     bytes_sent: 0,
                                     frames_sent: 0,
                                 },
@@ -254,6 +358,7 @@ where
                             frames_sent: 0,
                             request_open,
                             response_open: Instant::now(),
+                            first_byte: None,
                         })
                     }
                 });
@@ -263,6 +368,7 @@ where
                     let body = ResponseBody {
                         body,
                         inner,
+                        grpc: self.grpc.take(),
                         _p: PhantomData,
                     };
                     http::Response::from_parts(parts, body)
@@ -319,6 +425,7 @@ impl<B> ResponseBody<B> {
                             mut handle,
                             request_open,
                             response_open,
+                            first_byte,
                             bytes_sent,
                             frames_sent,
                             ..
@@ -331,6 +438,7 @@ impl<B> ResponseBody<B> {
                                     error,
                                     since_request_open: request_open.elapsed(),
                                     since_response_open: response_open.elapsed(),
+                                    since_request_open_to_first_byte: first_byte,
                                     bytes_sent,
                                     frames_sent,
                                 },
@@ -361,9 +469,15 @@ where
         let frame = frame.map(|frame| {
             let frame = frame.into_buf();
             if let Some(ref mut inner) = self.inner {
+                if inner.first_byte.is_none() && frame.has_remaining() {
+                    inner.first_byte = Some(inner.request_open.elapsed());
+                }
                 inner.frames_sent += 1;
                 inner.bytes_sent += frame.remaining() as u64;
             }
+            if let Some(ref mut grpc) = self.grpc {
+                grpc.observe(frame.bytes());
+            }
             frame
         });
         Ok(Async::Ready(frame))
@@ -380,6 +494,7 @@ where
                         mut handle,
                         request_open,
                         response_open,
+                        first_byte,
                         bytes_sent,
                         frames_sent,
                     } = i;
@@ -396,6 +511,7 @@ where
                                 grpc_status,
                                 since_request_open: request_open.elapsed(),
                                 since_response_open: response_open.elapsed(),
+                                since_request_open_to_first_byte: first_byte,
                                 bytes_sent,
                                 frames_sent,
                             },
@@ -408,3 +524,102 @@ where
         }
     }
 }
+
+// === RequestBody ===
+
+impl<B> Body for RequestBody<B>
+where
+    B: Body + 'static,
+{
+    /// The body chunk type
+    type Data = <B::Data as IntoBuf>::Buf;
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        let frame = try_ready!(self.body.poll_data());
+        let frame = frame.map(|frame| {
+            let frame = frame.into_buf();
+            if let Some(ref mut grpc) = self.grpc {
+                grpc.observe(frame.bytes());
+            }
+            if let Some(ref mut progress) = self.progress {
+                progress.observe(frame.remaining());
+            }
+            frame
+        });
+        Ok(Async::Ready(frame))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        self.body.poll_trailers()
+    }
+}
+
+// === Progress ===
+
+impl Progress {
+    fn new(interval: Duration, inner: &RespondInner) -> Self {
+        Progress {
+            handle: inner.handle.clone(),
+            ctx: Arc::clone(&inner.ctx),
+            interval,
+            request_open: inner.request_open,
+            bytes_sent: 0,
+            reported_at: inner.request_open,
+            reported_bytes: 0,
+        }
+    }
+
+    fn observe(&mut self, bytes: usize) {
+        self.bytes_sent += bytes as u64;
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.reported_at);
+        if elapsed < self.interval {
+            return;
+        }
+
+        let bytes_per_second = per_second(self.bytes_sent - self.reported_bytes, elapsed);
+        self.reported_at = now;
+        self.reported_bytes = self.bytes_sent;
+
+        let ctx = &self.ctx;
+        let since_request_open = now.duration_since(self.request_open);
+        let bytes_sent = self.bytes_sent;
+        self.handle.send(|| {
+            Event::StreamRequestProgress(
+                Arc::clone(ctx),
+                event::StreamRequestProgress {
+                    since_request_open,
+                    bytes_sent,
+                    bytes_per_second,
+                },
+            )
+        });
+    }
+}
+
+fn per_second(bytes: u64, elapsed: Duration) -> u64 {
+    let millis = elapsed.as_secs() * 1_000 + u64::from(elapsed.subsec_nanos() / 1_000_000);
+    if millis == 0 {
+        return 0;
+    }
+    bytes.saturating_mul(1_000) / millis
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::per_second;
+
+    #[test]
+    fn rates_are_per_second() {
+        assert_eq!(per_second(3_000, Duration::from_millis(1_500)), 2_000);
+        assert_eq!(per_second(100, Duration::from_secs(10)), 10);
+        assert_eq!(per_second(100, Duration::from_secs(0)), 0);
+    }
+}
@@ -1,17 +1,22 @@
+use std::io;
 use std::sync::Arc;
 use std::sync::atomic::AtomicUsize;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use futures_mpsc_lossy::Sender;
 use http::{Request, Response};
+use http::header::HeaderName;
 use tokio_connect;
+use tokio_core::reactor;
 use tokio_io::{AsyncRead, AsyncWrite};
 use tower::NewService;
 use tower_h2::{client, Body};
 
 use ctx;
-use telemetry::event;
+use telemetry::{event, grpc};
+use self::http::RequestBody;
 
+mod batch;
 pub mod http;
 mod transport;
 
@@ -23,8 +28,11 @@ pub use self::transport::{Connect, Transport};
 struct Handle(Option<Sender<event::Event>>);
 
 /// Supports the creation of telemetry scopes.
+///
+/// Request events only carry the values of headers on the allowlist. If a progress
+/// interval is set, request bodies report their progress at most once per interval.
 #[derive(Clone, Debug)]
-pub struct Sensors(Handle);
+pub struct Sensors(Handle, grpc::Messages, ctx::http::HeaderAllowlist, Option<Duration>);
 
 impl Handle {
     fn send<F>(&mut self, mk: F)
@@ -38,20 +46,38 @@ impl Handle {
             let ev = mk();
             trace!("event: {:?}", ev);
 
-            if tx.lossy_send(ev).is_err() {
-                debug!("dropped event");
-            }
+            batch::push(tx, ev);
         }
     }
 }
 
 impl Sensors {
-    pub(super) fn new(h: Sender<event::Event>) -> Self {
-        Sensors(Handle(Some(h)))
+    pub(super) fn new(
+        h: Sender<event::Event>,
+        allowed_headers: Vec<HeaderName>,
+        progress_interval: Option<Duration>,
+    ) -> Self {
+        Sensors(
+            Handle(Some(h)),
+            grpc::Messages::default(),
+            Arc::new(allowed_headers),
+            progress_interval,
+        )
     }
 
     pub fn null() -> Sensors {
-        Sensors(Handle(None))
+        Sensors(Handle(None), grpc::Messages::default(), Arc::new(Vec::new()), None)
+    }
+
+    /// Spawns a task that periodically sends the events buffered on the current thread.
+    ///
+    /// Must be called on the thread on which these sensors are used.
+    pub fn spawn_flush(&self, executor: &reactor::Handle) -> io::Result<()> {
+        if let Some(ref tx) = (self.0).0 {
+            let flush = batch::Flush::new(tx.clone(), executor)?;
+            executor.spawn(flush);
+        }
+        Ok(())
     }
 
     pub fn accept<T>(
@@ -84,9 +110,18 @@ impl Sensors {
     where
         A: Body + 'static,
         B: Body + 'static,
-        N: NewService<Request = Request<A>, Response = Response<B>, Error = client::Error>
+        N: NewService<
+            Request = Request<RequestBody<A>>,
+            Response = Response<B>,
+            Error = client::Error,
+        >
             + 'static,
     {
-        NewHttp::new(next_id, new_service, &self.0, client_ctx)
+        NewHttp::new(next_id, new_service, &self.0, &self.1, &self.2, self.3, client_ctx)
+    }
+
+    /// Returns the metrics describing the messages of gRPC streams.
+    pub fn grpc_messages(&self) -> &grpc::Messages {
+        &self.1
     }
 }
@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use super::event::Event;
+use ctx;
+
+/// Logs a detailed record of each request that takes longer than a threshold.
+///
+/// Records are emitted as requests complete (or fail) and include the time until the
+/// response headers were received and the time spent streaming the response body. The
+/// proxy does not retry requests, so each record describes a single attempt.
+///
+/// Records are logged at the `info` level under this module's target, so they may be
+/// enabled independently of other logging.
+#[derive(Clone, Debug)]
+pub struct SlowRequests {
+    threshold: Duration,
+}
+
+// ===== impl SlowRequests =====
+
+impl SlowRequests {
+    pub fn new(threshold: Duration) -> Self {
+        SlowRequests {
+            threshold,
+        }
+    }
+
+    pub fn inspect(&self, ev: &Event) {
+        if let Some(record) = self.record(ev) {
+            info!("{}", record);
+        }
+    }
+
+    /// Describes the request that `ev` completes, if it took at least the threshold.
+    fn record(&self, ev: &Event) -> Option<String> {
+        match *ev {
+            Event::StreamRequestFail(ref req, ref fail) => {
+                if fail.since_request_open < self.threshold {
+                    return None;
+                }
+                Some(format!(
+                    "slow request {}: failed before response after {:?}; error={:?}",
+                    describe(req),
+                    fail.since_request_open,
+                    fail.error,
+                ))
+            }
+
+            Event::StreamResponseFail(ref rsp, ref fail) => {
+                if fail.since_request_open < self.threshold {
+                    return None;
+                }
+                Some(format!(
+                    "slow request {}: status={} headers={:?} body={:?} total={:?} \
+                     bytes={} frames={} error={:?}",
+                    describe(&rsp.request),
+                    rsp.status.as_u16(),
+                    headers_latency(fail.since_request_open, fail.since_response_open),
+                    fail.since_response_open,
+                    fail.since_request_open,
+                    fail.bytes_sent,
+                    fail.frames_sent,
+                    fail.error,
+                ))
+            }
+
+            Event::StreamResponseEnd(ref rsp, ref end) => {
+                if end.since_request_open < self.threshold {
+                    return None;
+                }
+                Some(format!(
+                    "slow request {}: status={} headers={:?} body={:?} total={:?} \
+                     bytes={} frames={} grpc_status={:?}",
+                    describe(&rsp.request),
+                    rsp.status.as_u16(),
+                    headers_latency(end.since_request_open, end.since_response_open),
+                    end.since_response_open,
+                    end.since_request_open,
+                    end.bytes_sent,
+                    end.frames_sent,
+                    end.grpc_status,
+                ))
+            }
+
+            _ => None,
+        }
+    }
+}
+
+fn describe(req: &ctx::http::Request) -> String {
+    let direction = if req.server.proxy.is_inbound() {
+        "inbound"
+    } else {
+        "outbound"
+    };
+    format!(
+        "id={} {} {} {} peer={} endpoint={}",
+        req.id,
+        direction,
+        req.method,
+        req.uri,
+        req.server.remote,
+        req.client.remote,
+    )
+}
+
+/// Returns the time between the request being opened and response headers being received.
+///
+/// Events record the time elapsed from both points until the stream completed, so the
+/// time spent waiting for headers is their difference.
+fn headers_latency(since_request_open: Duration, since_response_open: Duration) -> Duration {
+    if since_request_open > since_response_open {
+        since_request_open - since_response_open
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use h2;
+    use http;
+
+    use super::*;
+    use telemetry::event;
+
+    fn request() -> Arc<ctx::http::Request> {
+        let process = ctx::Process::new("test", "test", "test");
+        let proxy = ctx::Proxy::outbound(&process);
+        let local: SocketAddr = "127.0.0.1:4140".parse().unwrap();
+        let remote: SocketAddr = "10.0.0.2:51234".parse().unwrap();
+        let endpoint: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        let server = ctx::transport::Server::new(&proxy, &local, &remote, &None);
+        let client = ctx::transport::Client::new(&proxy, &endpoint);
+        let req = http::Request::builder().uri("http://web/").body(()).unwrap();
+        ctx::http::Request::new(&req, &server, &client, &Arc::new(Vec::new()), 7)
+    }
+
+    fn end(since_request_open: Duration) -> Event {
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        Event::StreamResponseEnd(
+            ctx::http::Response::new(&rsp, &request()),
+            event::StreamResponseEnd {
+                grpc_status: None,
+                since_request_open,
+                since_response_open: Duration::from_millis(40),
+                since_request_open_to_first_byte: None,
+                bytes_sent: 512,
+                frames_sent: 2,
+            },
+        )
+    }
+
+    #[test]
+    fn records_requests_at_or_over_the_threshold() {
+        let slow = SlowRequests::new(Duration::from_millis(100));
+        assert_eq!(slow.record(&end(Duration::from_millis(99))), None);
+
+        let record = slow.record(&end(Duration::from_millis(100))).expect("slow request");
+        let expected = format!(
+            "slow request id=7 outbound GET http://web/ peer=10.0.0.2:51234 \
+             endpoint=10.0.0.1:8080: status=200 headers={:?} body={:?} total={:?} \
+             bytes=512 frames=2 grpc_status=None",
+            Duration::from_millis(60),
+            Duration::from_millis(40),
+            Duration::from_millis(100),
+        );
+        assert_eq!(record, expected);
+    }
+
+    #[test]
+    fn records_slow_failures() {
+        let slow = SlowRequests::new(Duration::from_millis(100));
+        let fail = |since_request_open| {
+            Event::StreamRequestFail(
+                request(),
+                event::StreamRequestFail {
+                    since_request_open,
+                    error: h2::Reason::REFUSED_STREAM,
+                },
+            )
+        };
+        assert_eq!(slow.record(&fail(Duration::from_millis(10))), None);
+
+        let record = slow.record(&fail(Duration::from_secs(1))).expect("slow failure");
+        let expected = format!(
+            "failed before response after {:?}; error={:?}",
+            Duration::from_secs(1),
+            h2::Reason::REFUSED_STREAM,
+        );
+        assert!(record.ends_with(&expected), "unexpected record: {}", record);
+    }
+}
@@ -0,0 +1,294 @@
+//! Exports metrics reports to a statsd server, e.g. a Datadog agent.
+//!
+//! Each report that is sent to the controller is also sent to the statsd server, as
+//! counters and timers with DogStatsD tags. Datagrams are sent without blocking, and
+//! metrics that can't be sent are dropped.
+//!
+//! Every metric is also tagged with the proxy's `Topology`.
+//!
+//! Reports only describe what happened since the previous report, which is what statsd
+//! expects. Serving OpenMetrics instead would require the proxy to keep cumulative
+//! counters, which it doesn't.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use control::pb::proxy::telemetry::{Latency, ReportRequest};
+use super::topology::Topology;
+
+const PREFIX: &str = "conduit.proxy";
+
+/// Keeps datagrams within a typical Ethernet MTU.
+const MAX_DATAGRAM_BYTES: usize = 1_432;
+
+/// Sends metrics to a statsd server over UDP.
+#[derive(Debug)]
+pub struct Statsd {
+    socket: UdpSocket,
+    addr: SocketAddr,
+
+    /// Tags added to every metric, e.g. `node:n1,zone:z1`.
+    topology_tags: String,
+}
+
+// ===== impl Statsd =====
+
+impl Statsd {
+    pub fn new(addr: SocketAddr, topology: &Topology) -> io::Result<Self> {
+        let local: SocketAddr = if addr.is_ipv4() {
+            "0.0.0.0:0".parse().expect("unspecified IPv4 address must parse")
+        } else {
+            "[::]:0".parse().expect("unspecified IPv6 address must parse")
+        };
+        let socket = UdpSocket::bind(&local)?;
+        socket.set_nonblocking(true)?;
+        let topology_tags = topology
+            .labels()
+            .iter()
+            .map(|&(k, ref v)| format!("{}:{}", k, tag_value(v)))
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(Statsd {
+            socket,
+            addr,
+            topology_tags,
+        })
+    }
+
+    pub(super) fn export(&self, report: &ReportRequest) {
+        let mut lines = lines(report);
+        add_tags(&mut lines, &self.topology_tags);
+        for datagram in datagrams(&lines) {
+            if let Err(e) = self.socket.send_to(datagram.as_bytes(), &self.addr) {
+                debug!("could not send metrics to statsd at {}: {}", self.addr, e);
+            }
+        }
+    }
+}
+
+/// Renders a report as statsd lines.
+fn lines(report: &ReportRequest) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for req in &report.requests {
+        let authority = req.ctx.as_ref().map(|c| c.authority.as_str()).unwrap_or("");
+        let tags = format!("authority:{}", tag_value(authority));
+        lines.push(format!("{}.requests:{}|c|#{}", PREFIX, req.count, tags));
+
+        for rsp in &req.responses {
+            let tags = match rsp.ctx {
+                Some(ref ctx) => format!("{},status_code:{}", tags, ctx.http_status_code),
+                None => tags.clone(),
+            };
+            let count = rsp.response_latencies
+                .iter()
+                .map(|l| u64::from(l.count))
+                .sum::<u64>();
+            lines.push(format!("{}.responses:{}|c|#{}", PREFIX, count, tags));
+
+            push_latencies(&mut lines, "response_latency", &rsp.response_latencies, &tags);
+            push_latencies(&mut lines, "first_byte_latency", &rsp.first_byte_latencies, &tags);
+            push_latencies(&mut lines, "end_latency", &rsp.end_latencies, &tags);
+        }
+    }
+
+    let accepts = report.server_transports.iter().map(|t| u64::from(t.connects)).sum::<u64>();
+    lines.push(format!("{}.tcp.accepts:{}|c", PREFIX, accepts));
+    let connects = report.client_transports.iter().map(|t| u64::from(t.connects)).sum::<u64>();
+    lines.push(format!("{}.tcp.connects:{}|c", PREFIX, connects));
+
+    // HTTP/2 connections to endpoints that failed, e.g. on GOAWAY, by error code.
+    let mut errors = BTreeMap::new();
+    for e in report.client_transports.iter().flat_map(|t| &t.connection_errors) {
+        *errors.entry(e.error_code).or_insert(0u64) += u64::from(e.count);
+    }
+    for (error_code, count) in errors {
+        lines.push(format!(
+            "{}.h2.connection_errors:{}|c|#error_code:{}",
+            PREFIX, count, error_code
+        ));
+    }
+
+    lines
+}
+
+/// Renders latencies as timers.
+///
+/// Latencies are in tenths of a millisecond. Each is sent once, with a sample rate that
+/// accounts for the number of times it was observed.
+fn push_latencies(lines: &mut Vec<String>, name: &str, latencies: &[Latency], tags: &str) {
+    for latency in latencies.iter().filter(|l| l.count > 0) {
+        lines.push(format!(
+            "{}.{}:{}|ms|@{}|#{}",
+            PREFIX,
+            name,
+            f64::from(latency.latency) / 10.0,
+            1.0 / f64::from(latency.count),
+            tags,
+        ));
+    }
+}
+
+/// Adds `tags` to each of `lines`, after any tags the line already has.
+fn add_tags(lines: &mut [String], tags: &str) {
+    if tags.is_empty() {
+        return;
+    }
+    for line in lines {
+        let separator = if line.contains("|#") { "," } else { "|#" };
+        line.push_str(separator);
+        line.push_str(tags);
+    }
+}
+
+/// Replaces the characters that delimit DogStatsD tags.
+fn tag_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            ',' | '|' | '#' | '\n' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Packs lines into as few datagrams as possible.
+fn datagrams(lines: &[String]) -> Vec<String> {
+    let mut datagrams = Vec::new();
+    let mut datagram = String::new();
+    for line in lines {
+        if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM_BYTES {
+            datagrams.push(datagram);
+            datagram = String::new();
+        }
+        if !datagram.is_empty() {
+            datagram.push('\n');
+        }
+        datagram.push_str(line);
+    }
+    if !datagram.is_empty() {
+        datagrams.push(datagram);
+    }
+    datagrams
+}
+
+#[cfg(test)]
+mod tests {
+    use control::pb::proxy::telemetry::{
+        ClientTransport,
+        ConnectionError,
+        RequestCtx,
+        RequestScope,
+        ResponseCtx,
+        ResponseScope,
+        ServerTransport,
+    };
+
+    use super::*;
+
+    #[test]
+    fn renders_reports_as_tagged_lines() {
+        let report = ReportRequest {
+            requests: vec![RequestScope {
+                ctx: Some(RequestCtx {
+                    authority: "web.default.svc.cluster.local:8080".into(),
+                    ..RequestCtx::default()
+                }),
+                count: 3,
+                responses: vec![ResponseScope {
+                    ctx: Some(ResponseCtx {
+                        http_status_code: 200,
+                    }),
+                    response_latencies: vec![
+                        Latency {
+                            latency: 15,
+                            count: 2,
+                        },
+                        Latency {
+                            latency: 100,
+                            count: 1,
+                        },
+                    ],
+                    ends: vec![],
+                    first_byte_latencies: vec![Latency {
+                        latency: 20,
+                        count: 3,
+                    }],
+                    end_latencies: vec![],
+                }],
+            }],
+            server_transports: vec![ServerTransport {
+                connects: 2,
+                ..ServerTransport::default()
+            }],
+            ..ReportRequest::default()
+        };
+
+        let tags = "authority:web.default.svc.cluster.local:8080";
+        assert_eq!(
+            lines(&report),
+            vec![
+                format!("conduit.proxy.requests:3|c|#{}", tags),
+                format!("conduit.proxy.responses:3|c|#{},status_code:200", tags),
+                format!("conduit.proxy.response_latency:1.5|ms|@0.5|#{},status_code:200", tags),
+                format!("conduit.proxy.response_latency:10|ms|@1|#{},status_code:200", tags),
+                format!(
+                    "conduit.proxy.first_byte_latency:2|ms|@0.3333333333333333|#{},status_code:200",
+                    tags
+                ),
+                "conduit.proxy.tcp.accepts:2|c".to_owned(),
+                "conduit.proxy.tcp.connects:0|c".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_connection_errors_by_error_code() {
+        let transport = |errors: Vec<(u32, u32)>| ClientTransport {
+            connection_errors: errors
+                .into_iter()
+                .map(|(error_code, count)| ConnectionError {
+                    error_code,
+                    count,
+                })
+                .collect(),
+            ..ClientTransport::default()
+        };
+        let report = ReportRequest {
+            client_transports: vec![transport(vec![(2, 1), (0, 3)]), transport(vec![(2, 2)])],
+            ..ReportRequest::default()
+        };
+
+        let lines = lines(&report);
+        assert_eq!(
+            &lines[lines.len() - 2..],
+            &[
+                "conduit.proxy.h2.connection_errors:3|c|#error_code:0".to_owned(),
+                "conduit.proxy.h2.connection_errors:3|c|#error_code:2".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_topology_tags() {
+        let mut lines = vec![
+            "conduit.proxy.requests:3|c|#authority:web".to_owned(),
+            "conduit.proxy.tcp.accepts:2|c".to_owned(),
+        ];
+        add_tags(&mut lines, "");
+        assert_eq!(lines[1], "conduit.proxy.tcp.accepts:2|c");
+
+        add_tags(&mut lines, "node:n1,zone:z1");
+        assert_eq!(lines[0], "conduit.proxy.requests:3|c|#authority:web,node:n1,zone:z1");
+        assert_eq!(lines[1], "conduit.proxy.tcp.accepts:2|c|#node:n1,zone:z1");
+    }
+
+    #[test]
+    fn packs_lines_into_datagrams() {
+        let line = "x".repeat(600);
+        let lines = vec![line.clone(), line.clone(), line.clone()];
+        let datagrams = datagrams(&lines);
+        assert_eq!(datagrams, vec![format!("{}\n{}", line, line), line]);
+    }
+}
@@ -77,9 +77,9 @@ impl Match {
             Match::Not(ref not) => !not.matches(ev),
 
             Match::Source(ref src) => match *ev {
-                Event::StreamRequestOpen(ref req) | Event::StreamRequestFail(ref req, _) => {
-                    src.matches(&req.server.remote)
-                }
+                Event::StreamRequestOpen(ref req) |
+                Event::StreamRequestProgress(ref req, _) |
+                Event::StreamRequestFail(ref req, _) => src.matches(&req.server.remote),
                 Event::StreamResponseOpen(ref rsp, _) |
                 Event::StreamResponseFail(ref rsp, _) |
                 Event::StreamResponseEnd(ref rsp, _) => src.matches(&rsp.request.server.remote),
@@ -87,9 +87,9 @@ impl Match {
             },
 
             Match::Destination(ref dst) => match *ev {
-                Event::StreamRequestOpen(ref req) | Event::StreamRequestFail(ref req, _) => {
-                    dst.matches(&req.client.remote)
-                }
+                Event::StreamRequestOpen(ref req) |
+                Event::StreamRequestProgress(ref req, _) |
+                Event::StreamRequestFail(ref req, _) => dst.matches(&req.client.remote),
                 Event::StreamResponseOpen(ref rsp, _) |
                 Event::StreamResponseFail(ref rsp, _) |
                 Event::StreamResponseEnd(ref rsp, _) => dst.matches(&rsp.request.client.remote),
@@ -97,9 +97,9 @@ impl Match {
             },
 
             Match::Http(ref http) => match *ev {
-                Event::StreamRequestOpen(ref req) | Event::StreamRequestFail(ref req, _) => {
-                    http.matches(req)
-                }
+                Event::StreamRequestOpen(ref req) |
+                Event::StreamRequestProgress(ref req, _) |
+                Event::StreamRequestFail(ref req, _) => http.matches(req),
 
                 Event::StreamResponseOpen(ref rsp, _) |
                 Event::StreamResponseFail(ref rsp, _) |
@@ -0,0 +1,137 @@
+//! Attributes metrics to where the proxy runs.
+//!
+//! The node, zone, and cluster that the proxy runs in are added as labels to metrics in
+//! the Prometheus text format, and as tags to statsd metrics, so that dashboards spanning
+//! several clusters can group metrics without joining them against other data. Each is
+//! omitted if it's unknown.
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use ctx;
+
+/// Labels identifying where the proxy runs.
+#[derive(Clone, Debug, Default)]
+pub struct Topology {
+    labels: Arc<Vec<(&'static str, String)>>,
+}
+
+// ===== impl Topology =====
+
+impl Topology {
+    pub fn new(process: &ctx::Process) -> Self {
+        let labels = vec![
+            ("node", &process.node),
+            ("zone", &process.zone),
+            ("cluster", &process.cluster),
+        ];
+        Topology {
+            labels: Arc::new(
+                labels
+                    .into_iter()
+                    .filter(|&(_, v)| !v.is_empty())
+                    .map(|(k, v)| (k, v.clone()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Adds the labels to each sample of `text`, which is in the Prometheus text format.
+    pub fn label_prometheus(&self, text: &str) -> String {
+        if self.labels.is_empty() {
+            return text.to_owned();
+        }
+
+        let mut labels = String::new();
+        for (i, &(k, ref v)) in self.labels.iter().enumerate() {
+            if i > 0 {
+                labels.push(',');
+            }
+            let _ = write!(labels, "{}=\"{}\"", k, escape_label_value(v));
+        }
+
+        let mut out = String::with_capacity(text.len() + labels.len() * 8);
+        for line in text.lines() {
+            let end = line.find(|c: char| c == '{' || c == ' ');
+            match end {
+                Some(i) if !line.starts_with('#') => {
+                    let (name, rest) = line.split_at(i);
+                    out.push_str(name);
+                    out.push('{');
+                    out.push_str(&labels);
+                    if rest.starts_with("{}") {
+                        out.push_str(&rest[1..]);
+                    } else if rest.starts_with('{') {
+                        out.push(',');
+                        out.push_str(&rest[1..]);
+                    } else {
+                        out.push('}');
+                        out.push_str(rest);
+                    }
+                }
+                _ => out.push_str(line),
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub(super) fn labels(&self) -> &[(&'static str, String)] {
+        &self.labels
+    }
+}
+
+fn escape_label_value(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topology(node: &str, zone: &str, cluster: &str) -> Topology {
+        let mut process = (*ctx::Process::new(node, "pod", "ns")).clone();
+        process.zone = zone.into();
+        process.cluster = cluster.into();
+        Topology::new(&process)
+    }
+
+    #[test]
+    fn labels_prometheus_samples() {
+        let text = "# HELP streams_refused_total Streams refused.\n\
+                    # TYPE streams_refused_total counter\n\
+                    streams_refused_total 3\n\
+                    grpc_messages_total{direction=\"request\"} 7\n\
+                    empty_total{} 0\n";
+        let labeled = topology("n1", "us-east-1a", "").label_prometheus(text);
+        assert_eq!(
+            labeled,
+            "# HELP streams_refused_total Streams refused.\n\
+             # TYPE streams_refused_total counter\n\
+             streams_refused_total{node=\"n1\",zone=\"us-east-1a\"} 3\n\
+             grpc_messages_total{node=\"n1\",zone=\"us-east-1a\",direction=\"request\"} 7\n\
+             empty_total{node=\"n1\",zone=\"us-east-1a\"} 0\n"
+        );
+    }
+
+    #[test]
+    fn omits_unknown_labels() {
+        let text = "streams_refused_total 3\n";
+        assert_eq!(topology("", "", "").label_prometheus(text), text);
+        assert!(topology("", "", "").labels().is_empty());
+        assert!(
+            topology("n\"1", "", "")
+                .label_prometheus(text)
+                .contains("{node=\"n\\\"1\"}")
+        );
+    }
+}
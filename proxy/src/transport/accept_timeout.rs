@@ -0,0 +1,105 @@
+use futures::{Async, Future, Poll};
+use tokio_core::reactor::{Handle, Timeout};
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Completes if a connection hasn't received its first request within a timeout, which
+/// includes any negotiation that precedes HTTP/2.
+///
+/// If no timeout is configured, or once a request is received, this never completes.
+pub struct AcceptTimeout {
+    received: Rc<Cell<bool>>,
+    timer: Option<Timeout>,
+}
+
+/// Records that a connection has received a request.
+#[derive(Clone, Debug)]
+pub struct FirstRequest(Rc<Cell<bool>>);
+
+// ===== impl AcceptTimeout =====
+
+impl AcceptTimeout {
+    pub fn new(timeout: Option<Duration>, handle: &Handle) -> (Self, FirstRequest) {
+        let received = Rc::new(Cell::new(false));
+        let timer = timeout.and_then(|t| match Timeout::new(t, handle) {
+            Ok(timer) => Some(timer),
+            Err(e) => {
+                warn!("could not create accept timeout: {}", e);
+                None
+            }
+        });
+
+        let timeout = AcceptTimeout {
+            received: received.clone(),
+            timer,
+        };
+        (timeout, FirstRequest(received))
+    }
+}
+
+impl Future for AcceptTimeout {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.received.get() {
+            self.timer = None;
+        }
+
+        let expired = match self.timer {
+            Some(ref mut timer) => match timer.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(())) => true,
+                Err(e) => {
+                    warn!("accept timeout failed: {}", e);
+                    false
+                }
+            },
+            None => return Ok(Async::NotReady),
+        };
+
+        // The connection isn't closed because the timer failed.
+        self.timer = None;
+        if expired && !self.received.get() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+// ===== impl FirstRequest =====
+
+impl FirstRequest {
+    pub fn received(&self) {
+        self.0.set(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Future};
+    use tokio_core::reactor::Core;
+
+    use std::thread;
+    use std::time::Duration;
+
+    use super::AcceptTimeout;
+
+    #[test]
+    fn expires_only_without_requests() {
+        let mut core = Core::new().unwrap();
+        let timeout = Some(Duration::from_millis(1));
+
+        let (silent, _) = AcceptTimeout::new(timeout, &core.handle());
+        assert_eq!(core.run(silent), Ok(()));
+
+        let (mut active, first_request) = AcceptTimeout::new(timeout, &core.handle());
+        first_request.received();
+        thread::sleep(Duration::from_millis(5));
+        let polled = core.run(future::lazy(|| Ok::<_, ()>(active.poll())));
+        assert!(polled.unwrap().unwrap().is_not_ready());
+    }
+}
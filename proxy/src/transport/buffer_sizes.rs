@@ -0,0 +1,131 @@
+use std::io;
+use std::net::TcpListener as StdTcpListener;
+
+use tokio_core::net::TcpStream;
+
+/// The sizes of a socket's kernel send and receive buffers, in bytes.
+///
+/// Each is the system's default unless it's set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferSizes {
+    recv: Option<usize>,
+    send: Option<usize>,
+}
+
+// ===== impl BufferSizes =====
+
+impl BufferSizes {
+    pub fn new(recv: Option<usize>, send: Option<usize>) -> Self {
+        BufferSizes {
+            recv,
+            send,
+        }
+    }
+
+    /// Sets the buffer sizes of the connections that `listener` accepts.
+    pub fn apply_to_listener(&self, listener: &StdTcpListener) {
+        if let Err(e) = self.set(listener) {
+            warn!("could not set buffer sizes on {:?}: {}", listener.local_addr(), e);
+        }
+    }
+
+    /// Sets the buffer sizes of `tcp`.
+    ///
+    /// Failures are logged rather than returned, since the connection is still usable.
+    pub fn apply(&self, tcp: &TcpStream) {
+        if let Err(e) = self.set(tcp) {
+            warn!(
+                "could not set buffer sizes on {:?}/{:?}: {}",
+                tcp.local_addr(),
+                tcp.peer_addr(),
+                e
+            );
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set<S>(&self, _: &S) -> io::Result<()> {
+        if self.recv.is_some() || self.send.is_some() {
+            debug!("no support for SO_RCVBUF or SO_SNDBUF");
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set<S: ::std::os::unix::io::AsRawFd>(&self, socket: &S) -> io::Result<()> {
+        use libc;
+
+        let fd = socket.as_raw_fd();
+        if let Some(size) = self.recv {
+            unsafe { linux::set_size(fd, libc::SO_RCVBUF, size)? };
+        }
+        if let Some(size) = self.send {
+            unsafe { linux::set_size(fd, libc::SO_SNDBUF, size)? };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use libc;
+    use std::{io, mem};
+    use std::os::unix::io::RawFd;
+
+    pub unsafe fn set_size(fd: RawFd, opt: libc::c_int, size: usize) -> io::Result<()> {
+        // Larger sizes are clamped by the kernel anyway.
+        let size = ::std::cmp::min(size, libc::c_int::max_value() as usize) as libc::c_int;
+        let ret = libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            opt,
+            &size as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        );
+        if ret == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub unsafe fn get_size(fd: RawFd, opt: libc::c_int) -> io::Result<usize> {
+        let mut size: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let ret = libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            opt,
+            &mut size as *mut _ as *mut libc::c_void,
+            &mut len,
+        );
+        if ret == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(size as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn sets_listener_buffer_sizes() {
+        use libc;
+        use std::os::unix::io::AsRawFd;
+
+        let listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = listener.as_raw_fd();
+        let default_send = unsafe { linux::get_size(fd, libc::SO_SNDBUF).unwrap() };
+
+        BufferSizes::new(Some(64 * 1024), None).apply_to_listener(&listener);
+
+        // Linux doubles the requested size to allow for bookkeeping overhead.
+        let recv = unsafe { linux::get_size(fd, libc::SO_RCVBUF).unwrap() };
+        assert_eq!(recv, 2 * 64 * 1024);
+        let send = unsafe { linux::get_size(fd, libc::SO_SNDBUF).unwrap() };
+        assert_eq!(send, default_send);
+    }
+}
@@ -9,14 +9,44 @@ use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 
 use dns;
+use super::buffer_sizes::BufferSizes;
+use super::dial_limit::{Acquire, DialLimit, Permit};
+use super::keepalive::Keepalive;
 
+/// Connects, and then sets the connection's socket options.
 #[must_use = "futures do nothing unless polled"]
-pub struct TcpStreamNewNoDelay(TcpStreamNew);
+pub struct TcpStreamNewNoDelay(TcpStreamNew, bool, Keepalive, BufferSizes);
 
+/// Establishes plaintext TCP connections to a fixed address.
+///
+/// Connections aren't encrypted, so reconnecting costs only the TCP and HTTP/2
+/// handshakes; there is no TLS session state to cache or resume across reconnects.
 #[derive(Debug, Clone)]
 pub struct Connect {
     addr: SocketAddr,
     handle: Handle,
+    limit: Option<DialLimit>,
+    nodelay: bool,
+    keepalive: Keepalive,
+    buffer_sizes: BufferSizes,
+}
+
+/// Waits for a `DialLimit`, if there is one, and then connects.
+#[must_use = "futures do nothing unless polled"]
+pub struct ConnectFuture {
+    addr: SocketAddr,
+    handle: Handle,
+    nodelay: bool,
+    keepalive: Keepalive,
+    buffer_sizes: BufferSizes,
+    state: ConnectState,
+}
+
+enum ConnectState {
+    Acquiring(Acquire),
+
+    /// The permit is held until the attempt finishes.
+    Connecting(TcpStreamNewNoDelay, Option<Permit>),
 }
 
 #[derive(Debug, Clone)]
@@ -53,18 +83,27 @@ impl Future for TcpStreamNewNoDelay {
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         let tcp = try_ready!(self.0.poll());
-        if let Err(e) = tcp.set_nodelay(true) {
-            warn!(
-                "could not set TCP_NODELAY on {:?}/{:?}: {}",
-                tcp.local_addr(),
-                tcp.peer_addr(),
-                e
-            );
-        }
+        set_nodelay(&tcp, self.1);
+        self.2.apply(&tcp);
+        self.3.apply(&tcp);
         Ok(Async::Ready(tcp))
     }
 }
 
+/// Sets `TCP_NODELAY` on `tcp`, disabling Nagle's algorithm if `nodelay` is set.
+///
+/// Failures are logged rather than returned, since the connection is still usable.
+pub fn set_nodelay(tcp: &TcpStream, nodelay: bool) {
+    if let Err(e) = tcp.set_nodelay(nodelay) {
+        warn!(
+            "could not set TCP_NODELAY on {:?}/{:?}: {}",
+            tcp.local_addr(),
+            tcp.peer_addr(),
+            e
+        );
+    }
+}
+
 // ===== impl Connect =====
 
 impl Connect {
@@ -73,6 +112,42 @@ impl Connect {
         Self {
             addr,
             handle: handle.clone(),
+            limit: None,
+            nodelay: true,
+            keepalive: Keepalive::default(),
+            buffer_sizes: BufferSizes::default(),
+        }
+    }
+
+    /// Waits for `limit` before each connection attempt.
+    pub fn with_limit(self, limit: Option<DialLimit>) -> Self {
+        Self {
+            limit,
+            ..self
+        }
+    }
+
+    /// Sets whether Nagle's algorithm is disabled on each connection, as it is by default.
+    pub fn with_nodelay(self, nodelay: bool) -> Self {
+        Self {
+            nodelay,
+            ..self
+        }
+    }
+
+    /// Enables TCP keepalive on each connection.
+    pub fn with_keepalive(self, keepalive: Keepalive) -> Self {
+        Self {
+            keepalive,
+            ..self
+        }
+    }
+
+    /// Sets the kernel buffer sizes of each connection.
+    pub fn with_buffer_sizes(self, buffer_sizes: BufferSizes) -> Self {
+        Self {
+            buffer_sizes,
+            ..self
         }
     }
 }
@@ -80,11 +155,69 @@ impl Connect {
 impl tokio_connect::Connect for Connect {
     type Connected = TcpStream;
     type Error = io::Error;
-    type Future = TcpStreamNewNoDelay;
+    type Future = ConnectFuture;
 
     fn connect(&self) -> Self::Future {
-        trace!("connect {}", self.addr);
-        TcpStreamNewNoDelay(TcpStream::connect(&self.addr, &self.handle))
+        let state = match self.limit {
+            Some(ref limit) => ConnectState::Acquiring(limit.acquire()),
+            None => {
+                let dial = dial(
+                    &self.addr,
+                    &self.handle,
+                    self.nodelay,
+                    self.keepalive,
+                    self.buffer_sizes,
+                );
+                ConnectState::Connecting(dial, None)
+            }
+        };
+        ConnectFuture {
+            addr: self.addr,
+            handle: self.handle.clone(),
+            nodelay: self.nodelay,
+            keepalive: self.keepalive,
+            buffer_sizes: self.buffer_sizes,
+            state,
+        }
+    }
+}
+
+fn dial(
+    addr: &SocketAddr,
+    handle: &Handle,
+    nodelay: bool,
+    keepalive: Keepalive,
+    buffer_sizes: BufferSizes,
+) -> TcpStreamNewNoDelay {
+    trace!("connect {}", addr);
+    TcpStreamNewNoDelay(TcpStream::connect(addr, handle), nodelay, keepalive, buffer_sizes)
+}
+
+// ===== impl ConnectFuture =====
+
+impl Future for ConnectFuture {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let permit = match self.state {
+                ConnectState::Acquiring(ref mut acquire) => {
+                    try_ready!(acquire.poll().map_err(|()| {
+                        io::Error::new(io::ErrorKind::Other, "dial limit is unusable")
+                    }))
+                }
+                ConnectState::Connecting(ref mut connect, _) => return connect.poll(),
+            };
+            let dial = dial(
+                &self.addr,
+                &self.handle,
+                self.nodelay,
+                self.keepalive,
+                self.buffer_sizes,
+            );
+            self.state = ConnectState::Connecting(dial, Some(permit));
+        }
     }
 }
 
@@ -122,7 +255,12 @@ impl tokio_connect::Connect for LookupAddressAndConnect {
                 info!("DNS resolved {} to {}", host, ip_addr);
                 let addr = SocketAddr::from((ip_addr, port));
                 trace!("connect {}", addr);
-                TcpStreamNewNoDelay(TcpStream::connect(&addr, &handle))
+                TcpStreamNewNoDelay(
+                    TcpStream::connect(&addr, &handle),
+                    true,
+                    Keepalive::default(),
+                    BufferSizes::default(),
+                )
             });
         Box::new(c)
     }
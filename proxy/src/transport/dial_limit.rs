@@ -0,0 +1,162 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use futures::{Async, Future, Poll};
+use futures::task::{self, Task};
+
+/// Counts connection attempts, across all destinations, that are waiting for another
+/// attempt to the same destination to finish.
+static PENDING: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Returns the number of connection attempts waiting for a `DialLimit`.
+pub fn pending_dials() -> usize {
+    PENDING.load(Ordering::Relaxed)
+}
+
+/// Limits the number of simultaneous connection attempts to a destination, so that an
+/// upstream that just restarted isn't flooded with SYNs by every request that was
+/// waiting for it.
+///
+/// All clones share the limit. Attempts over the limit wait, in no particular order,
+/// until an earlier attempt finishes; they're bounded only by the connect timeout.
+#[derive(Clone, Debug)]
+pub struct DialLimit(Arc<Shared>);
+
+/// Permits a single connection attempt, until it's dropped.
+#[derive(Debug)]
+pub struct Permit(DialLimit);
+
+/// Waits for a `Permit`.
+#[derive(Debug)]
+pub struct Acquire {
+    limit: DialLimit,
+
+    /// Identifies this attempt among the waiters, once it has waited.
+    waiting: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    max: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    active: usize,
+    next_id: usize,
+    waiters: Vec<(usize, Task)>,
+}
+
+// ===== impl DialLimit =====
+
+impl DialLimit {
+    /// Allows `max` simultaneous connection attempts, which must be greater than zero.
+    pub fn new(max: usize) -> Self {
+        assert!(max > 0, "dial limit must be greater than zero");
+        DialLimit(Arc::new(Shared {
+            max,
+            state: Mutex::new(State::default()),
+        }))
+    }
+
+    pub fn acquire(&self) -> Acquire {
+        Acquire {
+            limit: self.clone(),
+            waiting: None,
+        }
+    }
+
+    fn release(&self) {
+        let waiters = match self.0.state.lock() {
+            Ok(mut state) => {
+                state.active -= 1;
+                state.waiters.drain(..).collect::<Vec<_>>()
+            }
+            Err(_) => return,
+        };
+
+        // Every waiter tries again, and those that don't get the permit wait again.
+        for (_, task) in waiters {
+            task.notify();
+        }
+    }
+}
+
+// ===== impl Acquire =====
+
+impl Future for Acquire {
+    type Item = Permit;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Permit, ()> {
+        let mut state = self.limit.0.state.lock().map_err(|_| ())?;
+        if state.active < self.limit.0.max {
+            state.active += 1;
+            if self.waiting.take().is_some() {
+                PENDING.fetch_sub(1, Ordering::Relaxed);
+            }
+            return Ok(Async::Ready(Permit(self.limit.clone())));
+        }
+
+        let id = match self.waiting {
+            Some(id) => id,
+            None => {
+                let id = state.next_id;
+                state.next_id = state.next_id.wrapping_add(1);
+                self.waiting = Some(id);
+                PENDING.fetch_add(1, Ordering::Relaxed);
+                id
+            }
+        };
+        state.waiters.retain(|&(waiter, _)| waiter != id);
+        state.waiters.push((id, task::current()));
+        Ok(Async::NotReady)
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if let Some(id) = self.waiting.take() {
+            PENDING.fetch_sub(1, Ordering::Relaxed);
+            if let Ok(mut state) = self.limit.0.state.lock() {
+                state.waiters.retain(|&(waiter, _)| waiter != id);
+            }
+        }
+    }
+}
+
+// ===== impl Permit =====
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, Async, Future};
+
+    use super::DialLimit;
+
+    #[test]
+    fn waits_for_earlier_attempts() {
+        future::lazy(|| {
+            let limit = DialLimit::new(1);
+            let mut first = limit.acquire();
+            let mut second = limit.acquire();
+
+            let permit = match first.poll() {
+                Ok(Async::Ready(permit)) => permit,
+                _ => panic!("first attempt must not wait"),
+            };
+            assert!(second.poll().unwrap().is_not_ready());
+
+            drop(permit);
+            assert!(second.poll().unwrap().is_ready());
+            Ok::<(), ()>(())
+        }).wait()
+            .unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+//! Forwards TCP connections opaquely, for protocols that the proxy doesn't handle.
+//!
+//! Bytes are copied in both directions until both peers have finished writing. Each
+//! peer's end of stream is passed on to the other, so protocols that half-close their
+//! connections work.
+
+use std::io;
+use std::net::SocketAddr;
+
+use futures::Future;
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use tokio_io::AsyncRead;
+use tokio_io::io::{copy, shutdown};
+
+use super::{set_nodelay, Keepalive};
+
+/// Connects to `dst` and copies bytes between it and `client`, setting `nodelay` and
+/// `keepalive` on the connection to `dst`.
+///
+/// Resolves to the number of bytes sent to `dst` and the number received from it.
+pub fn forward(
+    client: TcpStream,
+    dst: &SocketAddr,
+    nodelay: bool,
+    keepalive: Keepalive,
+    handle: &Handle,
+) -> Box<Future<Item = (u64, u64), Error = io::Error>> {
+    let f = TcpStream::connect(dst, handle).and_then(move |server| {
+        set_nodelay(&server, nodelay);
+        keepalive.apply(&server);
+        let (client_rx, client_tx) = client.split();
+        let (server_rx, server_tx) = server.split();
+        let sent = copy(client_rx, server_tx)
+            .and_then(|(n, _, server_tx)| shutdown(server_tx).map(move |_| n));
+        let received = copy(server_rx, client_tx)
+            .and_then(|(n, _, client_tx)| shutdown(client_tx).map(move |_| n));
+        sent.join(received)
+    });
+    Box::new(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+    use tokio_core::net::{TcpListener, TcpStream};
+    use tokio_core::reactor::Core;
+    use tokio_io::AsyncRead;
+    use tokio_io::io::{copy, read_to_end, shutdown, write_all};
+
+    use super::forward;
+    use transport::Keepalive;
+
+    #[test]
+    fn forwards_both_directions() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let any = "127.0.0.1:0".parse().unwrap();
+
+        // Echoes everything it receives, and then closes.
+        let upstream = TcpListener::bind(&any, &handle).unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+        let echo = upstream.incoming().take(1).for_each(|(socket, _)| {
+            let (rx, tx) = socket.split();
+            copy(rx, tx).and_then(|(_, _, tx)| shutdown(tx)).map(|_| ())
+        });
+        handle.spawn(echo.map_err(|e| panic!("echo failed: {}", e)));
+
+        let front = TcpListener::bind(&any, &handle).unwrap();
+        let front_addr = front.local_addr().unwrap();
+        let forwards = {
+            let handle = handle.clone();
+            front.incoming().take(1).for_each(move |(socket, _)| {
+                forward(socket, &upstream_addr, true, Keepalive::default(), &handle).map(|_| ())
+            })
+        };
+        handle.spawn(forwards.map_err(|e| panic!("forward failed: {}", e)));
+
+        let client = TcpStream::connect(&front_addr, &handle)
+            .and_then(|socket| write_all(socket, b"ping"))
+            .and_then(|(socket, _)| shutdown(socket))
+            .and_then(|socket| read_to_end(socket, Vec::new()));
+        let (_, received) = core.run(client).unwrap();
+        assert_eq!(received, b"ping");
+    }
+}
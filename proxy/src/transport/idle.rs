@@ -0,0 +1,166 @@
+use futures::{Async, Future, Poll};
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Records when bytes were last read from or written to an I/O.
+#[derive(Debug)]
+pub struct Idle<T> {
+    io: T,
+    last_active: Rc<Cell<Instant>>,
+}
+
+/// Completes once an `Idle` I/O has transferred no bytes for a timeout.
+///
+/// If no timeout is configured, this never completes.
+pub struct IdleTimeout {
+    last_active: Rc<Cell<Instant>>,
+    timer: Option<(Timeout, Duration)>,
+}
+
+// ===== impl Idle =====
+
+impl<T> Idle<T> {
+    pub fn new(io: T, timeout: Option<Duration>, handle: &Handle) -> (Self, IdleTimeout) {
+        let last_active = Rc::new(Cell::new(Instant::now()));
+        let timer = timeout.and_then(|t| match Timeout::new(t, handle) {
+            Ok(timer) => Some((timer, t)),
+            Err(e) => {
+                warn!("could not create idle timeout: {}", e);
+                None
+            }
+        });
+
+        let idle = Idle {
+            io,
+            last_active: last_active.clone(),
+        };
+        let timeout = IdleTimeout {
+            last_active,
+            timer,
+        };
+        (idle, timeout)
+    }
+
+    fn record(&self, result: io::Result<usize>) -> io::Result<usize> {
+        if let Ok(n) = result {
+            if n > 0 {
+                self.last_active.set(Instant::now());
+            }
+        }
+        result
+    }
+}
+
+impl<T: io::Read> io::Read for Idle<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.io.read(buf);
+        self.record(result)
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Idle<T> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.io.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<T: io::Write> io::Write for Idle<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.io.write(buf);
+        self.record(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Idle<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+// ===== impl IdleTimeout =====
+
+impl Future for IdleTimeout {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (timer, timeout) = match self.timer {
+            Some((ref mut timer, timeout)) => (timer, timeout),
+            None => return Ok(Async::NotReady),
+        };
+
+        loop {
+            match timer.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(())) => {}
+                Err(e) => {
+                    warn!("idle timeout failed: {}", e);
+                    return Err(());
+                }
+            }
+
+            // The connection may have been used since the timer was set, in which case
+            // it's rescheduled to fire once the connection has been idle for long enough.
+            let deadline = self.last_active.get() + timeout;
+            if deadline <= Instant::now() {
+                return Ok(Async::Ready(()));
+            }
+            timer.reset(deadline);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use tokio_core::reactor::Core;
+
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn expires_once_idle() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let start = Instant::now();
+        let (_io, timeout) = Idle::new(io::empty(), Some(Duration::from_millis(20)), &handle);
+        assert_eq!(core.run(timeout), Ok(()));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // Without a timeout, an idle connection is never closed.
+        let (_io, mut timeout) = Idle::new(io::empty(), None, &handle);
+        let polled = core.run(future::lazy(|| Ok::<_, ()>(timeout.poll())));
+        assert!(polled.unwrap().unwrap().is_not_ready());
+    }
+
+    #[test]
+    fn io_resets_the_timeout() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let start = Instant::now();
+        let io = io::Cursor::new(b"ping".to_vec());
+        let (mut io, timeout) = Idle::new(io, Some(Duration::from_millis(50)), &handle);
+
+        let read = Timeout::new(Duration::from_millis(30), &handle)
+            .unwrap()
+            .map(move |_| {
+                let mut buf = [0u8; 4];
+                assert_eq!(io.read(&mut buf).unwrap(), 4);
+            })
+            .map_err(|_| ());
+        handle.spawn(read);
+
+        assert_eq!(core.run(timeout), Ok(()));
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+}
@@ -0,0 +1,178 @@
+use std::io;
+use std::time::Duration;
+
+use tokio_core::net::TcpStream;
+
+/// TCP keepalive settings, so that connections to peers that disappear without closing
+/// them, e.g. behind a NAT that has dropped its mapping, are eventually closed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Keepalive {
+    /// How long a connection may be idle before probes are sent. Keepalive is disabled
+    /// unless this is set.
+    idle: Option<Duration>,
+
+    /// How long to wait between probes, if not the system's default.
+    interval: Option<Duration>,
+
+    /// How many unanswered probes close the connection, if not the system's default.
+    probes: Option<u32>,
+}
+
+// ===== impl Keepalive =====
+
+impl Keepalive {
+    pub fn new(idle: Option<Duration>, interval: Option<Duration>, probes: Option<u32>) -> Self {
+        Keepalive {
+            idle,
+            interval,
+            probes,
+        }
+    }
+
+    /// Enables keepalive on `tcp`, if it's configured.
+    ///
+    /// Failures are logged rather than returned, since the connection is still usable.
+    pub fn apply(&self, tcp: &TcpStream) {
+        let idle = match self.idle {
+            Some(idle) => idle,
+            None => return,
+        };
+
+        if let Err(e) = tcp.set_keepalive(Some(idle)) {
+            warn!(
+                "could not set SO_KEEPALIVE on {:?}/{:?}: {}",
+                tcp.local_addr(),
+                tcp.peer_addr(),
+                e
+            );
+            return;
+        }
+
+        if let Err(e) = set_probes(tcp, self.interval, self.probes) {
+            warn!(
+                "could not set keepalive probes on {:?}/{:?}: {}",
+                tcp.local_addr(),
+                tcp.peer_addr(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_probes(
+    _: &TcpStream,
+    interval: Option<Duration>,
+    probes: Option<u32>,
+) -> io::Result<()> {
+    if interval.is_some() || probes.is_some() {
+        debug!("no support for TCP_KEEPINTVL or TCP_KEEPCNT");
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_probes(
+    tcp: &TcpStream,
+    interval: Option<Duration>,
+    probes: Option<u32>,
+) -> io::Result<()> {
+    use libc;
+    use std::os::unix::io::AsRawFd;
+
+    let fd = tcp.as_raw_fd();
+    if let Some(interval) = interval {
+        // The interval is in whole seconds, and must be at least one.
+        let secs = ::std::cmp::max(interval.as_secs(), 1);
+        unsafe { linux::set_tcp_opt(fd, libc::TCP_KEEPINTVL, secs as libc::c_int)? };
+    }
+    if let Some(probes) = probes {
+        unsafe { linux::set_tcp_opt(fd, libc::TCP_KEEPCNT, probes as libc::c_int)? };
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use libc;
+    use std::{io, mem};
+    use std::os::unix::io::RawFd;
+
+    pub unsafe fn set_tcp_opt(fd: RawFd, opt: libc::c_int, value: libc::c_int) -> io::Result<()> {
+        let ret = libc::setsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            opt,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        );
+        if ret == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener as StdTcpListener, TcpStream as StdTcpStream};
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    fn connect(core: &Core) -> (TcpStream, StdTcpListener) {
+        let listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = StdTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (TcpStream::from_stream(stream, &core.handle()).unwrap(), listener)
+    }
+
+    #[test]
+    fn enables_keepalive_only_if_configured() {
+        let core = Core::new().unwrap();
+
+        let (tcp, _listener) = connect(&core);
+        Keepalive::default().apply(&tcp);
+        assert_eq!(tcp.keepalive().unwrap(), None);
+
+        let (tcp, _listener) = connect(&core);
+        Keepalive::new(Some(Duration::from_secs(30)), None, None).apply(&tcp);
+        assert_eq!(tcp.keepalive().unwrap(), Some(Duration::from_secs(30)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sets_probe_interval_and_count() {
+        use libc;
+        use std::mem;
+        use std::os::unix::io::AsRawFd;
+
+        fn tcp_opt(tcp: &TcpStream, opt: libc::c_int) -> libc::c_int {
+            let mut value: libc::c_int = 0;
+            let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+            let ret = unsafe {
+                libc::getsockopt(
+                    tcp.as_raw_fd(),
+                    libc::IPPROTO_TCP,
+                    opt,
+                    &mut value as *mut _ as *mut libc::c_void,
+                    &mut len,
+                )
+            };
+            assert_eq!(ret, 0, "getsockopt failed: {}", io::Error::last_os_error());
+            value
+        }
+
+        let core = Core::new().unwrap();
+        let (tcp, _listener) = connect(&core);
+        let idle = Some(Duration::from_secs(30));
+        Keepalive::new(idle, Some(Duration::from_secs(5)), Some(3)).apply(&tcp);
+        assert_eq!(tcp_opt(&tcp, libc::TCP_KEEPINTVL), 5);
+        assert_eq!(tcp_opt(&tcp, libc::TCP_KEEPCNT), 3);
+
+        // Intervals are rounded down to whole seconds, but never to zero.
+        let (tcp, _listener) = connect(&core);
+        Keepalive::new(idle, Some(Duration::from_millis(500)), None).apply(&tcp);
+        assert_eq!(tcp_opt(&tcp, libc::TCP_KEEPINTVL), 1);
+    }
+}
@@ -0,0 +1,232 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+
+/// The backlog that `std::net::TcpListener::bind` listens with.
+const DEFAULT_BACKLOG: usize = 128;
+
+/// Binds a listener to `addr`.
+///
+/// If `reuse_port` is set, `SO_REUSEPORT` is set on the socket before it's bound, so
+/// that other sockets with the option set may bind the same port and share its
+/// connections. This is only supported on Linux.
+///
+/// If `addr` is an IPv6 address, `dual_stack` determines whether the listener also
+/// accepts IPv4 clients, as IPv4-mapped addresses, rather than leaving it to the
+/// system's default, e.g. Linux's `net.ipv6.bindv6only`.
+///
+/// `backlog` limits the connections that have been established but not yet accepted,
+/// if not the default of 128. The system may lower it, e.g. to Linux's
+/// `net.core.somaxconn`.
+pub fn bind(
+    addr: &SocketAddr,
+    reuse_port: bool,
+    dual_stack: bool,
+    backlog: Option<usize>,
+) -> io::Result<TcpListener> {
+    if !reuse_port && addr.is_ipv4() && backlog.is_none() {
+        return TcpListener::bind(addr);
+    }
+
+    bind_socket(addr, reuse_port, dual_stack, backlog.unwrap_or(DEFAULT_BACKLOG))
+}
+
+/// Returns the number of connections that have been dropped because a listener's accept
+/// queue was full, if the system reports it.
+///
+/// Linux counts these for the whole network namespace, e.g. a pod, rather than for each
+/// listener.
+#[cfg(not(target_os = "linux"))]
+pub fn accept_queue_overflows() -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+pub fn accept_queue_overflows() -> Option<u64> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut netstat = String::new();
+    File::open("/proc/net/netstat")
+        .and_then(|mut f| f.read_to_string(&mut netstat))
+        .ok()
+        .and_then(|_| parse_listen_overflows(&netstat))
+}
+
+/// Reads `ListenOverflows` from the contents of `/proc/net/netstat`, in which each line of
+/// counter names is followed by a line of their values.
+#[cfg(any(target_os = "linux", test))]
+fn parse_listen_overflows(netstat: &str) -> Option<u64> {
+    let mut lines = netstat.lines();
+    while let Some(names) = lines.next() {
+        let values = match lines.next() {
+            Some(values) => values,
+            None => return None,
+        };
+        if !names.starts_with("TcpExt:") {
+            continue;
+        }
+        return names
+            .split_whitespace()
+            .position(|name| name == "ListenOverflows")
+            .and_then(|i| values.split_whitespace().nth(i))
+            .and_then(|value| value.parse().ok());
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_socket(addr: &SocketAddr, reuse_port: bool, _: bool, _: usize) -> io::Result<TcpListener> {
+    if reuse_port {
+        return Err(io::Error::new(io::ErrorKind::Other, "no support for SO_REUSEPORT"));
+    }
+    debug!("no support for IPV6_V6ONLY or setting the accept backlog");
+    TcpListener::bind(addr)
+}
+
+#[cfg(target_os = "linux")]
+fn bind_socket(
+    addr: &SocketAddr,
+    reuse_port: bool,
+    dual_stack: bool,
+    backlog: usize,
+) -> io::Result<TcpListener> {
+    use libc;
+    use std::os::unix::io::FromRawFd;
+
+    let family = match *addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let fd = unsafe { libc::socket(family, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // Owns the socket, so that it's closed if any of the following steps fail.
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+    unsafe {
+        linux::set_opt(fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+        if reuse_port {
+            linux::set_opt(fd, libc::SOL_SOCKET, libc::SO_REUSEPORT, 1)?;
+        }
+        if addr.is_ipv6() {
+            let v6_only = if dual_stack { 0 } else { 1 };
+            linux::set_opt(fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, v6_only)?;
+        }
+        linux::bind(fd, addr)?;
+        // Larger backlogs are clamped by the kernel anyway.
+        let backlog = ::std::cmp::min(backlog, libc::c_int::max_value() as usize);
+        if libc::listen(fd, backlog as libc::c_int) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(listener)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use libc;
+    use std::{io, mem};
+    use std::net::SocketAddr;
+    use std::os::unix::io::RawFd;
+
+    pub unsafe fn set_opt(
+        fd: RawFd,
+        level: libc::c_int,
+        opt: libc::c_int,
+        value: libc::c_int,
+    ) -> io::Result<()> {
+        let ret = libc::setsockopt(
+            fd,
+            level,
+            opt,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        );
+        if ret == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub unsafe fn bind(fd: RawFd, addr: &SocketAddr) -> io::Result<()> {
+        let ret = match *addr {
+            SocketAddr::V4(ref a) => {
+                let mut sa: libc::sockaddr_in = mem::zeroed();
+                sa.sin_family = libc::AF_INET as libc::sa_family_t;
+                sa.sin_port = a.port().to_be();
+                sa.sin_addr.s_addr = u32::from(*a.ip()).to_be();
+                libc::bind(
+                    fd,
+                    &sa as *const _ as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                )
+            }
+            SocketAddr::V6(ref a) => {
+                let mut sa: libc::sockaddr_in6 = mem::zeroed();
+                sa.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sa.sin6_port = a.port().to_be();
+                sa.sin6_addr.s6_addr = a.ip().octets();
+                sa.sin6_flowinfo = a.flowinfo();
+                sa.sin6_scope_id = a.scope_id();
+                libc::bind(
+                    fd,
+                    &sa as *const _ as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                )
+            }
+        };
+        if ret == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_listen_overflows() {
+        let netstat = "\
+            TcpExt: SyncookiesSent ListenOverflows ListenDrops\n\
+            TcpExt: 0 17 21\n\
+            IpExt: InNoRoutes InTruncatedPkts\n\
+            IpExt: 0 0\n";
+        assert_eq!(parse_listen_overflows(netstat), Some(17));
+        assert_eq!(parse_listen_overflows("IpExt: InNoRoutes\nIpExt: 0\n"), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn shares_ports_with_reuse_port() {
+        let first = bind(&"127.0.0.1:0".parse().unwrap(), true, true, None).unwrap();
+        let addr = first.local_addr().unwrap();
+
+        let second = bind(&addr, true, true, None).expect("bind with SO_REUSEPORT");
+        assert_eq!(second.local_addr().unwrap(), addr);
+
+        assert!(bind(&addr, false, true, None).is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn accepts_ipv4_clients_when_dual_stack() {
+        use std::net::TcpStream;
+
+        let connects_over_ipv4 = |dual_stack: bool| {
+            // Hosts without IPv6 can't tell the difference.
+            let listener = match bind(&"[::]:0".parse().unwrap(), false, dual_stack, None) {
+                Ok(listener) => listener,
+                Err(_) => return dual_stack,
+            };
+            let port = listener.local_addr().unwrap().port();
+            TcpStream::connect(("127.0.0.1", port)).is_ok()
+        };
+
+        assert!(connects_over_ipv4(true));
+        assert!(!connects_over_ipv4(false));
+    }
+}
@@ -1,5 +1,27 @@
+mod accept_timeout;
+mod buffer_sizes;
 mod connect;
+mod dial_limit;
+mod forward;
+mod idle;
+mod keepalive;
+mod listen;
+mod prefixed;
+mod proxy_protocol;
 mod so_original_dst;
+mod socks;
 
-pub use self::connect::{Connect, LookupAddressAndConnect, TimeoutConnect, TimeoutError};
-pub use self::so_original_dst::get_original_dst;
+pub use self::accept_timeout::{AcceptTimeout, FirstRequest};
+pub use self::buffer_sizes::BufferSizes;
+pub use self::connect::{
+    set_nodelay, Connect, LookupAddressAndConnect, TimeoutConnect, TimeoutError,
+};
+pub use self::dial_limit::{pending_dials, DialLimit};
+pub use self::forward::forward;
+pub use self::idle::{Idle, IdleTimeout};
+pub use self::keepalive::Keepalive;
+pub use self::listen::{accept_queue_overflows, bind as bind_listener};
+pub use self::prefixed::Prefixed;
+pub use self::proxy_protocol::handshake as proxy_protocol_handshake;
+pub use self::so_original_dst::{get_original_dst, lookup_failures as original_dst_lookup_failures};
+pub use self::socks::handshake as socks_handshake;
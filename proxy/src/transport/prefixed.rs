@@ -0,0 +1,79 @@
+use futures::Poll;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::io;
+
+/// An I/O from which some bytes have already been read, e.g. to detect its protocol.
+///
+/// Those bytes are read again before any more are read from the I/O.
+#[derive(Debug)]
+pub struct Prefixed<T> {
+    prefix: Vec<u8>,
+    pos: usize,
+    io: T,
+}
+
+// ===== impl Prefixed =====
+
+impl<T> Prefixed<T> {
+    pub fn new(prefix: Vec<u8>, io: T) -> Self {
+        Prefixed {
+            prefix,
+            pos: 0,
+            io,
+        }
+    }
+}
+
+impl<T: io::Read> io::Read for Prefixed<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.prefix.len() {
+            let n = ::std::cmp::min(buf.len(), self.prefix.len() - self.pos);
+            buf[..n].copy_from_slice(&self.prefix[self.pos..self.pos + n]);
+            self.pos += n;
+            return Ok(n);
+        }
+
+        self.io.read(buf)
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Prefixed<T> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.io.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<T: io::Write> io::Write for Prefixed<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Prefixed<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn reads_prefix_first() {
+        let mut io = Prefixed::new(b"GET".to_vec(), &b" / HTTP/1.1"[..]);
+        let mut buf = [0u8; 2];
+        assert_eq!(io.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf, b"GE");
+
+        let mut rest = String::new();
+        io.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "T / HTTP/1.1");
+    }
+}
@@ -0,0 +1,223 @@
+//! Server-side PROXY protocol, versions 1 and 2.
+//!
+//! Load balancers that terminate TCP, e.g. AWS NLB or HAProxy, send a PROXY header
+//! before any of the client's bytes, carrying the address of the client that connected
+//! to them. Listeners configured to expect the header require it on every connection: a
+//! connection without one is closed, rather than trusting whatever the client sends.
+//!
+//! See <https://www.haproxy.org/download/1.8/doc/proxy-protocol.txt>.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str;
+
+use futures::{future, Future};
+use tokio_io::io::read_exact;
+use tokio_io::AsyncRead;
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// The longest version 1 header, including its prefix and trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+const V2_VERSION: u8 = 0x20;
+const V2_CMD_LOCAL: u8 = 0x0;
+const V2_CMD_PROXY: u8 = 0x1;
+
+const V2_TCP4: u8 = 0x11;
+const V2_TCP6: u8 = 0x21;
+
+/// Resolves to a stream, positioned after its PROXY header, and the client's address.
+type Header<T> = Box<Future<Item = (T, Option<SocketAddr>), Error = io::Error>>;
+
+/// Reads a PROXY header from `io`.
+///
+/// Resolves to the stream, positioned after the header, and the address of the client
+/// that the header describes. That address is `None` when the header doesn't describe a
+/// proxied TCP connection, e.g. for a load balancer's own health checks, in which case
+/// the connection's own peer address should be used.
+pub fn handshake<T>(io: T) -> Header<T>
+where
+    T: AsyncRead + 'static,
+{
+    // Both versions' headers are at least this long, and they differ from the start.
+    let f = read_exact(io, [0u8; 6]).and_then(|(io, start)| {
+        if start[..] == V1_PREFIX[..] {
+            read_v1(io)
+        } else if start[..] == V2_SIGNATURE[..6] {
+            read_v2(io)
+        } else {
+            let f: Header<T> = Box::new(future::err(invalid("missing PROXY protocol header")));
+            f
+        }
+    });
+
+    Box::new(f)
+}
+
+/// Reads the rest of a version 1 header, one byte at a time so that none of the
+/// client's bytes are consumed.
+fn read_v1<T>(io: T) -> Header<T>
+where
+    T: AsyncRead + 'static,
+{
+    let f = future::loop_fn((io, Vec::new()), |(io, mut line)| {
+        read_exact(io, [0u8; 1]).and_then(move |(io, b)| {
+            line.push(b[0]);
+            if line.ends_with(b"\r\n") {
+                let src = parse_v1(&line[..line.len() - 2])?;
+                return Ok(future::Loop::Break((io, src)));
+            }
+            if V1_PREFIX.len() + line.len() >= V1_MAX_LEN {
+                return Err(invalid("PROXY protocol header is too long"));
+            }
+            Ok(future::Loop::Continue((io, line)))
+        })
+    });
+
+    Box::new(f)
+}
+
+/// Reads the rest of a version 2 header.
+fn read_v2<T>(io: T) -> Header<T>
+where
+    T: AsyncRead + 'static,
+{
+    let f = read_exact(io, [0u8; 10]).and_then(|(io, head)| {
+        if head[..6] != V2_SIGNATURE[6..] {
+            return future::Either::A(future::err(invalid("missing PROXY protocol header")));
+        }
+        let (ver_cmd, family) = (head[6], head[7]);
+        let len = (usize::from(head[8]) << 8) | usize::from(head[9]);
+        let f = read_exact(io, vec![0u8; len]).and_then(move |(io, addrs)| {
+            let src = parse_v2(ver_cmd, family, &addrs)?;
+            Ok((io, src))
+        });
+        future::Either::B(f)
+    });
+
+    Box::new(f)
+}
+
+/// Parses a version 1 header, without its `PROXY ` prefix or trailing CRLF.
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = str::from_utf8(line).map_err(|_| invalid("invalid PROXY protocol header"))?;
+    let fields = line.split(' ').collect::<Vec<_>>();
+    let v6 = match fields[0] {
+        "TCP4" => false,
+        "TCP6" => true,
+        // The rest of the line is ignored, per the specification.
+        "UNKNOWN" => return Ok(None),
+        _ => return Err(invalid("unsupported PROXY protocol")),
+    };
+    if fields.len() != 5 {
+        return Err(invalid("invalid PROXY protocol header"));
+    }
+
+    let addr = |ip: &str, port: &str| -> io::Result<SocketAddr> {
+        let ip = ip.parse::<IpAddr>().map_err(|_| invalid("invalid PROXY address"))?;
+        let port = port.parse::<u16>().map_err(|_| invalid("invalid PROXY port"))?;
+        if ip.is_ipv6() != v6 {
+            return Err(invalid("PROXY address doesn't match its protocol"));
+        }
+        Ok(SocketAddr::new(ip, port))
+    };
+    let src = addr(fields[1], fields[3])?;
+    addr(fields[2], fields[4])?;
+    Ok(Some(src))
+}
+
+/// Parses the command, address family, and addresses of a version 2 header.
+fn parse_v2(ver_cmd: u8, family: u8, addrs: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if ver_cmd & 0xf0 != V2_VERSION {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    match ver_cmd & 0x0f {
+        V2_CMD_LOCAL => return Ok(None),
+        V2_CMD_PROXY => {}
+        _ => return Err(invalid("unsupported PROXY protocol command")),
+    }
+
+    let port = |b: &[u8]| (u16::from(b[0]) << 8) | u16::from(b[1]);
+    match family {
+        V2_TCP4 if addrs.len() >= 12 => {
+            let ip = Ipv4Addr::new(addrs[0], addrs[1], addrs[2], addrs[3]);
+            Ok(Some(SocketAddr::from((ip, port(&addrs[8..10])))))
+        }
+        V2_TCP6 if addrs.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&addrs[..16]);
+            Ok(Some(SocketAddr::from((Ipv6Addr::from(octets), port(&addrs[32..34])))))
+        }
+        V2_TCP4 | V2_TCP6 => Err(invalid("truncated PROXY addresses")),
+        // Other transports, e.g. UDP or UNIX sockets, aren't proxied as TCP connections.
+        _ => Ok(None),
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(bytes: &[u8]) -> io::Result<(Option<SocketAddr>, Vec<u8>)> {
+        handshake(io::Cursor::new(bytes.to_vec()))
+            .wait()
+            .map(|(io, src)| {
+                let pos = io.position() as usize;
+                (src, io.into_inner()[pos..].to_vec())
+            })
+    }
+
+    #[test]
+    fn reads_v1_headers() {
+        let (src, rest) = read(b"PROXY TCP4 10.1.2.3 10.0.0.1 51234 443\r\nPRI").unwrap();
+        assert_eq!(src, Some("10.1.2.3:51234".parse().unwrap()));
+        assert_eq!(rest, b"PRI".to_vec());
+
+        let (src, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 51234 443\r\n").unwrap();
+        assert_eq!(src, Some("[2001:db8::1]:51234".parse().unwrap()));
+
+        let (src, rest) = read(b"PROXY UNKNOWN ignored\r\nPRI").unwrap();
+        assert_eq!(src, None);
+        assert_eq!(rest, b"PRI".to_vec());
+
+        assert!(read(b"PROXY TCP4 2001:db8::1 10.0.0.1 51234 443\r\n").is_err());
+        assert!(read(b"PROXY TCP4 10.1.2.3 10.0.0.1 51234\r\n").is_err());
+        assert!(read(b"PROXY UDP4 10.1.2.3 10.0.0.1 51234 443\r\n").is_err());
+
+        let mut long = b"PROXY ".to_vec();
+        long.extend_from_slice(&[b'A'; 200]);
+        assert!(read(&long).is_err());
+    }
+
+    #[test]
+    fn reads_v2_headers() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, V2_TCP4, 0, 12]);
+        header.extend_from_slice(&[10, 1, 2, 3, 10, 0, 0, 1, 0xc8, 0x22, 0x01, 0xbb]);
+        header.extend_from_slice(b"PRI");
+        let (src, rest) = read(&header).unwrap();
+        assert_eq!(src, Some("10.1.2.3:51234".parse().unwrap()));
+        assert_eq!(rest, b"PRI".to_vec());
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0, 0, 0]);
+        assert_eq!(read(&local).unwrap().0, None);
+
+        let mut truncated = V2_SIGNATURE.to_vec();
+        truncated.extend_from_slice(&[0x21, V2_TCP6, 0, 12]);
+        truncated.extend_from_slice(&[0; 12]);
+        assert!(read(&truncated).is_err());
+    }
+
+    #[test]
+    fn requires_a_header() {
+        assert!(read(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n").is_err());
+    }
+}
@@ -1,6 +1,20 @@
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use tokio_core::net::TcpStream;
 
+/// Counts failed `SO_ORIGINAL_DST` lookups, which usually indicate a problem with the
+/// iptables rules that redirect connections to the proxy.
+///
+/// Lookups aren't cached: a (source, destination) 4-tuple identifies a single
+/// connection, and a reused tuple may have been redirected from a different original
+/// destination.
+static LOOKUP_FAILURES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Returns the number of failed `SO_ORIGINAL_DST` lookups.
+pub fn lookup_failures() -> usize {
+    LOOKUP_FAILURES.load(Ordering::Relaxed)
+}
+
 #[cfg(not(target_os = "linux"))]
 pub fn get_original_dst(_: &TcpStream) -> Option<SocketAddr> {
     debug!("no support for SO_ORIGINAL_DST");
@@ -16,6 +30,9 @@ pub fn get_original_dst(sock: &TcpStream) -> Option<SocketAddr> {
     debug!("get_original_dst {:?}", sock);
 
     let res = unsafe { linux::so_original_dst(sock.as_raw_fd()) };
+    if res.is_err() {
+        LOOKUP_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
     res.ok()
 }
 
@@ -0,0 +1,253 @@
+//! Server-side SOCKS5 negotiation (RFC 1928).
+//!
+//! Only the `CONNECT` command with no authentication is supported. Once negotiation
+//! completes, the client is expected to speak HTTP/2 over the same connection, which the
+//! proxy then routes like any other outbound connection.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use futures::{future, Future};
+use tokio_io::io::{read_exact, write_all};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+const VERSION: u8 = 5;
+
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xff;
+
+const CMD_CONNECT: u8 = 0x01;
+
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+const REPLY_SUCCEEDED: u8 = 0x00;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REPLY_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+/// Negotiates a SOCKS5 `CONNECT` on `io`.
+///
+/// Resolves to the negotiated stream and, if the client requested an IP address rather
+/// than a domain name, the requested destination.
+pub fn handshake<T>(io: T) -> Box<Future<Item = (T, Option<SocketAddr>), Error = io::Error>>
+where
+    T: AsyncRead + AsyncWrite + 'static,
+{
+    let f = read_exact(io, [0u8; 2])
+        .and_then(|(io, buf)| {
+            let (version, nmethods) = (buf[0], buf[1]);
+            if version != VERSION {
+                return future::Either::A(future::err(invalid("unsupported SOCKS version")));
+            }
+            future::Either::B(read_exact(io, vec![0u8; nmethods as usize]))
+        })
+        .and_then(|(io, methods)| {
+            if !methods.contains(&METHOD_NO_AUTH) {
+                let f = write_all(io, [VERSION, METHOD_NONE_ACCEPTABLE]).and_then(|_| {
+                    Err::<(T, [u8; 2]), _>(invalid("no acceptable SOCKS method"))
+                });
+                return future::Either::A(f);
+            }
+            future::Either::B(write_all(io, [VERSION, METHOD_NO_AUTH]))
+        })
+        .and_then(|(io, _)| read_exact(io, [0u8; 4]))
+        .and_then(|(io, buf)| {
+            let (version, cmd, atyp) = (buf[0], buf[1], buf[3]);
+            if version != VERSION {
+                let f = future::err(invalid("unsupported SOCKS version"));
+                return future::Either::A(future::Either::A(f));
+            }
+            if cmd != CMD_CONNECT {
+                let f = write_all(io, reply(REPLY_COMMAND_NOT_SUPPORTED)).and_then(|_| {
+                    Err::<(T, Result<Option<SocketAddr>, u8>), _>(invalid(
+                        "unsupported SOCKS command",
+                    ))
+                });
+                return future::Either::A(future::Either::B(f));
+            }
+            future::Either::B(read_dst(io, atyp))
+        })
+        .and_then(|(io, dst)| match dst {
+            Err(atyp) => {
+                debug!("unsupported SOCKS address type: {}", atyp);
+                let f = write_all(io, reply(REPLY_ADDRESS_TYPE_NOT_SUPPORTED)).and_then(|_| {
+                    Err::<(T, Option<SocketAddr>), _>(invalid("unsupported SOCKS address"))
+                });
+                future::Either::A(f)
+            }
+            Ok(dst) => {
+                trace!("SOCKS CONNECT {:?}", dst);
+                let f = write_all(io, reply(REPLY_SUCCEEDED)).map(move |(io, _)| (io, dst));
+                future::Either::B(f)
+            }
+        });
+
+    Box::new(f)
+}
+
+/// Reads the destination address of a request.
+///
+/// Resolves to `Err(atyp)` if the address type is not supported.
+fn read_dst<T>(
+    io: T,
+    atyp: u8,
+) -> Box<Future<Item = (T, Result<Option<SocketAddr>, u8>), Error = io::Error>>
+where
+    T: AsyncRead + 'static,
+{
+    match atyp {
+        ATYP_IPV4 => Box::new(read_exact(io, [0u8; 6]).map(|(io, b)| {
+            let ip = Ipv4Addr::new(b[0], b[1], b[2], b[3]);
+            let port = (u16::from(b[4]) << 8) | u16::from(b[5]);
+            (io, Ok(Some(SocketAddr::from((ip, port)))))
+        })),
+        ATYP_IPV6 => Box::new(read_exact(io, [0u8; 18]).map(|(io, b)| {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&b[..16]);
+            let ip = Ipv6Addr::from(octets);
+            let port = (u16::from(b[16]) << 8) | u16::from(b[17]);
+            (io, Ok(Some(SocketAddr::from((ip, port)))))
+        })),
+        ATYP_DOMAIN => Box::new(
+            read_exact(io, [0u8; 1])
+                .and_then(|(io, len)| read_exact(io, vec![0u8; len[0] as usize + 2]))
+                .map(|(io, _)| (io, Ok(None))),
+        ),
+        atyp => Box::new(future::ok((io, Err(atyp)))),
+    }
+}
+
+/// Builds a reply with an unspecified bound address.
+fn reply(code: u8) -> [u8; 10] {
+    [VERSION, code, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0]
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{Async, Poll};
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::rc::Rc;
+
+    const GREETING: &[u8] = &[VERSION, 1, METHOD_NO_AUTH];
+
+    /// Reads from `input`, and records what's written so that it can be checked even if
+    /// the handshake fails and drops the I/O.
+    struct Mock {
+        input: io::Cursor<Vec<u8>>,
+        written: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl Read for Mock {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl AsyncRead for Mock {}
+
+    impl Write for Mock {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncWrite for Mock {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    fn negotiate(bytes: &[u8]) -> (io::Result<Option<SocketAddr>>, Vec<u8>) {
+        let written = Rc::new(RefCell::new(Vec::new()));
+        let io = Mock {
+            input: io::Cursor::new(bytes.to_vec()),
+            written: written.clone(),
+        };
+        let dst = handshake(io).wait().map(|(_, dst)| dst);
+        let written = written.borrow().clone();
+        (dst, written)
+    }
+
+    fn request(cmd: u8, atyp: u8, addr: &[u8]) -> Vec<u8> {
+        let mut bytes = GREETING.to_vec();
+        bytes.extend_from_slice(&[VERSION, cmd, 0, atyp]);
+        bytes.extend_from_slice(addr);
+        bytes
+    }
+
+    fn replied(code: u8) -> Vec<u8> {
+        let mut bytes = vec![VERSION, METHOD_NO_AUTH];
+        bytes.extend_from_slice(&reply(code));
+        bytes
+    }
+
+    #[test]
+    fn rejects_greetings_without_an_acceptable_method() {
+        let (dst, written) = negotiate(&[VERSION, 2, 0x01, 0x02]);
+        assert!(dst.is_err());
+        assert_eq!(written, vec![VERSION, METHOD_NONE_ACCEPTABLE]);
+
+        let (dst, written) = negotiate(&[4, 1, METHOD_NO_AUTH]);
+        assert!(dst.is_err());
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn connects_to_ipv4_addrs() {
+        let addr = [10, 0, 0, 1, 0x1f, 0x90];
+        let (dst, written) = negotiate(&request(CMD_CONNECT, ATYP_IPV4, &addr));
+        assert_eq!(dst.unwrap(), Some("10.0.0.1:8080".parse().unwrap()));
+        assert_eq!(written, vec![VERSION, 0x00, VERSION, 0x00, 0, 0x01, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn connects_to_ipv6_addrs() {
+        let mut addr = vec![0x20, 0x01, 0x0d, 0xb8];
+        addr.extend_from_slice(&[0; 11]);
+        addr.extend_from_slice(&[0x01, 0x1f, 0x90]);
+        let (dst, written) = negotiate(&request(CMD_CONNECT, ATYP_IPV6, &addr));
+        assert_eq!(dst.unwrap(), Some("[2001:db8::1]:8080".parse().unwrap()));
+        assert_eq!(written, replied(REPLY_SUCCEEDED));
+    }
+
+    #[test]
+    fn connects_to_domains_without_a_destination() {
+        let mut addr = vec![11];
+        addr.extend_from_slice(b"example.com");
+        addr.extend_from_slice(&[0x1f, 0x90]);
+        let (dst, written) = negotiate(&request(CMD_CONNECT, ATYP_DOMAIN, &addr));
+        assert_eq!(dst.unwrap(), None);
+        assert_eq!(written, replied(REPLY_SUCCEEDED));
+    }
+
+    #[test]
+    fn fails_on_truncated_requests() {
+        let (dst, written) = negotiate(&request(CMD_CONNECT, ATYP_IPV4, &[10, 0]));
+        assert_eq!(dst.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(written, vec![VERSION, METHOD_NO_AUTH]);
+    }
+
+    #[test]
+    fn replies_to_unsupported_requests() {
+        let bind = 0x02;
+        let (dst, written) = negotiate(&request(bind, ATYP_IPV4, &[10, 0, 0, 1, 0x1f, 0x90]));
+        assert!(dst.is_err());
+        assert_eq!(written, replied(REPLY_COMMAND_NOT_SUPPORTED));
+
+        let (dst, written) = negotiate(&request(CMD_CONNECT, 0x05, &[]));
+        assert!(dst.is_err());
+        assert_eq!(written, replied(REPLY_ADDRESS_TYPE_NOT_SUPPORTED));
+    }
+}
@@ -0,0 +1,131 @@
+//! Releases memory held for past requests, once the proxy has been idle.
+//!
+//! The sidecar of a bursty workload, e.g. a cron job, would otherwise hold on to the
+//! memory of its busiest moment until it exits. Once no requests have been received for
+//! the configured period, every listener's cached routes are dropped, which closes their
+//! upstream connections and releases their buffers and destination watches, and the
+//! allocator is asked to return free memory to the OS. Routes are rebuilt as requests
+//! arrive.
+//!
+//! Responses that are still streaming when routes are dropped aren't interrupted.
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use futures::{Future, Stream};
+use tokio_core::reactor::{Handle, Interval};
+
+/// Counts requests, and holds what to release once they stop.
+///
+/// All clones share the count and the trims.
+#[derive(Clone, Default)]
+pub struct Idle {
+    requests: Arc<AtomicUsize>,
+    trims: Rc<RefCell<Vec<Box<Fn() -> usize>>>>,
+}
+
+/// Counts requests, on any thread.
+#[derive(Clone, Debug, Default)]
+pub struct Activity(Arc<AtomicUsize>);
+
+// ===== impl Idle =====
+
+impl Idle {
+    pub fn activity(&self) -> Activity {
+        Activity(self.requests.clone())
+    }
+
+    /// Adds something to release when the proxy is idle, which returns how many
+    /// cached items it dropped.
+    pub fn on_idle<F: Fn() -> usize + 'static>(&self, trim: F) {
+        self.trims.borrow_mut().push(Box::new(trim));
+    }
+
+    /// Releases memory each time no requests have been received for `period`.
+    ///
+    /// Memory is released once per idle period, rather than every `period` while the
+    /// proxy stays idle.
+    pub fn trim_after(
+        &self,
+        period: Duration,
+        executor: &Handle,
+    ) -> io::Result<Box<Future<Item = (), Error = ()>>> {
+        let idle = self.clone();
+        let mut last = idle.requests.load(Ordering::Relaxed);
+        let mut trimmed = false;
+        let f = Interval::new(period, executor)?
+            .for_each(move |()| {
+                let requests = idle.requests.load(Ordering::Relaxed);
+                if requests != last {
+                    last = requests;
+                    trimmed = false;
+                } else if !trimmed {
+                    trimmed = true;
+                    idle.trim();
+                }
+                Ok(())
+            })
+            .map_err(|e| error!("idle trim timer failed: {}", e));
+        Ok(Box::new(f))
+    }
+
+    fn trim(&self) {
+        let dropped = self.trims.borrow().iter().map(|trim| trim()).sum::<usize>();
+        release_free_memory();
+        debug!("idle; dropped {} cached routes", dropped);
+    }
+}
+
+// ===== impl Activity =====
+
+impl Activity {
+    pub fn request(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Asks the allocator to return the free memory at the top of the heap to the OS.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn release_free_memory() {
+    use libc;
+
+    extern "C" {
+        fn malloc_trim(pad: libc::size_t) -> libc::c_int;
+    }
+
+    unsafe {
+        malloc_trim(0);
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn release_free_memory() {}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::Idle;
+
+    #[test]
+    fn trims_everything_registered() {
+        let idle = Idle::default();
+        let calls = Rc::new(Cell::new(0));
+        for _ in 0..2 {
+            let calls = calls.clone();
+            idle.on_idle(move || {
+                calls.set(calls.get() + 1);
+                3
+            });
+        }
+
+        idle.activity().request();
+        idle.trim();
+        assert_eq!(calls.get(), 2);
+    }
+}
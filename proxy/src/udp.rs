@@ -0,0 +1,243 @@
+//! Relays UDP datagrams for workloads that don't speak HTTP/2.
+//!
+//! Each `Relay` listens on a single socket and forwards datagrams to a fixed target.
+//! Datagrams are grouped into flows by their source address; each flow is given its own
+//! upstream socket so that replies from the target can be returned to the right peer.
+//! Flows that are idle for `IDLE_TIMEOUT` are closed.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket as StdUdpSocket};
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll, Stream};
+use tokio_core::net::UdpSocket;
+use tokio_core::reactor::{Handle, Interval};
+
+/// How long a flow may be idle before it is closed.
+const IDLE_TIMEOUT_SECS: u64 = 60;
+
+/// The maximum number of concurrent flows per relay.
+const MAX_FLOWS: usize = 1_024;
+
+const MAX_DATAGRAM_LEN: usize = 64 * 1024;
+
+/// Forwards datagrams received on a socket to `target`.
+pub struct Relay {
+    socket: UdpSocket,
+    target: SocketAddr,
+    flows: HashMap<SocketAddr, Flow>,
+    expire: Interval,
+    executor: Handle,
+    buf: Vec<u8>,
+}
+
+/// Tracks datagrams exchanged with a single peer.
+struct Flow {
+    upstream: UdpSocket,
+    opened_at: Instant,
+    last_active: Instant,
+    stats: FlowStats,
+}
+
+#[derive(Debug, Default)]
+struct FlowStats {
+    rx_packets: u64,
+    rx_bytes: u64,
+    tx_packets: u64,
+    tx_bytes: u64,
+    dropped_packets: u64,
+}
+
+// ===== impl Relay =====
+
+impl Relay {
+    /// Relays datagrams received on `socket`, which has already been bound, so that its
+    /// address is known before the relay runs.
+    pub fn new(socket: StdUdpSocket, target: SocketAddr, executor: &Handle) -> io::Result<Self> {
+        let socket = UdpSocket::from_socket(socket, executor)?;
+        let expire = Interval::new(Duration::from_secs(IDLE_TIMEOUT_SECS / 2), executor)?;
+        info!("relaying UDP from {} to {}", socket.local_addr()?, target);
+        Ok(Relay {
+            socket,
+            target,
+            flows: HashMap::new(),
+            expire,
+            executor: executor.clone(),
+            buf: vec![0; MAX_DATAGRAM_LEN],
+        })
+    }
+
+    /// Forwards datagrams from downstream peers to the target.
+    fn poll_downstream(&mut self) -> io::Result<()> {
+        loop {
+            let (n, peer) = match self.socket.recv_from(&mut self.buf) {
+                Ok(r) => r,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            if !self.flows.contains_key(&peer) {
+                if self.flows.len() >= MAX_FLOWS {
+                    debug!("dropping datagram from {}: too many flows", peer);
+                    continue;
+                }
+                match Flow::open(&self.target, &self.executor) {
+                    Ok(flow) => {
+                        debug!("udp flow open {} -> {}", peer, self.target);
+                        self.flows.insert(peer, flow);
+                    }
+                    Err(e) => {
+                        warn!("could not open udp flow for {}: {}", peer, e);
+                        continue;
+                    }
+                }
+            }
+
+            let flow = self.flows.get_mut(&peer).expect("flow must exist");
+            flow.last_active = Instant::now();
+            flow.stats.rx_packets += 1;
+            flow.stats.rx_bytes += n as u64;
+
+            // Datagrams are inherently lossy, so rather than buffering, drop the datagram
+            // if the upstream socket isn't writable.
+            match flow.upstream.send_to(&self.buf[..n], &self.target) {
+                Ok(_) => {}
+                Err(e) => {
+                    trace!("dropping datagram from {}: {}", peer, e);
+                    flow.stats.dropped_packets += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns datagrams from the target to downstream peers.
+    fn poll_upstream(&mut self) {
+        for (peer, flow) in &mut self.flows {
+            loop {
+                let n = match flow.upstream.recv_from(&mut self.buf) {
+                    Ok((n, from)) => {
+                        if from != self.target {
+                            trace!("ignoring datagram from unexpected peer {}", from);
+                            continue;
+                        }
+                        n
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        // ICMP errors (i.e. connection refused) surface here; they
+                        // shouldn't end the flow.
+                        trace!("udp flow {} upstream error: {}", peer, e);
+                        continue;
+                    }
+                };
+
+                flow.last_active = Instant::now();
+                match self.socket.send_to(&self.buf[..n], peer) {
+                    Ok(_) => {
+                        flow.stats.tx_packets += 1;
+                        flow.stats.tx_bytes += n as u64;
+                    }
+                    Err(e) => {
+                        trace!("dropping datagram to {}: {}", peer, e);
+                        flow.stats.dropped_packets += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn expire_flows(&mut self) {
+        let now = Instant::now();
+        let idle = Duration::from_secs(IDLE_TIMEOUT_SECS);
+        let target = self.target;
+        self.flows.retain(|peer, flow| {
+            if now.duration_since(flow.last_active) < idle {
+                return true;
+            }
+
+            debug!(
+                "udp flow closed {} -> {} after {:?}; {:?}",
+                peer,
+                target,
+                flow.opened_at.elapsed(),
+                flow.stats,
+            );
+            false
+        });
+    }
+}
+
+impl Future for Relay {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<(), io::Error> {
+        while let Async::Ready(Some(())) = self.expire.poll()? {
+            self.expire_flows();
+        }
+
+        self.poll_downstream()?;
+        self.poll_upstream();
+
+        Ok(Async::NotReady)
+    }
+}
+
+// ===== impl Flow =====
+
+impl Flow {
+    fn open(target: &SocketAddr, executor: &Handle) -> io::Result<Self> {
+        let local = if target.is_ipv4() {
+            SocketAddr::from(([0, 0, 0, 0], 0))
+        } else {
+            SocketAddr::from(([0u16; 8], 0))
+        };
+        let upstream = UdpSocket::bind(&local, executor)?;
+        let now = Instant::now();
+        Ok(Flow {
+            upstream,
+            opened_at: now,
+            last_active: now,
+            stats: FlowStats::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket as StdUdpSocket;
+    use std::time::Duration;
+
+    use futures::Future;
+    use tokio_core::reactor::Core;
+
+    use super::Relay;
+
+    #[test]
+    fn relays_from_an_ephemeral_port() {
+        let mut core = Core::new().unwrap();
+
+        let target = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        target.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+
+        let listen = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = listen.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+        let relay = Relay::new(listen, target.local_addr().unwrap(), &core.handle()).unwrap();
+        core.handle().spawn(relay.map_err(|e| panic!("relay failed: {}", e)));
+
+        let client = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        client.send_to(b"ping", addr).unwrap();
+
+        let mut buf = [0; 8];
+        for _ in 0..100 {
+            core.turn(Some(Duration::from_millis(10)));
+            if let Ok((n, _)) = target.recv_from(&mut buf) {
+                assert_eq!(&buf[..n], b"ping");
+                return;
+            }
+        }
+        panic!("datagram was not relayed");
+    }
+}
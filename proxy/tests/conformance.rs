@@ -0,0 +1,282 @@
+//! Protocol conformance cases, run with `cargo test --features conformance`.
+//!
+//! HTTP/2 cases are written at the frame level, in the style of h2spec, against the
+//! public listener, which serves HTTP/2 only. HTTP/1.1 cases are run against the
+//! control listener, whose admin endpoints are the proxy's only HTTP/1.1 server.
+#![cfg(feature = "conformance")]
+
+#[macro_use]
+extern crate log;
+
+mod support;
+use self::support::*;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const DATA: u8 = 0x0;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+
+const ACK: u8 = 0x1;
+
+const PROTOCOL_ERROR: u32 = 0x1;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+
+#[derive(Debug)]
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+fn run_proxy() -> proxy::Listening {
+    let _ = env_logger::init();
+    let ctrl = controller::new().run();
+    proxy::new().controller(ctrl).run()
+}
+
+fn connect(addr: SocketAddr) -> TcpStream {
+    let conn = TcpStream::connect(addr).expect("connect");
+    conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    conn
+}
+
+fn encode(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+    let len = payload.len();
+    let mut frame = vec![
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+        kind,
+        flags,
+    ];
+    frame.extend_from_slice(&be32(stream_id & 0x7fff_ffff));
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn be32(n: u32) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    (b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32
+}
+
+/// Reads the next frame, or `None` once the connection is closed.
+fn read_frame(conn: &mut TcpStream) -> Option<Frame> {
+    let mut head = [0u8; 9];
+    if conn.read_exact(&mut head).is_err() {
+        return None;
+    }
+    let len = (head[0] as usize) << 16 | (head[1] as usize) << 8 | head[2] as usize;
+    let mut payload = vec![0u8; len];
+    if conn.read_exact(&mut payload).is_err() {
+        return None;
+    }
+    let frame = Frame {
+        kind: head[3],
+        flags: head[4],
+        stream_id: read_u32(&head[5..]) & 0x7fff_ffff,
+        payload,
+    };
+    trace!("read {:?}", frame);
+    Some(frame)
+}
+
+/// Reads frames until one of `kind` with all of `flags` set, skipping any others.
+fn expect_frame(conn: &mut TcpStream, kind: u8, flags: u8) -> Frame {
+    loop {
+        let f = read_frame(conn)
+            .unwrap_or_else(|| panic!("connection closed before a frame of type {:#x}", kind));
+        if f.kind == kind && f.flags & flags == flags {
+            return f;
+        }
+    }
+}
+
+/// Expects the connection to be closed with a GOAWAY carrying `reason`.
+fn expect_goaway(conn: &mut TcpStream, reason: u32) {
+    let goaway = expect_frame(conn, GOAWAY, 0);
+    assert_eq!(goaway.stream_id, 0);
+    assert_eq!(read_u32(&goaway.payload[4..8]), reason);
+    assert!(read_frame(conn).is_none(), "connection wasn't closed after GOAWAY");
+}
+
+/// Opens an HTTP/2 connection, exchanging the preface and settings.
+fn handshake(addr: SocketAddr) -> TcpStream {
+    let mut conn = connect(addr);
+    conn.write_all(PREFACE).unwrap();
+    conn.write_all(&encode(SETTINGS, 0, 0, &[])).unwrap();
+    expect_frame(&mut conn, SETTINGS, ACK);
+    conn
+}
+
+/// Sends a single HTTP/1.1 request head and reads everything until the connection closes.
+fn http1(addr: SocketAddr, request: &[u8]) -> String {
+    let mut conn = connect(addr);
+    // The proxy may close the connection before reading all of an invalid request.
+    let _ = conn.write_all(request);
+    let mut rsp = Vec::new();
+    let _ = conn.read_to_end(&mut rsp);
+    String::from_utf8_lossy(&rsp).into_owned()
+}
+
+// ===== HTTP/2 =====
+
+#[test]
+fn http2_sends_settings_after_preface() {
+    // RFC 7540, Section 3.5.
+    let proxy = run_proxy();
+    let mut conn = connect(proxy.inbound);
+    conn.write_all(PREFACE).unwrap();
+
+    let first = read_frame(&mut conn).expect("settings");
+    assert_eq!(first.kind, SETTINGS);
+    assert_eq!(first.flags & ACK, 0);
+    assert_eq!(first.stream_id, 0);
+}
+
+#[test]
+fn http2_acknowledges_settings() {
+    // RFC 7540, Section 6.5.3.
+    let proxy = run_proxy();
+    let mut conn = handshake(proxy.inbound);
+
+    conn.write_all(&encode(SETTINGS, 0, 0, &[0, 0x4, 0, 0, 0xff, 0xff])).unwrap();
+    let ack = expect_frame(&mut conn, SETTINGS, ACK);
+    assert!(ack.payload.is_empty());
+}
+
+#[test]
+fn http2_rejects_invalid_preface() {
+    // RFC 7540, Section 3.5.
+    let proxy = run_proxy();
+    let mut conn = connect(proxy.inbound);
+    conn.write_all(b"PRI * HTTP/2.0\r\n\r\nXX\r\n\r\n").unwrap();
+
+    while let Some(f) = read_frame(&mut conn) {
+        assert!(f.kind == SETTINGS || f.kind == GOAWAY, "unexpected {:?}", f);
+    }
+}
+
+#[test]
+fn http2_answers_ping() {
+    // RFC 7540, Section 6.7.
+    let proxy = run_proxy();
+    let mut conn = handshake(proxy.inbound);
+
+    conn.write_all(&encode(PING, 0, 0, b"conduit!")).unwrap();
+    let pong = expect_frame(&mut conn, PING, ACK);
+    assert_eq!(pong.payload, b"conduit!");
+}
+
+#[test]
+fn http2_rejects_ping_with_bad_length() {
+    // RFC 7540, Section 6.7.
+    let proxy = run_proxy();
+    let mut conn = handshake(proxy.inbound);
+
+    conn.write_all(&encode(PING, 0, 0, b"ping")).unwrap();
+    expect_goaway(&mut conn, FRAME_SIZE_ERROR);
+}
+
+#[test]
+fn http2_ignores_unknown_frames() {
+    // RFC 7540, Section 4.1.
+    let proxy = run_proxy();
+    let mut conn = handshake(proxy.inbound);
+
+    conn.write_all(&encode(0xfa, 0, 0, b"unknown")).unwrap();
+    conn.write_all(&encode(PING, 0, 0, b"conduit!")).unwrap();
+    let pong = expect_frame(&mut conn, PING, ACK);
+    assert_eq!(pong.payload, b"conduit!");
+}
+
+#[test]
+fn http2_rejects_data_on_stream_zero() {
+    // RFC 7540, Section 6.1.
+    let proxy = run_proxy();
+    let mut conn = handshake(proxy.inbound);
+
+    conn.write_all(&encode(DATA, 0, 0, b"hello")).unwrap();
+    expect_goaway(&mut conn, PROTOCOL_ERROR);
+}
+
+#[test]
+fn http2_rejects_data_on_idle_stream() {
+    // RFC 7540, Section 5.1.
+    let proxy = run_proxy();
+    let mut conn = handshake(proxy.inbound);
+
+    conn.write_all(&encode(DATA, 0, 1, b"hello")).unwrap();
+    expect_goaway(&mut conn, PROTOCOL_ERROR);
+}
+
+// ===== HTTP/1.1 =====
+
+#[test]
+fn http1_answers_with_a_closed_response() {
+    // RFC 7230, Sections 3.3.2 and 6.6.
+    let proxy = run_proxy();
+    let rsp = http1(proxy.control, b"GET / HTTP/1.1\r\nHost: proxy\r\n\r\n");
+
+    assert!(rsp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", rsp);
+    let (head, body) = rsp.split_at(rsp.find("\r\n\r\n").expect("end of head") + 4);
+    assert!(head.contains("\r\nconnection: close\r\n"), "{}", head);
+    let len = format!("\r\ncontent-length: {}\r\n", body.len());
+    assert!(head.contains(&len), "{}", head);
+}
+
+#[test]
+fn http1_answers_http10_requests() {
+    // RFC 7230, Section 2.6.
+    let proxy = run_proxy();
+    let rsp = http1(proxy.control, b"GET / HTTP/1.0\r\n\r\n");
+    assert!(rsp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", rsp);
+}
+
+#[test]
+fn http1_answers_unknown_targets_with_not_found() {
+    let proxy = run_proxy();
+    let rsp = http1(proxy.control, b"GET /nope HTTP/1.1\r\nHost: proxy\r\n\r\n");
+    assert!(rsp.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", rsp);
+}
+
+#[test]
+fn http1_rejects_unsupported_versions() {
+    // RFC 7230, Section 2.6.
+    let proxy = run_proxy();
+    let rsp = http1(proxy.control, b"GET / SPDY/3\r\nHost: proxy\r\n\r\n");
+    assert!(rsp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", rsp);
+}
+
+#[test]
+fn http1_rejects_malformed_headers() {
+    // RFC 7230, Section 3.2.
+    let proxy = run_proxy();
+    let rsp = http1(proxy.control, b"GET / HTTP/1.1\r\nno colon here\r\n\r\n");
+    assert!(rsp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", rsp);
+}
+
+#[test]
+fn http1_rejects_missing_targets() {
+    // RFC 7230, Section 3.1.1.
+    let proxy = run_proxy();
+    let rsp = http1(proxy.control, b"GET\r\n\r\n");
+    assert!(rsp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", rsp);
+}
+
+#[test]
+fn http1_closes_oversized_heads() {
+    let proxy = run_proxy();
+    let mut request = b"GET / HTTP/1.1\r\nx-padding: ".to_vec();
+    request.extend(::std::iter::repeat(b'a').take(16 * 1024));
+    let rsp = http1(proxy.control, &request);
+    assert!(rsp.is_empty(), "{}", rsp);
+}
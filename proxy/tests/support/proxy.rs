@@ -63,36 +63,35 @@ fn run(proxy: Proxy) -> Listening {
     let inbound = proxy.inbound;
     let outbound = proxy.outbound;
 
-    let mut config = conduit_proxy::config::Config::load_from_env().unwrap();
-
-    config.control_host_and_port = {
-        let control_url: url::Url = format!("tcp://{}", controller.addr).parse().unwrap();
-        url::HostAndPort {
-            host: control_url.host().unwrap().to_owned(),
-            port: control_url.port().unwrap(),
-        }
-    };
-
-    config.private_listener = conduit_proxy::config::Listener {
-        addr: "tcp://127.0.0.1:0".parse().unwrap(),
-    };
+    let mut config = conduit_proxy::config::Config::builder()
+        .private_listener(conduit_proxy::config::Listener::new(
+            "tcp://127.0.0.1:0".parse().unwrap(),
+        ))
+        .public_listener(conduit_proxy::config::Listener::new(
+            "tcp://127.0.0.1:0".parse().unwrap(),
+        ))
+        .control_listener(conduit_proxy::config::Listener::new(
+            "tcp://127.0.0.1:0".parse().unwrap(),
+        ))
+        .control_host_and_port({
+            let control_url: url::Url = format!("tcp://{}", controller.addr).parse().unwrap();
+            url::HostAndPort {
+                host: control_url.host().unwrap().to_owned(),
+                port: control_url.port().unwrap(),
+            }
+        });
 
     if let Some(ref inbound) = inbound {
-        config.private_forward = format!("tcp://{}", inbound.addr).parse().ok();
+        let forward = format!("tcp://{}", inbound.addr).parse().ok();
+        config = config.private_forward(forward);
     }
 
-    config.public_listener = conduit_proxy::config::Listener {
-        addr: "tcp://127.0.0.1:0".parse().unwrap(),
-    };
-
-    config.control_listener = conduit_proxy::config::Listener {
-        addr: "tcp://127.0.0.1:0".parse().unwrap(),
-    };
-
     if let Some(dur) = proxy.metrics_flush_interval {
-        config.metrics_flush_interval = dur;
+        config = config.metrics_flush_interval(dur);
     }
 
+    let config = config.build().unwrap();
+
     let main = conduit_proxy::Main::new(config);
 
     let control_addr = main.control_addr();
@@ -43,6 +43,8 @@ fn inbound_sends_telemetry() {
     let res = &req.responses[0];
     assert_eq!(res.ctx.as_ref().unwrap().http_status_code, 200);
     assert_eq!(res.response_latencies.len(), 1);
+    assert_eq!(res.first_byte_latencies.len(), 1);
+    assert_eq!(res.end_latencies.len(), 1);
     assert_eq!(res.ends.len(), 1);
     // ends
     let ends = &res.ends[0];
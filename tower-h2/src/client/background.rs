@@ -55,8 +55,14 @@ where C: Connect,
         use self::Task::*;
 
         match self.task {
-            // TODO: Log error?
-            Connection(ref mut f) => f.poll().map_err(|_| ()),
+            Connection(ref mut f) => f.poll().map_err(|e| {
+                // The reason is that of the GOAWAY frame or connection error that
+                // terminated the connection; in-flight streams fail with it.
+                match e.reason() {
+                    Some(reason) => debug!("client connection error: {:?}", reason),
+                    None => debug!("client connection error: {}", e),
+                }
+            }),
             Flush(ref mut f) => f.poll(),
         }
     }
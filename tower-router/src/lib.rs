@@ -93,6 +93,19 @@ where T: Recognize
             })),
         }
     }
+
+    /// Drops all cached routes, which are bound again as requests arrive, and returns
+    /// how many were dropped.
+    ///
+    /// Requests that are already being handled are unaffected.
+    pub fn clear(&self) -> usize {
+        // The routes are dropped after the lock is released.
+        let routes = {
+            let mut inner = self.inner.lock().unwrap();
+            mem::replace(&mut inner.routes, OrderMap::default())
+        };
+        routes.len()
+    }
 }
 
 impl<T> Service for Router<T>